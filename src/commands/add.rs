@@ -1,5 +1,7 @@
 use super::{Command, Execution};
-use crate::{errors::RitError, objects, repository::Repository, workspace::Entry, Session};
+use crate::{
+    errors::RitError, objects, repository::Repository, submodule, workspace::Entry, Session,
+};
 
 pub struct Add {
     paths: Vec<String>,
@@ -33,14 +35,17 @@ impl Add {
     }
 
     fn add_to_index(&mut self, entry: Entry) -> Result<(), RitError> {
-        let file = self.repo.workspace.read_file(&entry).map_err(|err| {
+        if entry.is_dir {
+            return self.add_gitlink(entry);
+        }
+
+        let (data, stat) = self.repo.workspace.read_data(&entry).map_err(|err| {
             self.repo.index.release_lock().unwrap();
 
             err
         })?;
-        let stat = self.repo.workspace.stat_file(&file);
 
-        let mut blob = objects::Blob::new(file);
+        let mut blob = objects::Blob::parse(data.into());
 
         let blob_id = self.repo.database.store(&mut blob).unwrap();
 
@@ -48,6 +53,29 @@ impl Add {
 
         Ok(())
     }
+
+    /// Stages a nested repository as a gitlink to the commit it has checked
+    /// out, replacing any files staged below it.
+    fn add_gitlink(&mut self, entry: Entry) -> Result<(), RitError> {
+        let head = submodule::head(&entry.absolute_path);
+        let stat = self.repo.workspace.stat_path(&entry.relative_path_name);
+
+        match (head, stat) {
+            (Some(head), Some(stat)) => {
+                self.repo.index.add(entry, head, stat);
+
+                Ok(())
+            }
+            _ => {
+                self.repo.index.release_lock()?;
+
+                Err(RitError::Submodule(format!(
+                    "'{}/' does not have a commit checked out",
+                    entry.relative_path_name
+                )))
+            }
+        }
+    }
 }
 
 impl Command for Add {
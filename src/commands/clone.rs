@@ -0,0 +1,417 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    id::Id,
+    objects::Author,
+    refs::{Refs, HEAD},
+    remote::{self, protocol_error, Advertisement, Refspec, Remote, UPLOAD_PACK},
+    repository::Repository,
+    Session,
+};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+const ORIGIN: &str = "origin";
+const HEADS_PREFIX: &str = "refs/heads/";
+const TAGS_PREFIX: &str = "refs/tags/";
+const DEFAULT_BRANCH: &str = "refs/heads/master";
+
+#[derive(Default)]
+pub struct CloneOptions {
+    pub source: String,
+    pub directory: Option<String>,
+    pub bare: bool,
+    pub branch: Option<String>,
+    pub upload_pack: Option<String>,
+}
+
+/// What a clone starts from: the branches and tags of the source and the
+/// branch its HEAD points at.
+struct Source {
+    url: String,
+    refs: Vec<(String, Id)>,
+    head: Option<String>,
+    head_id: Option<Id>,
+}
+
+/// Clones a repository. From a local path, objects are hardlinked when both
+/// repositories live on the same filesystem and copied otherwise, while from
+/// a url they are fetched through `upload-pack`. The branches of the source
+/// become `refs/remotes/origin/*` and the branch that the source's HEAD
+/// points at, or `--branch`, is checked out. A `--bare` clone has no working
+/// tree and keeps the branches as they are.
+pub struct Clone {
+    session: Session,
+    options: CloneOptions,
+}
+
+#[derive(Debug)]
+pub struct CloneResult {
+    directory: String,
+    bare: bool,
+    empty: bool,
+}
+
+impl fmt::Display for CloneResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bare {
+            write!(f, "Cloning into bare repository '{}'...", self.directory)?;
+        } else {
+            write!(f, "Cloning into '{}'...", self.directory)?;
+        }
+
+        if self.empty {
+            write!(
+                f,
+                "\nwarning: You appear to have cloned an empty repository."
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Clone {
+    pub fn new(session: Session, options: CloneOptions) -> Self {
+        Self { session, options }
+    }
+
+    /// Finds the git directory of a source given as a path.
+    fn source_git_path(&self) -> Result<PathBuf, RitError> {
+        let path = self.session.project_dir.join(&self.options.source);
+        let not_found = || {
+            RitError::CloneFailed(format!(
+                "repository '{}' does not exist",
+                self.options.source
+            ))
+        };
+
+        let path = fs::canonicalize(path).map_err(|_| not_found())?;
+
+        remote::git_dir(&path).ok_or_else(not_found)
+    }
+
+    /// Names the clone after the last component of the source, without its
+    /// `.git` suffix, unless `--bare` asks for one.
+    fn directory(&self, url: &str) -> String {
+        if let Some(directory) = &self.options.directory {
+            return directory.clone();
+        }
+
+        let name = default_directory(url);
+
+        if self.options.bare {
+            format!("{}.git", name)
+        } else {
+            name
+        }
+    }
+
+    /// Reads the refs of a local source and hardlinks or copies its objects.
+    fn copy_source(&self, source_path: &Path, git_path: &Path) -> Result<Source, RitError> {
+        self.copy_objects(&source_path.join("objects"), &git_path.join("objects"))?;
+
+        let source = Refs::new(source_path.to_path_buf());
+        let refs = source
+            .list_refs("refs/heads")
+            .into_iter()
+            .chain(source.list_refs("refs/tags"))
+            .filter_map(|name| Some((name.clone(), source.read_ref(&name)?)))
+            .collect();
+
+        Ok(Source {
+            url: remote::work_tree(source_path).to_string_lossy().to_string(),
+            refs,
+            head: source.current_ref(),
+            head_id: source.read_head(),
+        })
+    }
+
+    fn copy_objects(&self, source: &Path, target: &Path) -> Result<(), io::Error> {
+        fs::create_dir_all(target)?;
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let target_path = target.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                self.copy_objects(&entry.path(), &target_path)?;
+            } else if fs::hard_link(entry.path(), &target_path).is_err() {
+                fs::copy(entry.path(), &target_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches every branch and tag of the source through `upload-pack`.
+    fn fetch_source(&self, repo: &mut Repository) -> Result<Source, RitError> {
+        let mut remote = Remote::find(&repo.config, &self.options.source);
+
+        if let Some(program) = &self.options.upload_pack {
+            remote.set_program(UPLOAD_PACK, program.clone());
+        }
+
+        let mut conn = remote.connect(UPLOAD_PACK, &self.session.project_dir)?;
+        let advertisement = Advertisement::read(&mut conn.input).map_err(protocol_error)?;
+
+        let refs: Vec<(String, Id)> = advertisement
+            .refs
+            .iter()
+            .filter(|(name, _)| name.starts_with(HEADS_PREFIX) || name.starts_with(TAGS_PREFIX))
+            .cloned()
+            .collect();
+
+        let mut wants = vec![];
+
+        for (_, id) in &refs {
+            if !wants.contains(id) {
+                wants.push(id.clone());
+            }
+        }
+
+        remote::fetch_pack(&mut conn, &advertisement, repo, &wants, &[])?;
+        conn.finish().map_err(protocol_error)?;
+
+        let head_id = advertisement.get(HEAD).cloned();
+        let head = advertisement.symref(HEAD).map(String::from).or_else(|| {
+            // without the symref capability, guess from the branch HEAD
+            // points at, preferring the default one
+            let mut branches = refs.iter().filter(|(name, id)| {
+                name.starts_with(HEADS_PREFIX) && Some(id) == head_id.as_ref()
+            });
+            let first = branches.next().map(|(name, _)| name.clone());
+
+            refs.iter()
+                .find(|(name, id)| name == DEFAULT_BRANCH && Some(id) == head_id.as_ref())
+                .map(|(name, _)| name.clone())
+                .or(first)
+        });
+
+        Ok(Source {
+            url: remote.url,
+            refs,
+            head,
+            head_id,
+        })
+    }
+
+    /// Copies the branches of the source, under `refs/remotes/origin` unless
+    /// the clone is bare, and its tags. `refs/remotes/origin/HEAD` follows
+    /// the branch the source has checked out.
+    fn copy_refs(&self, source: &Source, repo: &Repository) -> Result<(), RitError> {
+        let committer = self.committer();
+        let message = format!("clone: from {}", source.url);
+        let remote_prefix = format!("refs/remotes/{}/", ORIGIN);
+
+        for (name, id) in &source.refs {
+            if name.starts_with(TAGS_PREFIX) || self.options.bare {
+                repo.refs
+                    .update_ref_logged(name, id, &committer, &message)?;
+            } else {
+                let target = name.replacen(HEADS_PREFIX, &remote_prefix, 1);
+                repo.refs
+                    .update_ref_logged(&target, id, &committer, &message)?;
+            }
+        }
+
+        let remote_head = source
+            .head
+            .as_ref()
+            .filter(|name| source.refs.iter().any(|(ref_name, _)| ref_name == *name));
+
+        if let (Some(name), false) = (remote_head, self.options.bare) {
+            repo.refs.write_symref(
+                &format!("{}{}", remote_prefix, HEAD),
+                &name.replacen(HEADS_PREFIX, &remote_prefix, 1),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn write_config(
+        &self,
+        repo: &mut Repository,
+        url: &str,
+        branch: Option<&str>,
+    ) -> Result<(), RitError> {
+        let config = &mut repo.config.local;
+        let fetch = Refspec::default_fetch(ORIGIN);
+
+        config.set("core.bare", &self.options.bare.to_string())?;
+        config.set(&format!("remote.{}.url", ORIGIN), url)?;
+
+        if !self.options.bare {
+            config.set(&format!("remote.{}.fetch", ORIGIN), &fetch)?;
+
+            if let Some(branch) = branch {
+                config.set(&format!("branch.{}.remote", branch), ORIGIN)?;
+                config.set(
+                    &format!("branch.{}.merge", branch),
+                    &format!("{}{}", HEADS_PREFIX, branch),
+                )?;
+            }
+        }
+
+        config.save()?;
+
+        Ok(())
+    }
+
+    fn committer(&self) -> Author {
+        Author::new(&self.session.author_name, &self.session.author_email)
+    }
+
+    /// Picks the branch to check out: `--branch` when given, or the one HEAD
+    /// points at in the source.
+    fn checkout_branch(&self, source: &Source) -> Result<Option<String>, RitError> {
+        let find = |name: &str| {
+            source
+                .refs
+                .iter()
+                .find(|(ref_name, _)| ref_name == name)
+                .map(|(_, id)| id.clone())
+        };
+
+        match &self.options.branch {
+            Some(branch) => {
+                if find(&format!("{}{}", HEADS_PREFIX, branch)).is_none() {
+                    return Err(RitError::CloneFailed(format!(
+                        "Remote branch {} not found in upstream {}",
+                        branch, ORIGIN
+                    )));
+                }
+
+                Ok(Some(branch.clone()))
+            }
+            None => Ok(source
+                .head
+                .as_ref()
+                .and_then(|name| name.strip_prefix(HEADS_PREFIX).map(String::from))),
+        }
+    }
+
+    /// Sets up the new repository at `work_path` and returns whether the
+    /// source turned out to be empty.
+    fn clone_into(&self, work_path: &Path) -> Result<bool, RitError> {
+        let git_path = if self.options.bare {
+            work_path.to_path_buf()
+        } else {
+            work_path.join(".git")
+        };
+
+        fs::create_dir_all(git_path.join("refs"))?;
+        fs::create_dir_all(git_path.join("objects"))?;
+
+        let mut repo = Repository::with_git_path(work_path.to_path_buf(), git_path.clone());
+
+        let source = if remote::is_path(&self.options.source) {
+            self.copy_source(&self.source_git_path()?, &git_path)?
+        } else {
+            self.fetch_source(&mut repo)?
+        };
+
+        let branch = self.checkout_branch(&source)?;
+
+        self.copy_refs(&source, &repo)?;
+        self.write_config(&mut repo, &source.url, branch.as_deref())?;
+
+        let head_id = match &branch {
+            Some(branch) => source
+                .refs
+                .iter()
+                .find(|(name, _)| name == &format!("{}{}", HEADS_PREFIX, branch))
+                .map(|(_, id)| id.clone()),
+            None => source.head_id.clone(),
+        };
+
+        let id = match head_id {
+            Some(id) => id,
+            None => {
+                let default = source.head.as_deref().unwrap_or(DEFAULT_BRANCH);
+                repo.refs.write_symref(HEAD, default)?;
+
+                return Ok(true);
+            }
+        };
+
+        let committer = self.committer();
+        let message = format!("clone: from {}", source.url);
+
+        match &branch {
+            Some(branch) => {
+                let branch_ref = format!("{}{}", HEADS_PREFIX, branch);
+                repo.refs.write_symref(HEAD, &branch_ref)?;
+
+                if !self.options.bare {
+                    repo.refs
+                        .update_ref_logged(&branch_ref, &id, &committer, &message)?;
+                }
+            }
+            None => repo
+                .refs
+                .update_ref_logged(HEAD, &id, &committer, &message)?,
+        }
+
+        if !self.options.bare {
+            repo.index.load_for_update()?;
+            repo.hard_reset(&id)?;
+            repo.index.write_updates()?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// The last component of `url`, without any `.git` suffix, which names a
+/// clone of it by default.
+pub fn default_directory(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix("/.git").unwrap_or(url);
+    let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
+
+impl Command for Clone {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let url = if remote::is_path(&self.options.source) {
+            self.source_git_path()?.to_string_lossy().to_string()
+        } else {
+            self.options.source.clone()
+        };
+
+        let directory = self.directory(&url);
+        let work_path = self.session.project_dir.join(&directory);
+        let existed = work_path.exists();
+
+        if fs::read_dir(&work_path).is_ok_and(|mut entries| entries.next().is_some()) {
+            return Err(RitError::CloneFailed(format!(
+                "destination path '{}' already exists and is not an empty directory.",
+                directory
+            )));
+        }
+
+        // like git, leave nothing behind when the clone fails, emptying a
+        // directory that was already there rather than removing it
+        let empty = self.clone_into(&work_path).inspect_err(|_| {
+            if !existed {
+                let _ = fs::remove_dir_all(&work_path);
+            } else if let Ok(entries) = fs::read_dir(&work_path) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let _ = fs::remove_dir_all(&path).or_else(|_| fs::remove_file(&path));
+                }
+            }
+        })?;
+
+        Ok(Execution::Clone(CloneResult {
+            directory,
+            bare: self.options.bare,
+            empty,
+        }))
+    }
+}
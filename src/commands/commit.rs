@@ -1,16 +1,142 @@
-use super::{Command, Execution};
-use crate::{errors::RitError, id::Id, objects, repository::Repository, Session};
-use std::fmt;
+use super::{write_commit, Command, Execution};
+use crate::{
+    database::Database,
+    editor,
+    errors::RitError,
+    id::Id,
+    index::Index,
+    objects,
+    repository::Repository,
+    revision::Revision,
+    submodule,
+    workspace::{matches_pathspec, Workspace},
+    Session,
+};
+use std::{
+    collections::BTreeSet,
+    env, fmt, fs,
+    io::{self, Read},
+    mem,
+    path::PathBuf,
+    process,
+};
+
+const COMMENT_CHAR: char = '#';
+const SCISSORS_LINE: &str = "# ------------------------ >8 ------------------------";
+
+/// Where the message comes from: the paragraphs given with `-m`, the file
+/// given with `-F`, where `-` reads standard input, or else the editor,
+/// started on `commit.template` or the message of the commit being amended
+/// unless `--no-edit` takes that as it is.
+///
+/// What is committed is the index, after `-a` has staged the changes to
+/// all tracked files or `--include` those to `paths`. Otherwise, `paths`
+/// are committed alone, from a temporary index of HEAD with just them
+/// updated, leaving the other changes staged in the index as they were.
+/// `--only` asks for that even without paths, which with `--amend` commits
+/// the tree of HEAD so that only the message and author change.
+#[derive(Default)]
+pub struct CommitOptions {
+    pub all: bool,
+    pub include: bool,
+    pub only: bool,
+    pub paths: Vec<String>,
+    pub messages: Vec<String>,
+    pub file: Option<String>,
+    pub fixup: Option<String>,
+    pub amend: bool,
+    pub reset_author: bool,
+    pub no_edit: bool,
+    pub cleanup: Option<Cleanup>,
+}
+
+/// How the message is tidied up before committing, as `--cleanup` says:
+/// `strip` drops comment lines on top of what `whitespace` does, which is
+/// removing trailing whitespace and blank lines at either end and folding
+/// runs of blank lines into one. `scissors` is like `whitespace`, but an
+/// edited message is cut at the scissors line, while `verbatim` leaves the
+/// message alone. By default, a message is stripped if it was edited and
+/// has its whitespace cleaned up otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cleanup {
+    Strip,
+    Whitespace,
+    Verbatim,
+    Scissors,
+}
+
+impl Cleanup {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "strip" => Some(Cleanup::Strip),
+            "whitespace" => Some(Cleanup::Whitespace),
+            "verbatim" => Some(Cleanup::Verbatim),
+            "scissors" => Some(Cleanup::Scissors),
+            _ => None,
+        }
+    }
+
+    fn apply(self, message: &str, edited: bool) -> String {
+        let message = match self {
+            Cleanup::Verbatim => return message.to_string(),
+            Cleanup::Scissors if edited => message
+                .split_once(&format!("{}\n", SCISSORS_LINE))
+                .map_or(message, |(kept, _)| kept),
+            _ => message,
+        };
+
+        let mut lines: Vec<&str> = vec![];
+
+        for line in message.lines() {
+            if self == Cleanup::Strip && line.starts_with(COMMENT_CHAR) {
+                continue;
+            }
+
+            let line = line.trim_end();
+
+            if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(line);
+            }
+        }
+
+        lines.join("\n").trim_end().to_string()
+    }
+
+    /// Whether nothing is left of the message but blank and, unless kept
+    /// verbatim, comment lines.
+    fn is_empty(self, message: &str) -> bool {
+        match self {
+            Cleanup::Verbatim => message.is_empty(),
+            _ => message
+                .lines()
+                .all(|line| line.trim().is_empty() || line.starts_with(COMMENT_CHAR)),
+        }
+    }
+
+    /// What the comment in the editor says about the lines starting with
+    /// `#`.
+    fn instructions(self) -> &'static str {
+        match self {
+            Cleanup::Strip => {
+                "Lines starting\n# with '#' will be ignored, and an empty message aborts the commit."
+            }
+            _ => {
+                "Lines starting\n# with '#' will be kept; you may remove them yourself if you want to.\n# An empty message aborts the commit."
+            }
+        }
+    }
+}
 
 pub struct Commit {
     session: Session,
+    options: CommitOptions,
     message: String,
     repo: Repository,
 }
 
 #[derive(Debug)]
 pub struct CommitResult {
-    parent_id: Option<String>,
+    parent_id: Option<Id>,
     commit_id: String,
     message: String,
 }
@@ -28,34 +154,367 @@ impl fmt::Display for CommitResult {
 
 impl Commit {
     pub fn new(session: Session, message: String) -> Self {
+        let options = CommitOptions {
+            messages: vec![message],
+            ..Default::default()
+        };
+
+        Self::with_options(session, options)
+    }
+
+    pub fn with_options(session: Session, options: CommitOptions) -> Self {
         let repo = Repository::new(session.project_dir.clone());
 
         Self {
             session,
-            message,
+            options,
+            message: String::new(),
             repo,
         }
     }
 
-    fn commit<'a>(&'a self, parent_id: &'a Option<String>, root_id: Id) -> Result<Id, RitError> {
-        let author = objects::Author::new(&self.session.author_name, &self.session.author_email);
+    /// Builds the message from the options; `--fixup` names the commit it
+    /// fixes so that `rebase --autosquash` can find it. Without a message
+    /// given, the one of `amended`, if any, is the starting point.
+    fn read_message(&self, amended: Option<&objects::Commit>) -> Result<String, RitError> {
+        let message = self.given_message()?;
 
-        let mut commit = objects::Commit::new(&parent_id, root_id, author, &self.message);
+        let fixup = match &self.options.fixup {
+            Some(rev) => {
+                let id = Revision::new(&self.repo, rev).resolve()?;
+                let commit = self.repo.database.load_commit(&id)?;
 
-        let commit_id = self.repo.database.store(&mut commit)?;
+                format!("fixup! {}", commit.title_line())
+            }
+            None => match message {
+                Some(message) => return self.clean_up(&message, false),
+                None => return self.edit_message(amended),
+            },
+        };
 
-        Ok(commit_id)
+        match message {
+            Some(message) => self.clean_up(&format!("{}\n\n{}", fixup, message), false),
+            None => Ok(fixup),
+        }
     }
 
-    fn get_root(&mut self) -> Result<objects::Tree, RitError> {
-        self.repo.index.load()?;
+    /// The message given with `-F` or `-m`, each `-m` being a paragraph.
+    fn given_message(&self) -> Result<Option<String>, RitError> {
+        let mut message = String::new();
 
-        let entries = self.repo.index.entries();
+        match self.options.file.as_deref() {
+            Some("-") => {
+                io::stdin().read_to_string(&mut message)?;
+            }
+            Some(file) => message = fs::read_to_string(self.session.project_dir.join(file))?,
+            None if self.options.messages.is_empty() => return Ok(None),
+            None => message = self.options.messages.join("\n\n"),
+        }
+
+        Ok(Some(message))
+    }
+
+    /// Lets the user write the message in the editor, starting from the
+    /// message of the amended commit or the template, with the changes to
+    /// be committed listed below it in comments.
+    fn edit_message(&self, amended: Option<&objects::Commit>) -> Result<String, RitError> {
+        let template = match amended {
+            Some(_) => None,
+            None => self.read_template()?,
+        };
+
+        let initial = match (amended, &template) {
+            (Some(commit), _) => commit.message.clone(),
+            (None, Some(template)) => template.clone(),
+            (None, None) => String::new(),
+        };
+
+        if self.options.no_edit {
+            return self.clean_up(&initial, false);
+        }
+
+        let base = match amended {
+            Some(commit) => commit.parent().cloned(),
+            None => self.repo.refs.read_head(),
+        };
+
+        let content = format!(
+            "{}\n{}",
+            initial.trim_end(),
+            self.status_comment(base.as_ref())?
+        );
+        let message = self.clean_up(&editor::edit_commit_file(&self.repo, &content)?, true)?;
+
+        let unedited =
+            template.is_some_and(|template| self.cleanup(true).apply(&template, true) == message);
+
+        if unedited {
+            return Err(RitError::CommitAborted(
+                "Aborting commit; you did not edit the message.".to_string(),
+            ));
+        }
+
+        Ok(message)
+    }
+
+    /// Reads the file `commit.template` names, relative to the home
+    /// directory if it starts with `~/`.
+    fn read_template(&self) -> Result<Option<String>, RitError> {
+        let path = match self.repo.config.get("commit.template") {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(env::var("HOME")?).join(rest),
+            None => self.session.project_dir.join(path),
+        };
+
+        Ok(Some(fs::read_to_string(path)?))
+    }
+
+    /// The comment shown below the message in the editor: how it will be
+    /// cleaned up and the changes staged since the commit `base`.
+    fn status_comment(&self, base: Option<&Id>) -> Result<String, RitError> {
+        let cleanup = self.cleanup(true);
+        let mut lines = vec![String::new()];
+
+        if cleanup == Cleanup::Scissors {
+            lines.push(SCISSORS_LINE.to_string());
+            lines.push("# Do not modify or remove the line above.".to_string());
+            lines.push("# Everything below it will be ignored.".to_string());
+        }
+
+        lines.push(format!(
+            "# Please enter the commit message for your changes. {}",
+            cleanup.instructions()
+        ));
+        lines.push("#".to_string());
+
+        match self.repo.refs.current_ref() {
+            Some(name) => lines.push(format!("# On branch {}", self.repo.refs.short_name(&name))),
+            None => lines.push("# HEAD detached".to_string()),
+        }
+
+        let base_items = self.repo.database.flatten_tree(base)?;
+        let mut changes = vec![];
+
+        for entry in self.repo.index.entries() {
+            match base_items.get(&entry.pathname) {
+                None => changes.push(("new file", entry.pathname)),
+                Some(item) if item.id != entry.id || item.mode != entry.mode => {
+                    changes.push(("modified", entry.pathname))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for pathname in base_items.keys() {
+            if !self.repo.index.is_tracked_file(pathname) {
+                changes.push(("deleted", pathname.clone()));
+            }
+        }
+
+        changes.sort_by(|a, b| a.1.cmp(&b.1));
+
+        if !changes.is_empty() {
+            lines.push("#".to_string());
+            lines.push("# Changes to be committed:".to_string());
+
+            for (kind, pathname) in changes {
+                lines.push(format!("#\t{:<12}{}", format!("{}:", kind), pathname));
+            }
+        }
+
+        lines.push("#".to_string());
+
+        Ok(format!("{}\n", lines.join("\n")))
+    }
+
+    fn cleanup(&self, edited: bool) -> Cleanup {
+        match self.options.cleanup {
+            Some(cleanup) => cleanup,
+            None if edited => Cleanup::Strip,
+            None => Cleanup::Whitespace,
+        }
+    }
+
+    /// Cleans up `message` as `--cleanup` says, refusing one that is left
+    /// empty.
+    fn clean_up(&self, message: &str, edited: bool) -> Result<String, RitError> {
+        let cleanup = self.cleanup(edited);
+        let message = cleanup.apply(message, edited);
+
+        if cleanup.is_empty(&message) {
+            return Err(RitError::CommitAborted(
+                "Aborting commit due to empty commit message.".to_string(),
+            ));
+        }
 
-        Ok(objects::Tree::build(entries))
+        Ok(message)
     }
 
-    fn get_result(&self, parent_id: Option<String>, commit_id: Id) -> CommitResult {
+    fn author(&self) -> objects::Author {
+        objects::Author::new(&self.session.author_name, &self.session.author_email)
+    }
+
+    fn commit(&mut self, parents: Vec<Id>, author: objects::Author) -> Result<Id, RitError> {
+        let committer = self.author();
+
+        write_commit::write_commit(&mut self.repo, parents, author, committer, &self.message)
+    }
+
+    /// Writes a commit of the index on top of HEAD, returning the parent and
+    /// the new commit.
+    fn write(&mut self) -> Result<(Option<Id>, Id), RitError> {
+        if self.repo.index.is_conflicted() {
+            return Err(RitError::UnmergedFiles);
+        }
+
+        self.message = self.read_message(None)?;
+
+        let parent_id = self.repo.refs.read_head();
+        let commit_id = self.commit(parent_id.iter().cloned().collect(), self.author())?;
+
+        Ok((parent_id, commit_id))
+    }
+
+    /// Writes a commit of the index that replaces HEAD, on top of the same
+    /// parents so that root and merge commits stay so. The editor starts on
+    /// the message of HEAD, whose author is kept unless `--reset-author`
+    /// asks for the current one.
+    fn write_amend(&mut self) -> Result<(Option<Id>, Id), RitError> {
+        if self.repo.index.is_conflicted() {
+            return Err(RitError::UnmergedFiles);
+        }
+
+        let head_id = self.repo.refs.read_head().ok_or(RitError::NothingToAmend)?;
+        let head = self.repo.database.load_commit(&head_id)?;
+
+        self.message = self.read_message(Some(&head))?;
+
+        let author = if self.options.reset_author {
+            self.author()
+        } else {
+            head.author
+        };
+
+        let commit_id = self.commit(head.parents.clone(), author)?;
+
+        Ok((head.parents.first().cloned(), commit_id))
+    }
+
+    /// Writes the commit, or the amended one, of the changes staged as the
+    /// options say.
+    fn write_staged(&mut self) -> Result<(Option<Id>, Id), RitError> {
+        let mut index = match self.stage_changes()? {
+            Some(index) => index,
+            None => return self.write_or_amend(),
+        };
+
+        mem::swap(&mut self.repo.index, &mut index);
+        let written = self.write_or_amend();
+        mem::swap(&mut self.repo.index, &mut index);
+
+        index.release_lock()?;
+
+        written
+    }
+
+    fn write_or_amend(&mut self) -> Result<(Option<Id>, Id), RitError> {
+        if self.options.amend {
+            self.write_amend()
+        } else {
+            self.write()
+        }
+    }
+
+    /// Updates the index with the changes to the tracked files that `-a`
+    /// or the paths name. For a commit of only the paths, they are updated
+    /// in a temporary index of HEAD too, which is returned to commit from,
+    /// and which `--only` with no paths commits as it is.
+    /// It is held locked as `next-index-<pid>` in the git directory until
+    /// the commit is written, like the index itself.
+    fn stage_changes(&mut self) -> Result<Option<Index>, RitError> {
+        if self.options.all {
+            for pathname in self.tracked_paths() {
+                let repo = &mut self.repo;
+                stage_path(&repo.workspace, &repo.database, &mut repo.index, &pathname)?;
+            }
+        }
+
+        if self.options.paths.is_empty() && !self.options.only {
+            return Ok(None);
+        }
+
+        let head_items = if self.options.include {
+            Default::default()
+        } else {
+            let head = self.repo.refs.read_head();
+            self.repo.database.flatten_tree(head.as_ref())?
+        };
+
+        let mut known = self.tracked_paths();
+        known.extend(head_items.keys().cloned());
+
+        let mut matched = BTreeSet::new();
+
+        for spec in &self.options.paths {
+            let paths: Vec<&String> = known
+                .iter()
+                .filter(|pathname| matches_pathspec(spec, pathname))
+                .collect();
+
+            if paths.is_empty() {
+                return Err(RitError::MissingFile(spec.clone()));
+            }
+
+            matched.extend(paths.into_iter().cloned());
+        }
+
+        for pathname in &matched {
+            let repo = &mut self.repo;
+            stage_path(&repo.workspace, &repo.database, &mut repo.index, pathname)?;
+        }
+
+        if self.options.include {
+            return Ok(None);
+        }
+
+        let path = self
+            .repo
+            .git_path
+            .join(format!("next-index-{}", process::id()));
+        let mut index = Index::new(path);
+        index.load_for_update()?;
+
+        for (pathname, item) in &head_items {
+            index.add_from_db(pathname, item);
+        }
+
+        for pathname in &matched {
+            let repo = &self.repo;
+
+            if let Err(err) = stage_path(&repo.workspace, &repo.database, &mut index, pathname) {
+                index.release_lock()?;
+
+                return Err(err);
+            }
+        }
+
+        Ok(Some(index))
+    }
+
+    fn tracked_paths(&self) -> BTreeSet<String> {
+        self.repo
+            .index
+            .entries()
+            .into_iter()
+            .map(|entry| entry.pathname)
+            .collect()
+    }
+
+    fn get_result(&self, parent_id: Option<Id>, commit_id: Id) -> CommitResult {
         CommitResult {
             parent_id,
             commit_id: commit_id.as_str,
@@ -66,20 +525,80 @@ impl Commit {
 
 impl Command for Commit {
     fn execute(&mut self) -> Result<Execution, RitError> {
-        let mut root = self.get_root()?;
+        self.repo.index.load_for_update()?;
 
-        root.traverse(|tree| {
-            let id = self.repo.database.store(tree).unwrap();
+        let (parent_id, commit_id) = match self.write_staged() {
+            Ok(written) => written,
+            Err(err) => {
+                self.repo.index.release_lock()?;
 
-            tree.id = Some(id);
-        });
+                return Err(err);
+            }
+        };
 
-        let root_id = self.repo.database.store(&mut root)?;
-        let parent_id = self.repo.refs.read_head();
-        let commit_id = self.commit(&parent_id, root_id)?;
+        // saves the cache tree filled in while writing the tree
+        self.repo.index.write_updates()?;
 
-        self.repo.refs.update_head(&commit_id)?;
+        let action = match parent_id {
+            _ if self.options.amend => "commit (amend)",
+            Some(_) => "commit",
+            None => "commit (initial)",
+        };
+        let reflog_message = format!("{}: {}", action, self.message.lines().next().unwrap());
+
+        self.repo
+            .refs
+            .update_head(&commit_id, &self.author(), &reflog_message)?;
 
         Ok(Execution::Commit(self.get_result(parent_id, commit_id)))
     }
 }
+
+/// Brings the entry for `pathname` in `index` up to date with the
+/// workspace, removing it if the file is gone. A submodule is staged at the
+/// commit it has checked out, and left alone while it is not cloned.
+fn stage_path(
+    workspace: &Workspace,
+    database: &Database,
+    index: &mut Index,
+    pathname: &str,
+) -> Result<(), RitError> {
+    let entry = workspace.entry(pathname);
+
+    let stat = match workspace.stat_path(pathname) {
+        Some(stat) => stat,
+        None => {
+            index.remove(pathname);
+
+            return Ok(());
+        }
+    };
+
+    let unchanged = index
+        .entry_for_path(pathname)
+        .is_some_and(|old| old.matches_stat(&stat) && old.matches_times(&stat));
+
+    if unchanged && !stat.is_dir() {
+        return Ok(());
+    }
+
+    if stat.is_dir() {
+        match submodule::head(&entry.absolute_path) {
+            Some(head) => index.add(entry, head, stat),
+            None if index
+                .entry_for_path(pathname)
+                .is_some_and(|old| old.is_gitlink()) => {}
+            None => index.remove(pathname),
+        }
+
+        return Ok(());
+    }
+
+    let (data, stat) = workspace.read_data(&entry)?;
+    let mut blob = objects::Blob::parse(data.into());
+    let id = database.store(&mut blob)?;
+
+    index.add(entry, id, stat);
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use super::{Command, Execution, UploadPack};
+use crate::{
+    errors::RitError,
+    remote::{self, pkt_line, DAEMON_PORT},
+    Session,
+};
+use std::{
+    fs,
+    io::{self, BufReader},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+const DEFAULT_LISTEN: &str = "0.0.0.0";
+const EXPORT_OK: &str = "git-daemon-export-ok";
+const UPLOAD_PACK_SERVICE: &str = "git-upload-pack";
+
+#[derive(Default)]
+pub struct DaemonOptions {
+    pub listen: Option<String>,
+    pub port: Option<u16>,
+    pub base_path: Option<PathBuf>,
+    pub export_all: bool,
+    pub timeout: Option<u64>,
+    pub directories: Vec<PathBuf>,
+}
+
+/// Serves repositories over the `git://` protocol. Each connection starts
+/// with a request naming a service and a repository, which is answered by
+/// running `upload-pack` for it on the connection. Only repositories holding a
+/// `git-daemon-export-ok` file are served, unless `--export-all` is given,
+/// and when directories are listed, only repositories inside them.
+pub struct Daemon {
+    session: Session,
+    options: DaemonOptions,
+}
+
+impl Daemon {
+    pub fn new(session: Session, options: DaemonOptions) -> Self {
+        Self { session, options }
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), RitError> {
+        let timeout = self.options.timeout.map(Duration::from_secs);
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        let mut input = BufReader::new(stream.try_clone()?);
+        let mut output = stream;
+
+        // a client that connects and leaves without a request is no error
+        let request = match pkt_line::read(&mut input) {
+            Ok(Some(request)) => String::from_utf8_lossy(&request).to_string(),
+            Ok(None) => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let request = request.split('\0').next().unwrap_or_default();
+        let request = request.strip_suffix('\n').unwrap_or(request);
+
+        let path = match request.split_once(' ') {
+            Some((UPLOAD_PACK_SERVICE, path)) => path,
+            _ => {
+                let message = format!("ERR service not enabled: '{}'", request);
+                pkt_line::write_line(&mut output, &message)?;
+
+                return Ok(());
+            }
+        };
+
+        let dir = match self.resolve(path) {
+            Some(dir) => dir,
+            None => {
+                let message = format!("ERR access denied or repository not exported: {}", path);
+                pkt_line::write_line(&mut output, &message)?;
+
+                return Ok(());
+            }
+        };
+
+        UploadPack::new(
+            self.session.clone(),
+            &dir.to_string_lossy(),
+            Box::new(input),
+            Box::new(output),
+        )
+        .execute()
+        .map(|_| ())
+    }
+
+    /// Finds the repository a request asks for within the base path, if it
+    /// is exported and inside one of the allowed directories.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let git_path = remote::find_repository(self.options.base_path.as_deref(), path)?;
+
+        if !self.options.export_all && !git_path.join(EXPORT_OK).exists() {
+            return None;
+        }
+
+        let allowed = self.options.directories.is_empty()
+            || self.options.directories.iter().any(|dir| {
+                fs::canonicalize(dir)
+                    .map(|dir| git_path.starts_with(dir))
+                    .unwrap_or(false)
+            });
+
+        if allowed {
+            Some(git_path)
+        } else {
+            None
+        }
+    }
+}
+
+impl Command for Daemon {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let listen = self.options.listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+        let port = self.options.port.unwrap_or(DAEMON_PORT);
+
+        let listener = TcpListener::bind((listen, port)).map_err(|_| {
+            RitError::Remote(format!(
+                "unable to allocate any listen sockets on port {}",
+                port
+            ))
+        })?;
+
+        let daemon = &*self;
+
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("rit daemon: {}", err);
+                        continue;
+                    }
+                };
+
+                scope.spawn(move || {
+                    if let Err(err) = daemon.handle(stream) {
+                        eprintln!("rit daemon: {}", err);
+                    }
+                });
+            }
+        });
+
+        Ok(Execution::Empty)
+    }
+}
@@ -0,0 +1,516 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    id::Id,
+    objects::{Author, Parsed},
+    refs::{Refs, HEAD},
+    remote::{self, protocol_error, Advertisement, RefMapping, Refspec, Remote, UPLOAD_PACK},
+    repository::Repository,
+    rev_list::{self, RevList},
+    Session,
+};
+use std::{fmt, fs};
+
+const DEFAULT_REMOTE: &str = "origin";
+const FETCH_HEAD: &str = "FETCH_HEAD";
+const HEADS_PREFIX: &str = "refs/heads/";
+const REMOTES_PREFIX: &str = "refs/remotes/";
+const TAGS_PREFIX: &str = "refs/tags/";
+
+#[derive(Default)]
+pub struct FetchOptions {
+    pub remote: Option<String>,
+    pub refspecs: Vec<String>,
+    pub prune: bool,
+    pub upload_pack: Option<String>,
+}
+
+/// Brings in refs of another repository as the refspecs of the remote say,
+/// by default its branches as `refs/remotes/<name>/*`, along with the tags
+/// that are not here yet. Objects come through `upload-pack` on the other
+/// side, which only sends what the commits here do not already have. Every
+/// ref fetched is listed in `FETCH_HEAD`, and with `--prune` the
+/// remote-tracking refs whose branch is gone from the other side are
+/// deleted.
+pub struct Fetch {
+    session: Session,
+    options: FetchOptions,
+    repo: Repository,
+}
+
+/// One line of the summary of `fetch` and `push`, in git's format.
+#[derive(Debug)]
+pub struct RefUpdate {
+    flag: char,
+    summary: String,
+    source: String,
+    target: Option<String>,
+    reason: Option<String>,
+}
+
+impl RefUpdate {
+    pub fn new(flag: char, summary: &str, source: &str, target: Option<&str>) -> Self {
+        Self {
+            flag,
+            summary: summary.to_string(),
+            source: source.to_string(),
+            target: target.map(String::from),
+            reason: None,
+        }
+    }
+
+    /// Describes moving a ref from `old_id` to `new_id`, which is forced
+    /// unless it is a fast-forward.
+    pub fn moved(old_id: &Id, new_id: &Id, fast_forward: bool, source: &str, target: &str) -> Self {
+        if fast_forward {
+            let summary = format!("{}..{}", old_id.short(), new_id.short());
+
+            Self::new(' ', &summary, source, Some(target))
+        } else {
+            let summary = format!("{}...{}", old_id.short(), new_id.short());
+
+            Self::new('+', &summary, source, Some(target)).because("forced update")
+        }
+    }
+
+    pub fn because(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.flag == '!'
+    }
+}
+
+impl fmt::Display for RefUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " {} {:<17} {}", self.flag, self.summary, self.source)?;
+
+        if let Some(target) = &self.target {
+            write!(f, " -> {}", target)?;
+        }
+
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchResult {
+    url: String,
+    updates: Vec<RefUpdate>,
+}
+
+impl fmt::Display for FetchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "From {}", self.url)?;
+
+        for update in &self.updates {
+            write!(f, "\n{}", update)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FetchResult {
+    pub fn is_rejected(&self) -> bool {
+        self.updates.iter().any(RefUpdate::is_rejected)
+    }
+}
+
+impl Fetch {
+    pub fn new(session: Session, options: FetchOptions) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+
+        Self {
+            session,
+            options,
+            repo,
+        }
+    }
+
+    /// The refspecs given on the command line, or else those of the remote.
+    /// Without any, only the HEAD of the other side is fetched, for
+    /// `FETCH_HEAD`.
+    fn refspecs(&self, remote: &Remote) -> Result<Vec<Refspec>, RitError> {
+        if !self.options.refspecs.is_empty() {
+            Refspec::parse_all(&self.options.refspecs)
+        } else if !remote.fetch.is_empty() {
+            remote.fetch_refspecs()
+        } else {
+            Ok(vec![Refspec::parse(HEAD)?])
+        }
+    }
+
+    /// Adds the tags of the other side that are not here yet, unless the
+    /// refspecs only fetch into `FETCH_HEAD`.
+    fn follow_tags(
+        &self,
+        refspecs: &[Refspec],
+        advertisement: &Advertisement,
+        mappings: &mut Vec<RefMapping>,
+    ) {
+        if refspecs.iter().all(|refspec| refspec.target.is_none()) {
+            return;
+        }
+
+        for (name, id) in &advertisement.refs {
+            let is_mapped = mappings
+                .iter()
+                .any(|mapping| mapping.target.as_ref() == Some(name));
+
+            if name.starts_with(TAGS_PREFIX)
+                && !is_mapped
+                && self.repo.refs.read_ref(name).is_none()
+            {
+                mappings.push(RefMapping {
+                    source: name.clone(),
+                    target: Some(name.clone()),
+                    id: id.clone(),
+                    force: false,
+                });
+            }
+        }
+    }
+
+    /// The sources of the fetched refs that `FETCH_HEAD` offers for merging:
+    /// those named on the command line, or the upstream of the current
+    /// branch when it comes from this remote. Failing that, the first ref is
+    /// offered when the first refspec names a single one.
+    fn merge_sources(
+        &self,
+        remote: &Remote,
+        refspecs: &[Refspec],
+        mappings: &[RefMapping],
+    ) -> Vec<String> {
+        let sources = mappings.iter().map(|mapping| &mapping.source);
+
+        if !self.options.refspecs.is_empty() {
+            return sources
+                .filter(|source| {
+                    refspecs.iter().any(|refspec| {
+                        !refspec.negative && !refspec.is_glob() && refspec.matches_source(source)
+                    })
+                })
+                .cloned()
+                .collect();
+        }
+
+        let config = &self.repo.config;
+        let branch = self
+            .repo
+            .refs
+            .current_ref()
+            .and_then(|name| name.strip_prefix(HEADS_PREFIX).map(String::from));
+
+        if let Some(branch) = branch {
+            let upstream_remote = config.get(&format!("branch.{}.remote", branch));
+            let merges = config.get_all(&format!("branch.{}.merge", branch));
+
+            if upstream_remote.is_some() && upstream_remote == remote.name && !merges.is_empty() {
+                return sources
+                    .filter(|source| merges.contains(source))
+                    .cloned()
+                    .collect();
+            }
+        }
+
+        match refspecs.first() {
+            Some(refspec) if !refspec.negative && !refspec.is_glob() => {
+                sources.take(1).cloned().collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Refuses to move the branch that is checked out, which would leave the
+    /// index and the working tree behind.
+    fn check_current_branch(&self, mappings: &[RefMapping]) -> Result<(), RitError> {
+        if self.repo.config.get_bool("core.bare") == Some(true) {
+            return Ok(());
+        }
+
+        let current = self.repo.refs.current_ref();
+
+        match mappings
+            .iter()
+            .find(|mapping| mapping.target.is_some() && mapping.target == current)
+        {
+            Some(mapping) => Err(RitError::Remote(format!(
+                "refusing to fetch into branch '{}' checked out at '{}'",
+                mapping.target.as_deref().unwrap_or_default(),
+                self.session.project_dir.display()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Lists the local commits, newest first, to tell the other side what
+    /// it does not have to send.
+    fn haves(&self) -> Result<Vec<Id>, RitError> {
+        let mut tips = vec![];
+
+        for name in self.repo.refs.list_refs("refs") {
+            let mut id = self.repo.refs.read_ref(&name);
+
+            while let Some(object_id) = id {
+                match self.repo.database.load(&object_id)? {
+                    Parsed::Tag(tag) => id = Some(tag.object),
+                    Parsed::Commit(_) => {
+                        tips.push(object_id);
+                        id = None;
+                    }
+                    _ => id = None,
+                }
+            }
+        }
+
+        let commits = RevList::new(&self.repo, tips, vec![]).commits()?;
+
+        Ok(commits.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// Deletes the local refs the refspecs store into whose source is gone
+    /// from the other side. Symbolic refs, like `refs/remotes/origin/HEAD`,
+    /// are left alone.
+    fn prune(
+        &self,
+        refspecs: &[Refspec],
+        advertisement: &Advertisement,
+    ) -> Result<Vec<RefUpdate>, RitError> {
+        let refs = &self.repo.refs;
+        let mut updates = vec![];
+
+        for refspec in refspecs.iter().filter(|refspec| !refspec.negative) {
+            let target = match &refspec.target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let names = match target.split_once('*') {
+                Some((prefix, _)) => refs.list_refs(prefix.trim_end_matches('/')),
+                None => vec![target.clone()],
+            };
+
+            for name in names {
+                let is_stale = refspec.map_target(&name).is_some()
+                    && refs.read_symref(&name).is_none()
+                    && refs.read_ref(&name).is_some()
+                    && !advertisement.refs.iter().any(|(source, _)| {
+                        refspec.map_source(source).as_deref() == Some(&name[..])
+                    });
+
+                if is_stale {
+                    refs.delete_ref(&name)?;
+                    updates.push(RefUpdate::new(
+                        '-',
+                        "[deleted]",
+                        "(none)",
+                        Some(short_target(refs, &name)),
+                    ));
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    fn update_ref(&self, mapping: &RefMapping) -> Result<Option<RefUpdate>, RitError> {
+        let (source, id) = (&mapping.source, &mapping.id);
+        let target = match &mapping.target {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+
+        let refs = &self.repo.refs;
+        let committer = Author::new(&self.session.author_name, &self.session.author_email);
+        let short_source = refs.short_name(source);
+        let short_target = short_target(refs, target);
+        let is_tag = target.starts_with(TAGS_PREFIX);
+
+        let old_id = refs.read_ref(target);
+
+        let (update, message) = match old_id {
+            Some(old_id) if &old_id == id => return Ok(None),
+            Some(_) if is_tag && !mapping.force => {
+                let update = RefUpdate::new('!', "[rejected]", short_source, Some(short_target));
+
+                return Ok(Some(update.because("would clobber existing tag")));
+            }
+            Some(_) if is_tag => (
+                RefUpdate::new('t', "[tag update]", short_source, Some(short_target)),
+                "updating tag",
+            ),
+            None if is_tag => (
+                RefUpdate::new('*', "[new tag]", short_source, Some(short_target)),
+                "storing tag",
+            ),
+            None if source.starts_with(HEADS_PREFIX) || source == HEAD => (
+                RefUpdate::new('*', "[new branch]", short_source, Some(short_target)),
+                "storing head",
+            ),
+            None => (
+                RefUpdate::new('*', "[new ref]", short_source, Some(short_target)),
+                "storing ref",
+            ),
+            Some(old_id) => {
+                let fast_forward = rev_list::is_ancestor(&self.repo, &old_id, id)?;
+
+                if !fast_forward && !mapping.force {
+                    let update =
+                        RefUpdate::new('!', "[rejected]", short_source, Some(short_target));
+
+                    return Ok(Some(update.because("non-fast-forward")));
+                }
+
+                let message = if fast_forward {
+                    "fast-forward"
+                } else {
+                    "forced-update"
+                };
+
+                (
+                    RefUpdate::moved(&old_id, id, fast_forward, short_source, short_target),
+                    message,
+                )
+            }
+        };
+
+        refs.update_ref_logged(target, id, &committer, &format!("fetch: {}", message))?;
+
+        Ok(Some(update))
+    }
+
+    /// Records every ref that was fetched in `FETCH_HEAD`, those offered for
+    /// merging first, each described the way `git merge` words its message.
+    fn write_fetch_head(
+        &self,
+        url: &str,
+        mappings: &[RefMapping],
+        merge_sources: &[String],
+    ) -> Result<(), RitError> {
+        let url = url.trim_end_matches('/');
+        let url = url.strip_suffix(".git").unwrap_or(url);
+        let mut content = String::new();
+
+        for for_merge in [true, false] {
+            for mapping in mappings
+                .iter()
+                .filter(|mapping| merge_sources.contains(&mapping.source) == for_merge)
+            {
+                let marker = if for_merge { "" } else { "not-for-merge" };
+                let source = &mapping.source;
+
+                let description = if source == HEAD {
+                    url.to_string()
+                } else if let Some(branch) = source.strip_prefix(HEADS_PREFIX) {
+                    format!("branch '{}' of {}", branch, url)
+                } else if let Some(tag) = source.strip_prefix(TAGS_PREFIX) {
+                    format!("tag '{}' of {}", tag, url)
+                } else if let Some(branch) = source.strip_prefix(REMOTES_PREFIX) {
+                    format!("remote-tracking branch '{}' of {}", branch, url)
+                } else {
+                    format!("'{}' of {}", source, url)
+                };
+
+                content.push_str(&format!("{}\t{}\t{}\n", mapping.id, marker, description));
+            }
+        }
+
+        fs::write(self.repo.git_path.join(FETCH_HEAD), content)?;
+
+        Ok(())
+    }
+}
+
+/// How a local ref is shown in the summary, with remote-tracking branches
+/// as `<remote>/<branch>`.
+fn short_target<'a>(refs: &Refs, name: &'a str) -> &'a str {
+    name.strip_prefix(REMOTES_PREFIX)
+        .unwrap_or_else(|| refs.short_name(name))
+}
+
+impl Command for Fetch {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let name = self.options.remote.as_deref().unwrap_or(DEFAULT_REMOTE);
+        let mut remote = Remote::find(&self.repo.config, name);
+
+        if let Some(program) = self.options.upload_pack.take() {
+            remote.set_program(UPLOAD_PACK, program);
+        }
+
+        let refspecs = self.refspecs(&remote)?;
+        let prune = self.options.prune || remote.prune == Some(true);
+
+        let mut conn = remote.connect(UPLOAD_PACK, &self.session.project_dir)?;
+        let advertisement = Advertisement::read(&mut conn.input).map_err(protocol_error)?;
+
+        let mut mappings = remote::map_refs(&refspecs, &advertisement.refs);
+
+        // a ref named on the command line has to be there
+        if !self.options.refspecs.is_empty() {
+            let missing = refspecs.iter().find(|refspec| {
+                !refspec.negative
+                    && !refspec.is_glob()
+                    && !advertisement
+                        .refs
+                        .iter()
+                        .any(|(name, _)| refspec.matches_source(name))
+            });
+
+            if let Some(refspec) = missing {
+                return Err(RitError::Remote(format!(
+                    "couldn't find remote ref {}",
+                    refspec.source
+                )));
+            }
+        }
+
+        self.follow_tags(&refspecs, &advertisement, &mut mappings);
+        self.check_current_branch(&mappings)?;
+
+        let mut wants: Vec<Id> = vec![];
+
+        for mapping in &mappings {
+            if !self.repo.database.exists(&mapping.id) && !wants.contains(&mapping.id) {
+                wants.push(mapping.id.clone());
+            }
+        }
+
+        let haves = if wants.is_empty() {
+            vec![]
+        } else {
+            self.haves()?
+        };
+
+        remote::fetch_pack(&mut conn, &advertisement, &mut self.repo, &wants, &haves)?;
+        conn.finish().map_err(protocol_error)?;
+
+        let mut updates = if prune {
+            self.prune(&refspecs, &advertisement)?
+        } else {
+            vec![]
+        };
+
+        for mapping in &mappings {
+            updates.extend(self.update_ref(mapping)?);
+        }
+
+        let merge_sources = self.merge_sources(&remote, &refspecs, &mappings);
+        self.write_fetch_head(&remote.url, &mappings, &merge_sources)?;
+
+        Ok(Execution::Fetch(FetchResult {
+            url: remote.url,
+            updates,
+        }))
+    }
+}
@@ -0,0 +1,508 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    id::{Id, OneOff},
+    objects::{
+        tree::{GITLINK_MODE, TREE_MODE},
+        Author,
+    },
+    refs::{HEAD, ORIG_HEAD},
+    repository::Repository,
+    Session,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::prelude::*,
+};
+
+const TREE_ENTRY_MODES: [u32; 5] = [0o100644, 0o100755, 0o120000, TREE_MODE, GITLINK_MODE];
+const KINDS: [&str; 4] = ["blob", "tree", "commit", "tag"];
+
+#[derive(Default)]
+pub struct FsckOptions {
+    pub lost_found: bool,
+}
+
+/// Re-hashes every loose and packed object and checks that trees, commits
+/// and tags are well formed, then follows refs, reflogs and the index to
+/// find the objects that are missing and those nothing points to any more.
+/// With `--lost-found`, the latter are written out under `lost-found/`.
+pub struct Fsck {
+    options: FsckOptions,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub struct FsckResult {
+    errors: Vec<String>,
+    dangling: Vec<String>,
+}
+
+impl FsckResult {
+    pub fn is_broken(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for FsckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<&String> = self.errors.iter().chain(&self.dangling).collect();
+
+        for (n, line) in lines.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An object that could be read back, with the objects it points to and
+/// the kind each of them is expected to be.
+struct Checked {
+    kind: String,
+    links: Vec<(Id, String)>,
+}
+
+impl Fsck {
+    pub fn new(session: Session, options: FsckOptions) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self { options, repo }
+    }
+
+    fn all_ids(&self) -> Vec<Id> {
+        let database = &self.repo.database;
+
+        let mut ids = database.loose_ids();
+        ids.extend(database.packed_ids());
+        ids.sort_by(|a, b| a.as_str.cmp(&b.as_str));
+        ids.dedup();
+
+        ids
+    }
+
+    /// Reads the object back and checks that it hashes to `id` and is well
+    /// formed. Objects that cannot be read or hash to something else are
+    /// left out, so anything pointing to them reports them as missing.
+    fn check_object(&self, id: &Id, errors: &mut Vec<String>) -> Option<Checked> {
+        let (kind, data) = match self.repo.database.read_object(id) {
+            Ok(object) => object,
+            Err(_) => {
+                errors.push(format!("error: {}: object corrupt or missing", id));
+                return None;
+            }
+        };
+
+        let header = format!("{} {}\0", kind, data.len());
+        let actual = OneOff::new(header.as_bytes().chain(&data[..]));
+
+        if actual != *id {
+            errors.push(format!("error: hash mismatch for {} (got {})", id, actual));
+            return None;
+        }
+
+        let links = match &kind[..] {
+            "blob" => Ok(vec![]),
+            "tree" => check_tree(&data),
+            "commit" => check_commit(&data),
+            "tag" => check_tag(&data),
+            _ => Err(format!("unknown object type '{}'", kind)),
+        };
+
+        let links = links.unwrap_or_else(|message| {
+            errors.push(format!("error in {} {}: {}", kind, id, message));
+            vec![]
+        });
+
+        Some(Checked { kind, links })
+    }
+
+    /// Collects the objects that refs, reflogs and the index point to,
+    /// reporting the pointers to objects that are not in the database.
+    fn roots(&mut self, objects: &HashMap<Id, Checked>, errors: &mut Vec<String>) -> Vec<Id> {
+        let refs = &self.repo.refs;
+        let mut roots = vec![];
+
+        let names = refs
+            .list_refs("refs")
+            .into_iter()
+            .chain([HEAD.to_string(), ORIG_HEAD.to_string()])
+            // a symbolic HEAD is checked through the branch it points to
+            .filter(|name| name != HEAD || refs.read_symref(HEAD).is_none());
+
+        for name in names {
+            match refs.read_ref(&name) {
+                Some(id) if objects.contains_key(&id) => roots.push(id),
+                Some(id) => errors.push(format!("error: {}: invalid sha1 pointer {}", name, id)),
+                None => {}
+            }
+        }
+
+        let log = refs.reflog();
+
+        for name in log.list() {
+            for entry in log.read(&name) {
+                for id in entry.old_id.into_iter().chain(Some(entry.new_id)) {
+                    if objects.contains_key(&id) {
+                        roots.push(id);
+                    } else {
+                        errors.push(format!("error: {}: invalid reflog entry {}", name, id));
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = self.repo.index.load() {
+            errors.push(format!("error: index file corrupt: {}", err));
+            return roots;
+        }
+
+        for entry in self.repo.index.entries() {
+            if entry.mode == GITLINK_MODE {
+                continue;
+            }
+
+            if objects.contains_key(&entry.id) {
+                roots.push(entry.id);
+            } else {
+                errors.push(format!(
+                    "error: {}: invalid sha1 pointer in index ({})",
+                    entry.id, entry.pathname
+                ));
+            }
+        }
+
+        roots
+    }
+
+    /// Writes a dangling object to `lost-found/commit` if it is a commit or
+    /// to `lost-found/other` otherwise, as its content for blobs and as its
+    /// id for everything else.
+    fn write_lost_found(&self, id: &Id, kind: &str) -> Result<(), RitError> {
+        let dir = self
+            .repo
+            .git_path
+            .join("lost-found")
+            .join(if kind == "commit" { "commit" } else { "other" });
+        fs::create_dir_all(&dir)?;
+
+        let content = if kind == "blob" {
+            self.repo.database.read_object(id)?.1.to_vec()
+        } else {
+            format!("{}\n", id).into_bytes()
+        };
+
+        fs::write(dir.join(&id.as_str), content)?;
+
+        Ok(())
+    }
+}
+
+impl Command for Fsck {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let mut errors = vec![];
+
+        for path in self.repo.database.corrupt_packs() {
+            errors.push(format!("error: {}: pack checksum mismatch", path.display()));
+        }
+
+        let ids = self.all_ids();
+        let mut objects = HashMap::new();
+
+        for id in &ids {
+            if let Some(checked) = self.check_object(id, &mut errors) {
+                objects.insert(id.clone(), checked);
+            }
+        }
+
+        let mut pending = self.roots(&objects, &mut errors);
+        let mut reachable = HashSet::new();
+
+        while let Some(id) = pending.pop() {
+            if !reachable.insert(id.clone()) {
+                continue;
+            }
+
+            if let Some(checked) = objects.get(&id) {
+                pending.extend(checked.links.iter().map(|(link, _)| link.clone()));
+            }
+        }
+
+        let mut referenced = HashSet::new();
+        let mut missing = vec![];
+
+        for id in ids.iter().filter(|id| objects.contains_key(id)) {
+            let checked = &objects[id];
+
+            for (link, expected) in &checked.links {
+                referenced.insert(link.clone());
+
+                match objects.get(link) {
+                    None => {
+                        errors.push(format!(
+                            "broken link from {:>7} {}\n              to {:>7} {}",
+                            checked.kind, id, expected, link
+                        ));
+                        missing.push(format!("missing {} {}", expected, link));
+                    }
+                    Some(target) if &target.kind != expected => errors.push(format!(
+                        "error: object {} is a {}, not a {}",
+                        link, target.kind, expected
+                    )),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        missing.sort();
+        missing.dedup();
+        errors.extend(missing);
+
+        let mut dangling = vec![];
+
+        for id in &ids {
+            let checked = match objects.get(id) {
+                Some(checked) if !reachable.contains(id) && !referenced.contains(id) => checked,
+                _ => continue,
+            };
+
+            dangling.push(format!("dangling {} {}", checked.kind, id));
+
+            if self.options.lost_found {
+                self.write_lost_found(id, &checked.kind)?;
+            }
+        }
+
+        Ok(Execution::Fsck(FsckResult { errors, dangling }))
+    }
+}
+
+fn parse_id(value: &str) -> Option<Id> {
+    if value.len() == 40
+        && value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        Id::from_hex(value)
+    } else {
+        None
+    }
+}
+
+fn is_valid_ident(value: &str) -> bool {
+    value.contains(" <") && Author::parse(value).is_some()
+}
+
+/// Checks the entries of a tree, which have to be sorted the way git sorts
+/// them, with the names of subtrees compared as if they ended in `/`.
+fn check_tree(data: &[u8]) -> Result<Vec<(Id, String)>, String> {
+    let mut links = vec![];
+    let mut previous: Option<(Vec<u8>, Vec<u8>)> = None;
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ');
+        let null = rest.iter().position(|b| *b == 0);
+
+        let (space, null) = match (space, null) {
+            (Some(space), Some(null)) if space < null && rest.len() >= null + 21 => (space, null),
+            _ => return Err("malformed tree entry".to_string()),
+        };
+
+        let mode_text = &rest[..space];
+        let name = &rest[space + 1..null];
+        let id = Id::parse(&rest[null + 1..null + 21]);
+        rest = &rest[null + 21..];
+
+        if mode_text.starts_with(b"0") {
+            return Err("contains zero-padded file modes".to_string());
+        }
+
+        let mode = std::str::from_utf8(mode_text)
+            .ok()
+            .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+            .filter(|mode| TREE_ENTRY_MODES.contains(mode))
+            .ok_or_else(|| "contains bad file modes".to_string())?;
+
+        match name {
+            b"" => return Err("contains empty pathname".to_string()),
+            b"." | b".." => return Err("contains '.' or '..'".to_string()),
+            b".git" => return Err("contains '.git'".to_string()),
+            _ if name.contains(&b'/') => return Err("contains full pathnames".to_string()),
+            _ => {}
+        }
+
+        let mut key = name.to_vec();
+        if mode == TREE_MODE {
+            key.push(b'/');
+        }
+
+        if let Some((previous_name, previous_key)) = &previous {
+            if previous_name == name {
+                return Err("contains duplicate file entries".to_string());
+            }
+            if previous_key > &key {
+                return Err("not properly sorted".to_string());
+            }
+        }
+
+        let kind = match mode {
+            TREE_MODE => Some("tree"),
+            GITLINK_MODE => None,
+            _ => Some("blob"),
+        };
+        links.extend(kind.map(|kind| (id, kind.to_string())));
+
+        previous = Some((name.to_vec(), key));
+    }
+
+    Ok(links)
+}
+
+/// Splits off the header lines of a commit or tag, which end at the first
+/// blank line.
+fn header_lines(data: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let end = data
+        .windows(2)
+        .position(|pair| pair == b"\n\n")
+        .map(|pos| pos + 1)
+        .or_else(|| data.ends_with(b"\n").then_some(data.len()))
+        .ok_or_else(|| "unterminated header".to_string())?;
+
+    let headers = std::str::from_utf8(&data[..end]).map_err(|_| "invalid header".to_string())?;
+
+    Ok(headers
+        .lines()
+        .map(|line| match line.split_once(' ') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (line.to_string(), String::new()),
+        })
+        .collect())
+}
+
+/// Checks that a commit starts with its tree, followed by its parents, the
+/// author and the committer.
+fn check_commit(data: &[u8]) -> Result<Vec<(Id, String)>, String> {
+    let lines = header_lines(data)?;
+    let mut lines = lines.iter().peekable();
+    let mut links = vec![];
+
+    match lines.next() {
+        Some((key, value)) if key == "tree" => {
+            let id = parse_id(value).ok_or_else(|| "invalid 'tree' line format".to_string())?;
+            links.push((id, "tree".to_string()));
+        }
+        _ => return Err("invalid format - expected 'tree' line".to_string()),
+    }
+
+    while let Some((_, value)) = lines.next_if(|(key, _)| key == "parent") {
+        let id = parse_id(value).ok_or_else(|| "invalid 'parent' line format".to_string())?;
+        links.push((id, "commit".to_string()));
+    }
+
+    for expected in ["author", "committer"] {
+        match lines.next() {
+            Some((key, value)) if key == expected && is_valid_ident(value) => {}
+            Some((key, _)) if key == expected => return Err(format!("invalid {} line", expected)),
+            _ => return Err(format!("invalid format - expected '{}' line", expected)),
+        }
+    }
+
+    Ok(links)
+}
+
+/// Checks that a tag names the object it points to and its type, then its
+/// own name and optionally who made it.
+fn check_tag(data: &[u8]) -> Result<Vec<(Id, String)>, String> {
+    let lines = header_lines(data)?;
+    let mut lines = lines.iter();
+
+    let id = match lines.next() {
+        Some((key, value)) if key == "object" => {
+            parse_id(value).ok_or_else(|| "invalid 'object' line format".to_string())?
+        }
+        _ => return Err("invalid format - expected 'object' line".to_string()),
+    };
+
+    let kind = match lines.next() {
+        Some((key, value)) if key == "type" && KINDS.contains(&&value[..]) => value.clone(),
+        Some((key, _)) if key == "type" => return Err("invalid 'type' value".to_string()),
+        _ => return Err("invalid format - expected 'type' line".to_string()),
+    };
+
+    match lines.next() {
+        Some((key, value)) if key == "tag" && !value.is_empty() => {}
+        _ => return Err("invalid format - expected 'tag' line".to_string()),
+    }
+
+    match lines.next() {
+        Some((key, value)) if key == "tagger" && !is_valid_ident(value) => {
+            return Err("invalid tagger line".to_string())
+        }
+        _ => {}
+    }
+
+    Ok(vec![(id, kind)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = vec![];
+
+        for (mode, name) in entries {
+            data.extend(format!("{} {}\0", mode, name).bytes());
+            data.extend([0u8; 20]);
+        }
+
+        data
+    }
+
+    #[test]
+    fn it_checks_the_order_and_modes_of_tree_entries() {
+        let valid = tree(&[("100644", "a.txt"), ("40000", "a"), ("160000", "b")]);
+        assert_eq!(2, check_tree(&valid).unwrap().len());
+
+        let cases = [
+            (
+                tree(&[("40000", "a"), ("100644", "a.txt")]),
+                "not properly sorted",
+            ),
+            (tree(&[("100644", "a"), ("40000", "a")]), "duplicate"),
+            (tree(&[("100664", "a")]), "bad file modes"),
+            (tree(&[("040000", "a")]), "zero-padded"),
+            (tree(&[("100644", "a/b")]), "full pathnames"),
+            (tree(&[("100644", "..")]), "'.' or '..'"),
+        ];
+
+        for (data, message) in cases {
+            assert!(
+                check_tree(&data).unwrap_err().contains(message),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn it_checks_the_order_of_commit_headers() {
+        let tree = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+        let author = "author A <a@b> 1700000000 +0000\n";
+        let committer = "committer A <a@b> 1700000000 +0000\n";
+
+        let commit = format!("{}{}{}\nmessage\n", tree, author, committer);
+        assert_eq!(1, check_commit(commit.as_bytes()).unwrap().len());
+
+        let commit = format!("{}{}{}\nmessage\n", author, tree, committer);
+        assert!(check_commit(commit.as_bytes()).is_err());
+
+        let commit = format!("{}{}\nmessage\n", tree, author);
+        assert!(check_commit(commit.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    fsmonitor::{self, Changes, QUIT_COMMAND},
+    remote::pkt_line,
+    repository::Repository,
+    Session,
+};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs, io,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    process::{self, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TOKEN_PREFIX: &str = "rit";
+const READ_INTERVAL: Duration = Duration::from_millis(100);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+const EVENT_BUFFER_SIZE: usize = 4096;
+
+pub enum FsmonitorAction {
+    Run,
+    Start,
+    Stop,
+    Status,
+}
+
+#[derive(Debug)]
+pub struct FsmonitorResult {
+    pub worktree: PathBuf,
+    pub running: bool,
+}
+
+/// Watches the worktree with inotify and answers queries for the paths
+/// changed since a token over a Unix socket in the git directory, so that
+/// `rit status` only needs to check those. Tokens name the session of the
+/// daemon and a sequence number of the changes it saw; one from another
+/// session, or from before the kernel dropped events, gets an answer saying
+/// anything may have changed.
+pub struct FsmonitorDaemon {
+    repo: Repository,
+    worktree: PathBuf,
+    action: FsmonitorAction,
+}
+
+impl FsmonitorDaemon {
+    pub fn new(session: Session, action: FsmonitorAction) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+
+        Self {
+            repo,
+            worktree: session.project_dir,
+            action,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        fsmonitor::query(&self.repo.git_path, "").is_ok()
+    }
+
+    fn run(&self) -> Result<(), RitError> {
+        let socket_path = fsmonitor::socket_path(&self.repo.git_path);
+
+        if self.is_running() {
+            return Err(self.error("is already running"));
+        }
+
+        // a socket left behind by a daemon that did not stop cleanly
+        if socket_path.exists() {
+            fs::remove_file(&socket_path)?;
+        }
+
+        let watcher = Mutex::new(Watcher::new(self.worktree.clone())?);
+        let listener = UnixListener::bind(&socket_path)?;
+        let stopped = AtomicBool::new(false);
+
+        let result = thread::scope(|scope| {
+            // reads events as they come so that the kernel does not drop any,
+            // while queries read the ones left before answering
+            scope.spawn(|| {
+                while !stopped.load(Ordering::Relaxed) {
+                    if let Err(err) = watcher.lock().unwrap().read_events() {
+                        eprintln!("rit fsmonitor--daemon: {}", err);
+                    }
+
+                    thread::sleep(READ_INTERVAL);
+                }
+            });
+
+            let result = self.serve(&listener, &watcher);
+            stopped.store(true, Ordering::Relaxed);
+
+            result
+        });
+
+        fs::remove_file(&socket_path)?;
+
+        result
+    }
+
+    fn serve(&self, listener: &UnixListener, watcher: &Mutex<Watcher>) -> Result<(), RitError> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("rit fsmonitor--daemon: {}", err);
+                    continue;
+                }
+            };
+
+            match self.handle(stream, watcher) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) => eprintln!("rit fsmonitor--daemon: {}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Answers one request, returning whether it asked the daemon to quit.
+    fn handle(&self, mut stream: UnixStream, watcher: &Mutex<Watcher>) -> io::Result<bool> {
+        let request = pkt_line::read_packetized(&mut stream)?;
+        let request = String::from_utf8_lossy(&request);
+
+        if request == QUIT_COMMAND {
+            pkt_line::flush(&mut stream)?;
+
+            return Ok(true);
+        }
+
+        let mut watcher = watcher.lock().unwrap();
+        watcher.read_events()?;
+
+        let changes = watcher.changes_since(&request);
+        pkt_line::write_packetized(&mut stream, &changes.to_bytes())?;
+
+        Ok(false)
+    }
+
+    /// Runs the daemon in the background and waits for it to answer.
+    fn start(&self) -> Result<(), RitError> {
+        if self.is_running() {
+            return Err(self.error("is already running"));
+        }
+
+        let mut child = process::Command::new(env::current_exe()?)
+            .args(["fsmonitor--daemon", "run"])
+            .current_dir(&self.worktree)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let started = SystemTime::now();
+
+        while started.elapsed().unwrap_or_default() < STARTUP_TIMEOUT {
+            if self.is_running() {
+                return Ok(());
+            }
+
+            if child.try_wait()?.is_some() {
+                break;
+            }
+
+            thread::sleep(READ_INTERVAL);
+        }
+
+        Err(self.error("failed to start"))
+    }
+
+    fn stop(&self) -> Result<(), RitError> {
+        if !self.is_running() {
+            return Err(self.error("is not running"));
+        }
+
+        fsmonitor::send(&self.repo.git_path, QUIT_COMMAND.as_bytes())?;
+
+        while self.is_running() {
+            thread::sleep(READ_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> RitError {
+        RitError::Fsmonitor(format!(
+            "fsmonitor--daemon {} for '{}'",
+            message,
+            self.worktree.display()
+        ))
+    }
+}
+
+impl Command for FsmonitorDaemon {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        match self.action {
+            FsmonitorAction::Run => self.run()?,
+            FsmonitorAction::Start => self.start()?,
+            FsmonitorAction::Stop => self.stop()?,
+            FsmonitorAction::Status => {
+                return Ok(Execution::Fsmonitor(FsmonitorResult {
+                    worktree: self.worktree.clone(),
+                    running: self.is_running(),
+                }))
+            }
+        }
+
+        Ok(Execution::Empty)
+    }
+}
+
+/// The changes seen in the worktree, by the sequence number of the last
+/// event for each path.
+struct Watcher {
+    inotify: Inotify,
+    worktree: PathBuf,
+    dirs: HashMap<WatchDescriptor, String>,
+    session_id: String,
+    seq: u64,
+    changes: HashMap<String, u64>,
+}
+
+impl Watcher {
+    fn new(worktree: PathBuf) -> io::Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init()?,
+            worktree,
+            dirs: HashMap::new(),
+            session_id: new_session_id(),
+            seq: 0,
+            changes: HashMap::new(),
+        };
+
+        watcher.watch_dir("")?;
+
+        Ok(watcher)
+    }
+
+    fn token(&self) -> String {
+        format!("{}:{}:{}", TOKEN_PREFIX, self.session_id, self.seq)
+    }
+
+    fn changes_since(&self, token: &str) -> Changes {
+        let seq = token
+            .strip_prefix(&format!("{}:{}:", TOKEN_PREFIX, self.session_id))
+            .and_then(|seq| seq.parse::<u64>().ok());
+
+        let paths = seq.map(|seq| {
+            let mut paths: Vec<String> = self
+                .changes
+                .iter()
+                .filter(|(_, changed)| **changed > seq)
+                .map(|(path, _)| path.clone())
+                .collect();
+
+            paths.sort();
+            paths
+        });
+
+        Changes {
+            token: self.token(),
+            paths,
+        }
+    }
+
+    /// Watches the directory at `pathname` and every directory below it,
+    /// apart from the git directory.
+    fn watch_dir(&mut self, pathname: &str) -> io::Result<()> {
+        let mask = WatchMask::MODIFY
+            | WatchMask::ATTRIB
+            | WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVE
+            | WatchMask::DONT_FOLLOW
+            | WatchMask::ONLYDIR;
+
+        let path = self.worktree.join(pathname);
+        let wd = self.inotify.add_watch(&path, mask)?;
+        self.dirs.insert(wd, pathname.to_string());
+
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() && !is_git_dir(pathname, &entry.file_name()) {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                // the directory may be gone again by now
+                if let Err(err) = self.watch_dir(&join(pathname, &name)) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records the events waiting to be read, without blocking.
+    fn read_events(&mut self) -> io::Result<()> {
+        let mut buffer = [0; EVENT_BUFFER_SIZE];
+
+        loop {
+            let events: Vec<(WatchDescriptor, EventMask, Option<OsString>)> = self
+                .inotify
+                .read_events(&mut buffer)?
+                .map(|event| (event.wd, event.mask, event.name.map(OsString::from)))
+                .collect();
+
+            if events.is_empty() {
+                return Ok(());
+            }
+
+            for (wd, mask, name) in events {
+                self.record(wd, mask, name)?;
+            }
+        }
+    }
+
+    fn record(
+        &mut self,
+        wd: WatchDescriptor,
+        mask: EventMask,
+        name: Option<OsString>,
+    ) -> io::Result<()> {
+        // events were lost, so no token handed out so far can be trusted
+        if mask.contains(EventMask::Q_OVERFLOW) {
+            self.session_id = new_session_id();
+            self.changes.clear();
+
+            return Ok(());
+        }
+
+        if mask.contains(EventMask::IGNORED) {
+            self.dirs.remove(&wd);
+
+            return Ok(());
+        }
+
+        let (dir, name) = match (self.dirs.get(&wd), name) {
+            (Some(dir), Some(name)) => (dir.clone(), name),
+            _ => return Ok(()),
+        };
+
+        if is_git_dir(&dir, &name) {
+            return Ok(());
+        }
+
+        let path = join(&dir, &name.to_string_lossy());
+
+        let path = if mask.contains(EventMask::ISDIR) {
+            if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                if let Err(err) = self.watch_dir(&path) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        return Err(err);
+                    }
+                }
+            }
+
+            format!("{}/", path)
+        } else {
+            path
+        };
+
+        self.seq += 1;
+        self.changes.insert(path, self.seq);
+
+        Ok(())
+    }
+}
+
+fn is_git_dir(dir: &str, name: &OsString) -> bool {
+    dir.is_empty() && name == ".git"
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn new_session_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    format!("{}.{}", process::id(), now.as_nanos())
+}
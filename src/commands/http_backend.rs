@@ -0,0 +1,361 @@
+use super::{Command, Execution, ReceivePack, UploadPack};
+use crate::{
+    errors::RitError,
+    remote::{self, pkt_line, ServiceMode},
+    Session,
+};
+use libflate::gzip;
+use std::{
+    io::{self, prelude::*, BufReader},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+};
+
+const DEFAULT_LISTEN: &str = "0.0.0.0";
+const EXPORT_OK: &str = "git-daemon-export-ok";
+const INFO_REFS: &str = "/info/refs";
+const SERVICES: [&str; 2] = ["git-upload-pack", "git-receive-pack"];
+
+/// The parts of an HTTP request that decide how it is answered. Under CGI
+/// they come from the environment, and the body from standard input.
+#[derive(Debug, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub remote_user: Option<String>,
+}
+
+#[derive(Default)]
+pub struct HttpBackendOptions {
+    pub project_root: Option<PathBuf>,
+    pub export_all: bool,
+    pub listen: Option<String>,
+    pub port: Option<u16>,
+    pub request: HttpRequest,
+}
+
+/// The status of a response, with its reason phrase.
+type Status = (u16, &'static str);
+
+/// The repository and service a request is for, and whether it asks for the
+/// advertisement of the refs.
+struct Route {
+    git_path: PathBuf,
+    service: &'static str,
+    advertise: bool,
+}
+
+/// How the status line of a response is written: as the `Status` header of
+/// a CGI script, or as a response of our own HTTP listener, which closes
+/// the connection after each one.
+#[derive(Clone, Copy)]
+enum Framing {
+    Cgi,
+    Http,
+}
+
+/// Serves repositories over git's smart HTTP protocol. A GET of
+/// `info/refs?service=<service>` advertises the refs, and the conversation
+/// carries on with a POST to `git-upload-pack` or `git-receive-pack` for
+/// every round, each answered by the service on its own. It runs as a CGI
+/// script under a web server, or listens for HTTP itself when a port is
+/// given.
+pub struct HttpBackend {
+    session: Session,
+    options: HttpBackendOptions,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl HttpBackend {
+    pub fn new(
+        session: Session,
+        options: HttpBackendOptions,
+        input: Box<dyn Read>,
+        output: Box<dyn Write>,
+    ) -> Self {
+        Self {
+            session,
+            options,
+            input,
+            output,
+        }
+    }
+
+    fn backend(&self) -> Backend<'_> {
+        Backend {
+            session: &self.session,
+            options: &self.options,
+        }
+    }
+}
+
+/// What requests are answered with, shared by the threads of the listener.
+struct Backend<'a> {
+    session: &'a Session,
+    options: &'a HttpBackendOptions,
+}
+
+impl Backend<'_> {
+    /// Answers `request`, reading its body from `input`. Errors in the
+    /// service itself come after the response has begun, so they are only
+    /// reported here.
+    fn respond(
+        &self,
+        request: &HttpRequest,
+        input: Box<dyn Read>,
+        mut output: Box<dyn Write>,
+        framing: Framing,
+    ) -> Result<(), RitError> {
+        let Route {
+            git_path,
+            service,
+            advertise,
+        } = match self.route(request) {
+            Ok(route) => route,
+            Err((status, message)) => {
+                let headers = [("Content-Type", "text/plain".to_string())];
+                write_head(&mut output, framing, status, &headers)?;
+                writeln!(output, "{}", message)?;
+
+                return Ok(output.flush()?);
+            }
+        };
+
+        let (content_type, mode) = if advertise {
+            let content_type = format!("application/x-{}-advertisement", service);
+            (content_type, ServiceMode::AdvertiseRefs)
+        } else {
+            let content_type = format!("application/x-{}-result", service);
+            (content_type, ServiceMode::StatelessRpc)
+        };
+
+        let input = match request.content_encoding.as_deref() {
+            Some("gzip") | Some("x-gzip") => Box::new(gzip::Decoder::new(input)?),
+            _ => input,
+        };
+
+        let headers = [
+            ("Content-Type", content_type),
+            ("Expires", "Fri, 01 Jan 1980 00:00:00 GMT".to_string()),
+            ("Pragma", "no-cache".to_string()),
+            (
+                "Cache-Control",
+                "no-cache, max-age=0, must-revalidate".to_string(),
+            ),
+        ];
+        write_head(&mut output, framing, (200, "OK"), &headers)?;
+
+        if advertise {
+            pkt_line::write_line(&mut output, &format!("# service={}", service))?;
+            pkt_line::flush(&mut output)?;
+        }
+
+        let dir = git_path.to_string_lossy();
+
+        if service == SERVICES[0] {
+            let mut command = UploadPack::new(self.session.clone(), &dir, input, output);
+            command.set_mode(mode);
+            command.execute()?;
+        } else {
+            let mut command = ReceivePack::new(self.session.clone(), &dir, input, output);
+            command.set_mode(mode);
+            command.execute()?;
+        }
+
+        Ok(())
+    }
+
+    /// Works out what a request is for, or the status and message to refuse
+    /// it with.
+    fn route(&self, request: &HttpRequest) -> Result<Route, (Status, String)> {
+        let not_found = || ((404, "Not Found"), "Repository not found".to_string());
+
+        let (repo_path, service, advertise) =
+            if let Some(repo_path) = request.path.strip_suffix(INFO_REFS) {
+                let service = request
+                    .query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("service="))
+                    .and_then(|name| SERVICES.iter().find(|service| **service == name))
+                    .ok_or_else(|| ((403, "Forbidden"), "Request not supported".to_string()))?;
+
+                (repo_path, *service, true)
+            } else {
+                SERVICES
+                    .iter()
+                    .find_map(|service| {
+                        let repo_path = request.path.strip_suffix(service)?;
+                        Some((repo_path.strip_suffix('/')?, *service, false))
+                    })
+                    .ok_or_else(not_found)?
+            };
+
+        let method = if advertise { "GET" } else { "POST" };
+
+        if request.method != method && !(advertise && request.method == "HEAD") {
+            return Err((
+                (405, "Method Not Allowed"),
+                format!("Method {} not allowed", request.method),
+            ));
+        }
+
+        let git_path = remote::find_repository(self.options.project_root.as_deref(), repo_path)
+            .ok_or_else(not_found)?;
+
+        if !self.options.export_all && !git_path.join(EXPORT_OK).exists() {
+            return Err(((404, "Not Found"), "Repository not exported".to_string()));
+        }
+
+        if !self.is_enabled(&git_path, service, request) {
+            return Err((
+                (403, "Forbidden"),
+                format!("Service not enabled: '{}'", service),
+            ));
+        }
+
+        let request_type = format!("application/x-{}-request", service);
+
+        if !advertise && request.content_type.as_deref() != Some(&request_type[..]) {
+            return Err((
+                (415, "Unsupported Media Type"),
+                format!("Expected content type '{}'", request_type),
+            ));
+        }
+
+        Ok(Route {
+            git_path,
+            service,
+            advertise,
+        })
+    }
+
+    /// Whether the repository allows `service` over HTTP. Fetching is allowed
+    /// unless `http.uploadpack` turns it off, while pushing needs either
+    /// `http.receivepack` or a user the web server has authenticated.
+    fn is_enabled(&self, git_path: &Path, service: &str, request: &HttpRequest) -> bool {
+        let config = match remote::open_repository(git_path) {
+            Ok(repo) => repo.config,
+            Err(_) => return false,
+        };
+
+        if service == SERVICES[0] {
+            config.get_bool("http.uploadpack") != Some(false)
+        } else {
+            config
+                .get_bool("http.receivepack")
+                .unwrap_or(request.remote_user.is_some())
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) -> Result<(), RitError> {
+        let mut input = BufReader::new(stream.try_clone()?);
+        let (request, body) = read_request(&mut input)?;
+
+        self.respond(
+            &request,
+            Box::new(io::Cursor::new(body)),
+            Box::new(stream),
+            Framing::Http,
+        )
+    }
+}
+
+fn write_head<W: Write>(
+    output: &mut W,
+    framing: Framing,
+    (code, reason): (u16, &str),
+    headers: &[(&str, String)],
+) -> io::Result<()> {
+    match framing {
+        Framing::Cgi => write!(output, "Status: {} {}\r\n", code, reason)?,
+        Framing::Http => write!(
+            output,
+            "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+            code, reason
+        )?,
+    }
+
+    for (name, value) in headers {
+        write!(output, "{}: {}\r\n", name, value)?;
+    }
+
+    write!(output, "\r\n")
+}
+
+/// Reads a request from a client of the listener, along with its body,
+/// which is either sized by `Content-Length` or sent in chunks.
+fn read_request<R: BufRead>(input: &mut R) -> io::Result<(HttpRequest, Vec<u8>)> {
+    let (request_line, headers) = remote::read_head(input)?;
+    let body = remote::read_body(input, &headers, false)?;
+
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed request"))?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let header = |name| remote::header(&headers, name).map(String::from);
+
+    let request = HttpRequest {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        content_type: header("Content-Type"),
+        content_encoding: header("Content-Encoding"),
+        remote_user: None,
+    };
+
+    Ok((request, body))
+}
+
+impl Command for HttpBackend {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let port = match self.options.port {
+            Some(port) => port,
+            None => {
+                let input = std::mem::replace(&mut self.input, Box::new(io::empty()));
+                let output = std::mem::replace(&mut self.output, Box::new(io::sink()));
+
+                self.backend()
+                    .respond(&self.options.request, input, output, Framing::Cgi)?;
+
+                return Ok(Execution::Empty);
+            }
+        };
+
+        let listen = self.options.listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+        let listener = TcpListener::bind((listen, port)).map_err(|_| {
+            RitError::Remote(format!(
+                "unable to allocate any listen sockets on port {}",
+                port
+            ))
+        })?;
+
+        let backend = &self.backend();
+
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("rit http-backend: {}", err);
+                        continue;
+                    }
+                };
+
+                scope.spawn(move || {
+                    if let Err(err) = backend.serve(stream) {
+                        eprintln!("rit http-backend: {}", err);
+                    }
+                });
+            }
+        });
+
+        Ok(Execution::Empty)
+    }
+}
@@ -0,0 +1,69 @@
+use super::{Command, Execution};
+use crate::{errors::RitError, id::Id, pack::Indexer, repository::Repository, Session};
+use std::{fmt, path::PathBuf};
+
+#[derive(Default)]
+pub struct IndexPackOptions {
+    pub path: Option<PathBuf>,
+    pub fix_thin: bool,
+}
+
+/// Builds the index of a pack. A pack given as a file gets its `.idx` next to
+/// it, while one read from standard input is stored in the repository, with
+/// `--fix-thin` allowing deltas against objects the repository already has.
+pub struct IndexPack {
+    options: IndexPackOptions,
+    data: Option<Vec<u8>>,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub struct IndexPackResult {
+    pub checksum: Id,
+}
+
+impl fmt::Display for IndexPackResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.checksum)
+    }
+}
+
+impl IndexPack {
+    pub fn new(session: Session, data: Vec<u8>, options: IndexPackOptions) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self {
+            options,
+            data: Some(data),
+            repo,
+        }
+    }
+}
+
+impl Command for IndexPack {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let data = self.data.take().unwrap_or_default();
+        let database = &self.repo.database;
+
+        let indexer = Indexer::new(data, |id| database.read_object(id).ok())
+            .map_err(|err| RitError::InvalidPack(err.to_string()))?;
+
+        let missing = indexer.thin_deltas();
+
+        if missing > 0 && (self.options.path.is_some() || !self.options.fix_thin) {
+            let noun = if missing == 1 { "delta" } else { "deltas" };
+
+            return Err(RitError::InvalidPack(format!(
+                "pack has {} unresolved {}",
+                missing, noun
+            )));
+        }
+
+        let checksum = match &self.options.path {
+            Some(path) => indexer.write_index(&path.with_extension("idx"))?,
+            None => indexer.write(&database.pack_dir())?,
+        };
+
+        Ok(Execution::IndexPack(IndexPackResult { checksum }))
+    }
+}
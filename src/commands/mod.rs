@@ -2,14 +2,51 @@ use crate::errors::RitError;
 use std::path::PathBuf;
 
 mod add;
+mod clone;
 mod commit;
+mod daemon;
+mod fetch;
+mod fsck;
+mod fsmonitor_daemon;
+mod http_backend;
+mod index_pack;
 mod init;
+mod push;
+mod rebase;
+mod receive_pack;
+mod reflog;
+mod remote;
+mod repack;
+mod stash;
 mod status;
+mod submodule;
+mod tag;
+mod unpack_objects;
+mod upload_pack;
+mod write_commit;
 
 pub use add::Add;
-pub use commit::Commit;
+pub use clone::{Clone, CloneOptions};
+pub use commit::{Cleanup, Commit, CommitOptions};
+pub use daemon::{Daemon, DaemonOptions};
+pub use fetch::{Fetch, FetchOptions};
+pub use fsck::{Fsck, FsckOptions};
+pub use fsmonitor_daemon::{FsmonitorAction, FsmonitorDaemon};
+pub use http_backend::{HttpBackend, HttpBackendOptions, HttpRequest};
+pub use index_pack::{IndexPack, IndexPackOptions};
 pub use init::Init;
+pub use push::{Push, PushOptions};
+pub use rebase::{Rebase, RebaseAction};
+pub use receive_pack::ReceivePack;
+pub use reflog::{Reflog, ReflogAction};
+pub use remote::{Remote, RemoteAction};
+pub use repack::{Repack, RepackOptions};
+pub use stash::{Stash, StashAction};
 pub use status::Status;
+pub use submodule::{Submodule, SubmoduleAction};
+pub use tag::{Tag, TagAction, TagSort};
+pub use unpack_objects::UnpackObjects;
+pub use upload_pack::UploadPack;
 
 #[derive(Clone)]
 pub struct Session {
@@ -25,6 +62,19 @@ pub trait Command {
 #[derive(Debug)]
 pub enum Execution {
     Empty,
+    Clone(clone::CloneResult),
     Commit(commit::CommitResult),
+    Fetch(fetch::FetchResult),
+    Fsck(fsck::FsckResult),
+    Fsmonitor(fsmonitor_daemon::FsmonitorResult),
+    IndexPack(index_pack::IndexPackResult),
+    Push(push::PushResult),
+    Rebase(rebase::RebaseResult),
+    Reflog(reflog::ReflogResult),
+    Remote(remote::RemoteResult),
+    Repack(repack::RepackResult),
+    Stash(stash::StashResult),
     Status(status::StatusResult),
+    Submodule(submodule::SubmoduleResult),
+    Tag(tag::TagResult),
 }
@@ -0,0 +1,318 @@
+use super::{fetch::RefUpdate, repack::pack_writer, Command, Execution};
+use crate::{
+    errors::RitError,
+    id::Id,
+    objects::Author,
+    pack::{self, PackObject},
+    remote::{
+        pkt_line, protocol_error, unexpected, Advertisement, Remote, Transport, RECEIVE_PACK,
+        ZERO_ID,
+    },
+    repository::Repository,
+    rev_list::{self, RevList},
+    Session,
+};
+use std::{
+    fmt,
+    io::{self, prelude::*},
+};
+
+const DEFAULT_REMOTE: &str = "origin";
+const HEADS_PREFIX: &str = "refs/heads/";
+
+#[derive(Default)]
+pub struct PushOptions {
+    pub remote: Option<String>,
+    pub branches: Vec<String>,
+    pub force: bool,
+    pub receive_pack: Option<String>,
+}
+
+/// Sends branches to another repository through `receive-pack` on the other
+/// side. A branch that the other side has moved on from is rejected unless
+/// `--force` is given, and the other side may still refuse an update, e.g.
+/// to the branch it has checked out. Remote-tracking branches follow the
+/// updates that went through.
+pub struct Push {
+    session: Session,
+    options: PushOptions,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub struct PushResult {
+    url: String,
+    updates: Vec<RefUpdate>,
+}
+
+impl PushResult {
+    pub fn is_rejected(&self) -> bool {
+        self.updates.iter().any(RefUpdate::is_rejected)
+    }
+}
+
+impl fmt::Display for PushResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.updates.is_empty() {
+            return write!(f, "Everything up-to-date");
+        }
+
+        write!(f, "To {}", self.url)?;
+
+        for update in &self.updates {
+            write!(f, "\n{}", update)?;
+        }
+
+        if self.is_rejected() {
+            write!(f, "\nerror: failed to push some refs to '{}'", self.url)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A ref update that is sent to the other side.
+struct RefCommand {
+    old_id: Option<Id>,
+    new_id: Id,
+    branch: String,
+}
+
+impl Push {
+    pub fn new(session: Session, options: PushOptions) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+
+        Self {
+            session,
+            options,
+            repo,
+        }
+    }
+
+    fn branches(&self) -> Result<Vec<String>, RitError> {
+        if !self.options.branches.is_empty() {
+            return Ok(self.options.branches.clone());
+        }
+
+        self.repo
+            .refs
+            .current_ref()
+            .and_then(|name| name.strip_prefix(HEADS_PREFIX).map(String::from))
+            .map(|branch| vec![branch])
+            .ok_or_else(|| RitError::Remote("You are not currently on a branch.".to_string()))
+    }
+
+    /// Decides what to do with each branch: send it, leave it alone when the
+    /// other side already has it, or reject it when it would lose commits.
+    fn plan(
+        &self,
+        advertisement: &Advertisement,
+    ) -> Result<(Vec<RefCommand>, Vec<RefUpdate>), RitError> {
+        let mut commands = vec![];
+        let mut rejected = vec![];
+
+        for branch in self.branches()? {
+            let name = format!("{}{}", HEADS_PREFIX, branch);
+            let new_id = self.repo.refs.read_ref(&name).ok_or_else(|| {
+                RitError::Remote(format!("src refspec {} does not match any", branch))
+            })?;
+            let old_id = advertisement.get(&name).cloned();
+
+            if old_id.as_ref() == Some(&new_id) {
+                continue;
+            }
+
+            if let (Some(old_id), false) = (&old_id, self.options.force) {
+                let reason = if !self.repo.database.exists(old_id) {
+                    Some("fetch first")
+                } else if !rev_list::is_ancestor(&self.repo, old_id, &new_id)? {
+                    Some("non-fast-forward")
+                } else {
+                    None
+                };
+
+                if let Some(reason) = reason {
+                    let update = RefUpdate::new('!', "[rejected]", &branch, Some(&branch));
+                    rejected.push(update.because(reason));
+                    continue;
+                }
+            }
+
+            commands.push(RefCommand {
+                old_id,
+                new_id,
+                branch,
+            });
+        }
+
+        Ok((commands, rejected))
+    }
+
+    fn send_commands(&self, conn: &mut Transport, commands: &[RefCommand]) -> io::Result<()> {
+        for (n, command) in commands.iter().enumerate() {
+            let old_id = command
+                .old_id
+                .as_ref()
+                .map_or(ZERO_ID.to_string(), |id| id.to_string());
+            let line = format!(
+                "{} {} {}{}",
+                old_id, command.new_id, HEADS_PREFIX, command.branch
+            );
+
+            if n == 0 {
+                pkt_line::write_line(&mut conn.output, &format!("{}\0report-status", line))?;
+            } else {
+                pkt_line::write_line(&mut conn.output, &line)?;
+            }
+        }
+
+        pkt_line::flush(&mut conn.output)
+    }
+
+    /// Builds a pack with what the new commits need, leaving out everything
+    /// reachable from the refs the other side advertised.
+    fn pack(
+        &self,
+        advertisement: &Advertisement,
+        commands: &[RefCommand],
+    ) -> Result<Vec<u8>, RitError> {
+        let include = commands
+            .iter()
+            .map(|command| command.new_id.clone())
+            .collect();
+        let exclude = advertisement
+            .refs
+            .iter()
+            .map(|(_, id)| id)
+            .filter(|id| {
+                self.repo.database.exists(id) && self.repo.database.load_commit(id).is_ok()
+            })
+            .cloned()
+            .collect();
+
+        let mut objects = vec![];
+
+        for (id, path) in RevList::new(&self.repo, include, exclude).objects()? {
+            let (kind, data) = self.repo.database.read_object(&id)?;
+
+            objects.push(PackObject {
+                id,
+                kind: pack::kind_number(&kind).unwrap_or_default(),
+                data,
+                path,
+            });
+        }
+
+        Ok(pack_writer(&self.repo.config).pack(objects)?)
+    }
+
+    /// Reads the outcome of each update from the report of `receive-pack`,
+    /// keyed by ref name, with the reason for those that were refused.
+    fn read_report(&self, conn: &mut Transport) -> io::Result<Vec<(String, Option<String>)>> {
+        let unpack = pkt_line::read_line(&mut conn.input)?.unwrap_or_default();
+
+        if unpack != "unpack ok" {
+            return Err(io::Error::other(format!(
+                "unpack failed: {}",
+                unpack.trim_start_matches("unpack ")
+            )));
+        }
+
+        let mut statuses = vec![];
+
+        while let Some(line) = pkt_line::read_line(&mut conn.input)? {
+            if let Some(name) = line.strip_prefix("ok ") {
+                statuses.push((name.to_string(), None));
+            } else if let Some(rest) = line.strip_prefix("ng ") {
+                let (name, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+                statuses.push((name.to_string(), Some(reason.to_string())));
+            } else {
+                return Err(unexpected(&line));
+            }
+        }
+
+        Ok(statuses)
+    }
+
+    fn track(&self, remote: &Remote, command: &RefCommand) -> Result<(), RitError> {
+        if let Some(remote_name) = &remote.name {
+            let committer = Author::new(&self.session.author_name, &self.session.author_email);
+            let name = format!("refs/remotes/{}/{}", remote_name, command.branch);
+
+            self.repo.refs.update_ref_logged(
+                &name,
+                &command.new_id,
+                &committer,
+                "update by push",
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Push {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let name = self.options.remote.as_deref().unwrap_or(DEFAULT_REMOTE);
+        let mut remote = Remote::find(&self.repo.config, name);
+
+        if let Some(program) = self.options.receive_pack.take() {
+            remote.set_program(RECEIVE_PACK, program);
+        }
+
+        let mut conn = remote.connect(RECEIVE_PACK, &self.session.project_dir)?;
+        let advertisement = Advertisement::read(&mut conn.input).map_err(protocol_error)?;
+
+        let (commands, mut updates) = self.plan(&advertisement)?;
+
+        self.send_commands(&mut conn, &commands)
+            .map_err(protocol_error)?;
+
+        if !commands.is_empty() {
+            let data = self.pack(&advertisement, &commands)?;
+            conn.output.write_all(&data).map_err(protocol_error)?;
+            conn.send().map_err(protocol_error)?;
+
+            let statuses = self.read_report(&mut conn).map_err(protocol_error)?;
+
+            for command in &commands {
+                let name = format!("{}{}", HEADS_PREFIX, command.branch);
+                let status = statuses
+                    .iter()
+                    .find(|(status_name, _)| status_name == &name);
+                let branch = &command.branch;
+
+                let update = match (status, &command.old_id) {
+                    (Some((_, Some(reason))), _) => {
+                        RefUpdate::new('!', "[remote rejected]", branch, Some(branch))
+                            .because(reason)
+                    }
+                    (None, _) => RefUpdate::new('!', "[remote failure]", branch, Some(branch))
+                        .because("remote failed to report status"),
+                    (Some((_, None)), None) => {
+                        RefUpdate::new('*', "[new branch]", branch, Some(branch))
+                    }
+                    (Some((_, None)), Some(old_id)) => {
+                        let fast_forward =
+                            rev_list::is_ancestor(&self.repo, old_id, &command.new_id)?;
+
+                        RefUpdate::moved(old_id, &command.new_id, fast_forward, branch, branch)
+                    }
+                };
+
+                if !update.is_rejected() {
+                    self.track(&remote, command)?;
+                }
+
+                updates.push(update);
+            }
+        }
+
+        conn.finish().map_err(protocol_error)?;
+
+        Ok(Execution::Push(PushResult {
+            url: remote.url,
+            updates,
+        }))
+    }
+}
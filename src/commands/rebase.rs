@@ -0,0 +1,744 @@
+use super::{write_commit, Command, Execution};
+use crate::{
+    editor,
+    errors::RitError,
+    id::Id,
+    merge,
+    objects::Author,
+    refs::{HEAD, ORIG_HEAD},
+    repository::Repository,
+    rev_list::RevList,
+    revision::Revision,
+    sequencer::{Sequencer, SequencerError, Step, DETACHED_HEAD},
+    Session,
+};
+use std::{fmt, process};
+
+const TODO_HELP: &str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's log message
+# x, exec <command> = run command (the rest of the line) using shell
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+";
+
+pub enum RebaseAction {
+    Start {
+        upstream: String,
+        onto: Option<String>,
+        interactive: bool,
+        autosquash: bool,
+    },
+    Continue,
+    Skip,
+    Abort,
+}
+
+pub struct Rebase {
+    session: Session,
+    action: Option<RebaseAction>,
+    repo: Repository,
+    sequencer: Sequencer,
+}
+
+#[derive(Debug)]
+pub enum RebaseResult {
+    UpToDate(String),
+    Done(String),
+    Stopped {
+        messages: Vec<String>,
+        id: Id,
+        subject: String,
+    },
+    Edit {
+        id: Id,
+        subject: String,
+    },
+    ExecFailed(String),
+    Aborted,
+}
+
+impl RebaseResult {
+    pub fn is_stopped(&self) -> bool {
+        matches!(
+            self,
+            RebaseResult::Stopped { .. } | RebaseResult::Edit { .. } | RebaseResult::ExecFailed(_)
+        )
+    }
+}
+
+impl fmt::Display for RebaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebaseResult::UpToDate(name) => write!(f, "Current branch {} is up to date.", name),
+            RebaseResult::Done(name) => write!(f, "Successfully rebased and updated {}.", name),
+            RebaseResult::Stopped {
+                messages,
+                id,
+                subject,
+            } => {
+                for message in messages {
+                    writeln!(f, "{}", message)?;
+                }
+
+                write!(
+                    f,
+                    "error: could not apply {}... {}
+hint: Resolve all conflicts manually, mark them as resolved with
+hint: \"rit add <conflicted_files>\", then run \"rit rebase --continue\".
+hint: You can instead skip this commit: run \"rit rebase --skip\".
+hint: To abort and get back to the state before \"rit rebase\", run \"rit rebase --abort\".",
+                    id.short(),
+                    subject
+                )
+            }
+            RebaseResult::Edit { id, subject } => write!(
+                f,
+                "Stopped at {}...  {}
+You can amend the commit now by staging changes with \"rit add\".
+
+Once you are satisfied with your changes, run
+
+  rit rebase --continue",
+                id.short(),
+                subject
+            ),
+            RebaseResult::ExecFailed(command) => write!(
+                f,
+                "warning: execution failed: {}
+You can fix the problem, and then run
+
+  rit rebase --continue",
+                command
+            ),
+            RebaseResult::Aborted => Ok(()),
+        }
+    }
+}
+
+impl Rebase {
+    pub fn new(session: Session, action: RebaseAction) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+        let sequencer = Sequencer::new(&repo.git_path);
+
+        Self {
+            session,
+            action: Some(action),
+            repo,
+            sequencer,
+        }
+    }
+
+    fn start(
+        &mut self,
+        upstream: &str,
+        onto: Option<&str>,
+        interactive: bool,
+        autosquash: bool,
+    ) -> Result<RebaseResult, RitError> {
+        if self.sequencer.is_in_progress() {
+            return Err(SequencerError::InProgress.into());
+        }
+
+        self.ensure_clean()?;
+
+        let upstream_id = Revision::new(&self.repo, upstream).resolve()?;
+        let onto_id = match onto {
+            Some(onto) => Revision::new(&self.repo, onto).resolve()?,
+            None => upstream_id.clone(),
+        };
+        let head_id = Revision::new(&self.repo, HEAD).resolve()?;
+
+        let head_name = self
+            .repo
+            .refs
+            .current_ref()
+            .unwrap_or_else(|| DETACHED_HEAD.to_string());
+
+        let mut commits =
+            RevList::new(&self.repo, vec![head_id.clone()], vec![upstream_id.clone()]).commits()?;
+        commits.reverse();
+
+        let is_up_to_date = match commits.first() {
+            Some((_, commit)) => commit.parent() == Some(&onto_id),
+            None => head_id == onto_id,
+        };
+
+        if is_up_to_date && onto_id == upstream_id && !interactive {
+            let name = self.repo.refs.short_name(&head_name).to_string();
+
+            return Ok(RebaseResult::UpToDate(name));
+        }
+
+        let mut steps: Vec<Step> = commits
+            .into_iter()
+            .filter(|(_, commit)| !commit.is_merge())
+            .map(|(id, commit)| Step::Pick(id, commit.title_line().to_string()))
+            .collect();
+
+        if autosquash {
+            steps = autosquash_steps(steps);
+        }
+
+        self.sequencer
+            .start(&head_name, &onto_id, &head_id, steps.clone())?;
+
+        if interactive {
+            let header = format!(
+                "# Rebase {}..{} onto {} ({} commands)\n",
+                upstream_id.short(),
+                head_id.short(),
+                onto_id.short(),
+                steps.len()
+            );
+
+            if let Err(err) = self.edit_todo(&steps, &header) {
+                self.sequencer.quit()?;
+
+                return Err(err);
+            }
+        }
+
+        self.repo.index.load_for_update()?;
+
+        let message = format!("rebase (start): checkout {}", onto.unwrap_or(upstream));
+
+        self.repo.refs.update_ref(ORIG_HEAD, &head_id)?;
+        self.repo
+            .refs
+            .update_ref_logged(HEAD, &onto_id, &self.committer(), &message)?;
+        self.repo.hard_reset(&onto_id)?;
+
+        self.resume()
+    }
+
+    /// Lets the user edit the todo list and replaces the planned steps with
+    /// the result.
+    fn edit_todo(&mut self, steps: &[Step], header: &str) -> Result<(), RitError> {
+        let mut content: String = steps
+            .iter()
+            .map(|step| format!("{}\n", step.to_short_string()))
+            .collect();
+
+        content.push('\n');
+        content.push_str(header);
+        content.push_str(TODO_HELP);
+
+        let editor = editor::sequence_editor(&self.repo.config);
+        let text = editor::edit(&editor, &self.sequencer.todo_path(), &content)?;
+
+        let mut steps = vec![];
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let step =
+                Step::parse_with(line, |name| Revision::new(&self.repo, name).resolve().ok())
+                    .ok_or_else(|| {
+                        SequencerError::InvalidTodo(format!("invalid line: {}", line))
+                    })?;
+
+            if step.is_squash() && !steps.iter().any(|step: &Step| step.commit().is_some()) {
+                let message = format!("cannot '{}' without a previous commit", step.command());
+
+                return Err(SequencerError::InvalidTodo(message).into());
+            }
+
+            steps.push(step);
+        }
+
+        if steps.is_empty() {
+            return Err(SequencerError::NothingToDo.into());
+        }
+
+        self.sequencer.set_todo(steps)?;
+
+        Ok(())
+    }
+
+    fn resume_stopped(&mut self) -> Result<RebaseResult, RitError> {
+        self.sequencer.load()?;
+        self.repo.index.load_for_update()?;
+
+        if self.repo.index.is_conflicted() {
+            self.repo.index.release_lock()?;
+
+            return Err(SequencerError::UnresolvedConflicts.into());
+        }
+
+        let head_id = self.head_id()?;
+
+        if let Some(amend_id) = self.sequencer.amend() {
+            let head_tree = self.repo.database.load_commit(&head_id)?.tree_id;
+
+            if amend_id == head_id && write_commit::write_tree(&mut self.repo)? != head_tree {
+                let message = self.repo.database.load_commit(&head_id)?.message;
+
+                self.rewrite_head(&message, "continue")?;
+            }
+        } else if let Some(step) = self.sequencer.current_step().cloned() {
+            if let Some(result) = self.commit_picked(&step)? {
+                return Ok(result);
+            }
+        }
+
+        self.resume()
+    }
+
+    fn skip(&mut self) -> Result<RebaseResult, RitError> {
+        self.sequencer.load()?;
+        self.repo.index.load_for_update()?;
+
+        let head_id = self.head_id()?;
+        self.repo.hard_reset(&head_id)?;
+
+        self.resume()
+    }
+
+    fn abort(&mut self) -> Result<RebaseResult, RitError> {
+        self.sequencer.load()?;
+        self.repo.index.load_for_update()?;
+
+        let orig_head = self
+            .sequencer
+            .orig_head
+            .clone()
+            .ok_or(SequencerError::NotInProgress)?;
+
+        self.repo.hard_reset(&orig_head)?;
+        self.restore_head(&orig_head, "abort")?;
+
+        self.sequencer.quit()?;
+        self.repo.index.write_updates()?;
+
+        Ok(RebaseResult::Aborted)
+    }
+
+    fn resume(&mut self) -> Result<RebaseResult, RitError> {
+        while let Some(step) = self.sequencer.next_step().cloned() {
+            self.sequencer.mark_done()?;
+
+            if let Some(result) = self.run_step(&step)? {
+                self.repo.index.write_updates()?;
+
+                return Ok(result);
+            }
+        }
+
+        self.finish()
+    }
+
+    /// Applies a single step, returning a result when the rebase has to stop.
+    fn run_step(&mut self, step: &Step) -> Result<Option<RebaseResult>, RitError> {
+        match step {
+            Step::Drop(..) => Ok(None),
+            Step::Exec(command) => self.exec(command),
+            step => {
+                let (id, subject) = step.commit().unwrap();
+                let messages = self.pick(id)?;
+
+                if self.repo.index.is_conflicted() {
+                    return Ok(Some(RebaseResult::Stopped {
+                        messages,
+                        id: id.clone(),
+                        subject: subject.to_string(),
+                    }));
+                }
+
+                self.commit_picked(step)
+            }
+        }
+    }
+
+    fn pick(&mut self, id: &Id) -> Result<Vec<String>, RitError> {
+        let commit = self.repo.database.load_commit(id)?;
+        let head_id = self.head_id()?;
+
+        let inputs = merge::Inputs {
+            left_name: HEAD.to_string(),
+            right_name: format!("{} ({})", id.short(), commit.title_line()),
+            base_id: commit.parent().cloned(),
+            left_id: head_id,
+            right_id: id.clone(),
+        };
+
+        merge::Resolve::new(&mut self.repo, inputs).execute()
+    }
+
+    /// Records the merged index for a commit step once it applied cleanly,
+    /// either straight away or after the user resolved its conflicts.
+    fn commit_picked(&mut self, step: &Step) -> Result<Option<RebaseResult>, RitError> {
+        match step {
+            Step::Pick(id, _) => {
+                self.commit_step(id, "pick")?;
+            }
+            Step::Reword(id, _) => {
+                if self.commit_step(id, "pick")?.is_some() {
+                    let message = self.repo.database.load_commit(id)?.message;
+                    let message = editor::edit_message(&self.repo, &message)?;
+
+                    self.rewrite_head(&message, "reword")?;
+                }
+            }
+            Step::Edit(id, subject) => {
+                self.commit_step(id, "edit")?;
+                self.sequencer.set_amend(&self.head_id()?)?;
+
+                return Ok(Some(RebaseResult::Edit {
+                    id: id.clone(),
+                    subject: subject.clone(),
+                }));
+            }
+            Step::Squash(id, _) | Step::Fixup(id, _) => self.squash(step, id)?,
+            Step::Drop(..) | Step::Exec(_) => {}
+        }
+
+        Ok(None)
+    }
+
+    /// Records the index as the rebased version of commit `id`, keeping its
+    /// author and message. Nothing is committed when the commit turned out to
+    /// be empty.
+    fn commit_step(&mut self, id: &Id, action: &str) -> Result<Option<Id>, RitError> {
+        let head_id = self.head_id()?;
+        let head_tree = self.repo.database.load_commit(&head_id)?.tree_id;
+
+        if write_commit::write_tree(&mut self.repo)? == head_tree {
+            return Ok(None);
+        }
+
+        let commit = self.repo.database.load_commit(id)?;
+        let committer = self.committer();
+
+        let commit_id = write_commit::write_commit(
+            &mut self.repo,
+            vec![head_id],
+            commit.author.clone(),
+            committer,
+            &commit.message,
+        )?;
+
+        self.move_head(&commit_id, action, commit.title_line())?;
+
+        Ok(Some(commit_id))
+    }
+
+    /// Melds commit `id` into HEAD. A squash adds its message to the one of
+    /// HEAD and the combined message is edited once the chain of squashes
+    /// and fixups ends.
+    fn squash(&mut self, step: &Step, id: &Id) -> Result<(), RitError> {
+        let head = self.repo.database.load_commit(&self.head_id()?)?;
+        let mut message = head.message.clone();
+
+        if let Step::Squash(..) = step {
+            let commit = self.repo.database.load_commit(id)?;
+
+            message = format!("{}\n\n{}", message.trim_end(), commit.message);
+        }
+
+        let is_chain_end = !self.sequencer.next_step().is_some_and(Step::is_squash);
+        let has_squash = self
+            .sequencer
+            .done()
+            .iter()
+            .rev()
+            .take_while(|step| step.is_squash())
+            .any(|step| matches!(step, Step::Squash(..)));
+
+        if is_chain_end && has_squash {
+            let content = format!("# This is a combination of commits.\n{}", message);
+
+            message = editor::edit_message(&self.repo, &content)?;
+        }
+
+        self.rewrite_head(&message, step.command())?;
+
+        Ok(())
+    }
+
+    fn exec(&mut self, command: &str) -> Result<Option<RebaseResult>, RitError> {
+        self.repo.index.write_updates()?;
+
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.session.project_dir)
+            .status()?;
+
+        self.repo.index.load_for_update()?;
+
+        if status.success() {
+            Ok(None)
+        } else {
+            Ok(Some(RebaseResult::ExecFailed(command.to_string())))
+        }
+    }
+
+    /// Replaces HEAD with a commit of the index that has the same parents and
+    /// author but the given message.
+    fn rewrite_head(&mut self, message: &str, action: &str) -> Result<Id, RitError> {
+        let head = self.repo.database.load_commit(&self.head_id()?)?;
+        let committer = self.committer();
+
+        let commit_id = write_commit::write_commit(
+            &mut self.repo,
+            head.parents,
+            head.author,
+            committer,
+            message,
+        )?;
+
+        self.move_head(&commit_id, action, message.lines().next().unwrap_or(""))?;
+
+        Ok(commit_id)
+    }
+
+    /// Moves the detached HEAD to a commit made by step `action`.
+    fn move_head(&self, id: &Id, action: &str, subject: &str) -> Result<(), RitError> {
+        let message = format!("rebase ({}): {}", action, subject);
+
+        Ok(self
+            .repo
+            .refs
+            .update_ref_logged(HEAD, id, &self.committer(), &message)?)
+    }
+
+    fn committer(&self) -> Author {
+        Author::new(&self.session.author_name, &self.session.author_email)
+    }
+
+    fn finish(&mut self) -> Result<RebaseResult, RitError> {
+        let head_id = self.head_id()?;
+
+        self.restore_head(&head_id, "finish")?;
+
+        self.sequencer.quit()?;
+        self.repo.index.write_updates()?;
+
+        Ok(RebaseResult::Done(self.sequencer.head_name.clone()))
+    }
+
+    /// Points the rebased branch at `id` and attaches HEAD to it again.
+    fn restore_head(&mut self, id: &Id, action: &str) -> Result<(), RitError> {
+        let head_name = self.sequencer.head_name.clone();
+        let committer = self.committer();
+        let old_head = self.repo.refs.read_head();
+
+        if head_name == DETACHED_HEAD {
+            let message = format!("rebase ({}): returning to {}", action, id);
+
+            self.repo
+                .refs
+                .update_ref_logged(HEAD, id, &committer, &message)?;
+
+            return Ok(());
+        }
+
+        if self.repo.refs.read_ref(&head_name).as_ref() != Some(id) {
+            let onto = self.sequencer.onto.as_ref().unwrap_or(id);
+            let message = format!("rebase ({}): {} onto {}", action, head_name, onto);
+
+            self.repo
+                .refs
+                .update_ref_logged(&head_name, id, &committer, &message)?;
+        }
+
+        let branch = self.repo.refs.short_name(&head_name).to_string();
+        let message = format!("rebase ({}): returning to {}", action, head_name);
+
+        self.repo.refs.set_head(&branch, id)?;
+        self.repo
+            .refs
+            .append_log(HEAD, old_head, id, &committer, &message)?;
+
+        Ok(())
+    }
+
+    fn head_id(&self) -> Result<Id, RitError> {
+        Ok(Revision::new(&self.repo, HEAD).resolve()?)
+    }
+
+    fn ensure_clean(&mut self) -> Result<(), RitError> {
+        let status = super::Status::new(self.session.clone()).execute()?;
+
+        if let Execution::Status(status) = status {
+            if !status.modified.is_empty() || !status.deleted.is_empty() {
+                return Err(SequencerError::UnstagedChanges.into());
+            }
+        }
+
+        self.repo.index.load()?;
+
+        if self.repo.index.is_conflicted() {
+            return Err(RitError::UnmergedFiles);
+        }
+
+        let head_tree = match self.repo.refs.read_head() {
+            Some(id) => self.repo.database.flatten_tree(Some(&id))?,
+            None => Default::default(),
+        };
+
+        let entries = self.repo.index.entries();
+        let is_clean = entries.len() == head_tree.len()
+            && entries.iter().all(|entry| {
+                head_tree
+                    .get(&entry.pathname)
+                    .is_some_and(|item| item.id == entry.id && item.mode == entry.mode)
+            });
+
+        if !is_clean {
+            return Err(SequencerError::UncommittedChanges.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for Rebase {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let result = match self.action.take() {
+            Some(RebaseAction::Start {
+                upstream,
+                onto,
+                interactive,
+                autosquash,
+            }) => self.start(&upstream, onto.as_deref(), interactive, autosquash),
+            Some(RebaseAction::Continue) => self.resume_stopped(),
+            Some(RebaseAction::Skip) => self.skip(),
+            Some(RebaseAction::Abort) => self.abort(),
+            None => Err(SequencerError::NotInProgress.into()),
+        };
+
+        // a step that failed halfway may leave the index locked
+        if result.is_err() && self.repo.index.is_locked() {
+            self.repo.index.release_lock()?;
+        }
+
+        Ok(Execution::Rebase(result?))
+    }
+}
+
+/// Moves every `fixup!` and `squash!` commit right after the commit its
+/// subject refers to and turns it into the matching step.
+fn autosquash_steps(steps: Vec<Step>) -> Vec<Step> {
+    let mut groups: Vec<Vec<Step>> = vec![];
+
+    for step in steps {
+        let target = step
+            .commit()
+            .and_then(|(_, subject)| squash_target(subject));
+
+        let position = target.and_then(|(_, target)| {
+            find_group(&groups, |_, subject| subject == target)
+                .or_else(|| find_group(&groups, |id, _| id.as_str.starts_with(target)))
+                .or_else(|| find_group(&groups, |_, subject| subject.starts_with(target)))
+        });
+
+        match (position, target, step.commit()) {
+            (Some(pos), Some((is_fixup, _)), Some((id, subject))) => {
+                let (id, subject) = (id.clone(), subject.to_string());
+
+                groups[pos].push(if is_fixup {
+                    Step::Fixup(id, subject)
+                } else {
+                    Step::Squash(id, subject)
+                });
+            }
+            _ => groups.push(vec![step]),
+        }
+    }
+
+    groups.into_iter().flatten().collect()
+}
+
+fn find_group<F>(groups: &[Vec<Step>], matches: F) -> Option<usize>
+where
+    F: Fn(&Id, &str) -> bool,
+{
+    groups.iter().position(|group| {
+        group[0]
+            .commit()
+            .is_some_and(|(id, subject)| matches(id, subject))
+    })
+}
+
+/// Splits a `fixup! ...` or `squash! ...` subject into whether it is a fixup
+/// and the subject (or commit id) it refers to.
+fn squash_target(subject: &str) -> Option<(bool, &str)> {
+    let (is_fixup, mut rest) = match subject.strip_prefix("fixup! ") {
+        Some(rest) => (true, rest),
+        None => (false, subject.strip_prefix("squash! ")?),
+    };
+
+    while let Some(inner) = rest
+        .strip_prefix("fixup! ")
+        .or_else(|| rest.strip_prefix("squash! "))
+    {
+        rest = inner;
+    }
+
+    Some((is_fixup, rest.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Id {
+        Id::from_hex(&format!("{:02x}", n).repeat(20)).unwrap()
+    }
+
+    fn pick(n: u8, subject: &str) -> Step {
+        Step::Pick(id(n), subject.to_string())
+    }
+
+    #[test]
+    fn it_moves_fixups_after_their_target() {
+        let steps = vec![
+            pick(1, "one"),
+            pick(2, "two"),
+            pick(3, "fixup! one"),
+            pick(4, "squash! two"),
+            pick(5, "fixup! fixup! one"),
+        ];
+
+        assert_eq!(
+            vec![
+                pick(1, "one"),
+                Step::Fixup(id(3), "fixup! one".to_string()),
+                Step::Fixup(id(5), "fixup! fixup! one".to_string()),
+                pick(2, "two"),
+                Step::Squash(id(4), "squash! two".to_string()),
+            ],
+            autosquash_steps(steps)
+        );
+    }
+
+    #[test]
+    fn it_finds_targets_by_id_prefix() {
+        let steps = vec![pick(1, "one"), pick(2, "fixup! 0101")];
+
+        assert_eq!(
+            vec![
+                pick(1, "one"),
+                Step::Fixup(id(2), "fixup! 0101".to_string())
+            ],
+            autosquash_steps(steps)
+        );
+    }
+
+    #[test]
+    fn it_keeps_fixups_without_a_target() {
+        let steps = vec![pick(1, "one"), pick(2, "fixup! missing")];
+
+        assert_eq!(steps.clone(), autosquash_steps(steps));
+    }
+}
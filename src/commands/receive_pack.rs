@@ -0,0 +1,253 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    id::Id,
+    objects::Author,
+    pack::Indexer,
+    refs::Refs,
+    remote::{self, pkt_line, protocol_error, unexpected, Advertisement, ServiceMode, ZERO_ID},
+    repository::Repository,
+    rev_list, Session,
+};
+use std::{
+    io::{self, prelude::*},
+    path::PathBuf,
+};
+
+const CAPABILITIES: [&str; 3] = ["report-status", "delete-refs", "ofs-delta"];
+
+/// A ref update requested by `push`. A zero id stands for a ref that does
+/// not exist, so an update from zero creates it and one to zero deletes it.
+struct Update {
+    old_id: Option<Id>,
+    new_id: Option<Id>,
+    name: String,
+}
+
+/// Takes objects and ref updates from `push` on the other end of a
+/// connection. After the refs are advertised, the client sends the updates
+/// it wants, then a pack with the objects they need. Each update is checked
+/// and applied on its own, and the outcome of every one of them is reported
+/// back. Over HTTP, the advertisement is a request of its own.
+pub struct ReceivePack {
+    session: Session,
+    path: PathBuf,
+    mode: ServiceMode,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl ReceivePack {
+    pub fn new(session: Session, dir: &str, input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+        let path = session.project_dir.join(dir);
+
+        Self {
+            session,
+            path,
+            mode: ServiceMode::default(),
+            input,
+            output,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ServiceMode) {
+        self.mode = mode;
+    }
+
+    fn advertisement(&self, repo: &Repository) -> Advertisement {
+        let refs = repo
+            .refs
+            .list_refs("refs")
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), repo.refs.read_ref(&name)?)))
+            .collect();
+
+        let mut capabilities: Vec<String> = CAPABILITIES.iter().map(|c| c.to_string()).collect();
+        capabilities.push(format!("agent=rit/{}", env!("CARGO_PKG_VERSION")));
+
+        Advertisement::new(refs, capabilities)
+    }
+
+    /// Reads the updates up to the first flush-pkt, along with whether the
+    /// client asked for a report.
+    fn read_updates(&mut self) -> io::Result<(Vec<Update>, bool)> {
+        let mut updates = vec![];
+        let mut report = false;
+
+        loop {
+            let line = match pkt_line::read_line(&mut self.input) {
+                Ok(Some(line)) => line,
+                Ok(None) => return Ok((updates, report)),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && updates.is_empty() => {
+                    return Ok((updates, report))
+                }
+                Err(err) => return Err(err),
+            };
+
+            let line = match line.split_once('\0') {
+                Some((line, capabilities)) => {
+                    report = capabilities.split(' ').any(|c| c == "report-status");
+                    line.to_string()
+                }
+                None => line,
+            };
+
+            let mut parts = line.splitn(3, ' ');
+            let mut parse_id = || {
+                parts
+                    .next()
+                    .filter(|id| id.len() == ZERO_ID.len())
+                    .map(|id| Id::from_hex(id).filter(|_| id != ZERO_ID))
+            };
+
+            let (old_id, new_id) = match (parse_id(), parse_id()) {
+                (Some(old_id), Some(new_id)) => (old_id, new_id),
+                _ => return Err(unexpected(&line)),
+            };
+            let name = parts.next().ok_or_else(|| unexpected(&line))?.to_string();
+
+            updates.push(Update {
+                old_id,
+                new_id,
+                name,
+            });
+        }
+    }
+
+    /// Checks an update against the state of the ref and the settings of
+    /// the repository, then applies it.
+    fn apply(&self, repo: &Repository, update: &Update) -> Result<(), String> {
+        let name = &update.name;
+
+        if !name.starts_with("refs/") || !Refs::is_valid_ref_name(name) {
+            return Err("funny refname".to_string());
+        }
+
+        let config = &repo.config;
+        let refs = &repo.refs;
+        let is_bare = remote::work_tree(&repo.git_path) == repo.git_path.as_path();
+        let is_current = !is_bare && refs.current_ref().as_deref() == Some(name);
+
+        if refs.read_ref(name) != update.old_id {
+            return Err("failed to lock".to_string());
+        }
+
+        let new_id = match &update.new_id {
+            Some(new_id) => new_id,
+            None => {
+                if config.get_bool("receive.denyDeletes") == Some(true) {
+                    return Err("deletion prohibited".to_string());
+                }
+
+                if is_current && denies(config.get("receive.denyDeleteCurrent")) {
+                    return Err("deletion of the current branch prohibited".to_string());
+                }
+
+                return refs.delete_ref(name).map_err(|err| err.to_string());
+            }
+        };
+
+        if !repo.database.exists(new_id) {
+            return Err("missing necessary objects".to_string());
+        }
+
+        if is_current && denies(config.get("receive.denyCurrentBranch")) {
+            return Err("branch is currently checked out".to_string());
+        }
+
+        if let Some(old_id) = &update.old_id {
+            let fast_forward = name.starts_with("refs/heads/")
+                && rev_list::is_ancestor(repo, old_id, new_id).unwrap_or(false);
+
+            if !fast_forward && config.get_bool("receive.denyNonFastForwards") == Some(true) {
+                return Err("non-fast-forward".to_string());
+            }
+        }
+
+        let committer = Author::new(&self.session.author_name, &self.session.author_email);
+
+        refs.update_ref_logged(name, new_id, &committer, "push")
+            .map_err(|err| err.to_string())
+    }
+
+    fn write_report(
+        &mut self,
+        unpacked: &Result<(), String>,
+        results: &[(String, Result<(), String>)],
+    ) -> io::Result<()> {
+        match unpacked {
+            Ok(()) => pkt_line::write_line(&mut self.output, "unpack ok")?,
+            Err(err) => pkt_line::write_line(&mut self.output, &format!("unpack {}", err))?,
+        }
+
+        for (name, result) in results {
+            match result {
+                Ok(()) => pkt_line::write_line(&mut self.output, &format!("ok {}", name))?,
+                Err(reason) => {
+                    pkt_line::write_line(&mut self.output, &format!("ng {} {}", name, reason))?
+                }
+            }
+        }
+
+        pkt_line::flush(&mut self.output)
+    }
+}
+
+/// Whether a `receive.deny*` setting refuses, which it does unless it is
+/// explicitly turned off.
+fn denies(setting: Option<String>) -> bool {
+    !matches!(
+        setting.as_deref().map(str::to_lowercase).as_deref(),
+        Some("ignore") | Some("warn") | Some("false") | Some("no") | Some("off") | Some("0")
+    )
+}
+
+impl Command for ReceivePack {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let mut repo = remote::open_repository(&self.path)?;
+
+        if self.mode != ServiceMode::StatelessRpc {
+            self.advertisement(&repo)
+                .write(&mut self.output)
+                .map_err(protocol_error)?;
+        }
+
+        if self.mode == ServiceMode::AdvertiseRefs {
+            return Ok(Execution::Empty);
+        }
+
+        let (updates, report) = self.read_updates().map_err(protocol_error)?;
+
+        if updates.is_empty() {
+            return Ok(Execution::Empty);
+        }
+
+        let unpacked = if updates.iter().any(|update| update.new_id.is_some()) {
+            Indexer::read_stream(&mut self.input)
+                .map_err(|err| err.to_string())
+                .and_then(|data| remote::store_pack(&mut repo, data).map_err(|err| err.to_string()))
+                .map(|_| ())
+        } else {
+            Ok(())
+        };
+
+        let results: Vec<(String, Result<(), String>)> = updates
+            .iter()
+            .map(|update| {
+                let result = match &unpacked {
+                    Ok(()) => self.apply(&repo, update),
+                    Err(_) => Err("unpacker error".to_string()),
+                };
+
+                (update.name.clone(), result)
+            })
+            .collect();
+
+        if report {
+            self.write_report(&unpacked, &results)
+                .map_err(protocol_error)?;
+        }
+
+        Ok(Execution::Empty)
+    }
+}
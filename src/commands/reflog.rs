@@ -0,0 +1,192 @@
+use super::{Command, Execution};
+use crate::{
+    date,
+    errors::RitError,
+    reflog::{self, ReflogError},
+    refs::HEAD,
+    repository::Repository,
+    revision::RevisionError,
+    Session,
+};
+use chrono::Local;
+use std::{collections::BTreeMap, fmt};
+
+const DEFAULT_EXPIRE: &str = "90.days.ago";
+
+pub enum ReflogAction {
+    Show {
+        name: Option<String>,
+    },
+    Expire {
+        names: Vec<String>,
+        all: bool,
+        expire: Option<String>,
+    },
+    Delete {
+        entries: Vec<String>,
+    },
+}
+
+pub struct Reflog {
+    action: Option<ReflogAction>,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub enum ReflogResult {
+    Show(Vec<String>),
+    Expired,
+    Deleted,
+}
+
+impl fmt::Display for ReflogResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflogResult::Show(lines) => write!(f, "{}", lines.join("\n")),
+            ReflogResult::Expired | ReflogResult::Deleted => Ok(()),
+        }
+    }
+}
+
+impl Reflog {
+    pub fn new(session: Session, action: ReflogAction) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self {
+            action: Some(action),
+            repo,
+        }
+    }
+
+    /// Lists the entries of the log, newest first, the way `<name>@{<n>}`
+    /// refers to them.
+    fn show(&self, name: Option<String>) -> Result<ReflogResult, RitError> {
+        let name = name.unwrap_or_else(|| HEAD.to_string());
+        let full_name = self.full_name(&name)?;
+
+        let lines = self
+            .repo
+            .refs
+            .reflog()
+            .read(&full_name)
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(n, entry)| {
+                format!(
+                    "{} {}@{{{}}}: {}",
+                    entry.new_id.short(),
+                    name,
+                    n,
+                    entry.message
+                )
+            })
+            .collect();
+
+        Ok(ReflogResult::Show(lines))
+    }
+
+    /// Drops the entries older than `expire`, which defaults to
+    /// `gc.reflogExpire` or 90 days; `never` keeps everything.
+    fn expire(
+        &self,
+        names: Vec<String>,
+        all: bool,
+        expire: Option<String>,
+    ) -> Result<ReflogResult, RitError> {
+        let expire = expire
+            .or_else(|| self.repo.config.get("gc.reflogExpire"))
+            .unwrap_or_else(|| DEFAULT_EXPIRE.to_string());
+
+        let cutoff = match &expire[..] {
+            "never" | "false" => return Ok(ReflogResult::Expired),
+            _ => date::parse(&expire, Local::now())
+                .ok_or_else(|| ReflogError::InvalidExpiry(expire.clone()))?,
+        };
+
+        let log = self.repo.refs.reflog();
+
+        let names = if all {
+            log.list()
+        } else {
+            names
+                .iter()
+                .map(|name| self.full_name(name))
+                .collect::<Result<_, _>>()?
+        };
+
+        for name in names {
+            let mut entries = log.read(&name);
+            entries.retain(|entry| entry.committer.time.timestamp() > cutoff);
+
+            log.write(&name, &entries)?;
+        }
+
+        Ok(ReflogResult::Expired)
+    }
+
+    /// Removes single entries given as `<name>@{<n>}`.
+    fn delete(&self, specs: Vec<String>) -> Result<ReflogResult, RitError> {
+        let mut selected: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+        for spec in &specs {
+            let (name, n) = reflog::parse_selector(spec)
+                .and_then(|(name, selector)| Some((name, selector.parse::<usize>().ok()?)))
+                .ok_or_else(|| ReflogError::NotAReflog(spec.clone()))?;
+
+            let full_name = self.full_name(if name.is_empty() { HEAD } else { name })?;
+            selected.entry(full_name).or_default().push(n);
+        }
+
+        let log = self.repo.refs.reflog();
+
+        for (name, mut positions) in selected {
+            let mut entries = log.read(&name);
+            let len = entries.len();
+
+            positions.sort_unstable();
+            positions.dedup();
+
+            if positions.last().is_some_and(|&n| n >= len) {
+                let name = self.repo.refs.short_name(&name).to_string();
+
+                return Err(RevisionError::LogTooShort(name, len).into());
+            }
+
+            for n in positions.into_iter().rev() {
+                entries.remove(len - 1 - n);
+            }
+
+            log.write(&name, &entries)?;
+        }
+
+        Ok(ReflogResult::Deleted)
+    }
+
+    fn full_name(&self, name: &str) -> Result<String, RitError> {
+        let full_name = self
+            .repo
+            .refs
+            .expand_name(name)
+            .ok_or_else(|| ReflogError::NotAReflog(name.to_string()))?;
+
+        if !self.repo.refs.reflog().exists(&full_name) {
+            return Err(ReflogError::NotAReflog(name.to_string()).into());
+        }
+
+        Ok(full_name)
+    }
+}
+
+impl Command for Reflog {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let result = match self.action.take() {
+            Some(ReflogAction::Expire { names, all, expire }) => self.expire(names, all, expire)?,
+            Some(ReflogAction::Delete { entries }) => self.delete(entries)?,
+            Some(ReflogAction::Show { name }) => self.show(name)?,
+            None => self.show(None)?,
+        };
+
+        Ok(Execution::Reflog(result))
+    }
+}
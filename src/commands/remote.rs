@@ -0,0 +1,252 @@
+use super::{Command, Execution};
+use crate::{
+    errors::RitError,
+    refs::Refs,
+    remote::{self, Refspec},
+    repository::Repository,
+    Session,
+};
+use std::fmt;
+
+const REMOTES_PREFIX: &str = "refs/remotes/";
+
+pub enum RemoteAction {
+    List { verbose: bool },
+    Add { name: String, url: String },
+    Remove { name: String },
+    Rename { old_name: String, new_name: String },
+    SetUrl { name: String, url: String },
+}
+
+/// Manages the remotes kept in the config as `remote.<name>.*`. Adding one
+/// sets it up to fetch its branches into `refs/remotes/<name>/*`, and
+/// renaming or removing it takes those refs and the branches that track it
+/// along.
+pub struct Remote {
+    action: Option<RemoteAction>,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub enum RemoteResult {
+    Updated,
+    List(Vec<String>),
+}
+
+impl fmt::Display for RemoteResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteResult::Updated => Ok(()),
+            RemoteResult::List(lines) => write!(f, "{}", lines.join("\n")),
+        }
+    }
+}
+
+impl Remote {
+    pub fn new(session: Session, action: RemoteAction) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self {
+            action: Some(action),
+            repo,
+        }
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.repo
+            .config
+            .subsections("remote")
+            .iter()
+            .any(|remote| remote == name)
+    }
+
+    fn check_exists(&self, name: &str) -> Result<(), RitError> {
+        if self.exists(name) {
+            Ok(())
+        } else {
+            Err(RitError::Remote(format!("No such remote: '{}'", name)))
+        }
+    }
+
+    /// Checks that `name` is free and can be used in the names of refs.
+    fn check_new_name(&self, name: &str) -> Result<(), RitError> {
+        if !Refs::is_valid_branch_name(name) {
+            return Err(RitError::Remote(format!(
+                "'{}' is not a valid remote name",
+                name
+            )));
+        }
+
+        if self.exists(name) {
+            return Err(RitError::Remote(format!("remote {} already exists.", name)));
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, verbose: bool) -> RemoteResult {
+        let config = &self.repo.config;
+        let mut lines = vec![];
+
+        for name in config.subsections("remote") {
+            if !verbose {
+                lines.push(name);
+                continue;
+            }
+
+            let url = config
+                .get(&format!("remote.{}.url", name))
+                .unwrap_or_default();
+            let push_url = config
+                .get(&format!("remote.{}.pushurl", name))
+                .unwrap_or_else(|| url.clone());
+
+            lines.push(format!("{}\t{} (fetch)", name, url));
+            lines.push(format!("{}\t{} (push)", name, push_url));
+        }
+
+        RemoteResult::List(lines)
+    }
+
+    fn add(&mut self, name: &str, url: &str) -> Result<RemoteResult, RitError> {
+        self.check_new_name(name)?;
+
+        let config = &mut self.repo.config.local;
+        config.set(&format!("remote.{}.url", name), url)?;
+        config.set(
+            &format!("remote.{}.fetch", name),
+            &Refspec::default_fetch(name),
+        )?;
+        config.save()?;
+
+        Ok(RemoteResult::Updated)
+    }
+
+    /// Removes the remote along with the remote-tracking refs its refspecs
+    /// store into, and stops the branches that track it from doing so.
+    fn remove(&mut self, name: &str) -> Result<RemoteResult, RitError> {
+        self.check_exists(name)?;
+
+        let remote = remote::Remote::find(&self.repo.config, name);
+        let refs = &self.repo.refs;
+
+        for refspec in remote.fetch_refspecs()? {
+            let target = match &refspec.target {
+                Some(target) if target.starts_with(REMOTES_PREFIX) => target,
+                _ => continue,
+            };
+
+            let names = match target.split_once('*') {
+                Some((prefix, _)) => refs.list_refs(prefix.trim_end_matches('/')),
+                None => vec![target.clone()],
+            };
+
+            for name in names {
+                refs.delete_ref(&name)?;
+            }
+        }
+
+        let tracking: Vec<String> = self
+            .repo
+            .config
+            .subsections("branch")
+            .into_iter()
+            .filter(|branch| {
+                let key = format!("branch.{}.remote", branch);
+                self.repo.config.get(&key).as_deref() == Some(name)
+            })
+            .collect();
+
+        let config = &mut self.repo.config.local;
+
+        for branch in tracking {
+            config.unset(&format!("branch.{}.remote", branch))?;
+            config.unset(&format!("branch.{}.merge", branch))?;
+        }
+
+        config.remove_section("remote", Some(name));
+        config.save()?;
+
+        Ok(RemoteResult::Updated)
+    }
+
+    /// Renames the remote, moving its remote-tracking refs and the refspecs
+    /// that store into them to the new name.
+    fn rename(&mut self, old_name: &str, new_name: &str) -> Result<RemoteResult, RitError> {
+        self.check_exists(old_name)?;
+        self.check_new_name(new_name)?;
+
+        let old_prefix = format!("{}{}/", REMOTES_PREFIX, old_name);
+        let new_prefix = format!("{}{}/", REMOTES_PREFIX, new_name);
+        let fetch_key = format!("remote.{}.fetch", new_name);
+
+        let config = &mut self.repo.config.local;
+        config.rename_section("remote", old_name, new_name);
+
+        let refspecs: Vec<String> = config
+            .get_all(&fetch_key)
+            .into_iter()
+            .flatten()
+            .map(|refspec| {
+                refspec.replace(&format!(":{}", old_prefix), &format!(":{}", new_prefix))
+            })
+            .collect();
+
+        config.unset(&fetch_key)?;
+
+        for refspec in &refspecs {
+            config.add(&fetch_key, refspec)?;
+        }
+
+        for branch in config.subsections("branch") {
+            let key = format!("branch.{}.remote", branch);
+
+            if config.get_all(&key).last() == Some(&Some(old_name.to_string())) {
+                config.set(&key, new_name)?;
+            }
+        }
+
+        config.save()?;
+
+        let refs = &self.repo.refs;
+
+        for name in refs.list_refs(old_prefix.trim_end_matches('/')) {
+            let new_ref = name.replacen(&old_prefix, &new_prefix, 1);
+
+            match refs.read_symref(&name) {
+                Some(target) => {
+                    refs.write_symref(&new_ref, &target.replacen(&old_prefix, &new_prefix, 1))?;
+                    refs.delete_ref(&name)?;
+                }
+                None => refs.rename_ref(&name, &new_ref)?,
+            }
+        }
+
+        Ok(RemoteResult::Updated)
+    }
+
+    fn set_url(&mut self, name: &str, url: &str) -> Result<RemoteResult, RitError> {
+        self.check_exists(name)?;
+
+        self.repo.config.set(&format!("remote.{}.url", name), url)?;
+
+        Ok(RemoteResult::Updated)
+    }
+}
+
+impl Command for Remote {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let result = match self.action.take() {
+            Some(RemoteAction::Add { name, url }) => self.add(&name, &url)?,
+            Some(RemoteAction::Remove { name }) => self.remove(&name)?,
+            Some(RemoteAction::Rename { old_name, new_name }) => {
+                self.rename(&old_name, &new_name)?
+            }
+            Some(RemoteAction::SetUrl { name, url }) => self.set_url(&name, &url)?,
+            Some(RemoteAction::List { verbose }) => self.list(verbose),
+            None => self.list(false),
+        };
+
+        Ok(Execution::Remote(result))
+    }
+}
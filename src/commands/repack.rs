@@ -0,0 +1,174 @@
+use super::{Command, Execution};
+use crate::{
+    config::Config,
+    errors::RitError,
+    id::Id,
+    objects::Parsed,
+    pack::{self, PackObject, Writer},
+    refs::{HEAD, ORIG_HEAD},
+    repository::Repository,
+    rev_list::RevList,
+    Session,
+};
+use std::{collections::HashSet, fmt, fs};
+
+const DEFAULT_WINDOW: i64 = 10;
+const DEFAULT_DEPTH: i64 = 50;
+
+#[derive(Default)]
+pub struct RepackOptions {
+    pub all: bool,
+    pub delete: bool,
+}
+
+/// Packs every reachable object with `-a`, or otherwise only those that are
+/// still loose. `-d` then deletes the loose copies of the packed objects and,
+/// with `-a`, the packs that the new one replaces.
+pub struct Repack {
+    options: RepackOptions,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub struct RepackResult {
+    total: usize,
+    deltas: usize,
+}
+
+impl fmt::Display for RepackResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total == 0 {
+            write!(f, "Nothing new to pack.")
+        } else {
+            write!(f, "Total {} (delta {})", self.total, self.deltas)
+        }
+    }
+}
+
+impl Repack {
+    pub fn new(session: Session, options: RepackOptions) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self { options, repo }
+    }
+
+    /// Lists the objects reachable from refs, reflogs and the index, each with
+    /// the path it was found at.
+    fn reachable_objects(&mut self) -> Result<Vec<(Id, String)>, RitError> {
+        let refs = &self.repo.refs;
+
+        let mut roots: Vec<Id> = refs
+            .list_refs("refs")
+            .iter()
+            .chain(&[HEAD.to_string(), ORIG_HEAD.to_string()])
+            .filter_map(|name| refs.read_ref(name))
+            .collect();
+
+        let log = refs.reflog();
+
+        for name in log.list() {
+            for entry in log.read(&name) {
+                roots.extend(entry.old_id);
+                roots.push(entry.new_id);
+            }
+        }
+
+        let mut tags = vec![];
+        let mut commits = vec![];
+        let mut others = vec![];
+        let mut seen = HashSet::new();
+
+        while let Some(id) = roots.pop() {
+            if !seen.insert(id.clone()) || !self.repo.database.exists(&id) {
+                continue;
+            }
+
+            match self.repo.database.load(&id)? {
+                Parsed::Tag(tag) => {
+                    roots.push(tag.object);
+                    tags.push((id, String::new()));
+                }
+                Parsed::Commit(_) => commits.push(id),
+                _ => others.push(id),
+            }
+        }
+
+        self.repo.index.load()?;
+        others.extend(self.repo.index.entries().into_iter().map(|entry| entry.id));
+
+        let mut rev_list = RevList::new(&self.repo, commits, vec![]);
+        rev_list.add_objects(others);
+
+        let mut objects = rev_list.objects()?;
+        objects.extend(tags);
+
+        Ok(objects)
+    }
+}
+
+/// A pack writer set up with `pack.window` and `pack.depth`.
+pub fn pack_writer(config: &Config) -> Writer {
+    let setting = |key: &str, default: i64| config.get_int(key).unwrap_or(default).max(0) as usize;
+
+    Writer::new(
+        setting("pack.window", DEFAULT_WINDOW),
+        setting("pack.depth", DEFAULT_DEPTH),
+    )
+}
+
+impl Command for Repack {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let mut objects = self.reachable_objects()?;
+
+        if !self.options.all {
+            let database = &self.repo.database;
+            objects.retain(|(id, _)| !database.is_packed(id));
+        }
+
+        let old_packs = self.repo.database.pack_index_paths();
+        let total = objects.len();
+
+        let mut pack_objects = vec![];
+
+        for (id, path) in objects {
+            let (kind, data) = self.repo.database.read_object(&id)?;
+
+            pack_objects.push(PackObject {
+                id,
+                kind: pack::kind_number(&kind).unwrap_or_default(),
+                data,
+                path,
+            });
+        }
+
+        let writer = pack_writer(&self.repo.config);
+
+        let mut deltas = 0;
+
+        if total > 0 {
+            let (checksum, count) = writer.write(&self.repo.database.pack_dir(), pack_objects)?;
+            deltas = count;
+
+            if self.options.delete && self.options.all {
+                let name = format!("pack-{}.idx", checksum);
+
+                for path in old_packs.iter().filter(|path| !path.ends_with(&name)) {
+                    fs::remove_file(path.with_extension("pack"))?;
+                    fs::remove_file(path)?;
+                }
+            }
+
+            self.repo.database.reload_packs();
+        }
+
+        if self.options.delete {
+            for id in self.repo.database.loose_ids() {
+                if self.repo.database.is_packed(&id) {
+                    self.repo.database.remove_loose(&id)?;
+                }
+            }
+        }
+
+        Ok(Execution::Repack(RepackResult { total, deltas }))
+    }
+}
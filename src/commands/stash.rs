@@ -0,0 +1,539 @@
+use super::{write_commit, Command, Execution};
+use crate::{
+    database::TreeDiff,
+    diff::{self, FileDiff},
+    errors::RitError,
+    id::Id,
+    index, merge,
+    objects::{Author, Blob, TreeEntry},
+    repository::Repository,
+    stash::{StashError, Stashes},
+    workspace::matches_pathspec,
+    Session,
+};
+use std::{collections::BTreeMap, fmt};
+
+pub enum StashAction {
+    Push {
+        message: Option<String>,
+        include_untracked: bool,
+        pathspecs: Vec<String>,
+    },
+    List,
+    Show {
+        stash: Option<String>,
+        patch: bool,
+    },
+    Apply {
+        stash: Option<String>,
+        restore_index: bool,
+    },
+    Pop {
+        stash: Option<String>,
+        restore_index: bool,
+    },
+    Drop {
+        stash: Option<String>,
+    },
+    Clear,
+}
+
+pub struct Stash {
+    session: Session,
+    action: Option<StashAction>,
+    repo: Repository,
+    stashes: Stashes,
+}
+
+#[derive(Debug)]
+pub enum StashResult {
+    Saved(String),
+    NoLocalChanges,
+    List(Vec<String>),
+    Show(String),
+    Applied {
+        messages: Vec<String>,
+        is_conflicted: bool,
+        dropped: Option<(usize, Id)>,
+    },
+    Dropped(usize, Id),
+    Cleared,
+}
+
+impl StashResult {
+    pub fn is_conflicted(&self) -> bool {
+        matches!(
+            self,
+            StashResult::Applied {
+                is_conflicted: true,
+                ..
+            }
+        )
+    }
+}
+
+impl fmt::Display for StashResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StashResult::Saved(message) => {
+                write!(f, "Saved working directory and index state {}", message)
+            }
+            StashResult::NoLocalChanges => write!(f, "No local changes to save"),
+            StashResult::List(lines) => write!(f, "{}", lines.join("\n")),
+            StashResult::Show(text) => write!(f, "{}", text.trim_end()),
+            StashResult::Applied {
+                messages,
+                is_conflicted,
+                dropped,
+            } => {
+                let mut lines = messages.clone();
+
+                if *is_conflicted {
+                    lines.push("The stash entry is kept in case you need it again.".to_string());
+                }
+
+                if let Some((n, id)) = dropped {
+                    lines.push(format!("Dropped refs/stash@{{{}}} ({})", n, id));
+                }
+
+                write!(f, "{}", lines.join("\n"))
+            }
+            StashResult::Dropped(n, id) => write!(f, "Dropped refs/stash@{{{}}} ({})", n, id),
+            StashResult::Cleared => Ok(()),
+        }
+    }
+}
+
+impl Stash {
+    pub fn new(session: Session, action: StashAction) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+        let stashes = Stashes::new(&repo.git_path);
+
+        Self {
+            session,
+            action: Some(action),
+            repo,
+            stashes,
+        }
+    }
+
+    fn push(
+        &mut self,
+        message: Option<String>,
+        include_untracked: bool,
+        pathspecs: Vec<String>,
+    ) -> Result<StashResult, RitError> {
+        let head_id = self
+            .repo
+            .refs
+            .read_head()
+            .ok_or(StashError::NoInitialCommit)?;
+
+        let untracked = if include_untracked {
+            self.untracked_files()?
+        } else {
+            vec![]
+        };
+
+        self.repo.index.load_for_update()?;
+
+        if self.repo.index.is_conflicted() {
+            self.repo.index.release_lock()?;
+
+            return Err(RitError::UnmergedFiles);
+        }
+
+        let matches = |pathname: &str| {
+            pathspecs.is_empty()
+                || pathspecs
+                    .iter()
+                    .any(|spec| matches_pathspec(spec, pathname))
+        };
+
+        let index_items: BTreeMap<String, TreeEntry> = self
+            .repo
+            .index
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.pathname.clone(), TreeEntry::new(entry.id, entry.mode)))
+            .collect();
+        let untracked: Vec<String> = untracked.into_iter().filter(|path| matches(path)).collect();
+
+        for spec in &pathspecs {
+            let is_known = index_items
+                .keys()
+                .chain(untracked.iter())
+                .any(|path| matches_pathspec(spec, path));
+
+            if !is_known {
+                self.repo.index.release_lock()?;
+
+                return Err(RitError::MissingFile(spec.clone()));
+            }
+        }
+
+        let head_items = self.repo.database.flatten_tree(Some(&head_id))?;
+        let mut index_tree = head_items.clone();
+        let mut work_tree = head_items.clone();
+
+        index_tree.retain(|path, _| !matches(path));
+        work_tree.retain(|path, _| !matches(path));
+
+        for (pathname, item) in &index_items {
+            if !matches(pathname) {
+                continue;
+            }
+
+            index_tree.insert(pathname.clone(), item.clone());
+
+            if let Some(item) = self.store_workspace_file(pathname)? {
+                work_tree.insert(pathname.clone(), item);
+            }
+        }
+
+        let mut untracked_tree = BTreeMap::new();
+
+        for pathname in &untracked {
+            if let Some(item) = self.store_workspace_file(pathname)? {
+                untracked_tree.insert(pathname.clone(), item);
+            }
+        }
+
+        if index_tree == head_items && work_tree == head_items && untracked_tree.is_empty() {
+            self.repo.index.release_lock()?;
+
+            return Ok(StashResult::NoLocalChanges);
+        }
+
+        let head = self.repo.database.load_commit(&head_id)?;
+        let branch = match self.repo.refs.current_ref() {
+            Some(name) => self.repo.refs.short_name(&name).to_string(),
+            None => "(no branch)".to_string(),
+        };
+        let description = format!("{}: {} {}", branch, head_id.short(), head.title_line());
+
+        let index_id = self.store_commit(
+            vec![head_id.clone()],
+            &index_tree,
+            &format!("index on {}", description),
+        )?;
+
+        let mut parents = vec![head_id.clone(), index_id];
+
+        if !untracked_tree.is_empty() {
+            let untracked_message = format!("untracked files on {}", description);
+
+            parents.push(self.store_commit(vec![], &untracked_tree, &untracked_message)?);
+        }
+
+        let message = match message {
+            Some(message) => format!("On {}: {}", branch, message),
+            None => format!("WIP on {}", description),
+        };
+
+        let stash_id = self.store_commit(parents, &work_tree, &message)?;
+        self.stashes.push(&stash_id, self.author(), &message)?;
+
+        self.repo.reset_matching(&head_id, matches)?;
+
+        for pathname in untracked_tree.keys() {
+            self.repo.workspace.remove(pathname)?;
+        }
+
+        self.repo.index.write_updates()?;
+
+        Ok(StashResult::Saved(message))
+    }
+
+    fn list(&self) -> StashResult {
+        let lines = self
+            .stashes
+            .list()
+            .iter()
+            .enumerate()
+            .map(|(n, entry)| format!("stash@{{{}}}: {}", n, entry.message))
+            .collect();
+
+        StashResult::List(lines)
+    }
+
+    fn show(&self, stash: Option<String>, patch: bool) -> Result<StashResult, RitError> {
+        let n = Stashes::parse_name(stash.as_deref())?;
+        let stash_id = self.stashes.get(n)?;
+        let commit = self.repo.database.load_commit(&stash_id)?;
+
+        let changes = self
+            .repo
+            .database
+            .tree_diff(commit.parent(), Some(&stash_id))?;
+
+        let diffs = changes
+            .into_iter()
+            .map(|(path, (a, b))| FileDiff::new(&self.repo.database, &path, a, b))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let text = if patch {
+            diffs.iter().map(FileDiff::to_patch).collect()
+        } else {
+            diff::format_stat(&diffs)
+        };
+
+        Ok(StashResult::Show(text))
+    }
+
+    fn apply(
+        &mut self,
+        stash: Option<String>,
+        restore_index: bool,
+        is_pop: bool,
+    ) -> Result<StashResult, RitError> {
+        let n = Stashes::parse_name(stash.as_deref())?;
+        let stash_id = self.stashes.get(n)?;
+        let commit = self.repo.database.load_commit(&stash_id)?;
+
+        let base_id = commit
+            .parent()
+            .cloned()
+            .ok_or_else(|| StashError::InvalidStash(format!("stash@{{{}}}", n)))?;
+        let index_id = commit.parents.get(1).cloned();
+        let untracked_id = commit.parents.get(2).cloned();
+
+        let work_diff = self
+            .repo
+            .database
+            .tree_diff(Some(&base_id), Some(&stash_id))?;
+        let untracked_items = self.repo.database.flatten_tree(untracked_id.as_ref())?;
+
+        self.check_workspace(&work_diff, &untracked_items)?;
+
+        self.repo.index.load_for_update()?;
+
+        if self.repo.index.is_conflicted() {
+            self.repo.index.release_lock()?;
+
+            return Err(RitError::UnmergedFiles);
+        }
+
+        let index_diff = match (&index_id, restore_index) {
+            (Some(index_id), true) => self
+                .repo
+                .database
+                .tree_diff(Some(&base_id), Some(index_id))?,
+            _ => TreeDiff::new(),
+        };
+
+        let is_index_clean = index_diff.iter().all(|(path, (old, _))| {
+            let current = self.repo.index.entry_for_path(path);
+
+            match (current, old) {
+                (Some(entry), Some(old)) => entry.id == old.id && entry.mode == old.mode,
+                (None, None) => true,
+                _ => false,
+            }
+        });
+
+        if !is_index_clean {
+            self.repo.index.release_lock()?;
+
+            return Err(StashError::IndexConflicts.into());
+        }
+
+        let left_id = write_commit::write_tree(&mut self.repo)?;
+        let left_items = self.repo.database.flatten_tree(Some(&left_id))?;
+
+        let inputs = merge::Inputs {
+            left_name: "Updated upstream".to_string(),
+            right_name: "Stashed changes".to_string(),
+            base_id: Some(base_id),
+            left_id,
+            right_id: stash_id.clone(),
+        };
+
+        let messages = merge::Resolve::new(&mut self.repo, inputs).execute()?;
+
+        for (pathname, item) in &untracked_items {
+            let blob = self.repo.database.load_blob(&item.id)?;
+
+            self.repo
+                .workspace
+                .write_file(pathname, &blob.data, item.mode)?;
+        }
+
+        if self.repo.index.is_conflicted() {
+            self.repo.index.write_updates()?;
+
+            return Ok(StashResult::Applied {
+                messages,
+                is_conflicted: true,
+                dropped: None,
+            });
+        }
+
+        for pathname in work_diff.keys() {
+            if let Some(item) = left_items.get(pathname) {
+                self.repo.index.add_from_db(pathname, item);
+            } else if self.repo.workspace.stat_path(pathname).is_none() {
+                self.repo.index.remove(pathname);
+            }
+        }
+
+        for (pathname, (_, new)) in &index_diff {
+            match new {
+                Some(item) => self.repo.index.add_from_db(pathname, item),
+                None => self.repo.index.remove(pathname),
+            }
+        }
+
+        self.repo.index.write_updates()?;
+
+        let dropped = if is_pop {
+            Some((n, self.stashes.drop(n)?))
+        } else {
+            None
+        };
+
+        Ok(StashResult::Applied {
+            messages,
+            is_conflicted: false,
+            dropped,
+        })
+    }
+
+    /// Refuses to apply a stash over local changes to the paths it touches
+    /// or over untracked files it would replace.
+    fn check_workspace(
+        &mut self,
+        work_diff: &TreeDiff,
+        untracked_items: &BTreeMap<String, TreeEntry>,
+    ) -> Result<(), RitError> {
+        let status = match super::Status::new(self.session.clone()).execute()? {
+            Execution::Status(status) => status,
+            _ => return Ok(()),
+        };
+
+        let changed: Vec<String> = status
+            .modified
+            .iter()
+            .chain(status.deleted.iter())
+            .map(|entry| entry.relative_path_name.clone())
+            .filter(|path| work_diff.contains_key(path))
+            .collect();
+
+        if !changed.is_empty() {
+            return Err(StashError::LocalChanges(changed).into());
+        }
+
+        self.repo.index.load()?;
+
+        let overwritten: Vec<String> = work_diff
+            .iter()
+            .filter(|(path, (_, new))| new.is_some() && !self.repo.index.is_tracked_file(path))
+            .map(|(path, _)| path.clone())
+            .chain(untracked_items.keys().cloned())
+            .filter(|path| self.repo.workspace.stat_path(path).is_some())
+            .collect();
+
+        if !overwritten.is_empty() {
+            return Err(StashError::UntrackedFiles(overwritten).into());
+        }
+
+        Ok(())
+    }
+
+    /// Lists the untracked files in the workspace, expanding untracked
+    /// directories into the files they contain.
+    fn untracked_files(&self) -> Result<Vec<String>, RitError> {
+        let status = match super::Status::new(self.session.clone()).execute()? {
+            Execution::Status(status) => status,
+            _ => return Ok(vec![]),
+        };
+
+        let mut paths: Vec<String> = status
+            .untracked
+            .iter()
+            .flat_map(|entry| self.repo.workspace.list_files(Some(&entry.absolute_path)))
+            .map(|entry| entry.relative_path_name)
+            .collect();
+
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    fn store_workspace_file(&self, pathname: &str) -> Result<Option<TreeEntry>, RitError> {
+        let entry = self.repo.workspace.entry(pathname);
+
+        match self.repo.workspace.stat_path(pathname) {
+            Some(stat) if !stat.is_dir() => {}
+            _ => return Ok(None),
+        }
+
+        let (data, stat) = self.repo.workspace.read_data(&entry)?;
+        let mode = index::Entry::mode_for_stat(&stat);
+
+        let mut blob = Blob::parse(data.into());
+        let id = self.repo.database.store(&mut blob)?;
+
+        Ok(Some(TreeEntry::new(id, mode)))
+    }
+
+    fn store_commit(
+        &self,
+        parents: Vec<Id>,
+        items: &BTreeMap<String, TreeEntry>,
+        message: &str,
+    ) -> Result<Id, RitError> {
+        let tree_id = write_commit::write_tree_from(&self.repo.database, items)?;
+        let author = self.author();
+
+        write_commit::store_commit(
+            &self.repo,
+            parents,
+            tree_id,
+            author.clone(),
+            author,
+            message,
+        )
+    }
+
+    fn author(&self) -> Author {
+        Author::new(&self.session.author_name, &self.session.author_email)
+    }
+
+    fn drop(&self, stash: Option<String>) -> Result<StashResult, RitError> {
+        let n = Stashes::parse_name(stash.as_deref())?;
+        let id = self.stashes.drop(n)?;
+
+        Ok(StashResult::Dropped(n, id))
+    }
+}
+
+impl Command for Stash {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let result = match self.action.take() {
+            Some(StashAction::Push {
+                message,
+                include_untracked,
+                pathspecs,
+            }) => self.push(message, include_untracked, pathspecs),
+            Some(StashAction::List) => Ok(self.list()),
+            Some(StashAction::Show { stash, patch }) => self.show(stash, patch),
+            Some(StashAction::Apply {
+                stash,
+                restore_index,
+            }) => self.apply(stash, restore_index, false),
+            Some(StashAction::Pop {
+                stash,
+                restore_index,
+            }) => self.apply(stash, restore_index, true),
+            Some(StashAction::Drop { stash }) => self.drop(stash),
+            Some(StashAction::Clear) | None => {
+                self.stashes.clear()?;
+
+                Ok(StashResult::Cleared)
+            }
+        }?;
+
+        Ok(Execution::Stash(result))
+    }
+}
@@ -1,12 +1,16 @@
 use super::{Command, Execution};
 use crate::{
     errors::RitError,
-    index,
+    fsmonitor,
+    index::{self, UntrackedCache, UntrackedDir},
     objects::{Blob, Storable},
     repository::Repository,
-    workspace, Session,
+    submodule, workspace, Session,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
 };
-use std::{collections::HashMap, path::PathBuf};
 
 pub struct Status {
     session: Session,
@@ -14,7 +18,7 @@ pub struct Status {
     untracked: Vec<workspace::Entry>,
     modified: Vec<workspace::Entry>,
     deleted: Vec<workspace::Entry>,
-    stats: HashMap<String, workspace::Stat>,
+    tracked_dirs: BTreeMap<String, BTreeSet<String>>,
 }
 
 #[derive(Debug)]
@@ -40,15 +44,36 @@ impl Status {
             untracked: vec![],
             modified: vec![],
             deleted: vec![],
-            stats: HashMap::new(),
+            tracked_dirs: BTreeMap::new(),
         }
     }
 
     fn detect_workspace_changes(&mut self) {
         for index_entry in self.repo.index.entries() {
+            if index_entry.stage() > 0 || index_entry.is_fsmonitor_valid() {
+                continue;
+            }
+
             let workspace_entry = self.build_workspace_entry(&index_entry);
+            let stat = self
+                .repo
+                .workspace
+                .stat_path(&index_entry.pathname)
+                .filter(|stat| !stat.is_dir() || index_entry.is_gitlink());
+
+            if index_entry.is_gitlink() {
+                match &stat {
+                    Some(stat) if !self.is_gitlink_unchanged(&index_entry, stat) => {
+                        self.modified.push(workspace_entry)
+                    }
+                    Some(_) => {}
+                    None => self.deleted.push(workspace_entry),
+                }
 
-            match self.stats.get(&index_entry.pathname) {
+                continue;
+            }
+
+            match &stat {
                 Some(stat) => {
                     match self.detect_entry_changes(&index_entry, &workspace_entry, stat) {
                         EntryChange::Changed => {
@@ -58,8 +83,9 @@ impl Status {
                             self.repo
                                 .index
                                 .update_entry_stat(&index_entry.pathname, stat);
+                            self.mark_unchanged(&index_entry.pathname);
                         }
-                        _ => {}
+                        EntryChange::Unchanged => self.mark_unchanged(&index_entry.pathname),
                     };
                 }
                 None => self.deleted.push(workspace_entry),
@@ -67,6 +93,41 @@ impl Status {
         }
     }
 
+    /// Asks the filesystem monitor what changed since the last status if
+    /// `core.fsmonitor` is set, so that the other entries need no stat.
+    /// Without an answer, every entry is checked.
+    fn query_fsmonitor(&mut self) {
+        let changes = match self.repo.config.get_bool("core.fsmonitor") {
+            Some(true) => {
+                let token = self.repo.index.fsmonitor_token().unwrap_or_default();
+
+                fsmonitor::query(&self.repo.git_path, token).ok()
+            }
+            _ => None,
+        };
+
+        let changes = match changes {
+            Some(changes) => changes,
+            None => return self.repo.index.set_fsmonitor_token(None),
+        };
+
+        for entry in self.repo.index.entries() {
+            if entry.is_fsmonitor_valid() && changes.includes(&entry.pathname) {
+                self.repo.index.set_fsmonitor_valid(&entry.pathname, false);
+            }
+        }
+
+        self.repo.index.set_fsmonitor_token(Some(changes.token));
+    }
+
+    /// Remembers that the file of an entry was found unchanged, for the
+    /// filesystem monitor to tell if it stays so.
+    fn mark_unchanged(&mut self, pathname: &str) {
+        if self.repo.index.fsmonitor_token().is_some() {
+            self.repo.index.set_fsmonitor_valid(pathname, true);
+        }
+    }
+
     fn detect_entry_changes(
         &self,
         index_entry: &index::Entry,
@@ -81,8 +142,8 @@ impl Status {
             return EntryChange::Unchanged;
         }
 
-        let file = self.repo.workspace.read_file(&workspace_entry).unwrap();
-        let mut blob = Blob::new(file);
+        let (data, _) = self.repo.workspace.read_data(workspace_entry).unwrap();
+        let mut blob = Blob::parse(data.into());
 
         let id = blob.store(|(_, _)| {}).unwrap();
 
@@ -93,55 +154,225 @@ impl Status {
         EntryChange::Changed
     }
 
+    /// Whether the submodule of a gitlink still has the recorded commit
+    /// checked out. One that was never cloned counts as unchanged.
+    fn is_gitlink_unchanged(&self, index_entry: &index::Entry, stat: &workspace::Stat) -> bool {
+        if !stat.is_dir() {
+            return false;
+        }
+
+        let path = self.session.project_dir.join(&index_entry.path);
+
+        match submodule::head(&path) {
+            Some(head) => head == index_entry.id,
+            None => !self.repo.workspace.is_repository(&index_entry.pathname),
+        }
+    }
+
+    /// The workspace entry for a tracked path, which is listed without a
+    /// trailing slash even when a directory is found there.
     fn build_workspace_entry(&self, index_entry: &index::Entry) -> workspace::Entry {
         let absolute_path = self.session.project_dir.join(&index_entry.path);
+        let mut entry = workspace::Entry::new(absolute_path, index_entry.path.clone());
+        entry.is_dir = false;
 
-        workspace::Entry::new(absolute_path, index_entry.path.clone())
+        entry
     }
 
-    fn scan_workspace(&mut self) {
-        self.do_scan_workspace(None);
+    /// Lists the untracked files and directories, reusing what the
+    /// untracked cache recorded for the directories that did not change.
+    fn scan_workspace(&mut self, cached: Option<&UntrackedDir>) -> Option<UntrackedDir> {
+        for entry in self.repo.index.entries() {
+            for dir in entry.parents() {
+                let parent = dir.parent().unwrap().to_string_lossy().to_string();
+                let name = dir.file_name().unwrap().to_string_lossy().to_string();
+
+                self.tracked_dirs.entry(parent).or_default().insert(name);
+            }
+        }
+
+        let root = self.scan_dir("", cached, false);
 
         self.untracked
             .sort_by_key(|entry| entry.relative_path.clone());
+
+        root
     }
 
-    fn do_scan_workspace(&mut self, prefix: Option<&PathBuf>) {
-        for (entry, stat) in self.repo.workspace.list_dir(prefix) {
-            if self.repo.index.is_tracked(&entry.relative_path_name) {
-                if entry.is_dir {
-                    self.do_scan_workspace(Some(&entry.absolute_path));
-                } else {
-                    self.stats.insert(entry.relative_path_name, stat);
+    /// Finds the untracked entries of the directory at `pathname`, or only
+    /// whether it has any if `check_only` is set, as happens for untracked
+    /// directories. Returns what the untracked cache should record for it.
+    fn scan_dir(
+        &mut self,
+        pathname: &str,
+        cached: Option<&UntrackedDir>,
+        check_only: bool,
+    ) -> Option<UntrackedDir> {
+        let stat = self
+            .repo
+            .workspace
+            .stat_path(pathname)
+            .filter(|stat| stat.is_dir())?;
+
+        let dir = cached
+            .filter(|cached| cached.is_fresh(&stat, check_only))
+            .and_then(|cached| self.revalidate_dir(pathname, cached, &stat))
+            .unwrap_or_else(|| self.list_dir(pathname, cached, &stat, check_only));
+
+        if !check_only {
+            for name in &dir.untracked {
+                let relative_path = join(pathname, name.trim_end_matches('/'));
+                self.untracked
+                    .push(self.repo.workspace.entry(&relative_path));
+            }
+        }
+
+        Some(dir)
+    }
+
+    /// Reuses the untracked entries recorded for an unchanged directory,
+    /// checking again the directories below it, which can change without
+    /// changing it. Returns `None` if one of its untracked directories is
+    /// now empty, as the rest of the directory then needs listing again.
+    fn revalidate_dir(
+        &mut self,
+        pathname: &str,
+        cached: &UntrackedDir,
+        stat: &workspace::Stat,
+    ) -> Option<UntrackedDir> {
+        let mut dir = UntrackedDir::new(stat, cached.check_only);
+
+        for name in &cached.untracked {
+            let is_dir = name
+                .strip_suffix('/')
+                .filter(|name| !self.repo.workspace.is_repository(&join(pathname, name)));
+
+            if let Some(name) = is_dir {
+                let child =
+                    self.scan_dir(&join(pathname, name), cached.children.get(name), true)?;
+
+                if child.untracked.is_empty() {
+                    return None;
                 }
-            } else if self.is_trackable_entry(&entry) {
-                self.untracked.push(entry);
+
+                dir.children.insert(name.to_string(), child);
+            }
+
+            dir.untracked.push(name.clone());
+        }
+
+        for name in self.tracked_dirs.get(pathname).cloned().unwrap_or_default() {
+            if let Some(child) =
+                self.scan_dir(&join(pathname, &name), cached.children.get(&name), false)
+            {
+                dir.children.insert(name, child);
             }
         }
+
+        Some(dir)
     }
 
-    fn is_trackable_entry(&self, entry: &workspace::Entry) -> bool {
-        if !entry.is_dir {
-            return !self.repo.index.is_tracked(&entry.relative_path_name);
+    fn list_dir(
+        &mut self,
+        pathname: &str,
+        cached: Option<&UntrackedDir>,
+        stat: &workspace::Stat,
+        check_only: bool,
+    ) -> UntrackedDir {
+        let mut dir = UntrackedDir::new(stat, check_only);
+        let path = self.session.project_dir.join(pathname);
+
+        let mut entries = self.repo.workspace.list_dir(Some(&path));
+        entries.sort_by_key(|(entry, _)| entry.is_dir);
+
+        for (entry, _) in entries {
+            let cached = cached.and_then(|cached| cached.children.get(&entry.name));
+
+            if entry.is_dir && self.repo.workspace.is_repository(&entry.relative_path_name) {
+                // a nested repository is listed as a whole, even if empty,
+                // and its files are left to it
+                if !self.repo.index.is_tracked(&entry.relative_path_name) {
+                    dir.untracked.push(format!("{}/", entry.name));
+                }
+            } else if self.repo.index.is_tracked(&entry.relative_path_name) {
+                if entry.is_dir {
+                    if let Some(child) = self.scan_dir(&entry.relative_path_name, cached, false) {
+                        dir.children.insert(entry.name, child);
+                    }
+                }
+            } else if entry.is_dir {
+                if let Some(child) = self.scan_dir(&entry.relative_path_name, cached, true) {
+                    if !child.untracked.is_empty() {
+                        dir.untracked.push(format!("{}/", entry.name));
+                    }
+
+                    dir.children.insert(entry.name, child);
+                }
+            } else {
+                dir.untracked.push(entry.name);
+            }
+
+            if check_only && !dir.untracked.is_empty() {
+                break;
+            }
         }
 
-        let mut nested_entries = self.repo.workspace.list_dir(Some(&entry.absolute_path));
+        dir
+    }
+
+    /// The untracked cache to use, depending on `core.untrackedCache`: one
+    /// is created if it is `true` and dropped if it is `false`, while
+    /// otherwise one that is already there is used.
+    fn untracked_cache(&self) -> Option<Option<UntrackedCache>> {
+        let worktree = fs::canonicalize(&self.session.project_dir)
+            .unwrap_or_else(|_| self.session.project_dir.clone());
 
-        nested_entries.sort_by_key(|(entry, _)| entry.is_dir);
+        let existing = self
+            .repo
+            .index
+            .untracked_cache()
+            .filter(|cache| cache.is_usable(&worktree))
+            .cloned();
 
-        nested_entries
-            .iter()
-            .any(|(entry, _)| self.is_trackable_entry(entry))
+        match self.repo.config.get("core.untrackedCache").as_deref() {
+            None | Some("keep") => existing.map(Some),
+            Some(_) => match self.repo.config.get_bool("core.untrackedCache") {
+                Some(true) => Some(existing.or_else(|| Some(UntrackedCache::new(&worktree)))),
+                _ => Some(None),
+            },
+        }
+    }
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
     }
 }
 
 impl Command for Status {
     fn execute(&mut self) -> Result<Execution, RitError> {
         self.repo.index.load_for_update()?;
+        self.query_fsmonitor();
+
+        let mut untracked_cache = self.untracked_cache();
+        let cached = untracked_cache
+            .as_ref()
+            .and_then(|cache| cache.as_ref()?.root().cloned());
 
-        self.scan_workspace();
+        let root = self.scan_workspace(cached.as_ref());
         self.detect_workspace_changes();
 
+        if let Some(mut cache) = untracked_cache.take() {
+            if let (Some(cache), Some(root)) = (&mut cache, root) {
+                cache.set_root(root);
+            }
+
+            self.repo.index.set_untracked_cache(cache);
+        }
+
         self.repo.index.write_updates()?;
 
         // TODO: -clone
@@ -0,0 +1,268 @@
+use super::{
+    clone::default_directory, Add, Clone, CloneOptions, Command, Execution, Fetch, FetchOptions,
+};
+use crate::{
+    errors::RitError,
+    id::Id,
+    index,
+    objects::Author,
+    refs::HEAD,
+    repository::Repository,
+    submodule::{self, Gitmodules, GITMODULES},
+    Session,
+};
+use std::fmt;
+
+const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+pub enum SubmoduleAction {
+    Add { url: String, path: Option<String> },
+    Init,
+    Update { init: bool },
+    Status,
+}
+
+/// Manages the repositories nested in the worktree as submodules. Each is
+/// staged as a gitlink to the commit it should have checked out, and named
+/// in `.gitmodules` along with the url to clone it from. `init` copies that
+/// url into the config, where `update` finds it to clone the submodule if
+/// needed and check out the recorded commit on a detached HEAD.
+pub struct Submodule {
+    session: Session,
+    action: Option<SubmoduleAction>,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub struct SubmoduleResult {
+    lines: Vec<String>,
+}
+
+impl fmt::Display for SubmoduleResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines.join("\n"))
+    }
+}
+
+impl Submodule {
+    pub fn new(session: Session, action: SubmoduleAction) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+
+        Self {
+            session,
+            action: Some(action),
+            repo,
+        }
+    }
+
+    /// The gitlinks of the index, once for each path even when conflicted.
+    fn gitlinks(&mut self) -> Result<Vec<index::Entry>, RitError> {
+        self.repo.index.load()?;
+
+        let mut entries: Vec<index::Entry> = self
+            .repo
+            .index
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.is_gitlink())
+            .collect();
+
+        entries.dedup_by(|a, b| a.pathname == b.pathname);
+
+        Ok(entries)
+    }
+
+    /// Clones `url` into `path` and stages it, recording it in
+    /// `.gitmodules`.
+    fn add(&mut self, url: &str, path: Option<&str>) -> Result<Vec<String>, RitError> {
+        let path = path
+            .map(|path| path.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| default_directory(url));
+
+        self.repo.index.load()?;
+
+        if self.repo.index.is_tracked(&path) {
+            return Err(RitError::Submodule(format!(
+                "'{}' already exists in the index",
+                path
+            )));
+        }
+
+        let options = CloneOptions {
+            source: url.to_string(),
+            directory: Some(path.clone()),
+            ..Default::default()
+        };
+        Clone::new(self.session.clone(), options).execute()?;
+
+        let mut gitmodules = Gitmodules::load(&self.session.project_dir);
+        gitmodules.add(&path, &path, url)?;
+        self.register(&path, url)?;
+
+        Add::new(self.session.clone(), vec![GITMODULES.to_string(), path]).execute()?;
+
+        Ok(vec![])
+    }
+
+    /// Copies the urls of the submodules from `.gitmodules` into the config,
+    /// leaving alone those that are there already.
+    fn init(&mut self) -> Result<Vec<String>, RitError> {
+        let gitmodules = Gitmodules::load(&self.session.project_dir);
+        let mut lines = vec![];
+
+        for entry in self.gitlinks()? {
+            let name = gitmodules.name_for_path(&entry.pathname);
+
+            if self.url(&name).is_some() {
+                continue;
+            }
+
+            let url = gitmodules.url(&name).ok_or_else(|| {
+                RitError::Submodule(format!(
+                    "No url found for submodule path '{}' in .gitmodules",
+                    entry.pathname
+                ))
+            })?;
+
+            self.register(&name, &url)?;
+
+            lines.push(format!(
+                "Submodule '{}' ({}) registered for path '{}'",
+                name, url, entry.pathname
+            ));
+        }
+
+        Ok(lines)
+    }
+
+    /// Checks out the recorded commit in each initialized submodule, cloning
+    /// the ones that are missing.
+    fn update(&mut self, init: bool) -> Result<Vec<String>, RitError> {
+        let mut lines = if init { self.init()? } else { vec![] };
+        let gitmodules = Gitmodules::load(&self.session.project_dir);
+
+        for entry in self.gitlinks()? {
+            if entry.stage() > 0 {
+                continue;
+            }
+
+            let name = gitmodules.name_for_path(&entry.pathname);
+
+            let url = match self.url(&name) {
+                Some(url) => url,
+                None => continue,
+            };
+
+            if !self.repo.workspace.is_repository(&entry.pathname) {
+                let options = CloneOptions {
+                    source: url,
+                    directory: Some(entry.pathname.clone()),
+                    ..Default::default()
+                };
+                Clone::new(self.session.clone(), options).execute()?;
+            }
+
+            if self.checkout(&entry.pathname, &entry.id)? {
+                lines.push(format!(
+                    "Submodule path '{}': checked out '{}'",
+                    entry.pathname, entry.id.as_str
+                ));
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Detaches the HEAD of the submodule at `pathname` at commit `id`,
+    /// fetching it first if it is missing. Returns whether anything changed.
+    fn checkout(&self, pathname: &str, id: &Id) -> Result<bool, RitError> {
+        let mut repo = self.open(pathname)?;
+
+        if repo.refs.current_ref().is_none() && repo.refs.read_head().as_ref() == Some(id) {
+            return Ok(false);
+        }
+
+        if !repo.database.exists(id) {
+            let session = Session {
+                project_dir: self.session.project_dir.join(pathname),
+                ..self.session.clone()
+            };
+            Fetch::new(session, FetchOptions::default()).execute()?;
+
+            repo = self.open(pathname)?;
+
+            if !repo.database.exists(id) {
+                return Err(RitError::Submodule(format!(
+                    "Fetched in submodule path '{}', but it did not contain {}",
+                    pathname, id.as_str
+                )));
+            }
+        }
+
+        repo.index.load_for_update()?;
+        repo.hard_reset(id)?;
+        repo.index.write_updates()?;
+        let committer = Author::new(&self.session.author_name, &self.session.author_email);
+        let message = format!("submodule update: checkout {}", id.as_str);
+        repo.refs
+            .update_ref_logged(HEAD, id, &committer, &message)?;
+
+        Ok(true)
+    }
+
+    /// Lists each submodule with the commit the index records for it, or
+    /// the one checked out with a `+` when they differ. A `-` marks one that
+    /// is not checked out and a `U` one with merge conflicts.
+    fn status(&mut self) -> Result<Vec<String>, RitError> {
+        let mut lines = vec![];
+
+        for entry in self.gitlinks()? {
+            let path = self.session.project_dir.join(&entry.path);
+
+            let (prefix, id) = match submodule::head(&path) {
+                _ if entry.stage() > 0 => ('U', NULL_ID.to_string()),
+                None => ('-', entry.id.as_str.clone()),
+                Some(head) if head != entry.id => ('+', head.as_str),
+                Some(_) => (' ', entry.id.as_str.clone()),
+            };
+
+            lines.push(format!("{}{} {}", prefix, id, entry.pathname));
+        }
+
+        Ok(lines)
+    }
+
+    fn url(&self, name: &str) -> Option<String> {
+        self.repo.config.get(&format!("submodule.{}.url", name))
+    }
+
+    fn register(&mut self, name: &str, url: &str) -> Result<(), RitError> {
+        let config = &mut self.repo.config.local;
+        config.set(&format!("submodule.{}.active", name), "true")?;
+        config.set(&format!("submodule.{}.url", name), url)?;
+        config.save()?;
+
+        Ok(())
+    }
+
+    fn open(&self, pathname: &str) -> Result<Repository, RitError> {
+        let path = self.session.project_dir.join(pathname);
+        let git_path = submodule::git_dir(&path)
+            .ok_or_else(|| RitError::Submodule(format!("not a git repository: '{}'", pathname)))?;
+
+        Ok(Repository::with_git_path(path, git_path))
+    }
+}
+
+impl Command for Submodule {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let lines = match self.action.take() {
+            Some(SubmoduleAction::Add { url, path }) => self.add(&url, path.as_deref())?,
+            Some(SubmoduleAction::Init) => self.init()?,
+            Some(SubmoduleAction::Update { init }) => self.update(init)?,
+            Some(SubmoduleAction::Status) | None => self.status()?,
+        };
+
+        Ok(Execution::Submodule(SubmoduleResult { lines }))
+    }
+}
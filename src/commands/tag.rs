@@ -0,0 +1,307 @@
+use super::{Command, Execution};
+use crate::{
+    editor,
+    errors::RitError,
+    id::Id,
+    objects::{self, Author},
+    refs::HEAD,
+    repository::Repository,
+    revision::Revision,
+    Session,
+};
+use std::{cmp::Ordering, fmt};
+
+const TAGS_PREFIX: &str = "refs/tags";
+
+pub enum TagAction {
+    Create {
+        name: String,
+        target: Option<String>,
+        message: Option<String>,
+        annotate: bool,
+        force: bool,
+    },
+    List {
+        patterns: Vec<String>,
+        sort: TagSort,
+    },
+    Delete {
+        names: Vec<String>,
+    },
+}
+
+/// The order of `tag --list`: by name, or by treating the digit runs in the
+/// names as numbers with `version:refname`, optionally reversed with `-`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TagSort {
+    version: bool,
+    reverse: bool,
+}
+
+impl TagSort {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (reverse, key) = match spec.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, spec),
+        };
+
+        let version = match key {
+            "refname" => false,
+            "version:refname" | "v:refname" => true,
+            _ => return None,
+        };
+
+        Some(Self { version, reverse })
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ordering = if self.version {
+            version_cmp(a, b)
+        } else {
+            a.cmp(b)
+        };
+
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+pub struct Tag {
+    session: Session,
+    action: Option<TagAction>,
+    repo: Repository,
+}
+
+#[derive(Debug)]
+pub enum TagResult {
+    Created,
+    List(Vec<String>),
+    Deleted(Vec<(String, Id)>),
+}
+
+impl fmt::Display for TagResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagResult::Created => Ok(()),
+            TagResult::List(names) => write!(f, "{}", names.join("\n")),
+            TagResult::Deleted(tags) => {
+                let lines: Vec<String> = tags
+                    .iter()
+                    .map(|(name, id)| format!("Deleted tag '{}' (was {})", name, id.short()))
+                    .collect();
+
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+impl Tag {
+    pub fn new(session: Session, action: TagAction) -> Self {
+        let repo = Repository::new(session.project_dir.clone());
+
+        Self {
+            session,
+            action: Some(action),
+            repo,
+        }
+    }
+
+    /// Creates a lightweight tag pointing straight at the target, or an
+    /// annotated one pointing at a new tag object when there is a message.
+    fn create(
+        &self,
+        name: &str,
+        target: Option<String>,
+        message: Option<String>,
+        annotate: bool,
+        force: bool,
+    ) -> Result<TagResult, RitError> {
+        let target = target.unwrap_or_else(|| HEAD.to_string());
+        let id = Revision::new(&self.repo, &target).resolve_object()?;
+
+        let id = if annotate || message.is_some() {
+            let message = match message {
+                Some(message) => message,
+                None => editor::edit_message(&self.repo, "")?,
+            };
+
+            if message.trim().is_empty() {
+                return Err(RitError::EmptyTagMessage);
+            }
+
+            let (kind, _) = self.repo.database.read_object(&id)?;
+            let tagger = Author::new(&self.session.author_name, &self.session.author_email);
+            let mut tag = objects::Tag::new(id, &kind, name, tagger, &message);
+
+            self.repo.database.store(&mut tag)?
+        } else {
+            id
+        };
+
+        self.repo.refs.create_tag(name, &id, force)?;
+
+        Ok(TagResult::Created)
+    }
+
+    fn list(&self, patterns: &[String], sort: TagSort) -> TagResult {
+        let mut names: Vec<String> = self
+            .repo
+            .refs
+            .list_refs(TAGS_PREFIX)
+            .iter()
+            .map(|name| self.repo.refs.short_name(name).to_string())
+            .filter(|name| patterns.is_empty() || patterns.iter().any(|p| glob_match(p, name)))
+            .collect();
+
+        names.sort_by(|a, b| sort.compare(a, b));
+
+        TagResult::List(names)
+    }
+
+    fn delete(&self, names: &[String]) -> Result<TagResult, RitError> {
+        let mut deleted = vec![];
+
+        for name in names {
+            let id = self.repo.refs.delete_tag(name)?;
+            deleted.push((name.clone(), id));
+        }
+
+        Ok(TagResult::Deleted(deleted))
+    }
+}
+
+/// Matches `name` against a shell glob supporting `*`, `?` and `[...]`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    glob_match_from(&pattern, &name)
+}
+
+fn glob_match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| glob_match_from(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && glob_match_from(&pattern[1..], &name[1..]),
+        Some('[') => match (pattern.iter().position(|&c| c == ']'), name.first()) {
+            (Some(end), Some(&c)) if end > 1 => {
+                class_match(&pattern[1..end], c) && glob_match_from(&pattern[end + 1..], &name[1..])
+            }
+            _ => name.first() == Some(&'[') && glob_match_from(&pattern[1..], &name[1..]),
+        },
+        Some(&c) => name.first() == Some(&c) && glob_match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+fn class_match(class: &[char], c: char) -> bool {
+    let (negate, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            matched |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    matched != negate
+}
+
+/// Compares names chunk by chunk, where runs of digits compare as numbers so
+/// that `v1.10` sorts after `v1.9`.
+fn version_cmp(a: &str, b: &str) -> Ordering {
+    let a_chunks = chunks(a);
+    let b_chunks = chunks(b);
+
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let is_number = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+
+        let ordering = if is_number(x) && is_number(y) {
+            let x = x.trim_start_matches('0');
+            let y = y.trim_start_matches('0');
+
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            x.cmp(y)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+fn chunks(name: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+
+    for (i, c) in name.char_indices().skip(1) {
+        let prev = name[..i].chars().last().unwrap_or(c);
+
+        if prev.is_ascii_digit() != c.is_ascii_digit() {
+            chunks.push(&name[start..i]);
+            start = i;
+        }
+    }
+
+    if start < name.len() {
+        chunks.push(&name[start..]);
+    }
+
+    chunks
+}
+
+impl Command for Tag {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let result = match self.action.take() {
+            Some(TagAction::Create {
+                name,
+                target,
+                message,
+                annotate,
+                force,
+            }) => self.create(&name, target, message, annotate, force)?,
+            Some(TagAction::Delete { names }) => self.delete(&names)?,
+            Some(TagAction::List { patterns, sort }) => self.list(&patterns, sort),
+            None => self.list(&[], TagSort::default()),
+        };
+
+        Ok(Execution::Tag(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_globs() {
+        assert!(glob_match("v1.*", "v1.10"));
+        assert!(glob_match("v?.0", "v2.0"));
+        assert!(glob_match("v[0-2]*", "v2.5"));
+        assert!(!glob_match("v[!0-2]*", "v2.5"));
+        assert!(!glob_match("v1.*", "v2.0"));
+    }
+
+    #[test]
+    fn it_compares_versions_numerically() {
+        let mut names = vec!["v1.10", "v1.9", "v1.2.1", "v1.2", "v10.0"];
+        names.sort_by(|a, b| version_cmp(a, b));
+
+        assert_eq!(vec!["v1.2", "v1.2.1", "v1.9", "v1.10", "v10.0"], names);
+    }
+}
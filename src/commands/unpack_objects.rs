@@ -0,0 +1,37 @@
+use super::{Command, Execution};
+use crate::{errors::RitError, objects::Raw, pack::Indexer, repository::Repository, Session};
+
+/// Stores every object of a pack read from standard input as a loose object.
+/// Deltas may be based on objects the repository already has.
+pub struct UnpackObjects {
+    data: Option<Vec<u8>>,
+    repo: Repository,
+}
+
+impl UnpackObjects {
+    pub fn new(session: Session, data: Vec<u8>) -> Self {
+        let repo = Repository::new(session.project_dir);
+
+        Self {
+            data: Some(data),
+            repo,
+        }
+    }
+}
+
+impl Command for UnpackObjects {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let data = self.data.take().unwrap_or_default();
+        let database = &self.repo.database;
+
+        let indexer = Indexer::new(data, |id| database.read_object(id).ok())
+            .map_err(|err| RitError::InvalidPack(err.to_string()))?;
+
+        for object in indexer.objects() {
+            let mut raw = Raw::new(object.kind_name(), object.data.clone());
+            database.store(&mut raw)?;
+        }
+
+        Ok(Execution::Empty)
+    }
+}
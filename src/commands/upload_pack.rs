@@ -0,0 +1,242 @@
+use super::{repack::pack_writer, Command, Execution};
+use crate::{
+    errors::RitError,
+    id::Id,
+    objects::Parsed,
+    pack::{self, PackObject},
+    refs::HEAD,
+    remote::{self, pkt_line, protocol_error, unexpected, Advertisement, ServiceMode},
+    repository::Repository,
+    rev_list::RevList,
+    Session,
+};
+use std::{
+    io::{self, prelude::*},
+    path::PathBuf,
+};
+
+const CAPABILITIES: [&str; 1] = ["ofs-delta"];
+
+/// Serves the objects of a repository to `fetch` and `clone` on the other end
+/// of a connection. It advertises the refs, reads which objects are wanted,
+/// acknowledges the first `have` it also has and sends a pack with whatever
+/// the wanted objects need beyond the commits both sides have. Over HTTP,
+/// the advertisement and each round of `have` lines come in requests of
+/// their own.
+pub struct UploadPack {
+    path: PathBuf,
+    mode: ServiceMode,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl UploadPack {
+    pub fn new(session: Session, dir: &str, input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+        Self {
+            path: session.project_dir.join(dir),
+            mode: ServiceMode::default(),
+            input,
+            output,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ServiceMode) {
+        self.mode = mode;
+    }
+
+    fn advertisement(&self, repo: &Repository) -> Result<Advertisement, RitError> {
+        let refs = &repo.refs;
+        let mut advertised = vec![];
+
+        if let Some(id) = refs.read_head() {
+            advertised.push((HEAD.to_string(), id));
+        }
+
+        for name in refs.list_refs("refs") {
+            let id = match refs.read_ref(&name) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            advertised.push((name.clone(), id.clone()));
+
+            if let Parsed::Tag(tag) = repo.database.load(&id)? {
+                advertised.push((format!("{}^{{}}", name), tag.object));
+            }
+        }
+
+        let mut capabilities: Vec<String> = CAPABILITIES.iter().map(|c| c.to_string()).collect();
+
+        if let (Some(name), Some(_)) = (refs.current_ref(), refs.read_head()) {
+            capabilities.push(format!("symref={}:{}", HEAD, name));
+        }
+
+        capabilities.push(format!("agent=rit/{}", env!("CARGO_PKG_VERSION")));
+
+        Ok(Advertisement::new(advertised, capabilities))
+    }
+
+    /// Reads the `want` lines up to the first flush-pkt. A client that has
+    /// everything hangs up without asking for anything.
+    fn read_wants(&mut self) -> io::Result<Vec<Id>> {
+        let mut wants = vec![];
+
+        loop {
+            let line = match pkt_line::read_line(&mut self.input) {
+                Ok(Some(line)) => line,
+                Ok(None) => return Ok(wants),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && wants.is_empty() => {
+                    return Ok(wants)
+                }
+                Err(err) => return Err(err),
+            };
+
+            let id = line
+                .strip_prefix("want ")
+                .and_then(|rest| Id::from_hex(rest.split(' ').next().unwrap_or_default()))
+                .ok_or_else(|| unexpected(&line))?;
+
+            wants.push(id);
+        }
+    }
+
+    /// Reads the `have` lines up to `done`, answering each flush-pkt with a
+    /// NAK until a common commit turns up, which gets the one ACK. Returns
+    /// the common commits, and whether `done` was reached, which over HTTP
+    /// only happens in the last request.
+    fn read_haves(&mut self, repo: &Repository) -> io::Result<(Vec<Id>, bool)> {
+        let mut common = vec![];
+
+        loop {
+            match pkt_line::read_line(&mut self.input)? {
+                None => {
+                    if common.is_empty() {
+                        pkt_line::write_line(&mut self.output, "NAK")?;
+                        self.output.flush()?;
+                    }
+
+                    if self.mode == ServiceMode::StatelessRpc {
+                        return Ok((common, false));
+                    }
+                }
+                Some(line) if line == "done" => {
+                    if common.is_empty() {
+                        pkt_line::write_line(&mut self.output, "NAK")?;
+                    }
+
+                    return Ok((common, true));
+                }
+                Some(line) => {
+                    let id = line
+                        .strip_prefix("have ")
+                        .and_then(Id::from_hex)
+                        .ok_or_else(|| unexpected(&line))?;
+
+                    if repo.database.exists(&id) && repo.database.load_commit(&id).is_ok() {
+                        common.push(id.clone());
+
+                        if common.len() == 1 {
+                            pkt_line::write_line(&mut self.output, &format!("ACK {}", id))?;
+                            self.output.flush()?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists what the wanted objects need, leaving out the history of the
+    /// common commits. Tags are peeled so that what they point at comes
+    /// along.
+    fn objects(
+        &self,
+        repo: &Repository,
+        wants: Vec<Id>,
+        common: Vec<Id>,
+    ) -> Result<Vec<(Id, String)>, RitError> {
+        let mut wants = wants;
+        let mut tags = vec![];
+        let mut commits = vec![];
+        let mut others = vec![];
+
+        while let Some(id) = wants.pop() {
+            match repo.database.load(&id)? {
+                Parsed::Tag(tag) => {
+                    wants.push(tag.object);
+                    tags.push((id, String::new()));
+                }
+                Parsed::Commit(_) => commits.push(id),
+                _ => others.push(id),
+            }
+        }
+
+        let mut rev_list = RevList::new(repo, commits, common);
+        rev_list.add_objects(others);
+
+        let mut objects = rev_list.objects()?;
+        objects.extend(tags);
+
+        Ok(objects)
+    }
+}
+
+impl Command for UploadPack {
+    fn execute(&mut self) -> Result<Execution, RitError> {
+        let repo = remote::open_repository(&self.path)?;
+
+        let advertisement = self.advertisement(&repo)?;
+
+        if self.mode != ServiceMode::StatelessRpc {
+            advertisement
+                .write(&mut self.output)
+                .map_err(protocol_error)?;
+        }
+
+        if self.mode == ServiceMode::AdvertiseRefs {
+            return Ok(Execution::Empty);
+        }
+
+        let wants = self.read_wants().map_err(protocol_error)?;
+
+        // Only what was advertised can be asked for, as git does unless
+        // `uploadpack.allowAnySHA1InWant` is set.
+        for id in &wants {
+            if !advertisement.refs.iter().any(|(_, tip)| tip == id) {
+                let message = format!("ERR upload-pack: not our ref {}", id);
+                pkt_line::write_line(&mut self.output, &message).map_err(protocol_error)?;
+
+                return Err(RitError::Remote(message));
+            }
+        }
+
+        if wants.is_empty() {
+            return Ok(Execution::Empty);
+        }
+
+        let (common, done) = self.read_haves(&repo).map_err(protocol_error)?;
+
+        if !done {
+            return Ok(Execution::Empty);
+        }
+
+        let mut objects = vec![];
+
+        for (id, path) in self.objects(&repo, wants, common)? {
+            let (kind, data) = repo.database.read_object(&id)?;
+
+            objects.push(PackObject {
+                id,
+                kind: pack::kind_number(&kind).unwrap_or_default(),
+                data,
+                path,
+            });
+        }
+
+        let data = pack_writer(&repo.config).pack(objects)?;
+
+        self.output.write_all(&data).map_err(protocol_error)?;
+        self.output.flush().map_err(protocol_error)?;
+
+        Ok(Execution::Empty)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    database::Database,
+    errors::RitError,
+    id::Id,
+    index::{self, CacheTree},
+    objects::{
+        self,
+        tree::{Node, TREE_MODE},
+        Author, TreeEntry,
+    },
+    repository::Repository,
+};
+use std::collections::BTreeMap;
+
+/// Stores the trees for every directory in the index and returns the id of
+/// the root tree. Directories the index's cache tree still has ids for are
+/// neither hashed again nor walked into, and the cache tree is filled in
+/// with the rest.
+pub fn write_tree(repo: &mut Repository) -> Result<Id, RitError> {
+    let mut cache_tree = repo.index.cache_tree().cloned().unwrap_or_default();
+
+    if let Some(id) = cache_tree.id() {
+        return Ok(id.clone());
+    }
+
+    let entries: Vec<_> = repo.index.iter().collect();
+    let id = store_entries(&repo.database, &entries, 0, &mut cache_tree)?;
+
+    repo.index.set_cache_tree(cache_tree);
+
+    Ok(id)
+}
+
+/// Stores the trees for a flat list of paths, as read by `flatten_tree`.
+pub fn write_tree_from(
+    database: &Database,
+    items: &BTreeMap<String, TreeEntry>,
+) -> Result<Id, RitError> {
+    let entries: Vec<_> = items
+        .iter()
+        .map(|(pathname, item)| index::Entry::create_from_db(pathname, item, 0))
+        .collect();
+    let entries: Vec<_> = entries.iter().collect();
+
+    store_entries(database, &entries, 0, &mut CacheTree::default())
+}
+
+/// Stores the tree for the directory whose path takes up the first
+/// `prefix_len` bytes of every one of `entries`, which are sorted by path.
+/// Each subdirectory is a run of consecutive entries; those `cache_tree`
+/// has a valid id for are skipped over, and the others are stored first
+/// and recorded in it.
+fn store_entries(
+    database: &Database,
+    entries: &[&index::Entry],
+    prefix_len: usize,
+    cache_tree: &mut CacheTree,
+) -> Result<Id, RitError> {
+    let mut tree = objects::Tree::new();
+    let mut names = vec![];
+    let mut pos = 0;
+
+    while pos < entries.len() {
+        let entry = entries[pos];
+        let rest = &entry.pathname[prefix_len..];
+
+        let name = match rest.split_once('/') {
+            Some((name, _)) => name.to_string(),
+            None => {
+                let node = Node::Stored(TreeEntry::new(entry.id.clone(), entry.mode));
+                tree.nodes.insert(rest.to_string(), node);
+                pos += 1;
+
+                continue;
+            }
+        };
+
+        let dir_len = prefix_len + name.len() + 1;
+        let dir = &entry.pathname[..dir_len];
+        let end = pos + entries[pos..].partition_point(|entry| entry.pathname.starts_with(dir));
+
+        let child = cache_tree.child_mut(&name);
+        let id = match child.id() {
+            Some(id) => id.clone(),
+            None => store_entries(database, &entries[pos..end], dir_len, child)?,
+        };
+
+        tree.nodes
+            .insert(name.clone(), Node::Stored(TreeEntry::new(id, TREE_MODE)));
+        names.push(name);
+        pos = end;
+    }
+
+    cache_tree.retain_children(&names.iter().collect::<Vec<_>>());
+
+    let id = database.store(&mut tree)?;
+    cache_tree.set(id.clone(), entries.len());
+
+    Ok(id)
+}
+
+/// Stores a commit of the current index on top of `parents`.
+pub fn write_commit(
+    repo: &mut Repository,
+    parents: Vec<Id>,
+    author: Author,
+    committer: Author,
+    message: &str,
+) -> Result<Id, RitError> {
+    let tree_id = write_tree(repo)?;
+
+    store_commit(repo, parents, tree_id, author, committer, message)
+}
+
+/// Stores a commit of an already written tree.
+pub fn store_commit(
+    repo: &Repository,
+    parents: Vec<Id>,
+    tree_id: Id,
+    author: Author,
+    committer: Author,
+    message: &str,
+) -> Result<Id, RitError> {
+    let mut commit = objects::Commit::new(parents, tree_id, author, committer, message);
+
+    Ok(repo.database.store(&mut commit)?)
+}
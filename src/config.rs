@@ -0,0 +1,586 @@
+use crate::lockfile::{LockError, Lockfile};
+use std::{env, fmt, fs, path::PathBuf};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    InvalidKey(String),
+    Lock(LockError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidKey(key) => write!(f, "invalid key: {}", key),
+            ConfigError::Lock(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<LockError> for ConfigError {
+    fn from(err: LockError) -> ConfigError {
+        ConfigError::Lock(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+}
+
+impl Key {
+    /// Splits `section.subsection.name`, where only the subsection is case
+    /// sensitive and may itself contain dots.
+    fn parse(key: &str) -> Result<Self, ConfigError> {
+        let invalid = || ConfigError::InvalidKey(key.to_string());
+
+        let first = key.find('.').ok_or_else(invalid)?;
+        let last = key.rfind('.').ok_or_else(invalid)?;
+
+        let section = key[..first].to_lowercase();
+        let name = key[last + 1..].to_lowercase();
+        let subsection = if first == last {
+            None
+        } else {
+            Some(key[first + 1..last].to_string())
+        };
+
+        if section.is_empty() || name.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            section,
+            subsection,
+            name,
+        })
+    }
+
+    fn same_section(&self, section: &str, subsection: &Option<String>) -> bool {
+        self.section == section && &self.subsection == subsection
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Section(String, Option<String>),
+    Variable(String, Option<String>, String, Option<String>),
+    Other,
+}
+
+/// A single git config file, kept as its original lines so that rewriting it
+/// preserves comments and formatting.
+pub struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<(String, Line)>,
+}
+
+impl ConfigFile {
+    pub fn new(path: PathBuf) -> Self {
+        let mut file = Self {
+            path,
+            lines: vec![],
+        };
+
+        file.load();
+
+        file
+    }
+
+    pub fn load(&mut self) {
+        let content = fs::read_to_string(&self.path).unwrap_or_default();
+
+        let mut section = (String::new(), None);
+        let mut lines = vec![];
+        let mut pending = String::new();
+
+        for raw in content.lines() {
+            pending.push_str(raw);
+
+            if raw.ends_with('\\') && !raw.ends_with("\\\\") {
+                pending.push('\n');
+                continue;
+            }
+
+            let text = std::mem::take(&mut pending);
+            let line = Self::parse_line(&text, &mut section);
+
+            lines.push((text, line));
+        }
+
+        self.lines = lines;
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<Option<String>> {
+        let key = match Key::parse(key) {
+            Ok(key) => key,
+            Err(_) => return vec![],
+        };
+
+        self.lines
+            .iter()
+            .filter_map(|(_, line)| match line {
+                Line::Variable(section, subsection, name, value)
+                    if key.same_section(section, subsection) && &key.name == name =>
+                {
+                    Some(value.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn subsections(&self, section: &str) -> Vec<String> {
+        let section = section.to_lowercase();
+        let mut names = vec![];
+
+        for (_, line) in &self.lines {
+            if let Line::Section(name, Some(subsection)) = line {
+                if name == &section && !names.contains(subsection) {
+                    names.push(subsection.clone());
+                }
+            }
+        }
+
+        names
+    }
+
+    /// Replaces every value of `key` with `value`, adding the variable (and
+    /// its section) when it is missing.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let parsed = Key::parse(key)?;
+        let positions = self.variable_positions(&parsed);
+
+        match positions.split_last() {
+            Some((last, rest)) => {
+                self.lines[*last] = Self::variable_line(&parsed, value);
+
+                for pos in rest.iter().rev() {
+                    self.lines.remove(*pos);
+                }
+
+                Ok(())
+            }
+            None => self.add(key, value),
+        }
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let parsed = Key::parse(key)?;
+        let line = Self::variable_line(&parsed, value);
+
+        match self.section_end(&parsed.section, &parsed.subsection) {
+            Some(pos) => self.lines.insert(pos, line),
+            None => {
+                let header = match &parsed.subsection {
+                    Some(subsection) => format!(
+                        "[{} \"{}\"]",
+                        parsed.section,
+                        subsection.replace('\\', "\\\\").replace('"', "\\\"")
+                    ),
+                    None => format!("[{}]", parsed.section),
+                };
+
+                self.lines.push((
+                    header,
+                    Line::Section(parsed.section.clone(), parsed.subsection.clone()),
+                ));
+                self.lines.push(line);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<(), ConfigError> {
+        let parsed = Key::parse(key)?;
+
+        for pos in self.variable_positions(&parsed).iter().rev() {
+            self.lines.remove(*pos);
+        }
+
+        Ok(())
+    }
+
+    /// Removes every section header called `section.subsection` together with
+    /// the variables under it, returning whether anything was found.
+    pub fn remove_section(&mut self, section: &str, subsection: Option<&str>) -> bool {
+        let section = section.to_lowercase();
+        let subsection = subsection.map(|s| s.to_string());
+
+        let mut is_inside = false;
+        let mut found = false;
+
+        self.lines.retain(|(_, line)| {
+            if let Line::Section(name, sub) = line {
+                is_inside = name == &section && sub == &subsection;
+                found |= is_inside;
+            }
+
+            !is_inside
+        });
+
+        found
+    }
+
+    pub fn rename_section(
+        &mut self,
+        section: &str,
+        old_subsection: &str,
+        new_subsection: &str,
+    ) -> bool {
+        let section = section.to_lowercase();
+        let mut found = false;
+
+        for (text, line) in self.lines.iter_mut() {
+            if let Line::Section(name, Some(sub)) = line {
+                if name == &section && sub == old_subsection {
+                    *sub = new_subsection.to_string();
+                    *text = format!("[{} \"{}\"]", section, new_subsection);
+                    found = true;
+                }
+            }
+
+            if let Line::Variable(name, Some(sub), _, _) = line {
+                if name == &section && sub == old_subsection {
+                    *sub = new_subsection.to_string();
+                }
+            }
+        }
+
+        found
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let mut lockfile = Lockfile::new(self.path.clone());
+        lockfile.hold_for_update()?;
+
+        for (text, _) in &self.lines {
+            lockfile.write(text.as_bytes())?;
+            lockfile.write(b"\n")?;
+        }
+
+        lockfile.commit()?;
+
+        Ok(())
+    }
+
+    fn variable_positions(&self, key: &Key) -> Vec<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, line))| match line {
+                Line::Variable(section, subsection, name, _) => {
+                    key.same_section(section, subsection) && &key.name == name
+                }
+                _ => false,
+            })
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    fn section_end(&self, section: &str, subsection: &Option<String>) -> Option<usize> {
+        let mut end = None;
+        let mut is_inside = false;
+
+        for (pos, (_, line)) in self.lines.iter().enumerate() {
+            match line {
+                Line::Section(name, sub) => {
+                    is_inside = name == section && sub == subsection;
+
+                    if is_inside {
+                        end = Some(pos + 1);
+                    }
+                }
+                Line::Variable(..) if is_inside => end = Some(pos + 1),
+                _ => {}
+            }
+        }
+
+        end
+    }
+
+    fn variable_line(key: &Key, value: &str) -> (String, Line) {
+        let text = format!("\t{} = {}", key.name, Self::quote(value));
+        let line = Line::Variable(
+            key.section.clone(),
+            key.subsection.clone(),
+            key.name.clone(),
+            Some(value.to_string()),
+        );
+
+        (text, line)
+    }
+
+    fn quote(value: &str) -> String {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t");
+
+        let needs_quotes = value.starts_with(' ')
+            || value.ends_with(' ')
+            || value.contains('#')
+            || value.contains(';');
+
+        if needs_quotes {
+            format!("\"{}\"", escaped)
+        } else {
+            escaped
+        }
+    }
+
+    fn parse_line(text: &str, section: &mut (String, Option<String>)) -> Line {
+        let trimmed = text.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            return Line::Other;
+        }
+
+        if trimmed.starts_with('[') {
+            if let Some(end) = trimmed.find(']') {
+                let header = &trimmed[1..end];
+
+                let (name, subsection) = match header.find(|c: char| c.is_whitespace()) {
+                    Some(pos) => {
+                        let subsection = header[pos..].trim().trim_matches('"');
+                        let subsection = subsection.replace("\\\"", "\"").replace("\\\\", "\\");
+
+                        (header[..pos].to_lowercase(), Some(subsection))
+                    }
+                    None => match header.find('.') {
+                        Some(pos) => (
+                            header[..pos].to_lowercase(),
+                            Some(header[pos + 1..].to_lowercase()),
+                        ),
+                        None => (header.to_lowercase(), None),
+                    },
+                };
+
+                *section = (name.clone(), subsection.clone());
+
+                return Line::Section(name, subsection);
+            }
+
+            return Line::Other;
+        }
+
+        let (name, value) = match trimmed.find('=') {
+            Some(pos) => (
+                &trimmed[..pos],
+                Some(Self::parse_value(&trimmed[pos + 1..])),
+            ),
+            None => (trimmed, None),
+        };
+
+        Line::Variable(
+            section.0.clone(),
+            section.1.clone(),
+            name.trim().to_lowercase(),
+            value,
+        )
+    }
+
+    fn parse_value(raw: &str) -> String {
+        let mut value = String::new();
+        let mut is_quoted = false;
+        let mut chars = raw.trim().chars().peekable();
+        let mut trailing_space = 0;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => is_quoted = !is_quoted,
+                '\\' => {
+                    match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('b') => {
+                            value.pop();
+                        }
+                        Some('\n') => {}
+                        Some(other) => value.push(other),
+                        None => {}
+                    }
+                    trailing_space = 0;
+                    continue;
+                }
+                '#' | ';' if !is_quoted => break,
+                c if c.is_whitespace() && !is_quoted => {
+                    trailing_space += 1;
+                    value.push(c);
+                    continue;
+                }
+                c => value.push(c),
+            }
+
+            trailing_space = 0;
+        }
+
+        value.truncate(value.len() - trailing_space);
+
+        value
+    }
+}
+
+/// The configuration of a repository: its own `.git/config` layered over the
+/// user's global `~/.gitconfig`. Values are read from the repository file
+/// first and all writes go to it.
+pub struct Config {
+    pub local: ConfigFile,
+    global: Option<ConfigFile>,
+}
+
+impl Config {
+    pub fn new(git_path: &std::path::Path) -> Self {
+        let global = env::var("HOME")
+            .ok()
+            .map(|home| ConfigFile::new(PathBuf::from(home).join(".gitconfig")));
+
+        Self {
+            local: ConfigFile::new(git_path.join("config")),
+            global,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.get_all(key).pop()
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<String> {
+        let mut values: Vec<Option<String>> = vec![];
+
+        if let Some(global) = &self.global {
+            values.extend(global.get_all(key));
+        }
+
+        values.extend(self.local.get_all(key));
+
+        values
+            .into_iter()
+            .map(|value| value.unwrap_or_else(|| "true".to_string()))
+            .collect()
+    }
+
+    /// Lists the subsections of `section`, e.g. the names of the remotes for
+    /// `remote`, from both files.
+    pub fn subsections(&self, section: &str) -> Vec<String> {
+        let mut names = vec![];
+
+        if let Some(global) = &self.global {
+            names.extend(global.subsections(section));
+        }
+
+        for name in self.local.subsections(section) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        names
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key)
+            .map(|value| matches!(&value.to_lowercase()[..], "true" | "yes" | "on" | "1"))
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        let value = self.get(key)?.to_lowercase();
+
+        let (digits, factor) = match value.chars().last()? {
+            'k' => (&value[..value.len() - 1], 1024),
+            'm' => (&value[..value.len() - 1], 1024 * 1024),
+            'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+            _ => (&value[..], 1),
+        };
+
+        digits.parse::<i64>().ok().map(|n| n * factor)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        self.local.set(key, value)?;
+        self.local.save()
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        self.local.add(key, value)?;
+        self.local.save()
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<(), ConfigError> {
+        self.local.unset(key)?;
+        self.local.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> ConfigFile {
+        let mut file = ConfigFile {
+            path: PathBuf::new(),
+            lines: vec![],
+        };
+        let mut section = (String::new(), None);
+
+        for text in content.lines() {
+            let line = ConfigFile::parse_line(text, &mut section);
+            file.lines.push((text.to_string(), line));
+        }
+
+        file
+    }
+
+    fn render(file: &ConfigFile) -> String {
+        file.lines
+            .iter()
+            .map(|(text, _)| format!("{}\n", text))
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_variables_from_sections_and_subsections() {
+        let file = parse(
+            "[core]\n\tEditor = vim # comment\n[remote \"origin\"]\n\turl = \"/tmp/a b\"\n\tfetch = one\n\tfetch = two\n",
+        );
+
+        assert_eq!(vec![Some("vim".to_string())], file.get_all("core.editor"));
+        assert_eq!(
+            vec![Some("/tmp/a b".to_string())],
+            file.get_all("remote.origin.url")
+        );
+        assert_eq!(2, file.get_all("remote.origin.fetch").len());
+        assert_eq!(vec!["origin".to_string()], file.subsections("remote"));
+    }
+
+    #[test]
+    fn it_treats_a_bare_variable_as_set() {
+        let file = parse("[core]\n\tbare\n");
+
+        assert_eq!(vec![None], file.get_all("core.bare"));
+    }
+
+    #[test]
+    fn it_adds_variables_to_existing_sections() {
+        let mut file = parse("# header\n[core]\n\tbare = false\n");
+
+        file.set("core.editor", "ed").unwrap();
+        file.set("remote.origin.url", "/srv/repo").unwrap();
+
+        assert_eq!(
+            "# header\n[core]\n\tbare = false\n\teditor = ed\n[remote \"origin\"]\n\turl = /srv/repo\n",
+            render(&file)
+        );
+    }
+
+    #[test]
+    fn it_removes_sections() {
+        let mut file = parse("[remote \"origin\"]\n\turl = a\n[core]\n\tbare = false\n");
+
+        assert!(file.remove_section("remote", Some("origin")));
+        assert_eq!("[core]\n\tbare = false\n", render(&file));
+    }
+}
@@ -1,19 +1,162 @@
-use crate::{id, objects};
-use libflate::zlib::Encoder;
+use crate::{
+    id::{self, Id},
+    objects::{self, Blob, Commit, Parsed, Tag, Tree, TreeEntry},
+    pack::Pack,
+};
+use bytes::Bytes;
+use libflate::zlib::{Decoder, Encoder};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use std::{
+    collections::BTreeMap,
+    fmt, fs,
     fs::{File, OpenOptions},
     io::{self, prelude::*},
     path::{Path, PathBuf},
 };
 
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io,
+    MissingObject(String),
+    Parse(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Io => write!(f, "Cannot access the object database"),
+            DatabaseError::MissingObject(id) => write!(f, "object {} is missing", id),
+            DatabaseError::Parse(id) => write!(f, "object {} is corrupt", id),
+        }
+    }
+}
+
+impl From<io::Error> for DatabaseError {
+    fn from(_err: io::Error) -> DatabaseError {
+        DatabaseError::Io
+    }
+}
+
+pub type TreeDiff = BTreeMap<String, (Option<TreeEntry>, Option<TreeEntry>)>;
+
+const PACK_DIR: &str = "pack";
+
+/// The objects of a repository, stored either as loose zlib files under
+/// `.git/objects` or in the packs of `.git/objects/pack`, which are only
+/// looked at when there is no loose copy.
 pub struct Database {
     path: PathBuf,
+    packs: Vec<Pack>,
 }
 
 impl Database {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        let packs = Self::open_packs(&path);
+
+        Self { path, packs }
+    }
+
+    fn open_packs(path: &Path) -> Vec<Pack> {
+        let mut index_paths: Vec<PathBuf> = fs::read_dir(path.join(PACK_DIR))
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        index_paths.sort();
+
+        index_paths
+            .iter()
+            .filter_map(|path| Pack::open(path).ok())
+            .collect()
+    }
+
+    /// Opens the packs again, e.g. after some were written or deleted.
+    pub fn reload_packs(&mut self) {
+        self.packs = Self::open_packs(&self.path);
+    }
+
+    pub fn pack_dir(&self) -> PathBuf {
+        self.path.join(PACK_DIR)
+    }
+
+    /// Lists the paths of the `.idx` files of the packs currently in use.
+    pub fn pack_index_paths(&self) -> Vec<PathBuf> {
+        self.packs.iter().map(|pack| pack.index_path()).collect()
+    }
+
+    pub fn is_packed(&self, id: &Id) -> bool {
+        self.packs.iter().any(|pack| pack.contains(id))
+    }
+
+    /// Lists the ids of all the objects stored as loose files.
+    pub fn loose_ids(&self) -> Vec<Id> {
+        let mut ids = vec![];
+
+        let dirs = match fs::read_dir(&self.path) {
+            Ok(dirs) => dirs,
+            Err(_) => return ids,
+        };
+
+        for dir in dirs.filter_map(|entry| entry.ok()) {
+            let prefix = dir.file_name().to_string_lossy().to_string();
+
+            if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+
+            if let Ok(entries) = fs::read_dir(dir.path()) {
+                ids.extend(entries.filter_map(|entry| {
+                    let name = entry.ok()?.file_name();
+
+                    Id::from_hex(&format!("{}{}", prefix, name.to_string_lossy()))
+                }));
+            }
+        }
+
+        ids.sort_by(|a, b| a.as_str.cmp(&b.as_str));
+
+        ids
+    }
+
+    /// Lists the ids of all the objects stored in packs.
+    pub fn packed_ids(&self) -> Vec<Id> {
+        let mut ids: Vec<Id> = self
+            .packs
+            .iter()
+            .flat_map(|pack| pack.index.ids())
+            .collect();
+
+        ids.sort_by(|a, b| a.as_str.cmp(&b.as_str));
+        ids.dedup();
+
+        ids
+    }
+
+    /// Lists the `.pack` files whose checksums do not match their content
+    /// or that of their index.
+    pub fn corrupt_packs(&self) -> Vec<PathBuf> {
+        self.packs
+            .iter()
+            .filter(|pack| !pack.verify_checksum().unwrap_or(false))
+            .map(|pack| pack.index_path().with_extension("pack"))
+            .collect()
+    }
+
+    /// Deletes the loose copy of an object, along with its directory once it
+    /// is empty.
+    pub fn remove_loose(&self, id: &Id) -> Result<(), io::Error> {
+        let path = self.object_path(&id.as_str);
+        fs::remove_file(&path)?;
+
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+
+        Ok(())
     }
 
     pub fn store<O>(&self, object: &mut O) -> Result<id::Id, io::Error>
@@ -27,11 +170,207 @@ impl Database {
         Ok(id)
     }
 
+    pub fn exists(&self, id: &Id) -> bool {
+        self.object_path(&id.as_str).exists() || self.is_packed(id)
+    }
+
+    pub fn read_object(&self, id: &Id) -> Result<(String, Bytes), DatabaseError> {
+        let file = match File::open(self.object_path(&id.as_str)) {
+            Ok(file) => file,
+            Err(_) => return self.read_packed(id),
+        };
+
+        let mut data = vec![];
+        Decoder::new(file)?.read_to_end(&mut data)?;
+
+        let corrupt = || DatabaseError::Parse(id.as_str.clone());
+
+        let space = data.iter().position(|b| *b == b' ').ok_or_else(corrupt)?;
+        let null = data.iter().position(|b| *b == 0x00).ok_or_else(corrupt)?;
+
+        let kind = String::from_utf8_lossy(&data[..space]).to_string();
+        let content = Bytes::from(data).slice(null + 1..);
+
+        Ok((kind, content))
+    }
+
+    fn read_packed(&self, id: &Id) -> Result<(String, Bytes), DatabaseError> {
+        let resolve = |base_id: &Id| self.read_object(base_id).ok();
+
+        self.packs
+            .iter()
+            .find_map(|pack| pack.read(id, resolve))
+            .ok_or_else(|| DatabaseError::MissingObject(id.as_str.clone()))?
+            .map_err(|_| DatabaseError::Parse(id.as_str.clone()))
+    }
+
+    pub fn load(&self, id: &Id) -> Result<Parsed, DatabaseError> {
+        let (kind, data) = self.read_object(id)?;
+        let corrupt = || DatabaseError::Parse(id.as_str.clone());
+
+        match &kind[..] {
+            "blob" => Ok(Parsed::Blob(Blob::parse(data))),
+            "tree" => Tree::parse(&data).map(Parsed::Tree).ok_or_else(corrupt),
+            "commit" => Commit::parse(&data).map(Parsed::Commit).ok_or_else(corrupt),
+            "tag" => Tag::parse(&data).map(Parsed::Tag).ok_or_else(corrupt),
+            _ => Err(corrupt()),
+        }
+    }
+
+    pub fn load_commit(&self, id: &Id) -> Result<Commit, DatabaseError> {
+        match self.load(id)? {
+            Parsed::Commit(commit) => Ok(commit),
+            _ => Err(DatabaseError::Parse(id.as_str.clone())),
+        }
+    }
+
+    pub fn load_tree(&self, id: &Id) -> Result<Tree, DatabaseError> {
+        match self.load(id)? {
+            Parsed::Tree(tree) => Ok(tree),
+            Parsed::Commit(commit) => self.load_tree(&commit.tree_id),
+            Parsed::Tag(tag) => self.load_tree(&tag.object),
+            _ => Err(DatabaseError::Parse(id.as_str.clone())),
+        }
+    }
+
+    pub fn load_tag(&self, id: &Id) -> Result<Tag, DatabaseError> {
+        match self.load(id)? {
+            Parsed::Tag(tag) => Ok(tag),
+            _ => Err(DatabaseError::Parse(id.as_str.clone())),
+        }
+    }
+
+    pub fn load_blob(&self, id: &Id) -> Result<Blob, DatabaseError> {
+        match self.load(id)? {
+            Parsed::Blob(blob) => Ok(blob),
+            _ => Err(DatabaseError::Parse(id.as_str.clone())),
+        }
+    }
+
+    pub fn prefix_match(&self, prefix: &str) -> Vec<Id> {
+        if prefix.len() < 2 {
+            return vec![];
+        }
+
+        let dir_path = self.path.join(&prefix[0..2]);
+
+        let mut ids: Vec<Id> = fs::read_dir(dir_path)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| {
+                        format!("{}{}", &prefix[0..2], entry.file_name().to_string_lossy())
+                    })
+                    .filter(|name| name.starts_with(prefix))
+                    .filter_map(|name| Id::from_hex(&name))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for pack in &self.packs {
+            ids.extend(pack.index.prefix_match(prefix));
+        }
+
+        ids.sort_by(|a, b| a.as_str.cmp(&b.as_str));
+        ids.dedup();
+
+        ids
+    }
+
+    pub fn tree_diff(&self, a: Option<&Id>, b: Option<&Id>) -> Result<TreeDiff, DatabaseError> {
+        let mut changes = TreeDiff::new();
+
+        self.compare_trees(a, b, "", &mut changes)?;
+
+        Ok(changes)
+    }
+
+    pub fn flatten_tree(
+        &self,
+        id: Option<&Id>,
+    ) -> Result<BTreeMap<String, TreeEntry>, DatabaseError> {
+        let changes = self.tree_diff(None, id)?;
+
+        Ok(changes
+            .into_iter()
+            .filter_map(|(path, (_, entry))| entry.map(|entry| (path, entry)))
+            .collect())
+    }
+
+    fn compare_trees(
+        &self,
+        a: Option<&Id>,
+        b: Option<&Id>,
+        prefix: &str,
+        changes: &mut TreeDiff,
+    ) -> Result<(), DatabaseError> {
+        if a == b {
+            return Ok(());
+        }
+
+        let a_entries = self.tree_entries(a)?;
+        let b_entries = self.tree_entries(b)?;
+
+        for (name, a_entry) in &a_entries {
+            let b_entry = b_entries.get(name);
+
+            if Some(a_entry) == b_entry {
+                continue;
+            }
+
+            self.compare_entries(Some(a_entry), b_entry, &join_path(prefix, name), changes)?;
+        }
+
+        for (name, b_entry) in &b_entries {
+            if !a_entries.contains_key(name) {
+                self.compare_entries(None, Some(b_entry), &join_path(prefix, name), changes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare_entries(
+        &self,
+        a: Option<&TreeEntry>,
+        b: Option<&TreeEntry>,
+        path: &str,
+        changes: &mut TreeDiff,
+    ) -> Result<(), DatabaseError> {
+        let a_tree = a.filter(|entry| entry.is_tree()).map(|entry| &entry.id);
+        let b_tree = b.filter(|entry| entry.is_tree()).map(|entry| &entry.id);
+
+        self.compare_trees(a_tree, b_tree, path, changes)?;
+
+        let a_blob = a.filter(|entry| !entry.is_tree()).cloned();
+        let b_blob = b.filter(|entry| !entry.is_tree()).cloned();
+
+        if a_blob.is_some() || b_blob.is_some() {
+            changes.insert(path.to_string(), (a_blob, b_blob));
+        }
+
+        Ok(())
+    }
+
+    fn tree_entries(&self, id: Option<&Id>) -> Result<BTreeMap<String, TreeEntry>, DatabaseError> {
+        match id {
+            Some(id) => Ok(self
+                .load_tree(id)?
+                .stored_entries()
+                .map(|(name, entry)| (name.clone(), entry.clone()))
+                .collect()),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn object_path(&self, id: &str) -> PathBuf {
+        self.path.join(&id[0..2]).join(&id[2..])
+    }
+
     fn write_object<C: Read>(&self, id: &str, mut content: C) -> Result<(), io::Error> {
         let dir_path = self.path.join(&id[0..2]);
         let object_path = dir_path.join(&id[2..]);
 
-        if object_path.exists() {
+        if object_path.exists() || Id::from_hex(id).is_some_and(|id| self.is_packed(&id)) {
             return Ok(());
         }
 
@@ -65,3 +404,11 @@ impl Database {
         format!("tmp_obj_{}", s)
     }
 }
+
+fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
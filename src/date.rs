@@ -0,0 +1,106 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+const DATE_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses the dates accepted by `<ref>@{<date>}` and `reflog expire` into a
+/// unix timestamp: `now`, `yesterday`, relative dates like `2.weeks.ago` or
+/// `3 days 4 hours ago`, and local dates like `2020-01-31 [12:30[:00]]`.
+pub fn parse(expr: &str, now: DateTime<Local>) -> Option<i64> {
+    let expr = expr.trim().to_lowercase();
+
+    match &expr[..] {
+        "now" | "all" => return Some(now.timestamp()),
+        "yesterday" => return Some((now - Duration::days(1)).timestamp()),
+        _ => {}
+    }
+
+    parse_relative(&expr, now).or_else(|| parse_absolute(&expr))
+}
+
+fn parse_relative(expr: &str, now: DateTime<Local>) -> Option<i64> {
+    let expr = expr.replace(['.', '_'], " ");
+    let mut words: Vec<&str> = expr.split_whitespace().collect();
+
+    if words.last() == Some(&"ago") {
+        words.pop();
+    }
+
+    if words.is_empty() || !words.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let mut time = now;
+
+    for pair in words.chunks(2) {
+        let n: i64 = pair[0].parse().ok()?;
+        let unit = pair[1].strip_suffix('s').unwrap_or(pair[1]);
+
+        let duration = match unit {
+            "sec" | "second" => Duration::seconds(n),
+            "min" | "minute" => Duration::minutes(n),
+            "hour" => Duration::hours(n),
+            "day" => Duration::days(n),
+            "week" => Duration::weeks(n),
+            "month" => Duration::days(30 * n),
+            "year" => Duration::days(365 * n),
+            _ => return None,
+        };
+
+        time = time - duration;
+    }
+
+    Some(time.timestamp())
+}
+
+fn parse_absolute(expr: &str) -> Option<i64> {
+    let date_time = DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(expr, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(expr, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+
+    Local
+        .from_local_datetime(&date_time)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.timestamp_opt(1_600_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn it_parses_relative_dates() {
+        assert_eq!(Some(1_600_000_000), parse("now", now()));
+        assert_eq!(Some(1_600_000_000 - 2 * 86400), parse("2.days.ago", now()));
+        assert_eq!(
+            Some(1_600_000_000 - 86400 - 3 * 3600),
+            parse("1 day 3 hours ago", now())
+        );
+        assert_eq!(Some(1_600_000_000 - 60), parse("1.minute", now()));
+        assert_eq!(None, parse("2.fortnights.ago", now()));
+    }
+
+    #[test]
+    fn it_parses_absolute_dates() {
+        let date_time = NaiveDate::from_ymd_opt(2020, 1, 31)
+            .and_then(|date| date.and_hms_opt(12, 30, 0))
+            .unwrap();
+        let expected = Local.from_local_datetime(&date_time).unwrap().timestamp();
+
+        assert_eq!(Some(expected), parse("2020-01-31 12:30", now()));
+        assert_eq!(None, parse("2020-13-01", now()));
+    }
+}
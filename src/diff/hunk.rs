@@ -0,0 +1,111 @@
+use super::{Edit, EditKind, Line};
+
+const CONTEXT: usize = 3;
+
+/// A group of edits close enough to each other to be shown together, with up
+/// to three unchanged lines of context around them.
+#[derive(Debug)]
+pub struct Hunk {
+    a_start: usize,
+    b_start: usize,
+    pub edits: Vec<Edit>,
+}
+
+impl Hunk {
+    pub fn filter(edits: &[Edit]) -> Vec<Hunk> {
+        let changes: Vec<usize> = edits
+            .iter()
+            .enumerate()
+            .filter(|(_, edit)| edit.kind != EditKind::Eql)
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut groups: Vec<(usize, usize)> = vec![];
+
+        for i in changes {
+            match groups.last_mut() {
+                Some((_, last)) if i - *last <= 2 * CONTEXT => *last = i,
+                _ => groups.push((i, i)),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(first, last)| {
+                let start = first.saturating_sub(CONTEXT);
+                let end = (last + CONTEXT + 1).min(edits.len());
+                let before = &edits[..start];
+
+                Hunk {
+                    a_start: before.iter().filter(|e| e.a_line.is_some()).count(),
+                    b_start: before.iter().filter(|e| e.b_line.is_some()).count(),
+                    edits: edits[start..end].to_vec(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn header(&self) -> String {
+        let a_lines: Vec<&Line> = self
+            .edits
+            .iter()
+            .filter_map(|e| e.a_line.as_ref())
+            .collect();
+        let b_lines: Vec<&Line> = self
+            .edits
+            .iter()
+            .filter_map(|e| e.b_line.as_ref())
+            .collect();
+
+        format!(
+            "@@ -{} +{} @@",
+            Self::range(&a_lines, self.a_start),
+            Self::range(&b_lines, self.b_start)
+        )
+    }
+
+    /// Formats a range like git: the size is left out for a single line and
+    /// an empty range starts at the line before it.
+    fn range(lines: &[&Line], default: usize) -> String {
+        let start = lines.first().map_or(default, |line| line.number);
+
+        match lines.len() {
+            1 => format!("{}", start),
+            size => format!("{},{}", start, size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{lines, Myers};
+
+    fn headers(a: &str, b: &str) -> Vec<String> {
+        let edits = Myers::new(lines(a), lines(b)).diff();
+
+        Hunk::filter(&edits).iter().map(Hunk::header).collect()
+    }
+
+    #[test]
+    fn it_keeps_three_lines_of_context() {
+        let a = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let b = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+
+        assert_eq!(vec!["@@ -2,7 +2,7 @@"], headers(a, b));
+    }
+
+    #[test]
+    fn it_splits_distant_changes() {
+        let a = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let b = "one\n2\n3\n4\n5\n6\n7\n8\n9\nten\n";
+
+        assert_eq!(vec!["@@ -1,4 +1,4 @@", "@@ -7,4 +7,4 @@"], headers(a, b));
+    }
+
+    #[test]
+    fn it_formats_added_files() {
+        assert_eq!(vec!["@@ -0,0 +1,2 @@"], headers("", "1\n2\n"));
+        assert_eq!(vec!["@@ -1 +0,0 @@"], headers("1\n", ""));
+    }
+}
@@ -0,0 +1,39 @@
+mod hunk;
+mod myers;
+mod patch;
+
+pub use hunk::Hunk;
+pub use myers::Myers;
+pub use patch::{format_stat, FileDiff};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub number: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditKind {
+    Eql,
+    Ins,
+    Del,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub kind: EditKind,
+    pub a_line: Option<Line>,
+    pub b_line: Option<Line>,
+}
+
+/// Splits a document into numbered lines, each keeping its trailing newline.
+pub fn lines(document: &str) -> Vec<Line> {
+    document
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(i, text)| Line {
+            number: i + 1,
+            text: text.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,129 @@
+use super::{Edit, EditKind, Line};
+
+/// Myers' O(ND) algorithm finding the shortest edit script between two lists
+/// of lines.
+pub struct Myers {
+    a: Vec<Line>,
+    b: Vec<Line>,
+}
+
+impl Myers {
+    pub fn new(a: Vec<Line>, b: Vec<Line>) -> Self {
+        Self { a, b }
+    }
+
+    pub fn diff(&self) -> Vec<Edit> {
+        let mut diff = vec![];
+
+        self.backtrack(|prev_x, prev_y, x, y| {
+            let a_line = self.a.get(prev_x).cloned();
+            let b_line = self.b.get(prev_y).cloned();
+
+            let edit = if x == prev_x {
+                Edit {
+                    kind: EditKind::Ins,
+                    a_line: None,
+                    b_line,
+                }
+            } else if y == prev_y {
+                Edit {
+                    kind: EditKind::Del,
+                    a_line,
+                    b_line: None,
+                }
+            } else {
+                Edit {
+                    kind: EditKind::Eql,
+                    a_line,
+                    b_line,
+                }
+            };
+
+            diff.push(edit);
+        });
+
+        diff.reverse();
+
+        diff
+    }
+
+    fn shortest_edit(&self) -> Vec<Vec<isize>> {
+        let n = self.a.len() as isize;
+        let m = self.b.len() as isize;
+        let max = n + m;
+        let offset = max + 1;
+
+        let mut v = vec![0isize; 2 * max as usize + 3];
+        let mut trace = vec![];
+
+        for d in 0..=max {
+            trace.push(v.clone());
+
+            let mut k = -d;
+
+            while k <= d {
+                let index = (k + offset) as usize;
+
+                let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                    v[index + 1]
+                } else {
+                    v[index - 1] + 1
+                };
+                let mut y = x - k;
+
+                while x < n && y < m && self.a[x as usize].text == self.b[y as usize].text {
+                    x += 1;
+                    y += 1;
+                }
+
+                v[index] = x;
+
+                if x >= n && y >= m {
+                    return trace;
+                }
+
+                k += 2;
+            }
+        }
+
+        trace
+    }
+
+    fn backtrack<F>(&self, mut step: F)
+    where
+        F: FnMut(usize, usize, usize, usize),
+    {
+        let mut x = self.a.len() as isize;
+        let mut y = self.b.len() as isize;
+        let offset = x + y + 1;
+
+        for (d, v) in self.shortest_edit().iter().enumerate().rev() {
+            let d = d as isize;
+            let k = x - y;
+            let index = (k + offset) as usize;
+
+            let prev_k = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                k + 1
+            } else {
+                k - 1
+            };
+
+            let prev_x = v[(prev_k + offset) as usize];
+            let prev_y = prev_x - prev_k;
+
+            while x > prev_x && y > prev_y {
+                step((x - 1) as usize, (y - 1) as usize, x as usize, y as usize);
+
+                x -= 1;
+                y -= 1;
+            }
+
+            if d > 0 {
+                step(prev_x as usize, prev_y as usize, x as usize, y as usize);
+            }
+
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+}
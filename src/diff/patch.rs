@@ -0,0 +1,200 @@
+use super::{lines, Edit, EditKind, Hunk, Myers};
+use crate::{
+    database::{Database, DatabaseError},
+    objects::TreeEntry,
+};
+use std::fmt::Write;
+
+const STAT_WIDTH: usize = 60;
+
+/// The changes made to a single path between two trees.
+pub struct FileDiff {
+    pub path: String,
+    a: Option<TreeEntry>,
+    b: Option<TreeEntry>,
+    edits: Vec<Edit>,
+}
+
+impl FileDiff {
+    pub fn new(
+        database: &Database,
+        path: &str,
+        a: Option<TreeEntry>,
+        b: Option<TreeEntry>,
+    ) -> Result<Self, DatabaseError> {
+        let a_text = Self::read(database, &a)?;
+        let b_text = Self::read(database, &b)?;
+
+        Ok(Self {
+            path: path.to_string(),
+            a,
+            b,
+            edits: Myers::new(lines(&a_text), lines(&b_text)).diff(),
+        })
+    }
+
+    pub fn insertions(&self) -> usize {
+        self.count(EditKind::Ins)
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.count(EditKind::Del)
+    }
+
+    pub fn to_patch(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "diff --git a/{} b/{}", self.path, self.path);
+
+        match (&self.a, &self.b) {
+            (None, Some(b)) => {
+                let _ = writeln!(out, "new file mode {:o}", b.mode);
+            }
+            (Some(a), None) => {
+                let _ = writeln!(out, "deleted file mode {:o}", a.mode);
+            }
+            (Some(a), Some(b)) if a.mode != b.mode => {
+                let _ = writeln!(out, "old mode {:o}\nnew mode {:o}", a.mode, b.mode);
+            }
+            _ => {}
+        }
+
+        let a_id = self
+            .a
+            .as_ref()
+            .map_or("0000000".to_string(), |a| a.id.short().to_string());
+        let b_id = self
+            .b
+            .as_ref()
+            .map_or("0000000".to_string(), |b| b.id.short().to_string());
+
+        if a_id == b_id {
+            return out;
+        }
+
+        let _ = write!(out, "index {}..{}", a_id, b_id);
+
+        match (&self.a, &self.b) {
+            (Some(a), Some(b)) if a.mode == b.mode => {
+                let _ = writeln!(out, " {:o}", a.mode);
+            }
+            _ => out.push('\n'),
+        }
+
+        let a_path = self
+            .a
+            .as_ref()
+            .map_or("/dev/null".to_string(), |_| format!("a/{}", self.path));
+        let b_path = self
+            .b
+            .as_ref()
+            .map_or("/dev/null".to_string(), |_| format!("b/{}", self.path));
+
+        let _ = writeln!(out, "--- {}\n+++ {}", a_path, b_path);
+
+        for hunk in Hunk::filter(&self.edits) {
+            let _ = writeln!(out, "{}", hunk.header());
+
+            for edit in &hunk.edits {
+                let (sign, line) = match edit.kind {
+                    EditKind::Eql => (' ', &edit.a_line),
+                    EditKind::Ins => ('+', &edit.b_line),
+                    EditKind::Del => ('-', &edit.a_line),
+                };
+
+                let text = line.as_ref().map_or("", |line| &line.text[..]);
+
+                out.push(sign);
+                out.push_str(text);
+
+                if !text.ends_with('\n') {
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+
+        out
+    }
+
+    fn count(&self, kind: EditKind) -> usize {
+        self.edits.iter().filter(|edit| edit.kind == kind).count()
+    }
+
+    fn read(database: &Database, item: &Option<TreeEntry>) -> Result<String, DatabaseError> {
+        match item {
+            Some(item) if item.is_gitlink() => {
+                Ok(format!("Subproject commit {}\n", item.id.as_str))
+            }
+            Some(item) => {
+                let blob = database.load_blob(&item.id)?;
+
+                Ok(String::from_utf8_lossy(&blob.data).to_string())
+            }
+            None => Ok(String::new()),
+        }
+    }
+}
+
+/// Summarises a set of file diffs like `git diff --stat`.
+pub fn format_stat(diffs: &[FileDiff]) -> String {
+    let mut out = String::new();
+
+    let name_width = diffs.iter().map(|diff| diff.path.len()).max().unwrap_or(0);
+    let max_changes = diffs
+        .iter()
+        .map(|diff| diff.insertions() + diff.deletions())
+        .max()
+        .unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+
+    for diff in diffs {
+        let (mut ins, mut del) = (diff.insertions(), diff.deletions());
+        let changes = ins + del;
+
+        if max_changes > STAT_WIDTH {
+            ins = (ins * STAT_WIDTH).div_ceil(max_changes);
+            del = (del * STAT_WIDTH).div_ceil(max_changes);
+        }
+
+        let _ = writeln!(
+            out,
+            " {:name_width$} | {:>count_width$} {}{}",
+            diff.path,
+            changes,
+            "+".repeat(ins),
+            "-".repeat(del),
+            name_width = name_width,
+            count_width = count_width
+        );
+    }
+
+    let insertions: usize = diffs.iter().map(FileDiff::insertions).sum();
+    let deletions: usize = diffs.iter().map(FileDiff::deletions).sum();
+
+    let _ = write!(
+        out,
+        " {} file{} changed",
+        diffs.len(),
+        if diffs.len() == 1 { "" } else { "s" }
+    );
+
+    if insertions > 0 || deletions == 0 {
+        let _ = write!(
+            out,
+            ", {} insertion{}(+)",
+            insertions,
+            if insertions == 1 { "" } else { "s" }
+        );
+    }
+
+    if deletions > 0 {
+        let _ = write!(
+            out,
+            ", {} deletion{}(-)",
+            deletions,
+            if deletions == 1 { "" } else { "s" }
+        );
+    }
+
+    out
+}
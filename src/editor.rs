@@ -0,0 +1,75 @@
+use crate::{config::Config, errors::RitError, repository::Repository};
+use std::{env, fs, path::Path, process};
+
+const DEFAULT_EDITOR: &str = "vi";
+const COMMIT_MESSAGE_FILE: &str = "COMMIT_EDITMSG";
+
+/// Picks the editor for commit messages, following git's precedence.
+pub fn commit_editor(config: &Config) -> String {
+    env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| config.get("core.editor"))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Picks the editor for rebase todo lists, which may be set separately from
+/// the commit message editor.
+pub fn sequence_editor(config: &Config) -> String {
+    env::var("GIT_SEQUENCE_EDITOR")
+        .ok()
+        .or_else(|| config.get("sequence.editor"))
+        .unwrap_or_else(|| commit_editor(config))
+}
+
+/// Writes `content` to `path`, lets the user edit it and returns what was
+/// left with the comment lines removed.
+pub fn edit(editor: &str, path: &Path, content: &str) -> Result<String, RitError> {
+    Ok(strip_comments(&edit_raw(editor, path, content)?))
+}
+
+/// Like `edit`, but returns what was left as it is.
+pub fn edit_raw(editor: &str, path: &Path, content: &str) -> Result<String, RitError> {
+    fs::write(path, content)?;
+
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(path)
+        .status()?;
+
+    if !status.success() {
+        return Err(RitError::Editor(editor.to_string()));
+    }
+
+    Ok(fs::read_to_string(path)?)
+}
+
+/// Lets the user edit a commit message in `.git/COMMIT_EDITMSG`.
+pub fn edit_message(repo: &Repository, message: &str) -> Result<String, RitError> {
+    Ok(strip_comments(&edit_commit_file(
+        repo,
+        &format!("{}\n", message.trim_end()),
+    )?))
+}
+
+/// Lets the user edit `content` in `.git/COMMIT_EDITMSG`, leaving it to the
+/// caller to clean up what is returned.
+pub fn edit_commit_file(repo: &Repository, content: &str) -> Result<String, RitError> {
+    let editor = commit_editor(&repo.config);
+    let path = repo.git_path.join(COMMIT_MESSAGE_FILE);
+
+    edit_raw(&editor, &path, content)
+}
+
+pub fn strip_comments(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line.trim_end()))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
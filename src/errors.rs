@@ -1,4 +1,8 @@
-use crate::{index::IndexError, lockfile::LockError, refs::RefsError};
+use crate::{
+    config::ConfigError, database::DatabaseError, index::IndexError, lockfile::LockError,
+    reflog::ReflogError, refs::RefsError, revision::RevisionError, sequencer::SequencerError,
+    stash::StashError,
+};
 use std::{env, fmt, io};
 
 #[derive(Debug)]
@@ -7,8 +11,24 @@ pub enum RitError {
     Env,
     Index(IndexError),
     Lock(LockError),
+    Config(ConfigError),
     Refs(RefsError),
+    Database(DatabaseError),
+    Revision(RevisionError),
+    Sequencer(SequencerError),
+    Stash(StashError),
+    Reflog(ReflogError),
     MissingFile(String),
+    UnmergedFiles,
+    Editor(String),
+    EmptyTagMessage,
+    NothingToAmend,
+    CommitAborted(String),
+    InvalidPack(String),
+    CloneFailed(String),
+    Remote(String),
+    Fsmonitor(String),
+    Submodule(String),
     PermissionDenied(String),
     UnknownCommand(String),
 }
@@ -24,6 +44,31 @@ impl fmt::Display for RitError {
             RitError::PermissionDenied(pathname) => {
                 write!(f, "open('{}'): Permission denied", pathname)
             }
+            RitError::Refs(err) => write!(f, "{}", err),
+            RitError::Config(err) => write!(f, "{}", err),
+            RitError::Index(err) => write!(f, "{}", err),
+            RitError::Database(err) => write!(f, "{}", err),
+            RitError::Revision(err) => write!(f, "{}", err),
+            RitError::Sequencer(err) => write!(f, "{}", err),
+            RitError::Stash(err) => write!(f, "{}", err),
+            RitError::Reflog(err) => write!(f, "{}", err),
+            RitError::UnmergedFiles => write!(
+                f,
+                "Committing is not possible because you have unmerged files."
+            ),
+            RitError::Editor(editor) => {
+                write!(f, "There was a problem with the editor '{}'.", editor)
+            }
+            RitError::EmptyTagMessage => write!(f, "no tag message?"),
+            RitError::NothingToAmend => write!(f, "You have nothing to amend."),
+            RitError::InvalidPack(message)
+            | RitError::CloneFailed(message)
+            | RitError::Remote(message)
+            | RitError::Fsmonitor(message)
+            | RitError::CommitAborted(message)
+            | RitError::Submodule(message) => {
+                write!(f, "{}", message)
+            }
             err => write!(f, "Internal error: {:?}", err),
         }
     }
@@ -53,8 +98,44 @@ impl From<LockError> for RitError {
     }
 }
 
+impl From<ConfigError> for RitError {
+    fn from(err: ConfigError) -> RitError {
+        RitError::Config(err)
+    }
+}
+
 impl From<IndexError> for RitError {
     fn from(err: IndexError) -> RitError {
         RitError::Index(err)
     }
 }
+
+impl From<DatabaseError> for RitError {
+    fn from(err: DatabaseError) -> RitError {
+        RitError::Database(err)
+    }
+}
+
+impl From<RevisionError> for RitError {
+    fn from(err: RevisionError) -> RitError {
+        RitError::Revision(err)
+    }
+}
+
+impl From<SequencerError> for RitError {
+    fn from(err: SequencerError) -> RitError {
+        RitError::Sequencer(err)
+    }
+}
+
+impl From<StashError> for RitError {
+    fn from(err: StashError) -> RitError {
+        RitError::Stash(err)
+    }
+}
+
+impl From<ReflogError> for RitError {
+    fn from(err: ReflogError) -> RitError {
+        RitError::Reflog(err)
+    }
+}
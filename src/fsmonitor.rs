@@ -0,0 +1,130 @@
+use crate::remote::pkt_line;
+use std::{
+    io,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The socket `rit fsmonitor--daemon` listens on, where git's own daemon
+/// would, so that either can answer the other's queries.
+const SOCKET_NAME: &str = "fsmonitor--daemon.ipc";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const QUIT_COMMAND: &str = "quit";
+
+/// What the filesystem monitor reports as changed since a token: the paths,
+/// relative to the worktree and with a `/` after directories, whose changes
+/// cover everything below them. No paths means anything may have changed,
+/// as when the token is not one the monitor handed out.
+#[derive(Debug)]
+pub struct Changes {
+    pub token: String,
+    pub paths: Option<Vec<String>>,
+}
+
+impl Changes {
+    /// Whether the file at `pathname` may have changed.
+    pub fn includes(&self, pathname: &str) -> bool {
+        let paths = match &self.paths {
+            Some(paths) => paths,
+            None => return true,
+        };
+
+        paths.iter().any(|path| match path.strip_suffix('/') {
+            Some(dir) => pathname.starts_with(path) || pathname == dir,
+            None => pathname == path || pathname.starts_with(&format!("{}/", path)),
+        })
+    }
+
+    /// The answer to a query: the new token and then each path, all ending
+    /// with a NUL byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.token.as_bytes().to_vec();
+        data.push(0);
+
+        let everything = vec!["/".to_string()];
+
+        for path in self.paths.as_ref().unwrap_or(&everything) {
+            data.extend(path.as_bytes());
+            data.push(0);
+        }
+
+        data
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        let mut fields = data
+            .split(|b| *b == 0)
+            .map(|field| String::from_utf8_lossy(field).to_string());
+
+        let token = fields
+            .next()
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no token in answer"))?;
+        let paths: Vec<String> = fields.filter(|path| !path.is_empty()).collect();
+
+        let paths = if paths.iter().any(|path| path == "/") {
+            None
+        } else {
+            Some(paths)
+        };
+
+        Ok(Self { token, paths })
+    }
+}
+
+pub fn socket_path(git_path: &Path) -> PathBuf {
+    git_path.join(SOCKET_NAME)
+}
+
+/// Asks the daemon watching the worktree of `git_path` what changed since
+/// `token`.
+pub fn query(git_path: &Path, token: &str) -> io::Result<Changes> {
+    Changes::parse(&send(git_path, token.as_bytes())?)
+}
+
+/// Sends `request` to the daemon and returns its answer, in the framing of
+/// git's IPC: the message as pkt-lines, ended by a flush-pkt, both ways.
+pub fn send(git_path: &Path, request: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket_path(git_path))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    pkt_line::write_packetized(&mut stream, request)?;
+
+    pkt_line::read_packetized(&mut stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(paths: Option<Vec<&str>>) -> Changes {
+        Changes {
+            token: "rit:1:2".to_string(),
+            paths: paths.map(|paths| paths.into_iter().map(String::from).collect()),
+        }
+    }
+
+    #[test]
+    fn it_round_trips_answers() {
+        for changes in [changes(Some(vec!["a.txt", "b/"])), changes(None)] {
+            let parsed = Changes::parse(&changes.to_bytes()).unwrap();
+
+            assert_eq!(changes.token, parsed.token);
+            assert_eq!(changes.paths, parsed.paths);
+        }
+    }
+
+    #[test]
+    fn it_covers_the_paths_below_changed_directories() {
+        let changes = changes(Some(vec!["a.txt", "b/", "c"]));
+
+        assert!(changes.includes("a.txt"));
+        assert!(!changes.includes("a.txt.orig"));
+        assert!(changes.includes("b/x/y.txt"));
+        assert!(changes.includes("c/z.txt"));
+        assert!(!changes.includes("cc/z.txt"));
+    }
+}
@@ -1,4 +1,10 @@
-use std::convert::TryInto;
+use std::{
+    convert::TryInto,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+const SHORT_LEN: usize = 7;
 
 #[derive(Clone, Debug)]
 pub struct Id {
@@ -12,7 +18,7 @@ impl Id {
         let mut stringified = String::new();
 
         for byte in data.iter() {
-            stringified.push_str(&format!("{:x?}", byte));
+            stringified.push_str(&format!("{:02x}", byte));
         }
 
         Self {
@@ -20,6 +26,26 @@ impl Id {
             as_bytes: data.try_into().unwrap(),
         }
     }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+
+        if hex.len() != 40 {
+            return None;
+        }
+
+        let mut bytes = [0u8; 20];
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+
+        Some(Self::parse(&bytes))
+    }
+
+    pub fn short(&self) -> &str {
+        &self.as_str[..SHORT_LEN]
+    }
 }
 
 impl PartialEq for Id {
@@ -27,3 +53,17 @@ impl PartialEq for Id {
         self.as_bytes == other.as_bytes
     }
 }
+
+impl Eq for Id {}
+
+impl Hash for Id {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes.hash(state);
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str)
+    }
+}
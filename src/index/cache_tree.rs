@@ -0,0 +1,183 @@
+use super::IndexError;
+use crate::id::Id;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::BTreeMap;
+
+pub const SIGNATURE: &[u8] = "TREE".as_bytes();
+
+/// The `TREE` extension, which remembers the tree id of every directory in
+/// the index along with how many entries it spans, so that writing a tree
+/// only has to hash the directories whose entries changed since. Changing a
+/// path invalidates the directories leading to it, and the whole cache is
+/// valid again once a tree has been written from the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheTree {
+    id: Option<Id>,
+    entry_count: usize,
+    children: BTreeMap<String, CacheTree>,
+}
+
+impl CacheTree {
+    pub fn parse(data: &[u8]) -> Result<Self, IndexError> {
+        let mut pos = 0;
+        let (_, root) = Self::read_node(data, &mut pos)?;
+
+        if pos != data.len() {
+            return Err(corrupt());
+        }
+
+        Ok(root)
+    }
+
+    /// Serializes each directory as its name, the number of entries it
+    /// spans (`-1` once invalidated), the number of subdirectories and then
+    /// its tree id if it is valid, followed by those of its subdirectories.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        self.write_node("", &mut buf);
+
+        buf.freeze()
+    }
+
+    /// The id of the tree if it is still valid.
+    pub fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    /// Records the tree written for this directory, which spans
+    /// `entry_count` index entries.
+    pub fn set(&mut self, id: Id, entry_count: usize) {
+        self.id = Some(id);
+        self.entry_count = entry_count;
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> &mut CacheTree {
+        self.children.entry(name.to_string()).or_default()
+    }
+
+    /// Drops the subdirectories that are no longer in the index.
+    pub fn retain_children(&mut self, names: &[&String]) {
+        self.children.retain(|name, _| names.contains(&name));
+    }
+
+    /// Invalidates the directories leading to `pathname`, and forgets the
+    /// directory at `pathname` itself as it is replaced or removed.
+    pub fn invalidate(&mut self, pathname: &str) {
+        self.id = None;
+        self.entry_count = 0;
+
+        match pathname.split_once('/') {
+            Some((name, rest)) => {
+                if let Some(child) = self.children.get_mut(name) {
+                    child.invalidate(rest);
+                }
+            }
+            None => {
+                self.children.remove(pathname);
+            }
+        }
+    }
+
+    fn read_node(data: &[u8], pos: &mut usize) -> Result<(String, Self), IndexError> {
+        let rest = data.get(*pos..).ok_or_else(corrupt)?;
+        let null = rest.iter().position(|b| *b == 0).ok_or_else(corrupt)?;
+        let newline = rest.iter().position(|b| *b == b'\n').ok_or_else(corrupt)?;
+
+        if newline < null {
+            return Err(corrupt());
+        }
+
+        let name = String::from_utf8_lossy(&rest[..null]).to_string();
+        let counts = std::str::from_utf8(&rest[null + 1..newline]).map_err(|_| corrupt())?;
+        *pos += newline + 1;
+
+        let (entry_count, subtree_count) = counts.split_once(' ').ok_or_else(corrupt)?;
+        let entry_count: i64 = entry_count.parse().map_err(|_| corrupt())?;
+        let subtree_count: usize = subtree_count.parse().map_err(|_| corrupt())?;
+
+        let mut node = Self::default();
+
+        if entry_count >= 0 {
+            let id = data.get(*pos..*pos + 20).ok_or_else(corrupt)?;
+            *pos += 20;
+
+            node.set(Id::parse(id), entry_count as usize);
+        }
+
+        for _ in 0..subtree_count {
+            let (name, child) = Self::read_node(data, pos)?;
+            node.children.insert(name, child);
+        }
+
+        Ok((name, node))
+    }
+
+    fn write_node(&self, name: &str, buf: &mut BytesMut) {
+        let entry_count = match self.id {
+            Some(_) => self.entry_count as i64,
+            None => -1,
+        };
+
+        buf.put(name.as_bytes());
+        buf.put_u8(0);
+        buf.put(format!("{} {}\n", entry_count, self.children.len()).as_bytes());
+
+        if let Some(id) = &self.id {
+            buf.put(&id.as_bytes[..]);
+        }
+
+        for (name, child) in &self.children {
+            child.write_node(name, buf);
+        }
+    }
+}
+
+fn corrupt() -> IndexError {
+    IndexError::Parse("Corrupt cache tree extension in the index".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Id {
+        Id::parse(&[n; 20])
+    }
+
+    fn cache_tree() -> CacheTree {
+        let mut root = CacheTree::default();
+        root.child_mut("a").child_mut("b").set(id(3), 1);
+        root.child_mut("a").set(id(2), 2);
+        root.child_mut("c").set(id(4), 1);
+        root.set(id(1), 4);
+
+        root
+    }
+
+    #[test]
+    fn it_round_trips_through_the_extension_format() {
+        let root = cache_tree();
+
+        assert_eq!(root, CacheTree::parse(&root.to_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_invalidates_the_directories_leading_to_a_path() {
+        let mut root = cache_tree();
+        root.invalidate("a/b/file.txt");
+
+        assert_eq!(None, root.id());
+        assert_eq!(None, root.child_mut("a").id());
+        assert_eq!(None, root.child_mut("a").child_mut("b").id());
+        assert_eq!(Some(&id(4)), root.child_mut("c").id());
+
+        root.invalidate("c");
+
+        assert!(!root.children.contains_key("c"));
+        assert_eq!(root, CacheTree::parse(&root.to_bytes()).unwrap());
+    }
+}
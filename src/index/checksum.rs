@@ -1,4 +1,4 @@
-use super::{bytes_to_uint32, IndexError};
+use super::{bytes_to_uint32, Extension, IndexError};
 use crate::id;
 use std::{
     fs::File,
@@ -36,12 +36,27 @@ impl Checksum {
         Ok(buf)
     }
 
-    pub fn verify_checksum(&mut self) -> Result<(), IndexError> {
-        self.consume_extension()?;
+    /// Reads the signature and data of the next extension, or nothing once
+    /// only the checksum is left.
+    pub fn read_extension(&mut self) -> Result<Option<Extension>, IndexError> {
+        if self.consumed_len >= self.file_len.saturating_sub(20) {
+            return Ok(None);
+        }
+
+        let signature = self.read(4)?;
+
+        let size = self.read(4)?;
+        let size = bytes_to_uint32(&size[..]);
 
+        let data = self.read(size as usize)?;
+
+        Ok(Some((signature, data)))
+    }
+
+    pub fn verify_checksum(&mut self) -> Result<(), IndexError> {
         let mut stored_id = vec![0u8; 20];
 
-        self.file.read_exact(&mut stored_id).unwrap();
+        self.file.read_exact(&mut stored_id)?;
 
         let generated_id = self.id_generator.commit().as_bytes;
 
@@ -53,17 +68,4 @@ impl Checksum {
 
         Ok(())
     }
-
-    fn consume_extension(&mut self) -> Result<(), IndexError> {
-        while self.consumed_len < self.file_len - 20 {
-            let _signature = self.read(4)?;
-
-            let size = self.read(4)?;
-            let size = bytes_to_uint32(&size[..]);
-
-            self.read(size as usize)?;
-        }
-
-        Ok(())
-    }
 }
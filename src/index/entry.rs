@@ -1,11 +1,24 @@
 use super::{bytes_to_uint16, bytes_to_uint32};
-use crate::{id, workspace};
+use crate::{
+    id,
+    objects::{tree::GITLINK_MODE, TreeEntry},
+    workspace,
+};
 use bytes::{BufMut, Bytes, BytesMut};
 use std::path::PathBuf;
 
 const REGULAR_MODE: u32 = 0o100644;
 const EXECUTABLE_MODE: u32 = 0o100755;
 const MAX_PATH_SIZE: usize = 0xfff;
+const STAGE_SHIFT: usize = 12;
+const STAGE_MASK: usize = 0x3000;
+const EXTENDED_FLAG: usize = 0x4000;
+const INTENT_TO_ADD_FLAG: u16 = 0x2000;
+const SKIP_WORKTREE_FLAG: u16 = 0x4000;
+
+/// The size of an entry up to its pathname, without the extended flags that
+/// version 3 and later may add.
+pub const ENTRY_HEADER_SIZE: usize = 62;
 
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -15,6 +28,8 @@ pub struct Entry {
     pub stat: workspace::Stat,
     pub mode: u32,
     flags: usize,
+    extended_flags: u16,
+    fsmonitor_valid: bool,
 }
 
 impl Entry {
@@ -30,11 +45,83 @@ impl Entry {
                 MAX_PATH_SIZE
             },
             stat,
+            extended_flags: 0,
+            fsmonitor_valid: false,
+        }
+    }
+
+    pub fn create_from_db(pathname: &str, item: &TreeEntry, stage: u8) -> Self {
+        let path_len = pathname.len().min(MAX_PATH_SIZE);
+
+        Self {
+            id: item.id.clone(),
+            path: PathBuf::from(pathname),
+            pathname: pathname.to_string(),
+            stat: workspace::Stat::default(),
+            mode: item.mode,
+            flags: path_len | ((stage as usize) << STAGE_SHIFT),
+            extended_flags: 0,
+            fsmonitor_valid: false,
         }
     }
 
-    fn mode_for_stat(stat: &workspace::Stat) -> u32 {
-        if stat.is_executable() {
+    pub fn stage(&self) -> u8 {
+        ((self.flags & STAGE_MASK) >> STAGE_SHIFT) as u8
+    }
+
+    /// Whether the entry was added with `git add -N`, recording only that
+    /// the path will be added.
+    pub fn is_intent_to_add(&self) -> bool {
+        self.extended_flags & INTENT_TO_ADD_FLAG != 0
+    }
+
+    /// Whether the entry is left out of the workspace, as in a sparse
+    /// checkout.
+    pub fn is_skip_worktree(&self) -> bool {
+        self.extended_flags & SKIP_WORKTREE_FLAG != 0
+    }
+
+    /// Whether the filesystem monitor says the file is unchanged since the
+    /// entry was last checked, so that it needs no stat. This is kept in
+    /// the `FSMN` extension rather than in the entry.
+    pub fn is_fsmonitor_valid(&self) -> bool {
+        self.fsmonitor_valid
+    }
+
+    pub fn set_fsmonitor_valid(&mut self, valid: bool) {
+        self.fsmonitor_valid = valid;
+    }
+
+    /// Whether the entry needs the extended flags only index versions 3 and
+    /// later can store.
+    pub fn has_extended_flags(&self) -> bool {
+        self.extended_flags != 0
+    }
+
+    /// Whether the fixed-size part of an entry says that two bytes of
+    /// extended flags follow.
+    pub fn is_extended(header: &[u8]) -> bool {
+        bytes_to_uint16(&header[60..62]) as usize & EXTENDED_FLAG != 0
+    }
+
+    pub fn key(&self) -> (String, u8) {
+        (self.pathname.clone(), self.stage())
+    }
+
+    /// Whether the entry is a submodule, recording the commit checked out in
+    /// the repository nested at its path.
+    pub fn is_gitlink(&self) -> bool {
+        self.mode == GITLINK_MODE
+    }
+
+    /// The mode to record for what `stat` describes. Only the directory of a
+    /// nested repository is ever added as a directory, as a gitlink.
+    pub fn mode_for_stat(stat: &workspace::Stat) -> u32 {
+        if stat.is_dir() {
+            GITLINK_MODE
+        } else if stat.is_symlink() {
+            workspace::SYMLINK_MODE
+        } else if stat.is_executable() {
             EXECUTABLE_MODE
         } else {
             REGULAR_MODE
@@ -84,36 +171,57 @@ impl Entry {
     }
 }
 
-impl From<Entry> for Bytes {
-    fn from(entry: Entry) -> Self {
+impl Entry {
+    /// Serializes the entry for an index of the given `version`. Version 4
+    /// stores the pathname as the number of bytes to drop from the end of
+    /// `previous_pathname` followed by what to append, without padding.
+    pub fn to_bytes(&self, version: u32, previous_pathname: &str) -> Bytes {
         let mut buf = BytesMut::new();
 
-        buf.put_u32(entry.stat.ctime as u32);
-        buf.put_u32(entry.stat.ctime_nsec as u32);
-        buf.put_u32(entry.stat.mtime as u32);
-        buf.put_u32(entry.stat.mtime_nsec as u32);
-        buf.put_u32(entry.stat.dev as u32);
-        buf.put_u32(entry.stat.ino as u32);
-        buf.put_u32(entry.mode);
-        buf.put_u32(entry.stat.uid);
-        buf.put_u32(entry.stat.gid);
-        buf.put_u32(entry.stat.size as u32);
-        buf.put(&entry.id.as_bytes[..]);
-        buf.put_u16(entry.flags as u16);
-
-        let pathname = format!("{}\0", entry.pathname);
-        buf.put(pathname.as_bytes());
-
-        while buf.len() % 8 != 0 {
+        buf.put_u32(self.stat.ctime as u32);
+        buf.put_u32(self.stat.ctime_nsec as u32);
+        buf.put_u32(self.stat.mtime as u32);
+        buf.put_u32(self.stat.mtime_nsec as u32);
+        buf.put_u32(self.stat.dev as u32);
+        buf.put_u32(self.stat.ino as u32);
+        buf.put_u32(self.mode);
+        buf.put_u32(self.stat.uid);
+        buf.put_u32(self.stat.gid);
+        buf.put_u32(self.stat.size as u32);
+        buf.put(&self.id.as_bytes[..]);
+
+        if self.has_extended_flags() {
+            buf.put_u16((self.flags | EXTENDED_FLAG) as u16);
+            buf.put_u16(self.extended_flags);
+        } else {
+            buf.put_u16(self.flags as u16);
+        }
+
+        if version == 4 {
+            let common = previous_pathname
+                .bytes()
+                .zip(self.pathname.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            put_varint(&mut buf, previous_pathname.len() - common);
+            buf.put(&self.pathname.as_bytes()[common..]);
             buf.put_u8(0);
+        } else {
+            let pathname = format!("{}\0", self.pathname);
+            buf.put(pathname.as_bytes());
+
+            while !buf.len().is_multiple_of(8) {
+                buf.put_u8(0);
+            }
         }
 
         buf.freeze()
     }
-}
 
-impl From<Vec<u8>> for Entry {
-    fn from(data: Vec<u8>) -> Self {
+    /// Reads an entry from its fixed-size part, including the extended flags
+    /// if it has them, and its pathname.
+    pub fn parse(data: &[u8], pathname: String) -> Self {
         let ctime = bytes_to_uint32(&data[..4]);
         let ctime_nsec = bytes_to_uint32(&data[4..8]);
         let mtime = bytes_to_uint32(&data[8..12]);
@@ -125,18 +233,13 @@ impl From<Vec<u8>> for Entry {
         let gid = bytes_to_uint32(&data[32..36]);
         let size = bytes_to_uint32(&data[36..40]);
         let id = id::Id::parse(&data[40..60]);
-        let flags = bytes_to_uint16(&data[60..62]);
+        let flags = bytes_to_uint16(&data[60..62]) as usize;
 
-        let mut path: Vec<u8> = vec![];
-        let mut pos = 62;
-
-        while data[pos] != 0x00 {
-            path.push(data[pos]);
-
-            pos += 1;
-        }
-
-        let pathname = String::from_utf8_lossy(&path).to_string();
+        let extended_flags = if flags & EXTENDED_FLAG != 0 {
+            bytes_to_uint16(&data[62..64])
+        } else {
+            0
+        };
 
         Self {
             id,
@@ -155,7 +258,25 @@ impl From<Vec<u8>> for Entry {
                 size: size.into(),
             },
             mode,
-            flags: flags.into(),
+            flags: flags & !EXTENDED_FLAG,
+            extended_flags,
+            fsmonitor_valid: false,
         }
     }
 }
+
+/// Writes `value` the way version 4 encodes how much of the previous
+/// pathname to drop: seven bits per byte, most significant first, with the
+/// high bit set on all but the last byte and one subtracted from every
+/// group but the last.
+fn put_varint(buf: &mut BytesMut, mut value: usize) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+
+    while value >> 7 != 0 {
+        value = (value >> 7) - 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+    }
+
+    bytes.reverse();
+    buf.put(&bytes[..]);
+}
@@ -0,0 +1,88 @@
+use bytes::{BufMut, BytesMut};
+use std::convert::TryInto;
+
+/// Reads an EWAH compressed bitmap, as used by index extensions: its size in
+/// bits, then a number of 64-bit words in which a marker word says how many
+/// words of all zeros or all ones come next, followed by how many words to
+/// take as they are. Returns the bits and the number of bytes read, or
+/// `None` if the data is cut short.
+pub fn parse(data: &[u8]) -> Option<(Vec<bool>, usize)> {
+    let mut pos = 0;
+    let mut take = |len: usize| {
+        let bytes = data.get(pos..pos + len)?;
+        pos += len;
+
+        Some(bytes)
+    };
+
+    let bit_count = u32::from_be_bytes(take(4)?.try_into().ok()?) as usize;
+    let word_count = u32::from_be_bytes(take(4)?.try_into().ok()?) as usize;
+
+    let mut words = vec![];
+    let mut remaining = word_count;
+
+    while remaining > 0 {
+        let marker = u64::from_be_bytes(take(8)?.try_into().ok()?);
+        remaining -= 1;
+
+        let run_bit = marker & 1;
+        let run_len = (marker >> 1) & 0xffff_ffff;
+        let literal_count = (marker >> 33) as usize;
+
+        for _ in 0..run_len {
+            words.push(if run_bit == 1 { u64::MAX } else { 0 });
+        }
+
+        if literal_count > remaining {
+            return None;
+        }
+
+        for _ in 0..literal_count {
+            words.push(u64::from_be_bytes(take(8)?.try_into().ok()?));
+        }
+
+        remaining -= literal_count;
+    }
+
+    // the position of the last marker word, used when appending
+    take(4)?;
+
+    let bits = (0..bit_count)
+        .map(|n| {
+            words
+                .get(n / 64)
+                .is_some_and(|word| word >> (n % 64) & 1 == 1)
+        })
+        .collect();
+
+    Some((bits, pos))
+}
+
+/// Writes the bits as an EWAH bitmap with a single marker word followed by
+/// all the words as they are.
+pub fn write(buf: &mut BytesMut, bits: impl Iterator<Item = bool>) {
+    let mut words: Vec<u64> = vec![];
+    let mut bit_count = 0;
+
+    for bit in bits {
+        if bit_count % 64 == 0 {
+            words.push(0);
+        }
+
+        if bit {
+            *words.last_mut().unwrap() |= 1 << (bit_count % 64);
+        }
+
+        bit_count += 1;
+    }
+
+    buf.put_u32(bit_count as u32);
+    buf.put_u32(words.len() as u32 + 1);
+    buf.put_u64((words.len() as u64) << 33);
+
+    for word in words {
+        buf.put_u64(word);
+    }
+
+    buf.put_u32(0);
+}
@@ -0,0 +1,56 @@
+use super::{bytes_to_uint32, ewah, IndexError};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::convert::TryInto;
+
+pub const SIGNATURE: &[u8] = "FSMN".as_bytes();
+
+const VERSION: u32 = 2;
+
+/// Reads the `FSMN` extension, which holds the token of the last answer
+/// from the filesystem monitor and a bitmap of the entries, in index order,
+/// that were not known to be unchanged as of that answer. Version 1 holds a
+/// timestamp in place of the token.
+pub fn parse(data: &[u8]) -> Result<(String, Vec<bool>), IndexError> {
+    let version = bytes_to_uint32(data.get(0..4).ok_or_else(corrupt)?);
+
+    let (token, rest) = match version {
+        1 => {
+            let timestamp = data.get(4..12).ok_or_else(corrupt)?;
+            let timestamp = u64::from_be_bytes(timestamp.try_into().unwrap());
+
+            (timestamp.to_string(), &data[12..])
+        }
+        2 => {
+            let rest = &data[4..];
+            let null = rest.iter().position(|b| *b == 0).ok_or_else(corrupt)?;
+            let token = String::from_utf8_lossy(&rest[..null]).to_string();
+
+            (token, &rest[null + 1..])
+        }
+        _ => return Err(corrupt()),
+    };
+
+    let size = bytes_to_uint32(rest.get(0..4).ok_or_else(corrupt)?) as usize;
+    let bitmap = rest.get(4..4 + size).ok_or_else(corrupt)?;
+    let (dirty, _) = ewah::parse(bitmap).ok_or_else(corrupt)?;
+
+    Ok((token, dirty))
+}
+
+pub fn to_bytes(token: &str, dirty: impl Iterator<Item = bool>) -> Bytes {
+    let mut bitmap = BytesMut::new();
+    ewah::write(&mut bitmap, dirty);
+
+    let mut buf = BytesMut::new();
+    buf.put_u32(VERSION);
+    buf.put(token.as_bytes());
+    buf.put_u8(0);
+    buf.put_u32(bitmap.len() as u32);
+    buf.put(bitmap);
+
+    buf.freeze()
+}
+
+fn corrupt() -> IndexError {
+    IndexError::Parse("Corrupt fsmonitor extension in the index".to_string())
+}
@@ -1,10 +1,14 @@
-use super::{bytes_to_uint32, Checksum, Entry, IndexError};
+use super::{
+    bytes_to_uint32, cache_tree, entry::ENTRY_HEADER_SIZE, fsmonitor, untracked_cache, CacheTree,
+    Checksum, Entry, Extension, IndexError, UntrackedCache,
+};
 use crate::{
     id,
     lockfile::{LockError, Lockfile},
+    objects::TreeEntry,
     workspace,
 };
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{BufMut, BytesMut};
 use sorted_vec::SortedSet;
 use std::{
     collections::{HashMap, HashSet},
@@ -13,23 +17,36 @@ use std::{
     path::PathBuf,
 };
 
-const VERSION: u32 = 2;
+const DEFAULT_VERSION: u32 = 2;
+const VERSIONS: [u32; 3] = [2, 3, 4];
 const HEADER_SIZE: usize = 12;
 const SIGNATURE: &[u8] = "DIRC".as_bytes();
-const ENTRY_MIN_SIZE: usize = 64;
+const ENTRY_BLOCK_SIZE: usize = 8;
+
+/// Extensions locating the entries by their offsets in the file, which a
+/// rewrite invalidates. git builds them again as it writes the index.
+const OFFSET_EXTENSIONS: [&[u8]; 2] = [b"EOIE", b"IEOT"];
+
+type EntryKey = (String, u8);
 
 pub struct Index {
     lockfile: Lockfile,
-    entries: HashMap<String, Entry>,
-    entry_path_names: SortedSet<String>,
+    entries: HashMap<EntryKey, Entry>,
+    entry_keys: SortedSet<EntryKey>,
     parents: HashMap<String, HashSet<String>>,
     id_builder: id::Additive,
     is_changed: bool,
+    version: u32,
+    default_version: u32,
+    cache_tree: Option<CacheTree>,
+    untracked_cache: Option<UntrackedCache>,
+    fsmonitor_token: Option<String>,
+    extensions: Vec<Extension>,
 }
 
 pub struct IndexIter<'a> {
-    entries: &'a HashMap<String, Entry>,
-    entry_path_names: &'a SortedSet<String>,
+    entries: &'a HashMap<EntryKey, Entry>,
+    entry_keys: &'a SortedSet<EntryKey>,
     cur: usize,
 }
 
@@ -37,13 +54,13 @@ impl<'a> Iterator for IndexIter<'a> {
     type Item = &'a Entry;
 
     fn next(&mut self) -> Option<&'a Entry> {
-        let name = self.entry_path_names.get(self.cur);
+        let key = self.entry_keys.get(self.cur);
 
-        match name {
-            Some(n) => {
+        match key {
+            Some(k) => {
                 self.cur += 1;
 
-                self.entries.get(n)
+                self.entries.get(k)
             }
             _ => None,
         }
@@ -55,17 +72,33 @@ impl Index {
         Self {
             lockfile: Lockfile::new(path),
             entries: HashMap::new(),
-            entry_path_names: SortedSet::new(),
+            entry_keys: SortedSet::new(),
             parents: HashMap::new(),
             id_builder: id::Additive::new(),
             is_changed: false,
+            version: DEFAULT_VERSION,
+            default_version: DEFAULT_VERSION,
+            cache_tree: None,
+            untracked_cache: None,
+            fsmonitor_token: None,
+            extensions: vec![],
         }
     }
 
-    pub fn iter(&mut self) -> IndexIter {
+    /// Sets the version a new index is written in, as `index.version` does.
+    /// An existing index keeps the version it was read in, and versions 2
+    /// and 3 are written as whichever of the two the entries need.
+    pub fn set_default_version(&mut self, version: u32) {
+        if VERSIONS.contains(&version) {
+            self.default_version = version;
+            self.version = version;
+        }
+    }
+
+    pub fn iter(&mut self) -> IndexIter<'_> {
         IndexIter {
             entries: &self.entries,
-            entry_path_names: &self.entry_path_names,
+            entry_keys: &self.entry_keys,
             cur: 0,
         }
     }
@@ -84,9 +117,11 @@ impl Index {
         if let Ok(f) = file {
             let mut reader = Checksum::new(f)?;
 
-            let entries_count = self.read_header(&mut reader)?;
+            let (version, entries_count) = self.read_header(&mut reader)?;
+            self.version = version;
 
             self.read_entries(&mut reader, entries_count)?;
+            self.read_extensions(&mut reader)?;
 
             reader.verify_checksum()?;
         };
@@ -95,31 +130,165 @@ impl Index {
     }
 
     pub fn is_tracked(&self, pathname: &str) -> bool {
-        self.entries.contains_key(pathname) || self.parents.contains_key(pathname)
+        self.is_tracked_file(pathname) || self.parents.contains_key(pathname)
+    }
+
+    pub fn is_tracked_file(&self, pathname: &str) -> bool {
+        (0..=3).any(|stage| self.entries.contains_key(&(pathname.to_string(), stage)))
+    }
+
+    pub fn entry_for_path(&self, pathname: &str) -> Option<&Entry> {
+        self.entries.get(&(pathname.to_string(), 0))
     }
 
     pub fn add(&mut self, workspace_entry: workspace::Entry, id: id::Id, stat: workspace::Stat) {
         let entry = Entry::new(workspace_entry, id, stat);
 
+        let unchanged = self
+            .entry_for_path(&entry.pathname)
+            .is_some_and(|old| old.id == entry.id && old.mode == entry.mode);
+
+        if !unchanged {
+            self.invalidate_caches(&entry.pathname);
+        }
+
         self.discard_conflicts(&entry);
+        self.remove_entry(&entry.pathname);
 
-        self.add_parents(&entry);
+        self.store_entry(entry);
+
+        self.is_changed = true;
+    }
+
+    /// Stages the blob behind `item` without a matching workspace stat, so the
+    /// next status check compares the file contents.
+    pub fn add_from_db(&mut self, pathname: &str, item: &TreeEntry) {
+        self.invalidate_caches(pathname);
+        self.remove_entry(pathname);
+        self.store_entry(Entry::create_from_db(pathname, item, 0));
 
-        self.entry_path_names.insert(entry.pathname.clone());
-        self.entries.insert(entry.pathname.clone(), entry);
+        self.is_changed = true;
+    }
+
+    /// Replaces the entry at `pathname` with the base, ours and theirs versions
+    /// of a conflicted merge, stored as stages 1, 2 and 3.
+    pub fn add_conflict_set(&mut self, pathname: &str, items: [Option<TreeEntry>; 3]) {
+        self.invalidate_caches(pathname);
+        self.remove_entry(pathname);
+
+        for (stage, item) in items.iter().enumerate() {
+            if let Some(item) = item {
+                let entry = Entry::create_from_db(pathname, item, stage as u8 + 1);
+
+                self.store_entry(entry);
+            }
+        }
+
+        self.is_changed = true;
+    }
+
+    pub fn remove(&mut self, pathname: &str) {
+        if self.is_tracked(pathname) {
+            self.invalidate_caches(pathname);
+        }
+
+        if let Some(children) = self.parents.get(pathname).cloned() {
+            for child in children {
+                self.remove_entry(&child);
+            }
+        }
+
+        self.remove_entry(pathname);
 
         self.is_changed = true;
     }
 
+    /// The tree ids recorded for the directories of the index, if any.
+    pub fn cache_tree(&self) -> Option<&CacheTree> {
+        self.cache_tree.as_ref()
+    }
+
+    /// Records the tree ids of the directories after writing a tree from the
+    /// index, to be saved with the next update.
+    pub fn set_cache_tree(&mut self, cache_tree: CacheTree) {
+        if self.cache_tree.as_ref() != Some(&cache_tree) {
+            self.cache_tree = Some(cache_tree);
+            self.is_changed = true;
+        }
+    }
+
+    /// The untracked files recorded for the directories of the workspace,
+    /// if any.
+    pub fn untracked_cache(&self) -> Option<&UntrackedCache> {
+        self.untracked_cache.as_ref()
+    }
+
+    /// Replaces or drops the untracked cache, to be saved with the next
+    /// update.
+    pub fn set_untracked_cache(&mut self, untracked_cache: Option<UntrackedCache>) {
+        if self.untracked_cache != untracked_cache {
+            self.untracked_cache = untracked_cache;
+            self.is_changed = true;
+        }
+    }
+
+    /// The token of the last answer from the filesystem monitor, which the
+    /// next query asks for the changes since.
+    pub fn fsmonitor_token(&self) -> Option<&str> {
+        self.fsmonitor_token.as_deref()
+    }
+
+    /// Records the token of an answer from the filesystem monitor, or stops
+    /// using one, in which case no entry is known unchanged anymore.
+    pub fn set_fsmonitor_token(&mut self, token: Option<String>) {
+        if token.is_none() {
+            for entry in self.entries.values_mut() {
+                entry.set_fsmonitor_valid(false);
+            }
+        }
+
+        if self.fsmonitor_token != token {
+            self.fsmonitor_token = token;
+            self.is_changed = true;
+        }
+    }
+
+    pub fn set_fsmonitor_valid(&mut self, pathname: &str, valid: bool) {
+        if let Some(entry) = self.entries.get_mut(&(pathname.to_string(), 0)) {
+            if entry.is_fsmonitor_valid() != valid {
+                entry.set_fsmonitor_valid(valid);
+                self.is_changed = true;
+            }
+        }
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.entries.values().any(|entry| entry.stage() > 0)
+    }
+
+    pub fn conflict_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .entries
+            .values()
+            .filter(|entry| entry.stage() > 0)
+            .map(|entry| entry.pathname.clone())
+            .collect();
+
+        paths.sort();
+        paths.dedup();
+
+        paths
+    }
+
     pub fn entries(&self) -> Vec<Entry> {
         let mut entries = self
             .entries
-            .iter()
+            .values()
             // TODO: -clone
-            .map(|(_, entry)| entry.clone())
+            .cloned()
             .collect::<Vec<_>>();
 
-        entries.sort_by_key(|entry| entry.pathname.clone());
+        entries.sort_by_key(|entry| entry.key());
 
         entries
     }
@@ -131,14 +300,40 @@ impl Index {
             return Ok(());
         }
 
+        self.version = self.write_version();
         self.write_header()?;
 
-        for entry in self.entries() {
-            let data: Bytes = entry.into();
+        let mut previous_pathname = String::new();
+
+        let entries = self.entries();
+
+        for entry in &entries {
+            let data = entry.to_bytes(self.version, &previous_pathname);
             let data = &data[..];
 
             self.lockfile.write(data)?;
             self.id_builder.add(data);
+
+            previous_pathname = entry.pathname.clone();
+        }
+
+        if let Some(cache_tree) = &self.cache_tree {
+            self.write_extension(cache_tree::SIGNATURE, &cache_tree.to_bytes())?;
+        }
+
+        if let Some(untracked_cache) = &self.untracked_cache {
+            self.write_extension(untracked_cache::SIGNATURE, &untracked_cache.to_bytes())?;
+        }
+
+        if let Some(token) = &self.fsmonitor_token {
+            let dirty = entries.iter().map(|entry| !entry.is_fsmonitor_valid());
+            let data = fsmonitor::to_bytes(token, dirty);
+
+            self.write_extension(fsmonitor::SIGNATURE, &data)?;
+        }
+
+        for (signature, data) in self.extensions.clone() {
+            self.write_extension(&signature, &data)?;
         }
 
         let id = self.id_builder.commit();
@@ -151,17 +346,31 @@ impl Index {
         Ok(())
     }
 
+    pub fn is_locked(&self) -> bool {
+        self.lockfile.is_held()
+    }
+
     pub fn release_lock(&mut self) -> Result<(), LockError> {
         self.lockfile.rollback()
     }
 
     pub fn update_entry_stat(&mut self, pathname: &str, stat: &workspace::Stat) {
-        if let Some(entry) = self.entries.get_mut(pathname) {
+        if let Some(entry) = self.entries.get_mut(&(pathname.to_string(), 0)) {
             entry.update_stat(stat);
             self.is_changed = true;
         }
     }
 
+    fn invalidate_caches(&mut self, pathname: &str) {
+        if let Some(cache_tree) = &mut self.cache_tree {
+            cache_tree.invalidate(pathname);
+        }
+
+        if let Some(untracked_cache) = &mut self.untracked_cache {
+            untracked_cache.invalidate(pathname);
+        }
+    }
+
     fn discard_conflicts(&mut self, entry: &Entry) {
         let parents = entry.parents();
 
@@ -178,15 +387,30 @@ impl Index {
         };
     }
 
+    fn store_entry(&mut self, entry: Entry) {
+        self.add_parents(&entry);
+
+        self.entry_keys.insert(entry.key());
+        self.entries.insert(entry.key(), entry);
+    }
+
     fn remove_entry(&mut self, pathname: &str) {
-        self.entry_path_names.remove_item(&pathname.to_string());
-        let entry = self.entries.remove(pathname);
+        let mut removed = None;
 
-        if entry.is_none() {
-            return;
+        for stage in 0..=3 {
+            let key = (pathname.to_string(), stage);
+
+            self.entry_keys.remove_item(&key);
+
+            if let Some(entry) = self.entries.remove(&key) {
+                removed = Some(entry);
+            }
         }
 
-        let entry = entry.unwrap();
+        let entry = match removed {
+            Some(entry) => entry,
+            None => return,
+        };
 
         for parent in entry.parents() {
             let dirname = parent.to_str().unwrap();
@@ -203,7 +427,7 @@ impl Index {
         }
     }
 
-    fn read_header(&self, reader: &mut Checksum) -> Result<u32, IndexError> {
+    fn read_header(&self, reader: &mut Checksum) -> Result<(u32, u32), IndexError> {
         let data = reader.read(HEADER_SIZE)?;
 
         let signature = &data[..4];
@@ -220,36 +444,108 @@ impl Index {
             return Err(IndexError::Parse(msg));
         };
 
-        if version != VERSION {
-            let msg = format!("Version: expected '{}' but found '{}'", VERSION, version,);
+        if !VERSIONS.contains(&version) {
+            let msg = format!("Version: expected '2', '3' or '4' but found '{}'", version);
 
             return Err(IndexError::Parse(msg));
         }
 
-        Ok(count)
+        Ok((version, count))
     }
 
     fn read_entries(&mut self, reader: &mut Checksum, count: u32) -> Result<(), IndexError> {
+        let mut previous_pathname = vec![];
+
         for _ in 0..count {
-            let mut bytes = reader.read(ENTRY_MIN_SIZE)?;
+            let mut header = reader.read(ENTRY_HEADER_SIZE)?;
+
+            if Entry::is_extended(&header) {
+                if self.version < 3 {
+                    let msg = "Extended flags in a version 2 index".to_string();
 
-            while bytes.last() != Some(&0x00) {
-                let mut chunk = reader.read(8)?;
+                    return Err(IndexError::Parse(msg));
+                }
 
-                bytes.append(&mut chunk);
+                header.append(&mut reader.read(2)?);
             }
 
-            let entry = Entry::from(bytes);
+            let pathname = if self.version == 4 {
+                read_compressed_pathname(reader, &mut previous_pathname)?
+            } else {
+                read_padded_pathname(reader, header.len())?
+            };
+
+            let entry = Entry::parse(&header, pathname);
+
+            self.store_entry(entry);
+        }
 
-            self.add_parents(&entry);
+        Ok(())
+    }
 
-            self.entry_path_names.insert(entry.pathname.clone());
-            self.entries.insert(entry.pathname.clone(), entry);
+    /// Reads the extensions after the entries. Those not understood are kept
+    /// as they are to be written back, unless their signature starts with a
+    /// lowercase letter, which marks them as required to read the index.
+    fn read_extensions(&mut self, reader: &mut Checksum) -> Result<(), IndexError> {
+        while let Some((signature, data)) = reader.read_extension()? {
+            if signature == cache_tree::SIGNATURE {
+                self.cache_tree = Some(CacheTree::parse(&data)?);
+            } else if signature == untracked_cache::SIGNATURE {
+                // the cache only saves time, so one that cannot be read is
+                // dropped and built again
+                self.untracked_cache = UntrackedCache::parse(&data).ok();
+            } else if signature == fsmonitor::SIGNATURE {
+                // like the untracked cache, a monitor token that cannot be
+                // used only means checking every entry again
+                if let Ok((token, dirty)) = fsmonitor::parse(&data) {
+                    self.apply_fsmonitor(token, dirty);
+                }
+            } else if !signature[0].is_ascii_uppercase() {
+                let msg = format!(
+                    "Index uses {} extension, which we do not understand",
+                    String::from_utf8_lossy(&signature)
+                );
+
+                return Err(IndexError::Parse(msg));
+            } else if !OFFSET_EXTENSIONS.contains(&&signature[..]) {
+                self.extensions.push((signature, data));
+            }
         }
 
         Ok(())
     }
 
+    fn apply_fsmonitor(&mut self, token: String, dirty: Vec<bool>) {
+        if dirty.len() > self.entry_keys.len() {
+            return;
+        }
+
+        for (n, key) in self.entry_keys.iter().enumerate() {
+            let valid = !dirty.get(n).copied().unwrap_or(false);
+            self.entries
+                .get_mut(key)
+                .unwrap()
+                .set_fsmonitor_valid(valid);
+        }
+
+        self.fsmonitor_token = Some(token);
+    }
+
+    /// Version 4 is kept unless the default changes; otherwise an index is
+    /// written as version 3 only when an entry needs the extended flags.
+    fn write_version(&self) -> u32 {
+        let extended = self
+            .entries
+            .values()
+            .any(|entry| entry.has_extended_flags());
+
+        match self.version {
+            4 => 4,
+            _ if extended => 3,
+            _ => 2,
+        }
+    }
+
     fn add_parents(&mut self, entry: &Entry) {
         for parent in entry.parents() {
             let parent_pathname: String = parent.to_string_lossy().into();
@@ -270,9 +566,15 @@ impl Index {
 
     fn clear(&mut self) {
         self.entries = HashMap::new();
+        self.entry_keys = SortedSet::new();
         self.parents = HashMap::new();
         self.id_builder = id::Additive::new();
         self.is_changed = false;
+        self.version = self.default_version;
+        self.cache_tree = None;
+        self.untracked_cache = None;
+        self.fsmonitor_token = None;
+        self.extensions = vec![];
     }
 
     fn open_index_file(&self) -> Result<File, io::Error> {
@@ -283,7 +585,7 @@ impl Index {
         let mut buf = BytesMut::new();
 
         buf.put(SIGNATURE);
-        buf.put_u32(VERSION);
+        buf.put_u32(self.version);
         buf.put_u32(self.entries.len() as u32);
 
         self.lockfile.write(&buf[..])?;
@@ -291,6 +593,68 @@ impl Index {
 
         Ok(())
     }
+
+    fn write_extension(&mut self, signature: &[u8], data: &[u8]) -> Result<(), LockError> {
+        let mut buf = BytesMut::new();
+
+        buf.put(signature);
+        buf.put_u32(data.len() as u32);
+        buf.put(data);
+
+        self.lockfile.write(&buf[..])?;
+        self.id_builder.add(&buf[..]);
+
+        Ok(())
+    }
+}
+
+/// Reads a NUL-terminated pathname along with the NULs that pad the entry,
+/// of `header_len` bytes so far, to a multiple of eight bytes.
+fn read_padded_pathname(reader: &mut Checksum, header_len: usize) -> Result<String, IndexError> {
+    let padded_len = (header_len / ENTRY_BLOCK_SIZE + 1) * ENTRY_BLOCK_SIZE;
+    let mut bytes = reader.read(padded_len - header_len)?;
+
+    while bytes.last() != Some(&0x00) {
+        let mut chunk = reader.read(ENTRY_BLOCK_SIZE)?;
+
+        bytes.append(&mut chunk);
+    }
+
+    let end = bytes.iter().position(|b| *b == 0x00).unwrap_or(bytes.len());
+
+    Ok(String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+/// Reads a version 4 pathname, which drops a number of bytes from the end
+/// of the previous one and appends a NUL-terminated suffix.
+fn read_compressed_pathname(
+    reader: &mut Checksum,
+    previous_pathname: &mut Vec<u8>,
+) -> Result<String, IndexError> {
+    let mut byte = reader.read(1)?[0];
+    let mut strip = (byte & 0x7f) as usize;
+
+    while byte & 0x80 != 0 {
+        byte = reader.read(1)?[0];
+        strip = ((strip + 1) << 7) | (byte & 0x7f) as usize;
+    }
+
+    if strip > previous_pathname.len() {
+        return Err(IndexError::Parse(
+            "Malformed name field in the index".to_string(),
+        ));
+    }
+
+    previous_pathname.truncate(previous_pathname.len() - strip);
+
+    loop {
+        match reader.read(1)?[0] {
+            0x00 => break,
+            byte => previous_pathname.push(byte),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(previous_pathname).to_string())
 }
 
 #[cfg(test)]
@@ -1,12 +1,21 @@
+mod cache_tree;
 mod checksum;
 mod entry;
 mod errors;
+mod ewah;
+mod fsmonitor;
 mod index;
+mod untracked_cache;
 
+pub use cache_tree::CacheTree;
 use checksum::Checksum;
 pub use entry::Entry;
 pub use errors::IndexError;
 pub use index::{Index, IndexIter};
+pub use untracked_cache::{UntrackedCache, UntrackedDir};
+
+/// The signature and data of an index extension.
+type Extension = (Vec<u8>, Vec<u8>);
 
 fn bytes_to_uint32(bytes: &[u8]) -> u32 {
     let mut num = [0u8; 4];
@@ -0,0 +1,467 @@
+use super::{bytes_to_uint32, ewah, IndexError};
+use crate::{id::Id, workspace::Stat};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::{collections::BTreeMap, path::Path};
+
+pub const SIGNATURE: &[u8] = "UNTR".as_bytes();
+
+/// The directory flags of `git status` showing untracked directories as a
+/// whole and hiding those without any files, which is how rit lists them.
+const DIR_FLAGS: u32 = 0b110;
+const EXCLUDE_PER_DIR: &str = ".gitignore";
+const STAT_DATA_SIZE: usize = 36;
+const NULL_ID: [u8; 20] = [0; 20];
+
+/// The `UNTR` extension, which remembers the untracked files and
+/// directories found in every directory along with the stat data of the
+/// directory, so that listing untracked files only reads the directories
+/// that changed since.
+///
+/// git also records what its exclude files hashed to, so that the lists it
+/// made can be trusted only as long as those stay the same. rit does not
+/// read exclude files, so it leaves alone whatever lists were made with
+/// some, and writes none itself to make git list the directories again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UntrackedCache {
+    idents: Vec<u8>,
+    info_exclude: ([u8; STAT_DATA_SIZE], [u8; 20]),
+    excludes_file: ([u8; STAT_DATA_SIZE], [u8; 20]),
+    dir_flags: u32,
+    exclude_per_dir: String,
+    root: Option<UntrackedDir>,
+}
+
+/// What the untracked cache knows about a directory: the untracked entries
+/// in it, with a `/` after directories, and the directories below it that
+/// were looked into. `check_only` marks untracked directories, which are
+/// only looked into to see if they hold any files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UntrackedDir {
+    pub valid: bool,
+    pub check_only: bool,
+    pub stat_data: [u32; 9],
+    pub exclude_id: Option<Id>,
+    pub untracked: Vec<String>,
+    pub children: BTreeMap<String, UntrackedDir>,
+}
+
+impl UntrackedDir {
+    /// A freshly listed directory with the given `stat`.
+    pub fn new(stat: &Stat, check_only: bool) -> Self {
+        Self {
+            valid: true,
+            check_only,
+            stat_data: stat_data(stat),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the list can be reused for a directory that now has `stat`.
+    pub fn is_fresh(&self, stat: &Stat, check_only: bool) -> bool {
+        self.valid
+            && self.check_only == check_only
+            && self.exclude_id.is_none()
+            && self.stat_data == stat_data(stat)
+    }
+
+    /// Marks the directories leading to `pathname` as needing to be listed
+    /// again, as its tracked state changed.
+    pub fn invalidate(&mut self, pathname: &str) {
+        self.valid = false;
+        self.untracked.clear();
+
+        if let Some((name, rest)) = pathname.split_once('/') {
+            if let Some(child) = self.children.get_mut(name) {
+                child.invalidate(rest);
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        1 + self
+            .children
+            .values()
+            .map(UntrackedDir::count)
+            .sum::<usize>()
+    }
+
+    fn read(reader: &mut Reader) -> Result<(String, Self), IndexError> {
+        let untracked_count = reader.varint()?;
+        let dir_count = reader.varint()?;
+        let name = reader.string()?;
+
+        let mut dir = Self::default();
+
+        for _ in 0..untracked_count {
+            dir.untracked.push(reader.string()?);
+        }
+
+        for _ in 0..dir_count {
+            let (name, child) = Self::read(reader)?;
+            dir.children.insert(name, child);
+        }
+
+        Ok((name, dir))
+    }
+
+    /// Visits the directories in the order their blocks are written.
+    fn visit<'a>(&'a self, dirs: &mut Vec<&'a UntrackedDir>) {
+        dirs.push(self);
+
+        for child in self.children.values() {
+            child.visit(dirs);
+        }
+    }
+
+    /// Calls `f` with the position of each directory in the order their
+    /// blocks are written.
+    fn visit_mut<F>(&mut self, n: &mut usize, f: &mut F) -> Result<(), IndexError>
+    where
+        F: FnMut(usize, &mut UntrackedDir) -> Result<(), IndexError>,
+    {
+        f(*n, self)?;
+        *n += 1;
+
+        for child in self.children.values_mut() {
+            child.visit_mut(n, f)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, name: &str, buf: &mut BytesMut) {
+        put_varint(buf, self.untracked.len());
+        put_varint(buf, self.children.len());
+        put_string(buf, name);
+
+        for name in &self.untracked {
+            put_string(buf, name);
+        }
+
+        for (name, child) in &self.children {
+            child.write(name, buf);
+        }
+    }
+}
+
+impl UntrackedCache {
+    /// An empty cache for the worktree at `worktree`.
+    pub fn new(worktree: &Path) -> Self {
+        let mut idents = ident(worktree).into_bytes();
+        idents.push(0);
+
+        Self {
+            idents,
+            info_exclude: ([0; STAT_DATA_SIZE], NULL_ID),
+            excludes_file: ([0; STAT_DATA_SIZE], NULL_ID),
+            dir_flags: DIR_FLAGS,
+            exclude_per_dir: EXCLUDE_PER_DIR.to_string(),
+            root: None,
+        }
+    }
+
+    /// Whether the cache was made for the worktree at `worktree` and with
+    /// the same way of listing directories as rit's.
+    pub fn is_usable(&self, worktree: &Path) -> bool {
+        let ident = ident(worktree);
+
+        self.dir_flags == DIR_FLAGS
+            && self
+                .idents
+                .split(|b| *b == 0)
+                .any(|known| known == ident.as_bytes())
+    }
+
+    /// The lists of the directories, unless git made them with exclude
+    /// files that rit does not read.
+    pub fn root(&self) -> Option<&UntrackedDir> {
+        if self.info_exclude.1 != NULL_ID || self.excludes_file.1 != NULL_ID {
+            return None;
+        }
+
+        self.root.as_ref()
+    }
+
+    /// Replaces the lists of the directories with those rit made.
+    pub fn set_root(&mut self, root: UntrackedDir) {
+        self.info_exclude = ([0; STAT_DATA_SIZE], NULL_ID);
+        self.excludes_file = ([0; STAT_DATA_SIZE], NULL_ID);
+        self.root = Some(root);
+    }
+
+    pub fn invalidate(&mut self, pathname: &str) {
+        if let Some(root) = &mut self.root {
+            root.invalidate(pathname);
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, IndexError> {
+        let mut reader = Reader { data, pos: 0 };
+
+        let idents_len = reader.varint()?;
+        let idents = reader.bytes(idents_len)?.to_vec();
+
+        let info_exclude_stat = reader.array()?;
+        let excludes_file_stat = reader.array()?;
+        let dir_flags = bytes_to_uint32(reader.bytes(4)?);
+        let info_exclude_id = reader.array()?;
+        let excludes_file_id = reader.array()?;
+        let exclude_per_dir = reader.string()?;
+
+        let mut cache = Self {
+            idents,
+            info_exclude: (info_exclude_stat, info_exclude_id),
+            excludes_file: (excludes_file_stat, excludes_file_id),
+            dir_flags,
+            exclude_per_dir,
+            root: None,
+        };
+
+        let dir_count = reader.varint()?;
+
+        if dir_count == 0 {
+            return Ok(cache);
+        }
+
+        let (_, mut root) = UntrackedDir::read(&mut reader)?;
+
+        if root.count() != dir_count {
+            return Err(corrupt());
+        }
+
+        let valid = reader.bitmap()?;
+        let check_only = reader.bitmap()?;
+        let exclude_valid = reader.bitmap()?;
+        let is_set = |bitmap: &[bool], n: usize| bitmap.get(n).copied().unwrap_or(false);
+
+        root.visit_mut(&mut 0, &mut |n, dir| {
+            dir.check_only = is_set(&check_only, n);
+            dir.valid = is_set(&valid, n);
+
+            if dir.valid {
+                for value in dir.stat_data.iter_mut() {
+                    *value = bytes_to_uint32(reader.bytes(4)?);
+                }
+            }
+
+            Ok(())
+        })?;
+
+        root.visit_mut(&mut 0, &mut |n, dir| {
+            if is_set(&exclude_valid, n) {
+                dir.exclude_id = Some(Id::parse(reader.bytes(20)?));
+            }
+
+            Ok(())
+        })?;
+
+        cache.root = Some(root);
+
+        Ok(cache)
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        put_varint(&mut buf, self.idents.len());
+        buf.put(&self.idents[..]);
+        buf.put(&self.info_exclude.0[..]);
+        buf.put(&self.excludes_file.0[..]);
+        buf.put_u32(self.dir_flags);
+        buf.put(&self.info_exclude.1[..]);
+        buf.put(&self.excludes_file.1[..]);
+        put_string(&mut buf, &self.exclude_per_dir);
+
+        let root = match &self.root {
+            Some(root) => root,
+            None => {
+                put_varint(&mut buf, 0);
+                return buf.freeze();
+            }
+        };
+
+        put_varint(&mut buf, root.count());
+        root.write("", &mut buf);
+
+        let mut dirs = vec![];
+        root.visit(&mut dirs);
+
+        ewah::write(&mut buf, dirs.iter().map(|dir| dir.valid));
+        ewah::write(&mut buf, dirs.iter().map(|dir| dir.check_only));
+        ewah::write(&mut buf, dirs.iter().map(|dir| dir.exclude_id.is_some()));
+
+        for dir in dirs.iter().filter(|dir| dir.valid) {
+            for value in &dir.stat_data {
+                buf.put_u32(*value);
+            }
+        }
+
+        for id in dirs.iter().filter_map(|dir| dir.exclude_id.as_ref()) {
+            buf.put(&id.as_bytes[..]);
+        }
+
+        buf.put_u8(0);
+
+        buf.freeze()
+    }
+}
+
+/// Says where and on what system a cache was made, as git only trusts the
+/// stat data it was made with there.
+fn ident(worktree: &Path) -> String {
+    let system = match std::env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "Darwin",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        os => os,
+    };
+
+    format!("Location {}, system {}", worktree.display(), system)
+}
+
+/// The stat data git keeps for a directory, cut down to 32 bits per field.
+fn stat_data(stat: &Stat) -> [u32; 9] {
+    [
+        stat.ctime as u32,
+        stat.ctime_nsec as u32,
+        stat.mtime as u32,
+        stat.mtime_nsec as u32,
+        stat.dev as u32,
+        stat.ino as u32,
+        stat.uid,
+        stat.gid,
+        stat.size as u32,
+    ]
+}
+
+fn corrupt() -> IndexError {
+    IndexError::Parse("Corrupt untracked cache extension in the index".to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], IndexError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(corrupt)?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], IndexError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+
+        Ok(array)
+    }
+
+    fn varint(&mut self) -> Result<usize, IndexError> {
+        let mut byte = self.bytes(1)?[0];
+        let mut value = (byte & 0x7f) as usize;
+
+        while byte & 0x80 != 0 {
+            byte = self.bytes(1)?[0];
+            value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        }
+
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, IndexError> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let null = rest.iter().position(|b| *b == 0).ok_or_else(corrupt)?;
+        let string = String::from_utf8_lossy(&rest[..null]).to_string();
+        self.pos += null + 1;
+
+        Ok(string)
+    }
+
+    fn bitmap(&mut self) -> Result<Vec<bool>, IndexError> {
+        let rest = self.data.get(self.pos..).ok_or_else(corrupt)?;
+        let (bits, len) = ewah::parse(rest).ok_or_else(corrupt)?;
+        self.pos += len;
+
+        Ok(bits)
+    }
+}
+
+fn put_varint(buf: &mut BytesMut, mut value: usize) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+
+    while value >> 7 != 0 {
+        value = (value >> 7) - 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+    }
+
+    bytes.reverse();
+    buf.put(&bytes[..]);
+}
+
+fn put_string(buf: &mut BytesMut, string: &str) {
+    buf.put(string.as_bytes());
+    buf.put_u8(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(names: &[&str], stat: u32) -> UntrackedDir {
+        UntrackedDir {
+            valid: stat > 0,
+            stat_data: [stat; 9],
+            untracked: names.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_round_trips_through_the_extension_format() {
+        let mut root = dir(&["1.txt", "u/"], 1);
+        root.children.insert(
+            "u".to_string(),
+            UntrackedDir {
+                check_only: true,
+                ..dir(&["x"], 2)
+            },
+        );
+
+        // enough directories for the bitmaps to span several words
+        for n in 0..100 {
+            let child = dir(&[], n % 3);
+            root.children.insert(format!("d{}", n), child);
+        }
+
+        root.children.get_mut("d7").unwrap().exclude_id = Some(Id::parse(&[7; 20]));
+
+        let mut cache = UntrackedCache::new(Path::new("/work"));
+        cache.set_root(root);
+
+        let parsed = UntrackedCache::parse(&cache.to_bytes()).unwrap();
+
+        assert_eq!(cache, parsed);
+        assert!(parsed.is_usable(Path::new("/work")));
+        assert!(!parsed.is_usable(Path::new("/elsewhere")));
+    }
+
+    #[test]
+    fn it_invalidates_the_directories_leading_to_a_path() {
+        let mut root = dir(&["1.txt"], 1);
+        root.children.insert("a".to_string(), dir(&["2.txt"], 1));
+        root.children.insert("b".to_string(), dir(&["3.txt"], 1));
+
+        root.invalidate("a/new.txt");
+
+        assert!(!root.valid && root.untracked.is_empty());
+        assert!(!root.children["a"].valid);
+        assert!(root.children["b"].valid);
+    }
+}
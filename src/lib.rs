@@ -4,20 +4,56 @@ mod workspace;
 
 mod database;
 
+mod pack;
+
+mod remote;
+
 mod refs;
 
 mod id;
 
 mod repository;
 
+mod revision;
+
+mod rev_list;
+
+mod diff;
+
+mod merge;
+
+mod sequencer;
+
+mod reflog;
+
+mod date;
+
+mod stash;
+
+mod editor;
+
+mod fsmonitor;
+
+mod submodule;
+
 mod commands;
 
 pub mod lockfile;
 
+pub mod config;
+
 pub mod index;
 
 pub mod errors;
 
 pub use commands::*;
 
+pub use id::Id;
+
+pub use remote::ServiceMode;
+
 pub use repository::Repository;
+
+pub use sequencer::SequencerError;
+
+pub use stash::StashError;
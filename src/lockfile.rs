@@ -37,13 +37,13 @@ pub struct Lockfile {
 }
 
 impl Lockfile {
-    pub fn new(mut path: PathBuf) -> Self {
-        let file_path = path.clone();
-        path.set_extension("lock");
+    pub fn new(path: PathBuf) -> Self {
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
 
         Self {
-            file_path,
-            lock_path: path,
+            file_path: path,
+            lock_path: PathBuf::from(lock_path),
             lock: None,
         }
     }
@@ -82,6 +82,10 @@ impl Lockfile {
         Ok(())
     }
 
+    pub fn is_held(&self) -> bool {
+        self.lock.is_some()
+    }
+
     pub fn rollback(&mut self) -> Result<(), LockError> {
         self.guard_stale_lock()?;
 
@@ -1,4 +1,8 @@
-use std::env;
+use std::{
+    env, fs,
+    io::{self, prelude::*},
+    path::PathBuf,
+};
 
 use rit::Command;
 
@@ -41,6 +45,36 @@ repository earlier: remove the file manually to continue.",
                 1
             }
         },
+        rit::errors::RitError::Sequencer(rit::SequencerError::NothingToDo)
+        | rit::errors::RitError::Stash(_)
+        | rit::errors::RitError::Reflog(_)
+        | rit::errors::RitError::Editor(_) => {
+            eprintln!("error: {}", err);
+            1
+        }
+        rit::errors::RitError::Revision(_) | rit::errors::RitError::Sequencer(_) => {
+            eprintln!("fatal: {}", err);
+            128
+        }
+        rit::errors::RitError::Refs(_)
+        | rit::errors::RitError::Config(_)
+        | rit::errors::RitError::EmptyTagMessage
+        | rit::errors::RitError::NothingToAmend
+        | rit::errors::RitError::InvalidPack(_)
+        | rit::errors::RitError::CloneFailed(_)
+        | rit::errors::RitError::Remote(_)
+        | rit::errors::RitError::Submodule(_) => {
+            eprintln!("fatal: {}", err);
+            128
+        }
+        rit::errors::RitError::CommitAborted(_) => {
+            eprintln!("{}", err);
+            1
+        }
+        rit::errors::RitError::UnmergedFiles => {
+            eprintln!("error: {}", err);
+            1
+        }
         rit::errors::RitError::UnknownCommand(command) => {
             eprintln!("rit: '{}' is not a rit command. See 'rit --help'.", command);
             1
@@ -74,6 +108,128 @@ fn handle_ok(execution: rit::Execution) -> i32 {
 
             0
         }
+        rit::Execution::Rebase(res) => {
+            println!("{}", res);
+
+            if res.is_stopped() {
+                1
+            } else {
+                0
+            }
+        }
+        rit::Execution::Reflog(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            0
+        }
+        rit::Execution::Clone(res) => {
+            eprintln!("{}", res);
+
+            0
+        }
+        rit::Execution::Fetch(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                eprintln!("{}", output);
+            }
+
+            if res.is_rejected() {
+                1
+            } else {
+                0
+            }
+        }
+        rit::Execution::Fsck(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            if res.is_broken() {
+                1
+            } else {
+                0
+            }
+        }
+        rit::Execution::Fsmonitor(res) => {
+            let state = if res.running { "is" } else { "is not" };
+            println!(
+                "fsmonitor-daemon {} watching '{}'",
+                state,
+                res.worktree.display()
+            );
+
+            if res.running {
+                0
+            } else {
+                1
+            }
+        }
+        rit::Execution::Submodule(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            0
+        }
+        rit::Execution::Remote(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            0
+        }
+        rit::Execution::Push(res) => {
+            eprintln!("{}", res);
+
+            if res.is_rejected() {
+                1
+            } else {
+                0
+            }
+        }
+        rit::Execution::IndexPack(res) => {
+            println!("{}", res);
+
+            0
+        }
+        rit::Execution::Repack(res) => {
+            println!("{}", res);
+
+            0
+        }
+        rit::Execution::Tag(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            0
+        }
+        rit::Execution::Stash(res) => {
+            let output = res.to_string();
+
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+
+            if res.is_conflicted() {
+                1
+            } else {
+                0
+            }
+        }
         _ => 0,
     }
 }
@@ -90,13 +246,39 @@ fn get_session() -> rit::Session {
     }
 }
 
+/// The session of a command serving a repository to another process, which
+/// may run where no identity is set up.
+fn get_server_session() -> rit::Session {
+    rit::Session {
+        author_name: env::var("GIT_AUTHOR_NAME").unwrap_or_default(),
+        author_email: env::var("GIT_AUTHOR_EMAIL").unwrap_or_default(),
+        project_dir: env::current_dir().unwrap(),
+    }
+}
+
+fn read_stdin() -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    io::stdin().read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
 fn main() {
     let mut args = env::args();
     args.next();
 
-    let session = get_session();
+    let command = args.next();
 
-    let result = match args.next().as_deref() {
+    let session = match command.as_deref() {
+        Some("upload-pack")
+        | Some("receive-pack")
+        | Some("daemon")
+        | Some("http-backend")
+        | Some("fsmonitor--daemon") => get_server_session(),
+        _ => get_session(),
+    };
+
+    let result = match command.as_deref() {
         Some("init") => {
             let path = args.next();
 
@@ -108,11 +290,674 @@ fn main() {
             rit::Add::new(session, paths).execute()
         }
         Some("commit") => {
-            let message = args.next().unwrap();
+            let mut options = rit::CommitOptions::default();
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "-a" | "--all" => options.all = true,
+                    "-i" | "--include" => options.include = true,
+                    "-o" | "--only" => options.only = true,
+                    "--" => options.paths.extend(args.by_ref()),
+                    "--amend" => options.amend = true,
+                    "--reset-author" => options.reset_author = true,
+                    "--no-edit" => options.no_edit = true,
+                    "-m" | "--message" => options.messages.extend(args.next()),
+                    _ if arg.starts_with("--message=") => {
+                        options.messages.push(arg["--message=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("-m") => options.messages.push(arg[2..].to_string()),
+                    "-F" | "--file" => options.file = args.next(),
+                    _ if arg.starts_with("--file=") => {
+                        options.file = Some(arg["--file=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("--cleanup=") => {
+                        let mode = &arg["--cleanup=".len()..];
+
+                        match rit::Cleanup::parse(mode) {
+                            Some(cleanup) => options.cleanup = Some(cleanup),
+                            None => {
+                                eprintln!("fatal: Invalid cleanup mode {}", mode);
+
+                                std::process::exit(128)
+                            }
+                        }
+                    }
+                    "--fixup" => options.fixup = args.next(),
+                    _ if arg.starts_with("--fixup=") => {
+                        options.fixup = Some(arg["--fixup=".len()..].to_string())
+                    }
+                    _ => options.paths.push(arg),
+                }
+            }
+
+            if [options.all, options.include, options.only]
+                .iter()
+                .filter(|given| **given)
+                .count()
+                > 1
+            {
+                eprintln!("fatal: Only one of --include/--only/--all can be used.");
+
+                std::process::exit(128)
+            }
+
+            if options.all && !options.paths.is_empty() {
+                eprintln!(
+                    "fatal: paths '{} ...' with -a does not make sense",
+                    options.paths[0]
+                );
+
+                std::process::exit(128)
+            }
+
+            if options.paths.is_empty() && (options.include || (options.only && !options.amend)) {
+                eprintln!("fatal: No paths with --include/--only does not make sense.");
 
-            rit::Commit::new(session, message).execute()
+                std::process::exit(128)
+            }
+
+            if options.reset_author && !options.amend {
+                eprintln!("fatal: --reset-author can be used only with --amend.");
+
+                std::process::exit(128)
+            }
+
+            if options.file.is_some() && !options.messages.is_empty() {
+                eprintln!("fatal: Option -m cannot be combined with -F.");
+
+                std::process::exit(128)
+            }
+
+            rit::Commit::with_options(session, options).execute()
         }
         Some("status") => rit::Status::new(session).execute(),
+        Some("rebase") => {
+            let mut upstream = None;
+            let mut onto = None;
+            let mut interactive = false;
+            let mut autosquash = false;
+            let mut action = None;
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "--continue" => action = Some(rit::RebaseAction::Continue),
+                    "--skip" => action = Some(rit::RebaseAction::Skip),
+                    "--abort" => action = Some(rit::RebaseAction::Abort),
+                    "--onto" => onto = args.next(),
+                    "-i" | "--interactive" => interactive = true,
+                    "--autosquash" => autosquash = true,
+                    "--no-autosquash" => autosquash = false,
+                    _ => upstream = Some(arg),
+                }
+            }
+
+            let action = action.or_else(|| {
+                upstream.map(|upstream| rit::RebaseAction::Start {
+                    upstream,
+                    onto,
+                    interactive,
+                    autosquash,
+                })
+            });
+
+            match action {
+                Some(action) => rit::Rebase::new(session, action).execute(),
+                None => {
+                    eprintln!(
+                        "usage: rit rebase [-i] [--autosquash] [--onto <newbase>] <upstream>"
+                    );
+                    eprintln!("   or: rit rebase (--continue | --skip | --abort)");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("stash") => {
+            let mut args: Vec<String> = args.collect();
+            let subcommand = match args.first() {
+                Some(arg) if !arg.starts_with('-') => Some(args.remove(0)),
+                _ => None,
+            };
+
+            let flag = |names: &[&str]| args.iter().any(|arg| names.contains(&&arg[..]));
+            let stash = args.iter().find(|arg| !arg.starts_with('-')).cloned();
+
+            let action = match subcommand.as_deref() {
+                None | Some("push") => {
+                    let mut args = args.clone().into_iter();
+                    let mut message = None;
+                    let mut include_untracked = false;
+                    let mut pathspecs = vec![];
+
+                    while let Some(arg) = args.next() {
+                        match &arg[..] {
+                            "-u" | "--include-untracked" => include_untracked = true,
+                            "-m" | "--message" => message = args.next(),
+                            "--" => pathspecs.extend(args.by_ref()),
+                            _ => pathspecs.push(arg),
+                        }
+                    }
+
+                    Some(rit::StashAction::Push {
+                        message,
+                        include_untracked,
+                        pathspecs,
+                    })
+                }
+                Some("list") => Some(rit::StashAction::List),
+                Some("show") => Some(rit::StashAction::Show {
+                    stash,
+                    patch: flag(&["-p", "--patch"]),
+                }),
+                Some("apply") => Some(rit::StashAction::Apply {
+                    stash,
+                    restore_index: flag(&["--index"]),
+                }),
+                Some("pop") => Some(rit::StashAction::Pop {
+                    stash,
+                    restore_index: flag(&["--index"]),
+                }),
+                Some("drop") => Some(rit::StashAction::Drop { stash }),
+                Some("clear") => Some(rit::StashAction::Clear),
+                _ => None,
+            };
+
+            match action {
+                Some(action) => rit::Stash::new(session, action).execute(),
+                None => {
+                    eprintln!("usage: rit stash list");
+                    eprintln!("   or: rit stash show [-p] [<stash>]");
+                    eprintln!("   or: rit stash drop [<stash>]");
+                    eprintln!("   or: rit stash (pop | apply) [--index] [<stash>]");
+                    eprintln!("   or: rit stash clear");
+                    eprintln!("   or: rit stash [push [-u] [-m <message>] [--] [<pathspec>...]]");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("reflog") => {
+            let mut args: Vec<String> = args.collect();
+            let subcommand = match args.first().map(String::as_str) {
+                Some("show") | Some("expire") | Some("delete") => Some(args.remove(0)),
+                _ => None,
+            };
+
+            let action = match subcommand.as_deref() {
+                None | Some("show") if args.len() <= 1 => {
+                    Some(rit::ReflogAction::Show { name: args.pop() })
+                }
+                Some("expire") => {
+                    let mut names = vec![];
+                    let mut all = false;
+                    let mut expire = None;
+
+                    for arg in args {
+                        match &arg[..] {
+                            "--all" => all = true,
+                            _ if arg.starts_with("--expire=") => {
+                                expire = Some(arg["--expire=".len()..].to_string())
+                            }
+                            _ => names.push(arg),
+                        }
+                    }
+
+                    Some(rit::ReflogAction::Expire { names, all, expire })
+                }
+                Some("delete") if !args.is_empty() => {
+                    Some(rit::ReflogAction::Delete { entries: args })
+                }
+                _ => None,
+            };
+
+            match action {
+                Some(action) => rit::Reflog::new(session, action).execute(),
+                None => {
+                    eprintln!("usage: rit reflog [show] [<ref>]");
+                    eprintln!("   or: rit reflog expire [--expire=<time>] [--all] [<ref>...]");
+                    eprintln!("   or: rit reflog delete <ref>@{{<n>}}...");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("clone") => {
+            let mut options = rit::CloneOptions::default();
+            let mut paths = vec![];
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "--bare" => options.bare = true,
+                    "-b" | "--branch" => options.branch = args.next(),
+                    "-u" | "--upload-pack" => options.upload_pack = args.next(),
+                    _ if arg.starts_with("--branch=") => {
+                        options.branch = Some(arg["--branch=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("--upload-pack=") => {
+                        options.upload_pack = Some(arg["--upload-pack=".len()..].to_string())
+                    }
+                    _ => paths.push(arg),
+                }
+            }
+
+            if paths.is_empty() || paths.len() > 2 {
+                eprintln!(
+                    "usage: rit clone [--bare] [-b <branch>] [-u <upload-pack>] <repo> [<dir>]"
+                );
+
+                std::process::exit(129)
+            }
+
+            options.directory = paths.get(1).cloned();
+            options.source = paths.swap_remove(0);
+
+            rit::Clone::new(session, options).execute()
+        }
+        Some("fetch") => {
+            let mut options = rit::FetchOptions::default();
+            let mut names = vec![];
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "-p" | "--prune" => options.prune = true,
+                    "--upload-pack" => options.upload_pack = args.next(),
+                    _ if arg.starts_with("--upload-pack=") => {
+                        options.upload_pack = Some(arg["--upload-pack=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("--") => {
+                        eprintln!(
+                            "usage: rit fetch [--prune] [--upload-pack <upload-pack>] \
+                             [<repository> [<refspec>...]]"
+                        );
+
+                        std::process::exit(129)
+                    }
+                    _ => names.push(arg),
+                }
+            }
+
+            let mut names = names.into_iter();
+            options.remote = names.next();
+            options.refspecs = names.collect();
+
+            rit::Fetch::new(session, options).execute()
+        }
+        Some("remote") => {
+            let args: Vec<String> = args.collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+            let action = match &args[..] {
+                [] => Some(rit::RemoteAction::List { verbose: false }),
+                ["-v"] | ["--verbose"] => Some(rit::RemoteAction::List { verbose: true }),
+                ["add", name, url] => Some(rit::RemoteAction::Add {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                }),
+                ["remove", name] | ["rm", name] => Some(rit::RemoteAction::Remove {
+                    name: name.to_string(),
+                }),
+                ["rename", old_name, new_name] => Some(rit::RemoteAction::Rename {
+                    old_name: old_name.to_string(),
+                    new_name: new_name.to_string(),
+                }),
+                ["set-url", name, url] => Some(rit::RemoteAction::SetUrl {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                }),
+                _ => None,
+            };
+
+            match action {
+                Some(action) => rit::Remote::new(session, action).execute(),
+                None => {
+                    eprintln!("usage: rit remote [-v | --verbose]");
+                    eprintln!("   or: rit remote add <name> <url>");
+                    eprintln!("   or: rit remote rename <old> <new>");
+                    eprintln!("   or: rit remote remove <name>");
+                    eprintln!("   or: rit remote set-url <name> <newurl>");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("submodule") => {
+            let args: Vec<String> = args.collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+            let action = match &args[..] {
+                [] | ["status"] => Some(rit::SubmoduleAction::Status),
+                ["add", url] => Some(rit::SubmoduleAction::Add {
+                    url: url.to_string(),
+                    path: None,
+                }),
+                ["add", url, path] => Some(rit::SubmoduleAction::Add {
+                    url: url.to_string(),
+                    path: Some(path.to_string()),
+                }),
+                ["init"] => Some(rit::SubmoduleAction::Init),
+                ["update"] => Some(rit::SubmoduleAction::Update { init: false }),
+                ["update", "--init"] => Some(rit::SubmoduleAction::Update { init: true }),
+                _ => None,
+            };
+
+            match action {
+                Some(action) => rit::Submodule::new(session, action).execute(),
+                None => {
+                    eprintln!("usage: rit submodule [status]");
+                    eprintln!("   or: rit submodule add <repository> [<path>]");
+                    eprintln!("   or: rit submodule init");
+                    eprintln!("   or: rit submodule update [--init]");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("push") => {
+            let mut options = rit::PushOptions::default();
+            let mut names = vec![];
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "-f" | "--force" => options.force = true,
+                    "--receive-pack" => options.receive_pack = args.next(),
+                    _ if arg.starts_with("--receive-pack=") => {
+                        options.receive_pack = Some(arg["--receive-pack=".len()..].to_string())
+                    }
+                    _ => names.push(arg),
+                }
+            }
+
+            let mut names = names.into_iter();
+            options.remote = names.next();
+            options.branches = names.collect();
+
+            rit::Push::new(session, options).execute()
+        }
+        Some(service @ "upload-pack") | Some(service @ "receive-pack") => {
+            let mut mode = rit::ServiceMode::default();
+            let mut dirs = vec![];
+
+            for arg in args {
+                match &arg[..] {
+                    "--advertise-refs" => mode = rit::ServiceMode::AdvertiseRefs,
+                    "--stateless-rpc" if mode == rit::ServiceMode::Connection => {
+                        mode = rit::ServiceMode::StatelessRpc
+                    }
+                    "--stateless-rpc" => (),
+                    _ => dirs.push(arg),
+                }
+            }
+
+            if dirs.len() != 1 {
+                eprintln!(
+                    "usage: rit {} [--stateless-rpc] [--advertise-refs] <dir>",
+                    service
+                );
+
+                std::process::exit(129)
+            }
+
+            let input = Box::new(io::stdin());
+            let output = Box::new(io::stdout());
+
+            if service == "upload-pack" {
+                let mut command = rit::UploadPack::new(session, &dirs[0], input, output);
+                command.set_mode(mode);
+                command.execute()
+            } else {
+                let mut command = rit::ReceivePack::new(session, &dirs[0], input, output);
+                command.set_mode(mode);
+                command.execute()
+            }
+        }
+        Some("daemon") => {
+            let mut options = rit::DaemonOptions::default();
+
+            for arg in args {
+                match &arg[..] {
+                    "--export-all" => options.export_all = true,
+                    _ if arg.starts_with("--listen=") => {
+                        options.listen = Some(arg["--listen=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("--port=") => match arg["--port=".len()..].parse() {
+                        Ok(port) => options.port = Some(port),
+                        Err(_) => {
+                            eprintln!("fatal: invalid port '{}'", &arg["--port=".len()..]);
+
+                            std::process::exit(128)
+                        }
+                    },
+                    _ if arg.starts_with("--timeout=") => match arg["--timeout=".len()..].parse() {
+                        Ok(timeout) => options.timeout = Some(timeout),
+                        Err(_) => {
+                            eprintln!("fatal: invalid timeout '{}'", &arg["--timeout=".len()..]);
+
+                            std::process::exit(128)
+                        }
+                    },
+                    _ if arg.starts_with("--base-path=") => {
+                        options.base_path = Some(PathBuf::from(&arg["--base-path=".len()..]))
+                    }
+                    _ if arg.starts_with("--") => {
+                        eprintln!(
+                            "usage: rit daemon [--listen=<host>] [--port=<n>] [--base-path=<path>] \
+                             [--export-all] [--timeout=<n>] [<directory>...]"
+                        );
+
+                        std::process::exit(129)
+                    }
+                    _ => options.directories.push(PathBuf::from(arg)),
+                }
+            }
+
+            rit::Daemon::new(session, options).execute()
+        }
+        Some("http-backend") => {
+            let mut options = rit::HttpBackendOptions {
+                project_root: env::var_os("GIT_PROJECT_ROOT").map(PathBuf::from),
+                export_all: env::var_os("GIT_HTTP_EXPORT_ALL").is_some(),
+                ..Default::default()
+            };
+
+            for arg in args {
+                match &arg[..] {
+                    _ if arg.starts_with("--listen=") => {
+                        options.listen = Some(arg["--listen=".len()..].to_string())
+                    }
+                    _ if arg.starts_with("--port=") => match arg["--port=".len()..].parse() {
+                        Ok(port) => options.port = Some(port),
+                        Err(_) => {
+                            eprintln!("fatal: invalid port '{}'", &arg["--port=".len()..]);
+
+                            std::process::exit(128)
+                        }
+                    },
+                    _ => {
+                        eprintln!("usage: rit http-backend [--listen=<host>] [--port=<n>]");
+
+                        std::process::exit(129)
+                    }
+                }
+            }
+
+            // run as a CGI script, the request comes in the environment
+            let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+
+            if options.port.is_some() && options.project_root.is_none() {
+                options.project_root = Some(session.project_dir.clone());
+            }
+
+            if options.project_root.is_none() {
+                options.request.path = var("PATH_TRANSLATED").unwrap_or_default();
+            } else {
+                options.request.path = var("PATH_INFO").unwrap_or_default();
+            }
+
+            options.request.method = var("REQUEST_METHOD").unwrap_or_else(|| "GET".to_string());
+            options.request.query = var("QUERY_STRING").unwrap_or_default();
+            options.request.content_type = var("CONTENT_TYPE");
+            options.request.content_encoding = var("HTTP_CONTENT_ENCODING");
+            options.request.remote_user = var("REMOTE_USER");
+
+            let input: Box<dyn Read> = match var("CONTENT_LENGTH").and_then(|n| n.parse().ok()) {
+                Some(length) => Box::new(io::stdin().take(length)),
+                None => Box::new(io::stdin()),
+            };
+
+            rit::HttpBackend::new(session, options, input, Box::new(io::stdout())).execute()
+        }
+        Some("index-pack") => {
+            let mut options = rit::IndexPackOptions::default();
+            let mut paths = vec![];
+            let mut stdin = false;
+
+            for arg in args {
+                match &arg[..] {
+                    "--stdin" => stdin = true,
+                    "--fix-thin" => options.fix_thin = true,
+                    _ => paths.push(PathBuf::from(arg)),
+                }
+            }
+
+            let data = match (paths.len(), stdin) {
+                (0, true) => read_stdin(),
+                (1, false) => fs::read(&paths[0]),
+                _ => {
+                    eprintln!("usage: rit index-pack [--fix-thin] (--stdin | <pack-file>)");
+
+                    std::process::exit(129)
+                }
+            };
+            options.path = paths.pop();
+
+            data.map_err(rit::errors::RitError::from)
+                .and_then(|data| rit::IndexPack::new(session, data, options).execute())
+        }
+        Some("unpack-objects") => match read_stdin() {
+            Ok(data) => rit::UnpackObjects::new(session, data).execute(),
+            Err(err) => Err(err.into()),
+        },
+        Some("repack") => {
+            let mut options = rit::RepackOptions::default();
+
+            for arg in args {
+                match &arg[..] {
+                    "-a" => options.all = true,
+                    "-d" => options.delete = true,
+                    "-ad" | "-da" => {
+                        options.all = true;
+                        options.delete = true;
+                    }
+                    _ => {
+                        eprintln!("usage: rit repack [-a] [-d]");
+
+                        std::process::exit(129)
+                    }
+                }
+            }
+
+            rit::Repack::new(session, options).execute()
+        }
+        Some("fsmonitor--daemon") => {
+            let action = match args.next().as_deref() {
+                Some("run") => Some(rit::FsmonitorAction::Run),
+                Some("start") => Some(rit::FsmonitorAction::Start),
+                Some("stop") => Some(rit::FsmonitorAction::Stop),
+                Some("status") => Some(rit::FsmonitorAction::Status),
+                _ => None,
+            };
+
+            match action {
+                Some(action) if args.next().is_none() => {
+                    rit::FsmonitorDaemon::new(session, action).execute()
+                }
+                _ => {
+                    eprintln!("usage: rit fsmonitor--daemon (run | start | stop | status)");
+
+                    std::process::exit(129)
+                }
+            }
+        }
+        Some("fsck") => {
+            let mut options = rit::FsckOptions::default();
+
+            for arg in args {
+                match &arg[..] {
+                    "--lost-found" => options.lost_found = true,
+                    _ => {
+                        eprintln!("usage: rit fsck [--lost-found]");
+
+                        std::process::exit(129)
+                    }
+                }
+            }
+
+            rit::Fsck::new(session, options).execute()
+        }
+        Some("gc") => {
+            let options = rit::RepackOptions {
+                all: true,
+                delete: true,
+            };
+
+            rit::Repack::new(session, options).execute()
+        }
+        Some("tag") => {
+            let mut names = vec![];
+            let mut message = None;
+            let mut annotate = false;
+            let mut force = false;
+            let mut list = false;
+            let mut delete = false;
+            let mut sort = Some(rit::TagSort::default());
+
+            while let Some(arg) = args.next() {
+                match &arg[..] {
+                    "-a" | "--annotate" => annotate = true,
+                    "-m" | "--message" => message = args.next(),
+                    "-f" | "--force" => force = true,
+                    "-l" | "--list" => list = true,
+                    "-d" | "--delete" => delete = true,
+                    _ if arg.starts_with("--sort=") => {
+                        sort = rit::TagSort::parse(&arg["--sort=".len()..])
+                    }
+                    _ => names.push(arg),
+                }
+            }
+
+            let action = match (sort, delete) {
+                (None, _) => None,
+                (_, true) if !names.is_empty() => Some(rit::TagAction::Delete { names }),
+                (_, true) => None,
+                (Some(sort), false) if list || names.is_empty() => Some(rit::TagAction::List {
+                    patterns: names,
+                    sort,
+                }),
+                (_, false) if names.len() <= 2 => {
+                    let mut names = names.into_iter();
+
+                    Some(rit::TagAction::Create {
+                        name: names.next().unwrap(),
+                        target: names.next(),
+                        message,
+                        annotate,
+                        force,
+                    })
+                }
+                _ => None,
+            };
+
+            match action {
+                Some(action) => rit::Tag::new(session, action).execute(),
+                None => {
+                    eprintln!("usage: rit tag [-a] [-f] [-m <msg>] <tagname> [<head>]");
+                    eprintln!("   or: rit tag -d <tagname>...");
+                    eprintln!("   or: rit tag -l [--sort=<key>] [<pattern>...]");
+
+                    std::process::exit(129)
+                }
+            }
+        }
         Some(name) => {
             let err = rit::errors::RitError::UnknownCommand(name.to_string());
 
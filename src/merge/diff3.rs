@@ -0,0 +1,220 @@
+use crate::diff::{self, EditKind, Line, Myers};
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq)]
+pub enum Chunk {
+    Clean(Vec<String>),
+    Conflict(Vec<String>, Vec<String>, Vec<String>),
+}
+
+/// Three-way merge of two documents derived from a common original.
+pub struct Diff3 {
+    o: Vec<String>,
+    a: Vec<String>,
+    b: Vec<String>,
+    match_a: HashMap<usize, usize>,
+    match_b: HashMap<usize, usize>,
+    line_o: usize,
+    line_a: usize,
+    line_b: usize,
+    chunks: Vec<Chunk>,
+}
+
+pub struct Diff3Result {
+    chunks: Vec<Chunk>,
+}
+
+impl Diff3 {
+    pub fn merge(o: &str, a: &str, b: &str) -> Diff3Result {
+        let o_lines = diff::lines(o);
+        let a_lines = diff::lines(a);
+        let b_lines = diff::lines(b);
+
+        let mut diff3 = Self {
+            match_a: Self::match_set(&o_lines, &a_lines),
+            match_b: Self::match_set(&o_lines, &b_lines),
+            o: Self::texts(o_lines),
+            a: Self::texts(a_lines),
+            b: Self::texts(b_lines),
+            line_o: 0,
+            line_a: 0,
+            line_b: 0,
+            chunks: vec![],
+        };
+
+        diff3.generate_chunks();
+
+        Diff3Result {
+            chunks: diff3.chunks,
+        }
+    }
+
+    fn texts(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|line| line.text).collect()
+    }
+
+    fn match_set(o: &[Line], file: &[Line]) -> HashMap<usize, usize> {
+        Myers::new(o.to_vec(), file.to_vec())
+            .diff()
+            .into_iter()
+            .filter(|edit| edit.kind == EditKind::Eql)
+            .map(|edit| (edit.a_line.unwrap().number, edit.b_line.unwrap().number))
+            .collect()
+    }
+
+    fn generate_chunks(&mut self) {
+        loop {
+            match self.find_next_mismatch() {
+                Some(1) => match self.find_next_match() {
+                    (o, Some(a), Some(b)) => self.emit_chunk(o, a, b),
+                    _ => return self.emit_final_chunk(),
+                },
+                Some(i) => self.emit_chunk(self.line_o + i, self.line_a + i, self.line_b + i),
+                None => return self.emit_final_chunk(),
+            }
+        }
+    }
+
+    fn find_next_mismatch(&self) -> Option<usize> {
+        let mut i = 1;
+
+        while self.in_bounds(i)
+            && Self::is_match(&self.match_a, self.line_o, self.line_a, i)
+            && Self::is_match(&self.match_b, self.line_o, self.line_b, i)
+        {
+            i += 1;
+        }
+
+        if self.in_bounds(i) {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    fn in_bounds(&self, i: usize) -> bool {
+        self.line_o + i <= self.o.len()
+            || self.line_a + i <= self.a.len()
+            || self.line_b + i <= self.b.len()
+    }
+
+    fn is_match(matches: &HashMap<usize, usize>, line_o: usize, offset: usize, i: usize) -> bool {
+        matches.get(&(line_o + i)) == Some(&(offset + i))
+    }
+
+    fn find_next_match(&self) -> (usize, Option<usize>, Option<usize>) {
+        let mut o = self.line_o + 1;
+
+        while o <= self.o.len() && !(self.match_a.contains_key(&o) && self.match_b.contains_key(&o))
+        {
+            o += 1;
+        }
+
+        (
+            o,
+            self.match_a.get(&o).cloned(),
+            self.match_b.get(&o).cloned(),
+        )
+    }
+
+    fn emit_chunk(&mut self, o: usize, a: usize, b: usize) {
+        let chunk = Self::chunk(
+            &self.o[self.line_o..o - 1],
+            &self.a[self.line_a..a - 1],
+            &self.b[self.line_b..b - 1],
+        );
+        self.chunks.push(chunk);
+
+        self.line_o = o - 1;
+        self.line_a = a - 1;
+        self.line_b = b - 1;
+    }
+
+    fn emit_final_chunk(&mut self) {
+        let chunk = Self::chunk(
+            &self.o[self.line_o..],
+            &self.a[self.line_a..],
+            &self.b[self.line_b..],
+        );
+        self.chunks.push(chunk);
+    }
+
+    fn chunk(o: &[String], a: &[String], b: &[String]) -> Chunk {
+        if a == o || a == b {
+            Chunk::Clean(b.to_vec())
+        } else if b == o {
+            Chunk::Clean(a.to_vec())
+        } else {
+            Chunk::Conflict(o.to_vec(), a.to_vec(), b.to_vec())
+        }
+    }
+}
+
+impl Diff3Result {
+    pub fn is_clean(&self) -> bool {
+        self.chunks
+            .iter()
+            .all(|chunk| matches!(chunk, Chunk::Clean(_)))
+    }
+
+    /// Renders the merged document, marking conflicting regions with the
+    /// names of both sides.
+    pub fn render(&self, a_name: &str, b_name: &str) -> String {
+        let mut text = String::new();
+
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Clean(lines) => text.push_str(&lines.concat()),
+                Chunk::Conflict(_, a, b) => {
+                    text.push_str(&format!("<<<<<<< {}\n", a_name));
+                    Self::push_lines(&mut text, a);
+                    text.push_str("=======\n");
+                    Self::push_lines(&mut text, b);
+                    text.push_str(&format!(">>>>>>> {}\n", b_name));
+                }
+            }
+        }
+
+        text
+    }
+
+    fn push_lines(text: &mut String, lines: &[String]) {
+        text.push_str(&lines.concat());
+
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_merges_changes_to_different_lines() {
+        let result = Diff3::merge("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n");
+
+        assert!(result.is_clean());
+        assert_eq!("A\nb\nC\n", result.render("ours", "theirs"));
+    }
+
+    #[test]
+    fn it_marks_changes_to_the_same_line_as_conflicts() {
+        let result = Diff3::merge("a\nb\nc\n", "a\nB\nc\n", "a\nX\nc\n");
+
+        assert!(!result.is_clean());
+        assert_eq!(
+            "a\n<<<<<<< ours\nB\n=======\nX\n>>>>>>> theirs\nc\n",
+            result.render("ours", "theirs")
+        );
+    }
+
+    #[test]
+    fn it_takes_additions_from_both_sides() {
+        let result = Diff3::merge("b\n", "a\nb\n", "b\nc\n");
+
+        assert!(result.is_clean());
+        assert_eq!("a\nb\nc\n", result.render("ours", "theirs"));
+    }
+}
@@ -0,0 +1,5 @@
+mod diff3;
+mod resolve;
+
+pub use diff3::Diff3;
+pub use resolve::{Inputs, Resolve};
@@ -0,0 +1,288 @@
+use super::Diff3;
+use crate::{
+    database::TreeDiff,
+    errors::RitError,
+    id::Id,
+    objects::{Blob, TreeEntry},
+    repository::Repository,
+};
+use bytes::Bytes;
+use std::{collections::BTreeMap, path::Path};
+
+/// The two sides of a merge and the commit they are compared against.
+pub struct Inputs {
+    pub left_name: String,
+    pub right_name: String,
+    pub base_id: Option<Id>,
+    pub left_id: Id,
+    pub right_id: Id,
+}
+
+/// Applies the changes between `base` and `right` on top of `left`, which has
+/// to be the commit checked out in the workspace and index. Paths changed on
+/// both sides are merged line by line; anything that cannot be reconciled is
+/// left in the index as conflict stages.
+pub struct Resolve<'a> {
+    repo: &'a mut Repository,
+    inputs: Inputs,
+    left_diff: TreeDiff,
+    right_diff: TreeDiff,
+    clean_diff: BTreeMap<String, Option<TreeEntry>>,
+    conflicts: BTreeMap<String, [Option<TreeEntry>; 3]>,
+    untracked: BTreeMap<String, TreeEntry>,
+    messages: Vec<String>,
+}
+
+impl<'a> Resolve<'a> {
+    pub fn new(repo: &'a mut Repository, inputs: Inputs) -> Self {
+        Self {
+            repo,
+            inputs,
+            left_diff: TreeDiff::new(),
+            right_diff: TreeDiff::new(),
+            clean_diff: BTreeMap::new(),
+            conflicts: BTreeMap::new(),
+            untracked: BTreeMap::new(),
+            messages: vec![],
+        }
+    }
+
+    /// Runs the merge and returns the messages describing what happened.
+    pub fn execute(mut self) -> Result<Vec<String>, RitError> {
+        self.prepare_tree_diffs()?;
+
+        for (pathname, item) in &self.clean_diff {
+            match item {
+                Some(item) => self.repo.checkout_entry(pathname, item)?,
+                None => {
+                    self.repo.workspace.remove(pathname)?;
+                    self.repo.index.remove(pathname);
+                }
+            }
+        }
+
+        for (pathname, items) in &self.conflicts {
+            self.repo.index.add_conflict_set(pathname, items.clone());
+        }
+
+        for (pathname, item) in &self.untracked {
+            let blob = self.repo.database.load_blob(&item.id)?;
+
+            self.repo
+                .workspace
+                .write_file(pathname, &blob.data, item.mode)?;
+        }
+
+        Ok(self.messages)
+    }
+
+    fn prepare_tree_diffs(&mut self) -> Result<(), RitError> {
+        let base_id = self.inputs.base_id.as_ref();
+
+        self.left_diff = self
+            .repo
+            .database
+            .tree_diff(base_id, Some(&self.inputs.left_id))?;
+        self.right_diff = self
+            .repo
+            .database
+            .tree_diff(base_id, Some(&self.inputs.right_id))?;
+
+        for (pathname, (base, right)) in self.right_diff.clone() {
+            if right.is_some() {
+                self.file_dir_conflict(&pathname, true);
+            }
+
+            self.same_path_conflict(&pathname, base, right)?;
+        }
+
+        for (pathname, (_, left)) in self.left_diff.clone() {
+            if left.is_some() {
+                self.file_dir_conflict(&pathname, false);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn same_path_conflict(
+        &mut self,
+        pathname: &str,
+        base: Option<TreeEntry>,
+        right: Option<TreeEntry>,
+    ) -> Result<(), RitError> {
+        if self.conflicts.contains_key(pathname) {
+            return Ok(());
+        }
+
+        let left = match self.left_diff.get(pathname) {
+            Some((_, left)) => left.clone(),
+            None => {
+                self.clean_diff.insert(pathname.to_string(), right);
+                return Ok(());
+            }
+        };
+
+        if left == right {
+            return Ok(());
+        }
+
+        if left.is_some() && right.is_some() {
+            self.messages.push(format!("Auto-merging {}", pathname));
+        }
+
+        let (id_ok, id) = self.merge_blobs(
+            base.as_ref().map(|item| &item.id),
+            left.as_ref().map(|item| &item.id),
+            right.as_ref().map(|item| &item.id),
+        )?;
+        let (mode_ok, mode) = Self::merge_modes(
+            base.as_ref().map(|item| item.mode),
+            left.as_ref().map(|item| item.mode),
+            right.as_ref().map(|item| item.mode),
+        );
+
+        self.clean_diff
+            .insert(pathname.to_string(), Some(TreeEntry::new(id, mode)));
+
+        if id_ok && mode_ok {
+            return Ok(());
+        }
+
+        self.log_conflict(pathname, &left, &right);
+        self.conflicts
+            .insert(pathname.to_string(), [base, left, right]);
+
+        Ok(())
+    }
+
+    fn merge_blobs(
+        &self,
+        base: Option<&Id>,
+        left: Option<&Id>,
+        right: Option<&Id>,
+    ) -> Result<(bool, Id), RitError> {
+        match (left, right) {
+            (None, Some(right)) => return Ok((false, right.clone())),
+            (Some(left), None) => return Ok((false, left.clone())),
+            (Some(left), Some(right)) if Some(left) == base || left == right => {
+                return Ok((true, right.clone()))
+            }
+            (Some(left), Some(right)) if Some(right) == base => return Ok((true, left.clone())),
+            _ => {}
+        }
+
+        let base = self.read_blob(base)?;
+        let left = self.read_blob(left)?;
+        let right = self.read_blob(right)?;
+
+        let merge = Diff3::merge(&base, &left, &right);
+        let data = merge.render(&self.inputs.left_name, &self.inputs.right_name);
+
+        let mut blob = Blob::parse(Bytes::from(data));
+        let id = self.repo.database.store(&mut blob)?;
+
+        Ok((merge.is_clean(), id))
+    }
+
+    fn merge_modes(base: Option<u32>, left: Option<u32>, right: Option<u32>) -> (bool, u32) {
+        match (left, right) {
+            (None, Some(right)) => (false, right),
+            (Some(left), None) => (false, left),
+            (Some(left), Some(right)) if Some(left) == base || left == right => (true, right),
+            (Some(left), Some(right)) if Some(right) == base => (true, left),
+            (Some(left), Some(_)) => (false, left),
+            (None, None) => (true, 0),
+        }
+    }
+
+    fn read_blob(&self, id: Option<&Id>) -> Result<String, RitError> {
+        match id {
+            Some(id) => {
+                let blob = self.repo.database.load_blob(id)?;
+
+                Ok(String::from_utf8_lossy(&blob.data).to_string())
+            }
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Handles a file added on one side at a path the other side uses as a
+    /// directory: the file is kept out of the way under `<path>~<side>`.
+    fn file_dir_conflict(&mut self, pathname: &str, is_right: bool) {
+        let (diff, name) = if is_right {
+            (&self.left_diff, &self.inputs.left_name)
+        } else {
+            (&self.right_diff, &self.inputs.right_name)
+        };
+
+        let mut found = vec![];
+
+        for parent in Path::new(pathname).ancestors().skip(1) {
+            let parent = parent.to_string_lossy().to_string();
+
+            if parent.is_empty() {
+                continue;
+            }
+
+            if let Some((old, Some(new))) = diff.get(&parent) {
+                found.push((parent, old.clone(), new.clone(), name.clone()));
+            }
+        }
+
+        for (parent, old, new, name) in found {
+            let items = if is_right {
+                [old, Some(new.clone()), None]
+            } else {
+                [old, None, Some(new.clone())]
+            };
+
+            let rename = format!("{}~{}", parent, name);
+
+            self.conflicts.insert(parent.clone(), items);
+            self.clean_diff.remove(&parent);
+            self.untracked.insert(rename.clone(), new);
+
+            self.messages.push(format!(
+                "CONFLICT (file/directory): There is a directory with name {} in {}. Adding {} as {}",
+                parent,
+                if is_right {
+                    &self.inputs.right_name
+                } else {
+                    &self.inputs.left_name
+                },
+                parent,
+                rename
+            ));
+        }
+    }
+
+    fn log_conflict(
+        &mut self,
+        pathname: &str,
+        left: &Option<TreeEntry>,
+        right: &Option<TreeEntry>,
+    ) {
+        let message = match (left, right) {
+            (Some(_), Some(_)) => format!("CONFLICT (content): Merge conflict in {}", pathname),
+            (None, _) => format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}. Version {} of {} left in tree.",
+                pathname,
+                self.inputs.left_name,
+                self.inputs.right_name,
+                self.inputs.right_name,
+                pathname
+            ),
+            (_, None) => format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}. Version {} of {} left in tree.",
+                pathname,
+                self.inputs.right_name,
+                self.inputs.left_name,
+                self.inputs.left_name,
+                pathname
+            ),
+        };
+
+        self.messages.push(message);
+    }
+}
@@ -3,12 +3,19 @@ use bytes::{buf::BufMutExt, Bytes, BytesMut};
 use std::{fmt, fs::File, io};
 
 pub struct Blob {
-    file: File,
+    pub data: Bytes,
 }
 
 impl Blob {
-    pub fn new(file: File) -> Self {
-        Self { file }
+    pub fn new(mut file: File) -> Self {
+        let mut data = BytesMut::new().writer();
+        io::copy(&mut file, &mut data).unwrap();
+
+        Self::parse(data.into_inner().freeze())
+    }
+
+    pub fn parse(data: Bytes) -> Self {
+        Self { data }
     }
 }
 
@@ -20,10 +27,7 @@ impl fmt::Display for Blob {
 
 impl Object for Blob {
     fn data(&mut self) -> Bytes {
-        let mut data = BytesMut::new().writer();
-        io::copy(&mut self.file, &mut data).unwrap();
-
-        data.into_inner().freeze()
+        self.data.clone()
     }
 }
 
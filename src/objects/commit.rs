@@ -5,28 +5,81 @@ use crate::{
 use bytes::{BufMut, Bytes, BytesMut};
 use std::fmt;
 
-pub struct Commit<'a> {
-    parent: &'a Option<String>,
-    tree_id: Id,
-    author: Author<'a>,
-    message: &'a str,
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub parents: Vec<Id>,
+    pub tree_id: Id,
+    pub author: Author,
+    pub committer: Author,
+    pub message: String,
 }
 
-impl<'a> Commit<'a> {
+impl Commit {
     pub fn new(
-        parent: &'a Option<String>,
+        parents: Vec<Id>,
         tree_id: Id,
-        author: Author<'a>,
-        message: &'a str,
+        author: Author,
+        committer: Author,
+        message: &str,
     ) -> Self {
         Self {
-            parent,
+            parents,
             tree_id,
             author,
-            message,
+            committer,
+            message: message.to_string(),
         }
     }
 
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data = String::from_utf8_lossy(data);
+        let (headers, message) = match data.find("\n\n") {
+            Some(pos) => (&data[..pos], &data[pos + 2..]),
+            None => (&data[..], ""),
+        };
+
+        let mut tree_id = None;
+        let mut parents = vec![];
+        let mut author = None;
+        let mut committer = None;
+
+        for line in headers.lines() {
+            let mut parts = line.splitn(2, ' ');
+
+            match (parts.next(), parts.next()) {
+                (Some("tree"), Some(value)) => tree_id = Id::from_hex(value),
+                (Some("parent"), Some(value)) => parents.push(Id::from_hex(value)?),
+                (Some("author"), Some(value)) => author = Author::parse(value),
+                (Some("committer"), Some(value)) | (Some("commiter"), Some(value)) => {
+                    committer = Author::parse(value)
+                }
+                _ => {}
+            }
+        }
+
+        let author = author?;
+
+        Some(Self {
+            parents,
+            tree_id: tree_id?,
+            committer: committer.unwrap_or_else(|| author.clone()),
+            author,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn parent(&self) -> Option<&Id> {
+        self.parents.first()
+    }
+
+    pub fn is_merge(&self) -> bool {
+        self.parents.len() > 1
+    }
+
+    pub fn title_line(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
     fn tree(&self) -> String {
         format!("tree {}\n", self.tree_id.as_str)
     }
@@ -35,30 +88,30 @@ impl<'a> Commit<'a> {
         format!("author {}\n", self.author)
     }
 
-    fn commiter(&self) -> String {
-        format!("commiter {}\n", self.author)
+    fn committer(&self) -> String {
+        format!("committer {}\n", self.committer)
     }
 }
 
-impl<'a> fmt::Display for Commit<'a> {
+impl fmt::Display for Commit {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "commit")
     }
 }
 
-impl<'a> Object for Commit<'a> {
+impl Object for Commit {
     fn data(&mut self) -> Bytes {
         let mut buf = BytesMut::new();
 
         buf.put(self.tree().as_bytes());
 
-        if let Some(parent) = self.parent {
-            let parent = format!("parent {}\n", parent);
+        for parent in &self.parents {
+            let parent = format!("parent {}\n", parent.as_str);
             buf.put(parent.as_bytes());
-        };
+        }
 
         buf.put(self.author().as_bytes());
-        buf.put(self.commiter().as_bytes());
+        buf.put(self.committer().as_bytes());
         buf.put(&b"\n"[..]);
         buf.put(self.message.as_bytes());
 
@@ -66,4 +119,4 @@ impl<'a> Object for Commit<'a> {
     }
 }
 
-impl<'a> Storable for Commit<'a> {}
+impl Storable for Commit {}
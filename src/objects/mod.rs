@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local, TimeZone};
 use std::{
     fmt,
     io::{prelude::*, Chain, Error},
@@ -9,11 +9,17 @@ pub mod commit;
 pub use commit::Commit;
 
 pub mod tree;
-pub use tree::Tree;
+pub use tree::{Tree, TreeEntry};
 
 pub mod blob;
 pub use blob::Blob;
 
+pub mod tag;
+pub use tag::Tag;
+
+pub mod raw;
+pub use raw::Raw;
+
 use crate::id;
 
 pub trait Object: fmt::Display {
@@ -46,23 +52,63 @@ pub trait Storable: Object {
     }
 }
 
-pub struct Author<'a> {
-    name: &'a str,
-    email: &'a str,
-    time: DateTime<Local>,
+pub enum Parsed {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+    pub time: DateTime<FixedOffset>,
 }
 
-impl<'a> Author<'a> {
-    pub fn new(name: &'a str, email: &'a str) -> Self {
+impl Author {
+    pub fn new(name: &str, email: &str) -> Self {
+        let now = Local::now();
+
         Self {
-            name,
-            email,
-            time: Local::now(),
+            name: name.to_string(),
+            email: email.to_string(),
+            time: now.with_timezone(now.offset()),
         }
     }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let email_start = value.find('<')?;
+        let email_end = value.find('>')?;
+
+        let name = value[..email_start].trim();
+        let email = &value[email_start + 1..email_end];
+
+        let mut time = value[email_end + 1..].split_whitespace();
+        let timestamp: i64 = time.next()?.parse().ok()?;
+        let offset = Self::parse_offset(time.next().unwrap_or("+0000"))?;
+
+        Some(Self {
+            name: name.to_string(),
+            email: email.to_string(),
+            time: offset.timestamp(timestamp, 0),
+        })
+    }
+
+    fn parse_offset(offset: &str) -> Option<FixedOffset> {
+        if offset.len() != 5 {
+            return None;
+        }
+
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let hours: i32 = offset[1..3].parse().ok()?;
+        let minutes: i32 = offset[3..5].parse().ok()?;
+
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+    }
 }
 
-impl<'a> fmt::Display for Author<'a> {
+impl fmt::Display for Author {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let timestamp = self.time.format("%s %z");
 
@@ -0,0 +1,33 @@
+use crate::objects::{Object, Storable};
+use bytes::Bytes;
+use std::fmt;
+
+/// An object of any kind kept exactly as it was received, e.g. from a pack,
+/// so that storing it does not depend on parsing and writing it back.
+pub struct Raw {
+    pub kind: String,
+    pub data: Bytes,
+}
+
+impl Raw {
+    pub fn new(kind: &str, data: Bytes) -> Self {
+        Self {
+            kind: kind.to_string(),
+            data,
+        }
+    }
+}
+
+impl fmt::Display for Raw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Object for Raw {
+    fn data(&mut self) -> Bytes {
+        self.data.clone()
+    }
+}
+
+impl Storable for Raw {}
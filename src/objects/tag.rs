@@ -0,0 +1,97 @@
+use crate::{
+    id::Id,
+    objects::{Author, Object, Storable},
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub object: Id,
+    pub kind: String,
+    pub name: String,
+    pub tagger: Option<Author>,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn new(object: Id, kind: &str, name: &str, tagger: Author, message: &str) -> Self {
+        let mut message = message.to_string();
+
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+
+        Self {
+            object,
+            kind: kind.to_string(),
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data = String::from_utf8_lossy(data);
+        let (headers, message) = match data.find("\n\n") {
+            Some(pos) => (&data[..pos], &data[pos + 2..]),
+            None => (&data[..], ""),
+        };
+
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+
+        for line in headers.lines() {
+            let mut parts = line.splitn(2, ' ');
+
+            match (parts.next(), parts.next()) {
+                (Some("object"), Some(value)) => object = Id::from_hex(value),
+                (Some("type"), Some(value)) => kind = Some(value.to_string()),
+                (Some("tag"), Some(value)) => name = Some(value.to_string()),
+                (Some("tagger"), Some(value)) => tagger = Author::parse(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            object: object?,
+            kind: kind?,
+            name: name?,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn title_line(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tag")
+    }
+}
+
+impl Object for Tag {
+    fn data(&mut self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        buf.put(format!("object {}\n", self.object.as_str).as_bytes());
+        buf.put(format!("type {}\n", self.kind).as_bytes());
+        buf.put(format!("tag {}\n", self.name).as_bytes());
+
+        if let Some(tagger) = &self.tagger {
+            buf.put(format!("tagger {}\n", tagger).as_bytes());
+        }
+
+        buf.put(&b"\n"[..]);
+        buf.put(self.message.as_bytes());
+
+        buf.freeze()
+    }
+}
+
+impl Storable for Tag {}
@@ -7,10 +7,36 @@ use bytes::{BufMut, Bytes, BytesMut};
 use indexmap::IndexMap;
 use std::{ffi::OsStr, fmt};
 
+pub const TREE_MODE: u32 = 0o40000;
+/// The mode of a submodule, whose entry names a commit of the nested
+/// repository rather than an object of this one.
+pub const GITLINK_MODE: u32 = 0o160000;
+
 #[derive(Debug)]
 pub enum Node {
     Tree(Tree),
     Entry(Entry),
+    Stored(TreeEntry),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeEntry {
+    pub id: Id,
+    pub mode: u32,
+}
+
+impl TreeEntry {
+    pub fn new(id: Id, mode: u32) -> Self {
+        Self { id, mode }
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == TREE_MODE
+    }
+
+    pub fn is_gitlink(&self) -> bool {
+        self.mode == GITLINK_MODE
+    }
 }
 
 #[derive(Debug)]
@@ -41,21 +67,37 @@ impl Tree {
         }
     }
 
-    pub fn traverse<C>(&mut self, consumer: C)
-    where
-        C: FnOnce(&mut Tree) + Copy,
-    {
-        for (_name, node) in &mut self.nodes {
-            if let Node::Tree(tree) = node {
-                tree.traverse(consumer);
-            }
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut tree = Tree::new();
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let space = pos + data[pos..].iter().position(|b| *b == b' ')?;
+            let null = space + data[space..].iter().position(|b| *b == 0x00)?;
+
+            let mode = std::str::from_utf8(&data[pos..space]).ok()?;
+            let mode = u32::from_str_radix(mode, 8).ok()?;
+            let name = String::from_utf8_lossy(&data[space + 1..null]).to_string();
+            let id = Id::parse(data.get(null + 1..null + 21)?);
+
+            tree.nodes
+                .insert(name, Node::Stored(TreeEntry::new(id, mode)));
+
+            pos = null + 21;
         }
 
-        consumer(self);
+        Some(tree)
+    }
+
+    pub fn stored_entries(&self) -> impl Iterator<Item = (&String, &TreeEntry)> {
+        self.nodes.iter().filter_map(|(name, node)| match node {
+            Node::Stored(entry) => Some((name, entry)),
+            _ => None,
+        })
     }
 
     pub fn mode(&self) -> u32 {
-        40000
+        TREE_MODE
     }
 
     fn add_node<'a>(
@@ -105,13 +147,18 @@ impl<'a> NodeInfo {
             Node::Tree(tree) => Self {
                 name,
                 id: tree.id.as_ref().unwrap().as_bytes,
-                mode: format!("{} ", tree.mode()),
+                mode: format!("{:o} ", tree.mode()),
             },
             Node::Entry(entry) => Self {
                 name,
                 id: entry.id.as_bytes,
                 mode: format!("{:o} ", entry.mode),
             },
+            Node::Stored(entry) => Self {
+                name,
+                id: entry.id.as_bytes,
+                mode: format!("{:o} ", entry.mode),
+            },
         }
     }
 }
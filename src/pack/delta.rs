@@ -0,0 +1,283 @@
+use super::corrupt;
+use std::{collections::HashMap, io};
+
+const BLOCK_SIZE: usize = 16;
+const MAX_BUCKET_SIZE: usize = 64;
+const MAX_COPY_SIZE: usize = 0x10000;
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+/// Builds the delta that turns `source` into `target`. Blocks of the source
+/// are indexed by content, matches found for them in the target are grown in
+/// both directions and become copy instructions, and the bytes in between
+/// are inserted.
+pub fn compress(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+
+    for (n, block) in source.chunks_exact(BLOCK_SIZE).enumerate() {
+        let offsets = index.entry(block).or_default();
+
+        if offsets.len() < MAX_BUCKET_SIZE {
+            offsets.push(n * BLOCK_SIZE);
+        }
+    }
+
+    let mut delta = vec![];
+    write_size(&mut delta, source.len());
+    write_size(&mut delta, target.len());
+
+    let mut insert: Vec<u8> = vec![];
+    let mut pos = 0;
+
+    while pos < target.len() {
+        let candidates = target
+            .get(pos..pos + BLOCK_SIZE)
+            .and_then(|block| index.get(block));
+
+        let best = candidates.and_then(|offsets| {
+            offsets
+                .iter()
+                .map(|&offset| (offset, match_len(&source[offset..], &target[pos..])))
+                .max_by_key(|&(_, len)| len)
+        });
+
+        match best {
+            Some((mut offset, mut len)) => {
+                let mut start = pos;
+
+                while !insert.is_empty() && offset > 0 && source[offset - 1] == target[start - 1] {
+                    insert.pop();
+                    offset -= 1;
+                    start -= 1;
+                    len += 1;
+                }
+
+                write_insert(&mut delta, &insert);
+                insert.clear();
+                write_copy(&mut delta, offset, len);
+
+                pos = start + len;
+            }
+            None => {
+                insert.push(target[pos]);
+                pos += 1;
+            }
+        }
+    }
+
+    write_insert(&mut delta, &insert);
+
+    delta
+}
+
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+
+        if size == 0 {
+            delta.push(byte);
+            return;
+        }
+
+        delta.push(byte | 0x80);
+    }
+}
+
+fn write_insert(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY_SIZE);
+        let mut op = 0x80;
+        let mut fields = vec![];
+
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+
+            if byte != 0 {
+                op |= 1 << i;
+                fields.push(byte);
+            }
+        }
+
+        // a size of 0x10000 is written as no size bytes at all
+        for i in 0..3 {
+            let byte = ((size & 0xffff) >> (8 * i)) as u8;
+
+            if byte != 0 {
+                op |= 1 << (4 + i);
+                fields.push(byte);
+            }
+        }
+
+        delta.push(op);
+        delta.extend(fields);
+
+        offset += size;
+        len -= size;
+    }
+}
+
+/// Rebuilds an object from its delta base: after the sizes of the base and
+/// the result, a delta is a list of instructions that either copy a range of
+/// the base or insert the bytes that follow them.
+pub fn expand(source: &[u8], delta: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut pos = 0;
+
+    let source_size = read_size(delta, &mut pos)?;
+    let target_size = read_size(delta, &mut pos)?;
+
+    if source_size != source.len() {
+        return Err(corrupt("delta base has the wrong size"));
+    }
+
+    // the declared size is not trusted with the allocation, but copies and
+    // inserts rarely add up to much more than the base and the delta
+    let mut target = Vec::with_capacity(target_size.min(source.len() + delta.len()));
+
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+
+        if op & 0x80 != 0 {
+            let offset = read_copy_field(delta, &mut pos, op, 4)?;
+            let size = match read_copy_field(delta, &mut pos, op >> 4, 3)? {
+                0 => 0x10000,
+                size => size,
+            };
+
+            let range = source
+                .get(offset..offset + size)
+                .ok_or_else(|| corrupt("delta copies past the end of its base"))?;
+
+            target.extend_from_slice(range);
+        } else if op != 0 {
+            let data = delta
+                .get(pos..pos + op as usize)
+                .ok_or_else(|| corrupt("delta inserts past its end"))?;
+
+            target.extend_from_slice(data);
+            pos += op as usize;
+        } else {
+            return Err(corrupt("unknown delta instruction"));
+        }
+
+        if target.len() > target_size {
+            return Err(corrupt("delta result has the wrong size"));
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(corrupt("delta result has the wrong size"));
+    }
+
+    Ok(target)
+}
+
+/// Reads a size stored seven bits at a time, least significant first.
+fn read_size(data: &[u8], pos: &mut usize) -> Result<usize, io::Error> {
+    let mut size = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = *data.get(*pos).ok_or_else(|| corrupt("truncated delta"))?;
+        *pos += 1;
+
+        if shift >= usize::BITS {
+            return Err(corrupt("delta size overflows"));
+        }
+
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Reads the little-endian field of a copy instruction whose bytes are only
+/// present when the matching bit of `bits` is set.
+fn read_copy_field(data: &[u8], pos: &mut usize, bits: u8, len: usize) -> Result<usize, io::Error> {
+    let mut value = 0;
+
+    for i in 0..len {
+        if bits & (1 << i) != 0 {
+            let byte = *data.get(*pos).ok_or_else(|| corrupt("truncated delta"))?;
+            *pos += 1;
+
+            value |= (byte as usize) << (8 * i);
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_copies_and_inserts() {
+        let source = b"the quick brown fox";
+        let delta = [19, 16, 0x90, 10, 3, b'r', b'e', b'd', 0x91, 16, 3];
+
+        assert_eq!(
+            b"the quick redfox".to_vec(),
+            expand(source, &delta).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_round_trips_compressed_deltas() {
+        let source: Vec<u8> = (0..5000).map(|n| (n * 7 % 251) as u8).collect();
+        let mut target = source[100..3000].to_vec();
+        target.extend_from_slice(b"something new in the middle");
+        target.extend_from_slice(&source[2000..]);
+
+        let delta = compress(&source, &target);
+
+        assert!(delta.len() < 100);
+        assert_eq!(target, expand(&source, &delta).unwrap());
+        assert_eq!(
+            b"abc".to_vec(),
+            expand(b"", &compress(b"", b"abc")).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_rejects_a_base_of_the_wrong_size() {
+        assert!(expand(b"abc", &[4, 1, 1, b'x']).is_err());
+    }
+
+    #[test]
+    fn it_rejects_overlong_sizes() {
+        let mut delta = vec![3];
+        delta.extend(vec![0xff; 12]);
+        delta.push(1);
+
+        assert!(expand(b"abc", &delta).is_err());
+    }
+
+    #[test]
+    fn it_rejects_results_larger_than_declared_without_allocating_them() {
+        // a base of 3 bytes declared to become usize::MAX bytes, copied
+        // 0x10000 bytes at a time
+        let mut delta = vec![
+            3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        delta.extend([0x80, 0x80]);
+
+        assert!(expand(b"abc", &delta).is_err());
+        assert!(expand(b"abc", &[3, 2, 0x90, 3]).is_err());
+    }
+}
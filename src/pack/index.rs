@@ -0,0 +1,130 @@
+use super::{bytes_to_uint32, bytes_to_uint64, corrupt};
+use crate::id::{self, Id};
+use std::{fs, io, path::Path};
+
+pub const IDX_SIGNATURE: [u8; 4] = [0xff, b't', b'O', b'c'];
+pub const IDX_VERSION: u32 = 2;
+const HEADER_SIZE: usize = 8;
+const FANOUT_SIZE: usize = 256 * 4;
+const ID_SIZE: usize = 20;
+pub const LARGE_OFFSET: u32 = 0x8000_0000;
+
+/// A version 2 `.idx` file: a fan-out table counting the ids that start with
+/// each byte, followed by the sorted ids, their CRC32s and their offsets in
+/// the pack, with offsets past 2GB moved to a table of 64-bit values.
+pub struct PackIndex {
+    data: Vec<u8>,
+    count: usize,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        Self::parse(fs::read(path)?)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self, io::Error> {
+        if data.len() < HEADER_SIZE + FANOUT_SIZE
+            || data[0..4] != IDX_SIGNATURE
+            || bytes_to_uint32(&data[4..8]) != IDX_VERSION
+        {
+            return Err(corrupt("unsupported pack index"));
+        }
+
+        let count = bytes_to_uint32(&data[HEADER_SIZE + FANOUT_SIZE - 4..][..4]) as usize;
+
+        if data.len() < HEADER_SIZE + FANOUT_SIZE + count * (ID_SIZE + 8) + 2 * ID_SIZE {
+            return Err(corrupt("truncated pack index"));
+        }
+
+        Ok(Self { data, count })
+    }
+
+    /// Finds the offset of an object in the pack.
+    pub fn lookup(&self, id: &Id) -> Option<u64> {
+        let (mut low, mut high) = self.fanout_range(id.as_bytes[0]);
+
+        while low < high {
+            let mid = (low + high) / 2;
+
+            match self.id_bytes(mid).cmp(&id.as_bytes[..]) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(self.offset(mid)),
+            }
+        }
+
+        None
+    }
+
+    /// Lists the ids of every object in the pack, in order.
+    pub fn ids(&self) -> Vec<Id> {
+        (0..self.count)
+            .map(|n| Id::parse(self.id_bytes(n)))
+            .collect()
+    }
+
+    /// Whether the checksum at the end of the file matches its content.
+    pub fn verify_checksum(&self) -> bool {
+        let end = self.data.len() - ID_SIZE;
+
+        id::OneOff::new(&self.data[..end]).as_bytes[..] == self.data[end..]
+    }
+
+    /// Lists the ids starting with the hexadecimal `prefix`.
+    pub fn prefix_match(&self, prefix: &str) -> Vec<Id> {
+        let first = match prefix
+            .get(0..2)
+            .and_then(|b| u8::from_str_radix(b, 16).ok())
+        {
+            Some(first) => first,
+            None => return vec![],
+        };
+
+        let (low, high) = self.fanout_range(first);
+
+        (low..high)
+            .map(|n| Id::parse(self.id_bytes(n)))
+            .filter(|id| id.as_str.starts_with(prefix))
+            .collect()
+    }
+
+    fn fanout_range(&self, first: u8) -> (usize, usize) {
+        let fanout = |n: usize| {
+            let start = HEADER_SIZE + n * 4;
+
+            bytes_to_uint32(&self.data[start..start + 4]) as usize
+        };
+
+        let low = if first == 0 {
+            0
+        } else {
+            fanout(first as usize - 1)
+        };
+
+        (low, fanout(first as usize))
+    }
+
+    fn id_bytes(&self, n: usize) -> &[u8] {
+        let start = HEADER_SIZE + FANOUT_SIZE + n * ID_SIZE;
+
+        &self.data[start..start + ID_SIZE]
+    }
+
+    fn crc_start(&self) -> usize {
+        HEADER_SIZE + FANOUT_SIZE + self.count * ID_SIZE
+    }
+
+    fn offset(&self, n: usize) -> u64 {
+        let offsets_start = self.crc_start() + self.count * 4;
+        let start = offsets_start + n * 4;
+        let offset = bytes_to_uint32(&self.data[start..start + 4]);
+
+        if offset & LARGE_OFFSET == 0 {
+            return offset as u64;
+        }
+
+        let large_start = offsets_start + self.count * 4 + (offset & !LARGE_OFFSET) as usize * 8;
+
+        bytes_to_uint64(&self.data[large_start..large_start + 8])
+    }
+}
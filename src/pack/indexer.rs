@@ -0,0 +1,382 @@
+use super::{
+    bytes_to_uint32, corrupt, crc32, delta, inflate, kind_name, kind_number,
+    pack_file::{Base, EntryHeader},
+    writer::{build_index, checksum, compress, entry_header, write_file},
+    OFS_DELTA, REF_DELTA, SIGNATURE, VERSION,
+};
+use crate::id::{Id, OneOff};
+use bytes::Bytes;
+use libflate::zlib::Decoder;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, prelude::*},
+    path::Path,
+};
+
+const HEADER_SIZE: usize = 12;
+const TRAILER_SIZE: usize = 20;
+
+/// An object read from a pack, with its deltas applied.
+pub struct PackedObject {
+    pub id: Id,
+    pub kind: u8,
+    pub data: Bytes,
+    pub offset: u64,
+    pub crc32: u32,
+}
+
+impl PackedObject {
+    pub fn kind_name(&self) -> &'static str {
+        kind_name(self.kind).unwrap_or_default()
+    }
+}
+
+struct Entry {
+    header: EntryHeader,
+    offset: u64,
+    data: Vec<u8>,
+    crc32: u32,
+}
+
+/// Reads a whole pack as received over the wire, without an index. Entries
+/// are inflated one after another, which is the only way to find where each
+/// of them ends, and deltas are then resolved against the objects of the pack
+/// or, for thin packs, against bases the receiver already has.
+pub struct Indexer {
+    data: Vec<u8>,
+    objects: Vec<PackedObject>,
+    external: Vec<(Id, u8, Bytes)>,
+    thin_deltas: usize,
+}
+
+impl Indexer {
+    /// Parses `data`, using `resolve` to load the bases of `REF_DELTA`
+    /// entries that are not in the pack.
+    pub fn new<F>(data: Vec<u8>, resolve: F) -> Result<Self, io::Error>
+    where
+        F: Fn(&Id) -> Option<(String, Bytes)>,
+    {
+        let entries = Self::read_entries(&data)?;
+
+        let mut indexer = Self {
+            data,
+            objects: vec![],
+            external: vec![],
+            thin_deltas: 0,
+        };
+        indexer.resolve_entries(entries, resolve)?;
+
+        Ok(indexer)
+    }
+
+    /// Reads one pack from a stream that may go on after it, such as the
+    /// connection to another repository. Nothing says how long an entry is,
+    /// so each one is inflated as it is read to find where the next starts.
+    pub fn read_stream<R: Read>(input: &mut R) -> Result<Vec<u8>, io::Error> {
+        let mut reader = RecordingReader {
+            input,
+            data: vec![],
+        };
+
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+
+        if &header[0..4] != SIGNATURE || bytes_to_uint32(&header[4..8]) != VERSION {
+            return Err(corrupt("unsupported pack file"));
+        }
+
+        for _ in 0..bytes_to_uint32(&header[8..12]) {
+            let mut byte = reader.read_byte()?;
+            let kind = (byte >> 4) & 0x7;
+
+            while byte & 0x80 != 0 {
+                byte = reader.read_byte()?;
+            }
+
+            match kind {
+                OFS_DELTA => while reader.read_byte()? & 0x80 != 0 {},
+                REF_DELTA => reader.read_exact(&mut [0; 20])?,
+                _ => (),
+            }
+
+            io::copy(&mut Decoder::new(&mut reader)?, &mut io::sink())?;
+        }
+
+        reader.read_exact(&mut [0; TRAILER_SIZE])?;
+
+        Ok(reader.data)
+    }
+
+    pub fn objects(&self) -> &[PackedObject] {
+        &self.objects
+    }
+
+    /// Counts the deltas whose base is not in the pack.
+    pub fn thin_deltas(&self) -> usize {
+        self.thin_deltas
+    }
+
+    /// Writes the `.idx` of a pack that is already in place.
+    pub fn write_index(&self, path: &Path) -> Result<Id, io::Error> {
+        let checksum = Id::parse(&self.data[self.data.len() - TRAILER_SIZE..]);
+        let entries = self
+            .objects
+            .iter()
+            .map(|object| (object.id.clone(), object.crc32, object.offset))
+            .collect();
+
+        write_file(path, &build_index(entries, &checksum))?;
+
+        Ok(checksum)
+    }
+
+    /// Stores the pack and its index in `dir` as `pack-<checksum>`. The
+    /// external bases of a thin pack are appended to it first, since a pack
+    /// on disk has to contain every object its deltas need.
+    pub fn write(mut self, dir: &Path) -> Result<Id, io::Error> {
+        if !self.external.is_empty() {
+            self.complete_thin_pack()?;
+        }
+
+        fs::create_dir_all(dir)?;
+
+        let checksum = Id::parse(&self.data[self.data.len() - TRAILER_SIZE..]);
+        let name = format!("pack-{}", checksum);
+
+        write_file(&dir.join(format!("{}.pack", name)), &self.data)?;
+        self.write_index(&dir.join(format!("{}.idx", name)))
+    }
+
+    fn read_entries(data: &[u8]) -> Result<Vec<Entry>, io::Error> {
+        if data.len() < HEADER_SIZE + TRAILER_SIZE
+            || &data[0..4] != SIGNATURE
+            || bytes_to_uint32(&data[4..8]) != VERSION
+        {
+            return Err(corrupt("unsupported pack file"));
+        }
+
+        let end = data.len() - TRAILER_SIZE;
+
+        if checksum(&data[..end]).as_bytes[..] != data[end..] {
+            return Err(corrupt("pack is corrupted (SHA1 mismatch)"));
+        }
+
+        let count = bytes_to_uint32(&data[8..12]) as usize;
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = HEADER_SIZE;
+
+        for _ in 0..count {
+            let header = EntryHeader::parse(&data[pos..end], pos as u64)?;
+            let start = pos + header.len;
+
+            let mut reader = CountingReader::new(&data[start..end]);
+            let inflated = inflate(&mut reader, header.size, end - start)?;
+
+            let next = start + reader.count;
+
+            entries.push(Entry {
+                header,
+                offset: pos as u64,
+                data: inflated,
+                crc32: crc32(&data[pos..next]),
+            });
+
+            pos = next;
+        }
+
+        if pos != end {
+            return Err(corrupt("pack has junk at the end"));
+        }
+
+        Ok(entries)
+    }
+
+    /// Applies deltas until every entry is a whole object. Bases usually come
+    /// first, but a `REF_DELTA` may name one further on, so entries whose
+    /// base is not known yet are retried until no more progress is made.
+    fn resolve_entries<F>(&mut self, entries: Vec<Entry>, resolve: F) -> Result<(), io::Error>
+    where
+        F: Fn(&Id) -> Option<(String, Bytes)>,
+    {
+        let by_offset: HashMap<u64, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(n, entry)| (entry.offset, n))
+            .collect();
+
+        let mut resolved: Vec<Option<(u8, Bytes, Id)>> = vec![None; entries.len()];
+        let mut by_id: HashMap<Id, (u8, Bytes)> = HashMap::new();
+        let mut pending = entries.len();
+
+        while pending > 0 {
+            let mut progress = false;
+
+            for (n, entry) in entries.iter().enumerate() {
+                if resolved[n].is_some() {
+                    continue;
+                }
+
+                let base = match &entry.header.base {
+                    None => Some((entry.header.kind, Bytes::new())),
+                    Some(Base::Offset(offset)) => {
+                        let base = by_offset
+                            .get(offset)
+                            .ok_or_else(|| corrupt("delta base offset out of bounds"))?;
+
+                        resolved[*base]
+                            .as_ref()
+                            .map(|(kind, data, _)| (*kind, data.clone()))
+                    }
+                    Some(Base::Ref(id)) => by_id.get(id).cloned(),
+                };
+
+                let (kind, data) = match (base, &entry.header.base) {
+                    (None, _) => continue,
+                    (Some((kind, _)), None) => (kind, Bytes::from(entry.data.clone())),
+                    (Some((kind, base)), Some(_)) => {
+                        (kind, Bytes::from(delta::expand(&base, &entry.data)?))
+                    }
+                };
+
+                let id = object_id(kind, &data);
+                by_id.insert(id.clone(), (kind, data.clone()));
+                resolved[n] = Some((kind, data, id));
+
+                pending -= 1;
+                progress = true;
+            }
+
+            if progress {
+                continue;
+            }
+
+            let missing = self.external.len();
+
+            for entry in &entries {
+                if let Some(Base::Ref(id)) = &entry.header.base {
+                    if by_id.contains_key(id) {
+                        continue;
+                    }
+
+                    // the base may also be the result of a delta that is
+                    // still waiting for one of the external bases
+                    let base =
+                        resolve(id).and_then(|(kind, data)| Some((kind_number(&kind)?, data)));
+
+                    if let Some((kind, data)) = base {
+                        self.thin_deltas += entries
+                            .iter()
+                            .filter(|other| other.header.base == entry.header.base)
+                            .count();
+
+                        by_id.insert(id.clone(), (kind, data.clone()));
+                        self.external.push((id.clone(), kind, data));
+                    }
+                }
+            }
+
+            if self.external.len() == missing {
+                return Err(corrupt("pack has unresolved deltas"));
+            }
+        }
+
+        self.objects = entries
+            .into_iter()
+            .zip(resolved)
+            .filter_map(|(entry, object)| {
+                let (kind, data, id) = object?;
+
+                Some(PackedObject {
+                    id,
+                    kind,
+                    data,
+                    offset: entry.offset,
+                    crc32: entry.crc32,
+                })
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Appends the external bases as whole objects, then fixes up the object
+    /// count and the checksum.
+    fn complete_thin_pack(&mut self) -> Result<(), io::Error> {
+        self.data.truncate(self.data.len() - TRAILER_SIZE);
+
+        for (id, kind, data) in self.external.drain(..) {
+            let mut entry = entry_header(kind, data.len());
+            entry.extend(compress(&data)?);
+
+            self.objects.push(PackedObject {
+                id,
+                kind,
+                data,
+                offset: self.data.len() as u64,
+                crc32: crc32(&entry),
+            });
+            self.data.extend(entry);
+        }
+
+        let count = self.objects.len() as u32;
+        self.data[8..12].copy_from_slice(&count.to_be_bytes());
+
+        let checksum = checksum(&self.data);
+        self.data.extend_from_slice(&checksum.as_bytes);
+
+        Ok(())
+    }
+}
+
+fn object_id(kind: u8, data: &[u8]) -> Id {
+    let header = format!("{} {}\0", kind_name(kind).unwrap_or_default(), data.len());
+
+    OneOff::new(header.as_bytes().chain(data))
+}
+
+/// Keeps track of how much of a pack the decoder has consumed, which tells
+/// where the next entry starts.
+struct CountingReader<'a> {
+    data: &'a [u8],
+    count: usize,
+}
+
+impl<'a> CountingReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, count: 0 }
+    }
+}
+
+impl<'a> Read for CountingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (&self.data[self.count..]).read(buf)?;
+        self.count += n;
+
+        Ok(n)
+    }
+}
+
+/// Keeps a copy of everything read from a stream.
+struct RecordingReader<'a, R> {
+    input: &'a mut R,
+    data: Vec<u8>,
+}
+
+impl<'a, R: Read> RecordingReader<'a, R> {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.read_exact(&mut byte)?;
+
+        Ok(byte[0])
+    }
+}
+
+impl<'a, R: Read> Read for RecordingReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.input.read(buf)?;
+        self.data.extend_from_slice(&buf[..n]);
+
+        Ok(n)
+    }
+}
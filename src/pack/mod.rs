@@ -0,0 +1,129 @@
+mod delta;
+mod index;
+mod indexer;
+mod pack_file;
+mod writer;
+
+pub use index::PackIndex;
+pub use indexer::Indexer;
+pub use pack_file::Pack;
+pub use writer::{PackObject, Writer};
+
+use libflate::zlib::Decoder;
+use std::io::{self, Read};
+
+pub const SIGNATURE: &[u8; 4] = b"PACK";
+pub const VERSION: u32 = 2;
+
+pub const COMMIT: u8 = 1;
+pub const TREE: u8 = 2;
+pub const BLOB: u8 = 3;
+pub const TAG: u8 = 4;
+pub const OFS_DELTA: u8 = 6;
+pub const REF_DELTA: u8 = 7;
+
+/// How many times larger than its compressed form deflated data can be.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+pub fn kind_name(kind: u8) -> Option<&'static str> {
+    match kind {
+        COMMIT => Some("commit"),
+        TREE => Some("tree"),
+        BLOB => Some("blob"),
+        TAG => Some("tag"),
+        _ => None,
+    }
+}
+
+pub fn kind_number(name: &str) -> Option<u8> {
+    match name {
+        "commit" => Some(COMMIT),
+        "tree" => Some(TREE),
+        "blob" => Some(BLOB),
+        "tag" => Some(TAG),
+        _ => None,
+    }
+}
+
+/// The CRC-32 that `.idx` files keep for every compressed entry.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc = CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+
+    !crc
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[n] = crc;
+        n += 1;
+    }
+
+    table
+}
+
+/// Inflates a compressed entry that should come to `size` bytes, out of at
+/// most `available` compressed ones. The size comes from the pack, so it
+/// only bounds how much is inflated and allocated up front.
+fn inflate<R: Read>(input: R, size: usize, available: usize) -> Result<Vec<u8>, io::Error> {
+    let mut data = Vec::with_capacity(size.min(available.saturating_mul(MAX_DEFLATE_RATIO)));
+
+    Decoder::new(input)?
+        .take(size as u64 + 1)
+        .read_to_end(&mut data)?;
+
+    if data.len() != size {
+        return Err(corrupt("pack entry has the wrong size"));
+    }
+
+    Ok(data)
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn bytes_to_uint32(bytes: &[u8]) -> u32 {
+    let mut num = [0u8; 4];
+    num.clone_from_slice(bytes);
+
+    u32::from_be_bytes(num)
+}
+
+fn bytes_to_uint64(bytes: &[u8]) -> u64 {
+    let mut num = [0u8; 8];
+    num.clone_from_slice(bytes);
+
+    u64::from_be_bytes(num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_crc32() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+}
@@ -0,0 +1,268 @@
+use super::{
+    bytes_to_uint32, corrupt, delta, inflate, kind_name, PackIndex, OFS_DELTA, REF_DELTA,
+    SIGNATURE, VERSION,
+};
+use crate::id::{self, Id};
+use bytes::Bytes;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, prelude::*, BufReader, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+const MAX_HEADER_SIZE: usize = 32;
+const CACHE_SIZE: usize = 256;
+const MAX_DELTA_DEPTH: usize = 10_000;
+const ID_SIZE: usize = 20;
+
+/// Where the base of a delta lives: earlier in the same pack or, for a
+/// `REF_DELTA`, wherever the object with that id is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base {
+    Offset(u64),
+    Ref(Id),
+}
+
+/// The header in front of each compressed entry of a pack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryHeader {
+    pub kind: u8,
+    pub size: usize,
+    pub base: Option<Base>,
+    pub len: usize,
+}
+
+impl EntryHeader {
+    /// Parses the header of the entry at `offset`, given the bytes that start
+    /// there: the type and size packed into a variable-length integer,
+    /// followed by the base of deltas.
+    pub fn parse(data: &[u8], offset: u64) -> Result<Self, io::Error> {
+        let truncated = || corrupt("truncated pack entry");
+        let mut pos = 0;
+
+        let mut byte = *data.first().ok_or_else(truncated)?;
+        let kind = (byte >> 4) & 0x7;
+        let mut size = (byte & 0xf) as usize;
+        let mut shift = 4;
+
+        while byte & 0x80 != 0 {
+            pos += 1;
+            byte = *data.get(pos).ok_or_else(truncated)?;
+
+            if shift >= usize::BITS {
+                return Err(corrupt("pack entry size overflows"));
+            }
+
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        pos += 1;
+
+        let base = match kind {
+            OFS_DELTA => {
+                byte = *data.get(pos).ok_or_else(truncated)?;
+                let mut distance = (byte & 0x7f) as u64;
+
+                while byte & 0x80 != 0 {
+                    pos += 1;
+                    byte = *data.get(pos).ok_or_else(truncated)?;
+                    distance = (distance + 1)
+                        .checked_mul(0x80)
+                        .ok_or_else(|| corrupt("delta base offset out of bounds"))?
+                        | (byte & 0x7f) as u64;
+                }
+
+                pos += 1;
+
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| corrupt("delta base offset out of bounds"))?;
+
+                Some(Base::Offset(base_offset))
+            }
+            REF_DELTA => {
+                let id = data.get(pos..pos + 20).ok_or_else(truncated)?;
+                pos += 20;
+
+                Some(Base::Ref(Id::parse(id)))
+            }
+            _ if kind_name(kind).is_some() => None,
+            _ => return Err(corrupt("unknown pack entry type")),
+        };
+
+        Ok(Self {
+            kind,
+            size,
+            base,
+            len: pos,
+        })
+    }
+}
+
+/// A `.pack` file and its `.idx`. Objects stored as deltas are rebuilt from
+/// their chain of bases, and recently used bases are kept in memory since
+/// many deltas usually share them.
+pub struct Pack {
+    path: PathBuf,
+    pub index: PackIndex,
+    cache: RefCell<HashMap<u64, (u8, Bytes)>>,
+}
+
+impl Pack {
+    pub fn open(index_path: &Path) -> Result<Self, io::Error> {
+        let path = index_path.with_extension("pack");
+
+        let mut header = [0u8; 8];
+        File::open(&path)?.read_exact(&mut header)?;
+
+        if &header[0..4] != SIGNATURE || bytes_to_uint32(&header[4..8]) != VERSION {
+            return Err(corrupt("unsupported pack file"));
+        }
+
+        Ok(Self {
+            path,
+            index: PackIndex::open(index_path)?,
+            cache: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.path.with_extension("idx")
+    }
+
+    /// Whether the pack and its index are intact, going by the checksums
+    /// at the end of each.
+    pub fn verify_checksum(&self) -> Result<bool, io::Error> {
+        let data = fs::read(&self.path)?;
+
+        if data.len() < ID_SIZE {
+            return Ok(false);
+        }
+
+        let end = data.len() - ID_SIZE;
+        let checksum = id::OneOff::new(&data[..end]);
+
+        Ok(checksum.as_bytes[..] == data[end..] && self.index.verify_checksum())
+    }
+
+    pub fn contains(&self, id: &Id) -> bool {
+        self.index.lookup(id).is_some()
+    }
+
+    /// Reads the type and content of an object if the pack has it. `resolve`
+    /// loads the bases of `REF_DELTA` entries that live outside the pack.
+    pub fn read<F>(&self, id: &Id, resolve: F) -> Option<Result<(String, Bytes), io::Error>>
+    where
+        F: Fn(&Id) -> Option<(String, Bytes)>,
+    {
+        let offset = self.index.lookup(id)?;
+
+        Some(self.read_at(offset, resolve).map(|(kind, data)| {
+            let name = kind_name(kind).unwrap_or_default().to_string();
+
+            (name, data)
+        }))
+    }
+
+    /// Reads the object at `offset`, first walking down its chain of deltas
+    /// until reaching a whole object, and then applying them on the way back.
+    pub fn read_at<F>(&self, offset: u64, resolve: F) -> Result<(u8, Bytes), io::Error>
+    where
+        F: Fn(&Id) -> Option<(String, Bytes)>,
+    {
+        let mut file = File::open(&self.path)?;
+        let mut deltas = vec![];
+        let mut offset = offset;
+
+        let (mut base_offset, kind, mut data) = loop {
+            if let Some((kind, data)) = self.cache.borrow().get(&offset) {
+                break (Some(offset), *kind, data.clone());
+            }
+
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(corrupt("delta chain is too long"));
+            }
+
+            let (header, data) = self.read_entry(&mut file, offset)?;
+
+            match header.base {
+                None => break (Some(offset), header.kind, Bytes::from(data)),
+                Some(Base::Offset(base_offset)) => {
+                    deltas.push((offset, data));
+                    offset = base_offset;
+                }
+                Some(Base::Ref(base_id)) => {
+                    deltas.push((offset, data));
+
+                    if let Some(base_offset) = self.index.lookup(&base_id) {
+                        offset = base_offset;
+                        continue;
+                    }
+
+                    let (kind, data) =
+                        resolve(&base_id).ok_or_else(|| corrupt("missing delta base"))?;
+
+                    break (None, super::kind_number(&kind).unwrap_or_default(), data);
+                }
+            }
+        };
+
+        while let Some((offset, delta)) = deltas.pop() {
+            if let Some(base_offset) = base_offset {
+                self.remember(base_offset, kind, &data);
+            }
+
+            data = Bytes::from(delta::expand(&data, &delta)?);
+            base_offset = Some(offset);
+        }
+
+        Ok((kind, data))
+    }
+
+    fn read_entry(
+        &self,
+        file: &mut File,
+        offset: u64,
+    ) -> Result<(EntryHeader, Vec<u8>), io::Error> {
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = vec![0u8; MAX_HEADER_SIZE];
+        let count = read_up_to(file, &mut buf)?;
+
+        let header = EntryHeader::parse(&buf[..count], offset)?;
+
+        let start = offset + header.len as u64;
+        let available = file.metadata()?.len().saturating_sub(start);
+        file.seek(SeekFrom::Start(start))?;
+
+        let data = inflate(BufReader::new(file), header.size, available as usize)?;
+
+        Ok((header, data))
+    }
+
+    fn remember(&self, offset: u64, kind: u8, data: &Bytes) {
+        let mut cache = self.cache.borrow_mut();
+
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(offset, (kind, data.clone()));
+    }
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> Result<usize, io::Error> {
+    let mut count = 0;
+
+    while count < buf.len() {
+        match file.read(&mut buf[count..])? {
+            0 => break,
+            n => count += n,
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,291 @@
+use super::{
+    crc32, delta,
+    index::{IDX_SIGNATURE, IDX_VERSION, LARGE_OFFSET},
+    OFS_DELTA, SIGNATURE, VERSION,
+};
+use crate::id::{Additive, Id};
+use bytes::Bytes;
+use libflate::zlib::Encoder;
+use std::{
+    fs,
+    io::{self, prelude::*},
+    path::Path,
+};
+
+const MIN_DELTA_SIZE: usize = 50;
+
+/// An object's id, CRC-32 and offset in the pack, as listed in its index.
+type IndexEntry = (Id, u32, u64);
+
+/// An object to be written to a pack, along with the path it was found at,
+/// which helps bring together the versions of a file when looking for
+/// delta bases.
+pub struct PackObject {
+    pub id: Id,
+    pub kind: u8,
+    pub data: Bytes,
+    pub path: String,
+}
+
+struct Delta {
+    base: usize,
+    data: Vec<u8>,
+}
+
+/// Writes a set of objects to a `.pack` and its `.idx`. Objects are sorted so
+/// that similar ones end up close to each other, and each one is compared with
+/// the `window` objects before it to find a base it can be stored as a delta
+/// of, without building chains longer than `depth`.
+pub struct Writer {
+    window: usize,
+    depth: usize,
+}
+
+impl Writer {
+    pub fn new(window: usize, depth: usize) -> Self {
+        Self { window, depth }
+    }
+
+    /// Writes the pack to `dir` and returns its checksum, which names the
+    /// files as `pack-<checksum>.pack` and `.idx`, and the number of objects
+    /// stored as deltas.
+    pub fn write(&self, dir: &Path, objects: Vec<PackObject>) -> Result<(Id, usize), io::Error> {
+        let (pack, entries, delta_count) = self.build(objects)?;
+
+        let checksum = Id::parse(&pack[pack.len() - 20..]);
+        let index = build_index(entries, &checksum);
+
+        fs::create_dir_all(dir)?;
+
+        let name = format!("pack-{}", checksum);
+        write_file(&dir.join(format!("{}.pack", name)), &pack)?;
+        write_file(&dir.join(format!("{}.idx", name)), &index)?;
+
+        Ok((checksum, delta_count))
+    }
+
+    /// Builds a pack to be sent to another repository, which indexes it
+    /// itself.
+    pub fn pack(&self, objects: Vec<PackObject>) -> Result<Vec<u8>, io::Error> {
+        Ok(self.build(objects)?.0)
+    }
+
+    fn build(
+        &self,
+        mut objects: Vec<PackObject>,
+    ) -> Result<(Vec<u8>, Vec<IndexEntry>, usize), io::Error> {
+        objects.sort_by(|a, b| {
+            (a.kind, name_hash(&a.path), b.data.len()).cmp(&(
+                b.kind,
+                name_hash(&b.path),
+                a.data.len(),
+            ))
+        });
+
+        let deltas = self.find_deltas(&objects);
+
+        let mut pack = SIGNATURE.to_vec();
+        pack.extend_from_slice(&VERSION.to_be_bytes());
+        pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+        let mut offsets = vec![];
+        let mut entries = vec![];
+
+        for (n, object) in objects.iter().enumerate() {
+            let offset = pack.len() as u64;
+            offsets.push(offset);
+
+            let entry = match &deltas[n] {
+                Some(delta) => {
+                    let mut entry = entry_header(OFS_DELTA, delta.data.len());
+                    entry.extend(base_distance(offset - offsets[delta.base]));
+                    entry.extend(compress(&delta.data)?);
+                    entry
+                }
+                None => {
+                    let mut entry = entry_header(object.kind, object.data.len());
+                    entry.extend(compress(&object.data)?);
+                    entry
+                }
+            };
+
+            entries.push((object.id.clone(), crc32(&entry), offset));
+            pack.extend(entry);
+        }
+
+        let checksum = checksum(&pack);
+        pack.extend_from_slice(&checksum.as_bytes);
+
+        let delta_count = deltas.iter().filter(|delta| delta.is_some()).count();
+
+        Ok((pack, entries, delta_count))
+    }
+
+    fn find_deltas(&self, objects: &[PackObject]) -> Vec<Option<Delta>> {
+        let mut deltas: Vec<Option<Delta>> = vec![];
+        let mut depths = vec![0; objects.len()];
+
+        for (n, target) in objects.iter().enumerate() {
+            let mut best: Option<Delta> = None;
+
+            if target.data.len() >= MIN_DELTA_SIZE {
+                for base in n.saturating_sub(self.window)..n {
+                    let source = &objects[base];
+
+                    if source.kind != target.kind || depths[base] >= self.depth {
+                        continue;
+                    }
+
+                    let limit = match &best {
+                        Some(delta) => delta.data.len(),
+                        None => target.data.len() / 2,
+                    };
+
+                    if target.data.len().saturating_sub(source.data.len()) >= limit {
+                        continue;
+                    }
+
+                    let data = delta::compress(&source.data, &target.data);
+
+                    if data.len() < limit {
+                        best = Some(Delta { base, data });
+                    }
+                }
+            }
+
+            if let Some(delta) = &best {
+                depths[n] = depths[delta.base] + 1;
+            }
+
+            deltas.push(best);
+        }
+
+        deltas
+    }
+}
+
+/// Git's hash of the path an object was found at, which mostly depends on its
+/// last characters so that files with the same name or extension sort next to
+/// each other.
+fn name_hash(path: &str) -> u32 {
+    path.bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .fold(0u32, |hash, byte| {
+            (hash >> 2).wrapping_add((byte as u32) << 24)
+        })
+}
+
+pub fn entry_header(kind: u8, mut size: usize) -> Vec<u8> {
+    let mut bytes = vec![(kind << 4) | (size & 0xf) as u8];
+    size >>= 4;
+
+    while size > 0 {
+        *bytes.last_mut().unwrap() |= 0x80;
+        bytes.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+
+    bytes
+}
+
+/// Encodes how far back the base of an `OFS_DELTA` is, most significant
+/// byte first, with each continuation adding one to the remaining value.
+fn base_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+
+    bytes.reverse();
+
+    bytes
+}
+
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut encoder = Encoder::new(vec![])?;
+    encoder.write_all(data)?;
+
+    encoder.finish().into_result()
+}
+
+pub fn checksum(data: &[u8]) -> Id {
+    let mut hasher = Additive::new();
+    hasher.add(data);
+
+    hasher.commit()
+}
+
+pub fn build_index(mut entries: Vec<IndexEntry>, pack_checksum: &Id) -> Vec<u8> {
+    entries.sort_by_key(|entry| entry.0.as_bytes);
+
+    let mut index = IDX_SIGNATURE.to_vec();
+    index.extend_from_slice(&IDX_VERSION.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+
+    for (id, _, _) in &entries {
+        fanout[id.as_bytes[0] as usize] += 1;
+    }
+
+    let mut count = 0;
+
+    for n in fanout.iter() {
+        count += n;
+        index.extend_from_slice(&count.to_be_bytes());
+    }
+
+    for (id, _, _) in &entries {
+        index.extend_from_slice(&id.as_bytes);
+    }
+
+    for (_, crc, _) in &entries {
+        index.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    let mut large_offsets = vec![];
+
+    for (_, _, offset) in &entries {
+        let value = if *offset < LARGE_OFFSET as u64 {
+            *offset as u32
+        } else {
+            large_offsets.push(*offset);
+            LARGE_OFFSET | (large_offsets.len() - 1) as u32
+        };
+
+        index.extend_from_slice(&value.to_be_bytes());
+    }
+
+    for offset in large_offsets {
+        index.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    index.extend_from_slice(&pack_checksum.as_bytes);
+    let index_checksum = checksum(&index);
+    index.extend_from_slice(&index_checksum.as_bytes);
+
+    index
+}
+
+/// Writes through a temporary file so that readers never see a partial pack.
+pub fn write_file(path: &Path, data: &[u8]) -> Result<(), io::Error> {
+    let temp_path = path.with_extension("tmp");
+
+    fs::write(&temp_path, data)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_encodes_base_distances() {
+        assert_eq!(vec![0x05], base_distance(5));
+        assert_eq!(vec![0x80, 0x00], base_distance(128));
+        assert_eq!(vec![0x81, 0x7f], base_distance(383));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{id::Id, objects::Author};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+
+const LOGS_DIR: &str = "logs";
+const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug)]
+pub enum ReflogError {
+    Io,
+    InvalidExpiry(String),
+    NotAReflog(String),
+}
+
+impl fmt::Display for ReflogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflogError::Io => write!(f, "Cannot access the reflog"),
+            ReflogError::InvalidExpiry(value) => {
+                write!(f, "invalid timestamp '{}' given to '--expire'", value)
+            }
+            ReflogError::NotAReflog(name) => write!(f, "not a reflog: {}", name),
+        }
+    }
+}
+
+impl From<io::Error> for ReflogError {
+    fn from(_err: io::Error) -> ReflogError {
+        ReflogError::Io
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogEntry {
+    pub old_id: Option<Id>,
+    pub new_id: Id,
+    pub committer: Author,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+
+        let old_id = Id::from_hex(header.get(0..40)?);
+        let new_id = Id::from_hex(header.get(41..81)?)?;
+        let committer = Author::parse(header.get(82..)?)?;
+
+        Some(Self {
+            old_id: old_id.filter(|id| id.as_str != NULL_ID),
+            new_id,
+            committer,
+            message: message.to_string(),
+        })
+    }
+
+    fn format(&self) -> String {
+        let old_id = match &self.old_id {
+            Some(id) => id.as_str.clone(),
+            None => NULL_ID.to_string(),
+        };
+
+        format!(
+            "{} {} {}\t{}\n",
+            old_id,
+            self.new_id.as_str,
+            self.committer,
+            self.message.lines().next().unwrap_or("")
+        )
+    }
+}
+
+/// Splits `<ref>@{<selector>}` into the ref name, which may be empty, and
+/// the selector.
+pub fn parse_selector(expr: &str) -> Option<(&str, &str)> {
+    let (name, rest) = expr.split_once("@{")?;
+    let selector = rest.strip_suffix('}')?;
+
+    if selector.is_empty() || selector.contains(['{', '}']) {
+        return None;
+    }
+
+    Some((name, selector))
+}
+
+/// The history of the values a ref had, kept in `.git/logs/<ref>` with one
+/// line per update, oldest first.
+pub struct Reflog {
+    path: PathBuf,
+}
+
+impl Reflog {
+    pub fn new(git_path: &Path) -> Self {
+        Self {
+            path: git_path.join(LOGS_DIR),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path.join(name).is_file()
+    }
+
+    /// Lists the names of all the refs that have a log, e.g. `HEAD` and
+    /// `refs/heads/master`.
+    pub fn list(&self) -> Vec<String> {
+        let mut names = vec![];
+        let mut dirs = vec![String::new()];
+
+        while let Some(dir) = dirs.pop() {
+            let entries = match fs::read_dir(self.path.join(&dir)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let name = if dir.is_empty() {
+                    file_name
+                } else {
+                    format!("{}/{}", dir, file_name)
+                };
+
+                if entry.path().is_dir() {
+                    dirs.push(name);
+                } else {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort();
+
+        names
+    }
+
+    pub fn append(&self, name: &str, entry: &ReflogEntry) -> Result<(), io::Error> {
+        let path = self.path.join(name);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        file.write_all(entry.format().as_bytes())
+    }
+
+    /// Reads the entries of the log for `name`, oldest first.
+    pub fn read(&self, name: &str) -> Vec<ReflogEntry> {
+        fs::read_to_string(self.path.join(name))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(ReflogEntry::parse)
+            .collect()
+    }
+
+    pub fn write(&self, name: &str, entries: &[ReflogEntry]) -> Result<(), io::Error> {
+        let content: String = entries.iter().map(ReflogEntry::format).collect();
+        let path = self.path.join(name);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, content)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), io::Error> {
+        let path = self.path.join(name);
+
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_selectors() {
+        assert_eq!(Some(("master", "1")), parse_selector("master@{1}"));
+        assert_eq!(Some(("", "2.days.ago")), parse_selector("@{2.days.ago}"));
+        assert_eq!(None, parse_selector("master@{}"));
+        assert_eq!(None, parse_selector("master"));
+    }
+
+    #[test]
+    fn it_round_trips_entries() {
+        let line = format!(
+            "{} {} name <email> 1600000000 +0200\tWIP on master: 1234567 subject\n",
+            NULL_ID,
+            "ab".repeat(20)
+        );
+
+        let entry = ReflogEntry::parse(line.trim_end()).unwrap();
+
+        assert_eq!(None, entry.old_id);
+        assert_eq!("ab".repeat(20), entry.new_id.as_str);
+        assert_eq!("WIP on master: 1234567 subject", entry.message);
+        assert_eq!(line, entry.format());
+    }
+}
@@ -1,34 +1,69 @@
 use crate::{
     id::Id,
     lockfile::{LockError, Lockfile},
+    objects::Author,
+    reflog::{Reflog, ReflogEntry},
 };
-use std::{
-    fmt, fs,
-    io::{self, prelude::*},
-    path::PathBuf,
-};
+use std::{fmt, fs, io, path::PathBuf};
+
+pub const HEAD: &str = "HEAD";
+pub const ORIG_HEAD: &str = "ORIG_HEAD";
+
+const DEFAULT_BRANCH: &str = "refs/heads/master";
+const HEADS_DIR: &str = "refs/heads";
+const TAGS_DIR: &str = "refs/tags";
+const REMOTES_DIR: &str = "refs/remotes";
+const SYMREF_PREFIX: &str = "ref: ";
+const PACKED_REFS: &str = "packed-refs";
+const LOGGED_PREFIXES: [&str; 3] = ["refs/heads/", "refs/remotes/", "refs/notes/"];
 
 #[derive(Debug)]
-pub struct RefsError;
+pub enum RefsError {
+    Io,
+    Lock(LockError),
+    InvalidBranch(String),
+    BranchExists(String),
+    InvalidTag(String),
+    TagExists(String),
+    TagNotFound(String),
+}
 
 impl fmt::Display for RefsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RefsError")
+        match self {
+            RefsError::Io => write!(f, "Cannot access refs"),
+            RefsError::Lock(err) => write!(f, "{}", err),
+            RefsError::InvalidBranch(name) => {
+                write!(f, "'{}' is not a valid branch name.", name)
+            }
+            RefsError::BranchExists(name) => {
+                write!(f, "A branch named '{}' already exists.", name)
+            }
+            RefsError::InvalidTag(name) => write!(f, "'{}' is not a valid tag name.", name),
+            RefsError::TagExists(name) => write!(f, "tag '{}' already exists", name),
+            RefsError::TagNotFound(name) => write!(f, "tag '{}' not found.", name),
+        }
     }
 }
 
 impl From<io::Error> for RefsError {
     fn from(_err: io::Error) -> RefsError {
-        RefsError
+        RefsError::Io
     }
 }
 
 impl From<LockError> for RefsError {
-    fn from(_err: LockError) -> RefsError {
-        RefsError
+    fn from(err: LockError) -> RefsError {
+        RefsError::Lock(err)
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ref {
+    Symbolic(String),
+    Direct(Id),
+}
+
 pub struct Refs(PathBuf);
 
 impl Refs {
@@ -36,26 +71,393 @@ impl Refs {
         Self(path)
     }
 
-    pub fn update_head(&self, id: &Id) -> Result<(), RefsError> {
-        let mut lockfile = Lockfile::new(self.head_path());
-        lockfile.hold_for_update()?;
+    pub fn update_head(&self, id: &Id, committer: &Author, message: &str) -> Result<(), RefsError> {
+        if self.read_raw(HEAD).is_none() {
+            self.write_symref(HEAD, DEFAULT_BRANCH)?;
+        }
 
-        lockfile.write(&id.as_str.as_bytes())?;
-        lockfile.write("\n".as_bytes())?;
-        lockfile.commit()?;
+        match self.current_ref() {
+            Some(name) => self.update_ref_logged(&name, id, committer, message),
+            None => self.update_ref_logged(HEAD, id, committer, message),
+        }
+    }
+
+    pub fn read_head(&self) -> Option<Id> {
+        self.read_ref(HEAD)
+    }
+
+    /// Points HEAD at the branch named `revision` when there is one, detaching it
+    /// at `id` otherwise.
+    pub fn set_head(&self, revision: &str, id: &Id) -> Result<(), RefsError> {
+        let branch = self.branch_path(revision);
+
+        if self.read_raw(&branch).is_some() {
+            self.write_symref(HEAD, &branch)
+        } else {
+            self.update_ref(HEAD, id)
+        }
+    }
+
+    /// The full name of the branch HEAD points at, or `None` when it is detached.
+    pub fn current_ref(&self) -> Option<String> {
+        match self.read_raw(HEAD) {
+            Some(Ref::Symbolic(name)) => Some(name),
+            Some(Ref::Direct(_)) => None,
+            None => Some(DEFAULT_BRANCH.to_string()),
+        }
+    }
+
+    pub fn read_ref(&self, name: &str) -> Option<Id> {
+        self.lookup_paths(name)
+            .into_iter()
+            .find_map(|path| self.resolve(&path))
+    }
+
+    pub fn update_ref(&self, name: &str, id: &Id) -> Result<(), RefsError> {
+        let content = format!("{}\n", id.as_str);
+
+        self.write_ref(name, content.as_bytes())
+    }
+
+    /// Moves `name` to `id` and records the move in its reflog, and in the
+    /// one of HEAD too when HEAD is attached to `name`.
+    pub fn update_ref_logged(
+        &self,
+        name: &str,
+        id: &Id,
+        committer: &Author,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        let old_id = self.read_ref(name);
+
+        self.update_ref(name, id)?;
+        self.append_log(name, old_id.clone(), id, committer, message)?;
+
+        if name != HEAD && self.current_ref().as_deref() == Some(name) {
+            self.append_log(HEAD, old_id, id, committer, message)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds an entry to the reflog of `name`. Like git with
+    /// `core.logAllRefUpdates`, only HEAD, branches, remote-tracking refs and
+    /// notes get a log unless one already exists.
+    pub fn append_log(
+        &self,
+        name: &str,
+        old_id: Option<Id>,
+        new_id: &Id,
+        committer: &Author,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        let reflog = self.reflog();
+        let is_logged = name == HEAD
+            || LOGGED_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+            || reflog.exists(name);
+
+        if !is_logged {
+            return Ok(());
+        }
+
+        let entry = ReflogEntry {
+            old_id,
+            new_id: new_id.clone(),
+            committer: committer.clone(),
+            message: message.to_string(),
+        };
+
+        Ok(reflog.append(name, &entry)?)
+    }
+
+    pub fn reflog(&self) -> Reflog {
+        Reflog::new(&self.0)
+    }
+
+    /// Deletes a ref along with its reflog.
+    pub fn delete_ref(&self, name: &str) -> Result<(), RefsError> {
+        let path = self.0.join(name);
+
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+
+        self.remove_packed_ref(name)?;
+        self.reflog().delete(name)?;
+
+        Ok(())
+    }
+
+    /// Moves a ref to a new name, taking its reflog along.
+    pub fn rename_ref(&self, old_name: &str, new_name: &str) -> Result<(), RefsError> {
+        let id = match self.read_ref(old_name) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let reflog = self.reflog();
+        let entries = reflog.read(old_name);
+
+        self.update_ref(new_name, &id)?;
+
+        if !entries.is_empty() {
+            reflog.write(new_name, &entries)?;
+        }
+
+        self.delete_ref(old_name)
+    }
+
+    /// The name of the ref that `name` points at, when it is a symbolic ref.
+    pub fn read_symref(&self, name: &str) -> Option<String> {
+        match self.read_raw(name)? {
+            Ref::Symbolic(target) => Some(target),
+            Ref::Direct(_) => None,
+        }
+    }
+
+    /// The full name of the existing ref that `name` abbreviates, e.g.
+    /// `refs/heads/master` for `master`.
+    pub fn expand_name(&self, name: &str) -> Option<String> {
+        self.lookup_paths(name)
+            .into_iter()
+            .find(|path| self.read_raw(path).is_some())
+    }
+
+    pub fn create_branch(&self, name: &str, id: &Id) -> Result<(), RefsError> {
+        if !Self::is_valid_branch_name(name) {
+            return Err(RefsError::InvalidBranch(name.to_string()));
+        }
+
+        let path = self.branch_path(name);
+
+        if self.read_raw(&path).is_some() {
+            return Err(RefsError::BranchExists(name.to_string()));
+        }
+
+        self.update_ref(&path, id)
+    }
+
+    /// Points `refs/tags/<name>` at `id`, replacing an existing tag only when
+    /// `force` is set.
+    pub fn create_tag(&self, name: &str, id: &Id, force: bool) -> Result<(), RefsError> {
+        if !Self::is_valid_branch_name(name) {
+            return Err(RefsError::InvalidTag(name.to_string()));
+        }
+
+        let path = self.tag_path(name);
+
+        if !force && self.read_raw(&path).is_some() {
+            return Err(RefsError::TagExists(name.to_string()));
+        }
+
+        self.update_ref(&path, id)
+    }
+
+    /// Deletes a tag and returns the object it pointed at. A name no tag
+    /// could have, such as one climbing out of `refs/tags`, is not found.
+    pub fn delete_tag(&self, name: &str) -> Result<Id, RefsError> {
+        if !Self::is_valid_branch_name(name) {
+            return Err(RefsError::TagNotFound(name.to_string()));
+        }
+
+        let path = self.tag_path(name);
+
+        let id = self
+            .read_ref(&path)
+            .ok_or_else(|| RefsError::TagNotFound(name.to_string()))?;
+
+        self.delete_ref(&path)?;
+
+        Ok(id)
+    }
+
+    /// Lists the full names of the refs under `prefix`, e.g. `refs/tags`,
+    /// whether they are stored as files or in `packed-refs`.
+    pub fn list_refs(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .packed_refs()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(&format!("{}/", prefix)))
+            .collect();
+        let mut dirs = vec![prefix.to_string()];
+
+        while let Some(dir) = dirs.pop() {
+            let entries = match fs::read_dir(self.0.join(&dir)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = format!("{}/{}", dir, entry.file_name().to_string_lossy());
+
+                if entry.path().is_dir() {
+                    dirs.push(name);
+                } else if !name.ends_with(".lock") {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
+    pub fn short_name<'a>(&self, name: &'a str) -> &'a str {
+        name.strip_prefix("refs/heads/")
+            .or_else(|| name.strip_prefix("refs/tags/"))
+            .or_else(|| name.strip_prefix("refs/"))
+            .unwrap_or(name)
+    }
+
+    pub fn is_valid_branch_name(name: &str) -> bool {
+        !(name.is_empty()
+            || name.starts_with('.')
+            || name.starts_with('-')
+            || name.ends_with('/')
+            || name.ends_with(".lock")
+            || name.contains("..")
+            || name.contains("/.")
+            || name.contains("@{")
+            || name
+                .chars()
+                .any(|c| c.is_control() || " ~^:?*[\\".contains(c)))
+    }
+
+    /// Checks a full ref name the way `git check-ref-format` does, so that
+    /// names coming from elsewhere cannot point outside of `refs`.
+    pub fn is_valid_ref_name(name: &str) -> bool {
+        !(name.is_empty()
+            || name.ends_with('.')
+            || name.contains("..")
+            || name.contains("@{")
+            || name
+                .chars()
+                .any(|c| c.is_control() || " ~^:?*[\\".contains(c))
+            || name
+                .split('/')
+                .any(|part| part.is_empty() || part.starts_with('.') || part.ends_with(".lock")))
+    }
+
+    fn branch_path(&self, name: &str) -> String {
+        format!("{}/{}", HEADS_DIR, name)
+    }
+
+    fn tag_path(&self, name: &str) -> String {
+        format!("{}/{}", TAGS_DIR, name)
+    }
+
+    fn lookup_paths(&self, name: &str) -> Vec<String> {
+        vec![
+            name.to_string(),
+            format!("refs/{}", name),
+            self.tag_path(name),
+            self.branch_path(name),
+            format!("{}/{}", REMOTES_DIR, name),
+            format!("{}/{}/{}", REMOTES_DIR, name, HEAD),
+        ]
+    }
+
+    fn resolve(&self, name: &str) -> Option<Id> {
+        match self.read_raw(name)? {
+            Ref::Symbolic(target) => self.resolve(&target),
+            Ref::Direct(id) => Some(id),
+        }
+    }
+
+    fn read_raw(&self, name: &str) -> Option<Ref> {
+        let path = self.0.join(name);
+
+        if !path.is_file() {
+            return self
+                .packed_refs()
+                .into_iter()
+                .find(|(packed_name, _)| packed_name == name)
+                .map(|(_, id)| Ref::Direct(id));
+        }
+
+        let content = fs::read_to_string(path).ok()?;
+        let content = content.trim();
+
+        match content.strip_prefix(SYMREF_PREFIX) {
+            Some(target) => Some(Ref::Symbolic(target.to_string())),
+            None => Id::from_hex(content).map(Ref::Direct),
+        }
+    }
+
+    /// Reads the refs that `git pack-refs` moved into a single file, as
+    /// `<id> <name>` lines. Lines starting with `^` give the commit an
+    /// annotated tag peels to and are skipped.
+    fn packed_refs(&self) -> Vec<(String, Id)> {
+        let content = fs::read_to_string(self.0.join(PACKED_REFS)).unwrap_or_default();
+
+        content
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| {
+                let (id, name) = line.split_once(' ')?;
+
+                Some((name.to_string(), Id::from_hex(id)?))
+            })
+            .collect()
+    }
+
+    fn remove_packed_ref(&self, name: &str) -> Result<(), RefsError> {
+        let path = self.0.join(PACKED_REFS);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Ok(()),
+        };
+
+        let mut lines = content.lines().peekable();
+        let mut kept = String::new();
+        let mut found = false;
+
+        while let Some(line) = lines.next() {
+            if line.split_once(' ').map(|(_, packed)| packed) == Some(name) {
+                found = true;
+
+                while lines.peek().is_some_and(|next| next.starts_with('^')) {
+                    lines.next();
+                }
+
+                continue;
+            }
+
+            kept.push_str(line);
+            kept.push('\n');
+        }
+
+        if found {
+            self.write_ref(PACKED_REFS, kept.as_bytes())?;
+        }
 
         Ok(())
     }
 
-    pub fn read_head(&self) -> Option<String> {
-        let file = fs::File::open(self.head_path()).ok();
-        let mut head = String::new();
+    pub fn write_symref(&self, name: &str, target: &str) -> Result<(), RefsError> {
+        let content = format!("{}{}\n", SYMREF_PREFIX, target);
 
-        file.and_then(|mut f| f.read_to_string(&mut head).ok())
-            .map(|_| head)
+        self.write_ref(name, content.as_bytes())
     }
 
-    fn head_path(&self) -> PathBuf {
-        self.0.join("HEAD")
+    fn write_ref(&self, name: &str, content: &[u8]) -> Result<(), RefsError> {
+        let path = self.0.join(name);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut lockfile = Lockfile::new(path);
+        lockfile.hold_for_update()?;
+
+        lockfile.write(content)?;
+        lockfile.commit()?;
+
+        Ok(())
     }
 }
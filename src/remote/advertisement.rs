@@ -0,0 +1,141 @@
+use super::{pkt_line, ZERO_ID};
+use crate::id::Id;
+use std::io::{self, prelude::*};
+
+const NO_REFS: &str = "capabilities^{}";
+const PEELED_SUFFIX: &str = "^{}";
+
+/// The refs a repository offers at the start of a connection, along with the
+/// capabilities of the program serving it, which ride on the first line
+/// after a NUL byte.
+#[derive(Debug, Default)]
+pub struct Advertisement {
+    pub refs: Vec<(String, Id)>,
+    pub capabilities: Vec<String>,
+}
+
+impl Advertisement {
+    pub fn new(refs: Vec<(String, Id)>, capabilities: Vec<String>) -> Self {
+        Self { refs, capabilities }
+    }
+
+    /// Reads the advertisement up to its flush-pkt. Peeled tags are left out,
+    /// since every object they point at is reachable from the tag anyway.
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut advertisement = Self::default();
+
+        while let Some(line) = pkt_line::read_line(input)? {
+            let line = match line.split_once('\0') {
+                Some((line, capabilities)) => {
+                    advertisement.capabilities =
+                        capabilities.split(' ').map(String::from).collect();
+                    line.to_string()
+                }
+                None => line,
+            };
+
+            let (id, name) = line.split_once(' ').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("protocol error: unexpected '{}'", line),
+                )
+            })?;
+
+            if name == NO_REFS || name.ends_with(PEELED_SUFFIX) {
+                continue;
+            }
+
+            if let Some(id) = Id::from_hex(id) {
+                advertisement.refs.push((name.to_string(), id));
+            }
+        }
+
+        Ok(advertisement)
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let capabilities = self.capabilities.join(" ");
+
+        if self.refs.is_empty() {
+            let line = format!("{} {}\0{}", ZERO_ID, NO_REFS, capabilities);
+            pkt_line::write_line(output, &line)?;
+        }
+
+        for (n, (name, id)) in self.refs.iter().enumerate() {
+            let line = if n == 0 {
+                format!("{} {}\0{}", id, name, capabilities)
+            } else {
+                format!("{} {}", id, name)
+            };
+
+            pkt_line::write_line(output, &line)?;
+        }
+
+        pkt_line::flush(output)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Id> {
+        self.refs
+            .iter()
+            .find(|(ref_name, _)| ref_name == name)
+            .map(|(_, id)| id)
+    }
+
+    pub fn has(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|offered| offered == capability)
+    }
+
+    /// The target of a symbolic ref announced as `symref=HEAD:refs/heads/x`.
+    pub fn symref(&self, name: &str) -> Option<&str> {
+        let prefix = format!("symref={}:", name);
+
+        self.capabilities
+            .iter()
+            .find_map(|capability| capability.strip_prefix(&prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_what_it_writes() {
+        let id = Id::from_hex("0123456789abcdef0123456789abcdef01234567").unwrap();
+        let refs = vec![
+            ("HEAD".to_string(), id.clone()),
+            ("refs/heads/master".to_string(), id.clone()),
+            ("refs/tags/v1^{}".to_string(), id),
+        ];
+        let capabilities = vec![
+            "ofs-delta".to_string(),
+            "symref=HEAD:refs/heads/master".to_string(),
+        ];
+
+        let mut output = vec![];
+        Advertisement::new(refs, capabilities)
+            .write(&mut output)
+            .unwrap();
+
+        let advertisement = Advertisement::read(&mut &output[..]).unwrap();
+
+        assert_eq!(2, advertisement.refs.len());
+        assert!(advertisement.has("ofs-delta"));
+        assert_eq!(Some("refs/heads/master"), advertisement.symref("HEAD"));
+    }
+
+    #[test]
+    fn it_reads_an_empty_repository() {
+        let mut output = vec![];
+        Advertisement::new(vec![], vec!["report-status".to_string()])
+            .write(&mut output)
+            .unwrap();
+
+        let advertisement = Advertisement::read(&mut &output[..]).unwrap();
+
+        assert!(advertisement.refs.is_empty());
+        assert!(advertisement.has("report-status"));
+    }
+}
@@ -0,0 +1,316 @@
+mod advertisement;
+mod http;
+pub mod pkt_line;
+mod refspec;
+mod transport;
+
+pub use advertisement::Advertisement;
+pub use http::{header, read_body, read_head};
+pub use refspec::{map_refs, RefMapping, Refspec};
+pub use transport::{is_path, Transport, DAEMON_PORT};
+
+use crate::{config::Config, errors::RitError, id::Id, pack::Indexer, repository::Repository};
+use std::{
+    fs,
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+};
+
+pub const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+pub const UPLOAD_PACK: &str = "upload-pack";
+pub const RECEIVE_PACK: &str = "receive-pack";
+
+const MULTI_ACK: &str = "multi_ack_detailed";
+const FETCH_CAPABILITIES: [&str; 3] = [MULTI_ACK, "ofs-delta", "thin-pack"];
+const HAVES_PER_ROUND: usize = 32;
+
+/// How `upload-pack` and `receive-pack` talk to their client: over one
+/// connection for the whole conversation, or over HTTP, where the refs are
+/// advertised in answer to one request and each later request is answered
+/// on its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ServiceMode {
+    #[default]
+    Connection,
+    AdvertiseRefs,
+    StatelessRpc,
+}
+
+/// Another repository that objects are fetched from or pushed to, either
+/// configured as `remote.<name>.url` or given directly as a url.
+pub struct Remote {
+    pub name: Option<String>,
+    pub url: String,
+    pub fetch: Vec<String>,
+    pub prune: Option<bool>,
+    upload_pack: Option<String>,
+    receive_pack: Option<String>,
+    ssh_command: Option<String>,
+    credential_helpers: Vec<String>,
+}
+
+impl Remote {
+    pub fn find(config: &Config, name: &str) -> Self {
+        let setting = |key: &str| config.get(&format!("remote.{}.{}", name, key));
+
+        let (remote_name, url) = match setting("url") {
+            Some(url) => (Some(name.to_string()), url),
+            None => (None, name.to_string()),
+        };
+
+        Self {
+            name: remote_name,
+            url,
+            fetch: config.get_all(&format!("remote.{}.fetch", name)),
+            prune: config
+                .get_bool(&format!("remote.{}.prune", name))
+                .or_else(|| config.get_bool("fetch.prune")),
+            upload_pack: setting("uploadpack"),
+            receive_pack: setting("receivepack"),
+            ssh_command: config.get("core.sshCommand"),
+            credential_helpers: config.get_all("credential.helper"),
+        }
+    }
+
+    pub fn fetch_refspecs(&self) -> Result<Vec<Refspec>, RitError> {
+        Refspec::parse_all(&self.fetch)
+    }
+
+    /// Overrides the program run on the other side for `service`, as with
+    /// `--upload-pack` or `--receive-pack`.
+    pub fn set_program(&mut self, service: &str, program: String) {
+        match service {
+            UPLOAD_PACK => self.upload_pack = Some(program),
+            _ => self.receive_pack = Some(program),
+        }
+    }
+
+    /// Starts `service` on the other side. Paths in the url are relative to
+    /// `dir`.
+    pub fn connect(&self, service: &str, dir: &Path) -> Result<Transport, RitError> {
+        Transport::connect(self, service, dir).map_err(protocol_error)
+    }
+}
+
+/// Finds the git directory of the repository at `path`, which is either a
+/// bare repository or has one in `.git`.
+pub fn git_dir(path: &Path) -> Option<PathBuf> {
+    [path.join(".git"), path.to_path_buf()]
+        .iter()
+        .find(|path| path.join("objects").is_dir() && path.join("refs").is_dir())
+        .cloned()
+}
+
+/// Finds the git directory of the repository a daemon or HTTP request names,
+/// either at `path` or with `.git` added, within `base_path`. Paths that are
+/// not absolute or climb out with `..` find nothing.
+pub fn find_repository(base_path: Option<&Path>, path: &str) -> Option<PathBuf> {
+    let requested = Path::new(path);
+
+    if !requested.is_absolute()
+        || requested
+            .components()
+            .any(|component| component == Component::ParentDir)
+    {
+        return None;
+    }
+
+    let dir = match base_path {
+        Some(base_path) => base_path.join(requested.strip_prefix("/").ok()?),
+        None => requested.to_path_buf(),
+    };
+
+    let candidates = [dir.clone(), PathBuf::from(format!("{}.git", dir.display()))];
+    let git_path = candidates.iter().find_map(|dir| git_dir(dir))?;
+
+    fs::canonicalize(git_path).ok()
+}
+
+/// The directory a user would name to refer to a repository: its working
+/// tree rather than the `.git` directory inside it.
+pub fn work_tree(git_path: &Path) -> &Path {
+    if git_path.ends_with(".git") {
+        git_path.parent().unwrap_or(git_path)
+    } else {
+        git_path
+    }
+}
+
+/// Opens the repository a server command was started for.
+pub fn open_repository(path: &Path) -> Result<Repository, RitError> {
+    let git_path = git_dir(path).ok_or_else(|| {
+        RitError::Remote(format!(
+            "'{}' does not appear to be a git repository",
+            path.display()
+        ))
+    })?;
+
+    Ok(Repository::with_git_path(
+        work_tree(&git_path).to_path_buf(),
+        git_path,
+    ))
+}
+
+pub fn unexpected(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("protocol error: unexpected '{}'", line),
+    )
+}
+
+pub fn protocol_error(err: io::Error) -> RitError {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe => {
+            RitError::Remote("the remote end hung up unexpectedly".to_string())
+        }
+        _ => RitError::Remote(err.to_string()),
+    }
+}
+
+/// Asks the other side of `conn` for `wants`, telling it which commits are
+/// already here until it acknowledges one it has too, and stores the pack it
+/// sends back. Returns how many objects the pack held.
+pub fn fetch_pack(
+    conn: &mut Transport,
+    advertisement: &Advertisement,
+    repo: &mut Repository,
+    wants: &[Id],
+    haves: &[Id],
+) -> Result<usize, RitError> {
+    negotiate(conn, advertisement, wants, haves).map_err(protocol_error)?;
+
+    if wants.is_empty() {
+        return Ok(0);
+    }
+
+    let head = skip_acknowledgements(&mut conn.input).map_err(protocol_error)?;
+    let data =
+        Indexer::read_stream(&mut (&head[..]).chain(&mut conn.input)).map_err(protocol_error)?;
+
+    store_pack(repo, data)
+}
+
+fn negotiate(
+    conn: &mut Transport,
+    advertisement: &Advertisement,
+    wants: &[Id],
+    haves: &[Id],
+) -> io::Result<()> {
+    let stateless = conn.is_stateless();
+    let output = &mut conn.output;
+
+    if wants.is_empty() {
+        return pkt_line::flush(output);
+    }
+
+    let capabilities: Vec<&str> = FETCH_CAPABILITIES
+        .iter()
+        .filter(|capability| advertisement.has(capability))
+        .filter(|capability| !stateless || **capability != MULTI_ACK)
+        .cloned()
+        .collect();
+
+    for (n, id) in wants.iter().enumerate() {
+        if n == 0 && !capabilities.is_empty() {
+            pkt_line::write_line(output, &format!("want {} {}", id, capabilities.join(" ")))?;
+        } else {
+            pkt_line::write_line(output, &format!("want {}", id))?;
+        }
+    }
+
+    pkt_line::flush(output)?;
+
+    // over HTTP, the haves go along with the wants in a single request,
+    // which ends the negotiation too
+    if stateless {
+        for id in haves {
+            pkt_line::write_line(output, &format!("have {}", id))?;
+        }
+
+        pkt_line::write_line(output, "done")?;
+
+        return conn.send();
+    }
+
+    let multi_ack = capabilities.contains(&MULTI_ACK);
+    let mut acknowledged = false;
+
+    for round in haves.chunks(HAVES_PER_ROUND) {
+        for id in round {
+            pkt_line::write_line(output, &format!("have {}", id))?;
+        }
+
+        pkt_line::flush(output)?;
+
+        // with multi_ack, every common commit is acknowledged and each round
+        // ends in a NAK, otherwise the round gets a single ACK or NAK
+        loop {
+            let acknowledgement = read_acknowledgement(&mut conn.input)?;
+            acknowledged |= acknowledgement;
+
+            if !multi_ack || !acknowledgement {
+                break;
+            }
+        }
+
+        if acknowledged {
+            break;
+        }
+    }
+
+    pkt_line::write_line(output, "done")?;
+    output.flush()?;
+
+    // without multi_ack, the first ACK is also the last word before the pack
+    if multi_ack || !acknowledged {
+        read_acknowledgement(&mut conn.input)?;
+    }
+
+    Ok(())
+}
+
+fn read_acknowledgement<R: io::Read>(input: &mut R) -> io::Result<bool> {
+    match pkt_line::read_line(input)? {
+        Some(line) if line == "NAK" => Ok(false),
+        Some(line) if line.starts_with("ACK ") => Ok(true),
+        line => Err(unexpected(&line.unwrap_or_default())),
+    }
+}
+
+/// Reads past the acknowledgements that are left before the pack, such as
+/// those a server without multi_ack sends for every common commit after the
+/// first, and returns the start of the pack.
+fn skip_acknowledgements<R: io::Read>(input: &mut R) -> io::Result<[u8; 4]> {
+    loop {
+        let mut head = [0; 4];
+        input.read_exact(&mut head)?;
+
+        if &head == b"PACK" {
+            return Ok(head);
+        }
+
+        let line = pkt_line::read_line(&mut (&head[..]).chain(&mut *input))?;
+
+        match line {
+            Some(line) if line == "NAK" || line.starts_with("ACK ") => (),
+            line => return Err(unexpected(&line.unwrap_or_default())),
+        }
+    }
+}
+
+/// Indexes a pack received from another repository and keeps it, completing
+/// it first when its deltas refer to objects that only this side has.
+pub fn store_pack(repo: &mut Repository, data: Vec<u8>) -> Result<usize, RitError> {
+    let database = &repo.database;
+
+    let indexer = Indexer::new(data, |id| database.read_object(id).ok())
+        .map_err(|err| RitError::InvalidPack(err.to_string()))?;
+    let count = indexer.objects().len();
+
+    if count > 0 {
+        indexer.write(&database.pack_dir())?;
+        repo.database.reload_packs();
+    }
+
+    Ok(count)
+}
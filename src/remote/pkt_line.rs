@@ -0,0 +1,120 @@
+use std::io::{self, prelude::*};
+
+const MAX_LEN: usize = 65520;
+
+/// Writes `data` as one pkt-line, prefixed by its length including the four
+/// hex digits of the prefix itself.
+pub fn write<W: Write>(output: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.len() + 4 > MAX_LEN {
+        return Err(invalid("pkt-line too long"));
+    }
+
+    output.write_all(format!("{:04x}", data.len() + 4).as_bytes())?;
+    output.write_all(data)
+}
+
+pub fn write_line<W: Write>(output: &mut W, line: &str) -> io::Result<()> {
+    write(output, format!("{}\n", line).as_bytes())
+}
+
+/// Writes a flush-pkt, which ends a section of the conversation, and sends
+/// everything written so far.
+pub fn flush<W: Write>(output: &mut W) -> io::Result<()> {
+    output.write_all(b"0000")?;
+    output.flush()
+}
+
+/// Writes `data` split into as many pkt-lines as it takes, followed by a
+/// flush-pkt, as messages over git's IPC sockets are.
+pub fn write_packetized<W: Write>(output: &mut W, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(MAX_LEN - 4) {
+        write(output, chunk)?;
+    }
+
+    flush(output)
+}
+
+/// Reads pkt-lines up to a flush-pkt and joins them back together.
+pub fn read_packetized<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+
+    while let Some(chunk) = read(input)? {
+        data.extend(chunk);
+    }
+
+    Ok(data)
+}
+
+/// Reads one pkt-line, or `None` for a flush-pkt.
+pub fn read<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut prefix = [0; 4];
+    input.read_exact(&mut prefix)?;
+
+    let len = std::str::from_utf8(&prefix)
+        .ok()
+        .and_then(|prefix| usize::from_str_radix(prefix, 16).ok())
+        .ok_or_else(|| invalid("protocol error: bad line length character"))?;
+
+    match len {
+        0 => Ok(None),
+        1..=3 => Err(invalid("protocol error: bad line length")),
+        _ => {
+            let mut data = vec![0; len - 4];
+            input.read_exact(&mut data)?;
+
+            Ok(Some(data))
+        }
+    }
+}
+
+/// Reads one pkt-line as text without its trailing newline. An `ERR` line
+/// from the other side becomes an error.
+pub fn read_line<R: Read>(input: &mut R) -> io::Result<Option<String>> {
+    let line = match read(input)? {
+        Some(data) => String::from_utf8_lossy(&data).to_string(),
+        None => return Ok(None),
+    };
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+
+    match line.strip_prefix("ERR ") {
+        Some(message) => Err(io::Error::other(format!("remote error: {}", message))),
+        None => Ok(Some(line.to_string())),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_frames_lines() {
+        let mut output = vec![];
+        write_line(&mut output, "want abc").unwrap();
+        flush(&mut output).unwrap();
+
+        assert_eq!(b"000dwant abc\n0000".to_vec(), output);
+
+        let mut input = &output[..];
+        assert_eq!(Some("want abc".to_string()), read_line(&mut input).unwrap());
+        assert_eq!(None, read_line(&mut input).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_bad_lengths() {
+        assert!(read(&mut &b"00x1"[..]).is_err());
+        assert!(read(&mut &b"0002"[..]).is_err());
+    }
+
+    #[test]
+    fn it_splits_long_messages() {
+        let data = vec![b'x'; MAX_LEN * 2];
+        let mut output = vec![];
+        write_packetized(&mut output, &data).unwrap();
+
+        assert_eq!(data, read_packetized(&mut &output[..]).unwrap());
+    }
+}
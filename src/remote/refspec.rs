@@ -0,0 +1,249 @@
+use crate::{errors::RitError, id::Id};
+
+const HEADS_PREFIX: &str = "refs/heads/";
+
+/// The places a short name like `master` is looked for among the refs of
+/// the other side, in the order git tries them.
+const SHORT_NAME_RULES: [&str; 5] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+];
+
+/// Says which refs of the other side to fetch and where to keep them, as in
+/// `+refs/heads/*:refs/remotes/origin/*`. A leading `+` lets the local ref
+/// move even when that is not a fast-forward, a `*` on both sides maps a
+/// whole namespace, and a leading `^` leaves the matching refs out of what
+/// the other refspecs would fetch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refspec {
+    pub source: String,
+    pub target: Option<String>,
+    pub force: bool,
+    pub negative: bool,
+}
+
+/// A ref of the other side that a fetch brings in, with the local ref it is
+/// stored as, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefMapping {
+    pub source: String,
+    pub target: Option<String>,
+    pub id: Id,
+    pub force: bool,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> Result<Self, RitError> {
+        let invalid = || RitError::Remote(format!("invalid refspec '{}'", spec));
+
+        let (negative, rest) = match spec.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (force, rest) = match rest.strip_prefix('+') {
+            Some(rest) if !negative => (true, rest),
+            Some(_) => return Err(invalid()),
+            None => (false, rest),
+        };
+
+        let (source, target) = match rest.split_once(':') {
+            Some((source, target)) => (source, Some(target).filter(|target| !target.is_empty())),
+            None => (rest, None),
+        };
+
+        let stars = |side: &str| side.matches('*').count();
+        let target_stars = target.map_or(0, stars);
+
+        if source.is_empty()
+            || stars(source) > 1
+            || target_stars > 1
+            || (target.is_some() && stars(source) != target_stars)
+            || (negative && target.is_some())
+            || source.contains(':')
+        {
+            return Err(invalid());
+        }
+
+        let target = target.map(|target| {
+            if target.starts_with("refs/") {
+                target.to_string()
+            } else {
+                format!("{}{}", HEADS_PREFIX, target)
+            }
+        });
+
+        Ok(Self {
+            source: source.to_string(),
+            target,
+            force,
+            negative,
+        })
+    }
+
+    pub fn parse_all(specs: &[String]) -> Result<Vec<Self>, RitError> {
+        specs.iter().map(|spec| Self::parse(spec)).collect()
+    }
+
+    /// The refspec a remote is set up with by `clone` and `remote add`.
+    pub fn default_fetch(remote_name: &str) -> String {
+        format!("+{}*:refs/remotes/{}/*", HEADS_PREFIX, remote_name)
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.source.contains('*')
+    }
+
+    pub fn matches_source(&self, name: &str) -> bool {
+        if self.is_glob() {
+            match_glob(&self.source, name).is_some()
+        } else {
+            SHORT_NAME_RULES
+                .iter()
+                .any(|rule| rule.replace("{}", &self.source) == name)
+        }
+    }
+
+    /// The local ref that the ref `name` of the other side is stored as.
+    pub fn map_source(&self, name: &str) -> Option<String> {
+        let target = self.target.as_ref()?;
+
+        if self.is_glob() {
+            let matched = match_glob(&self.source, name)?;
+            Some(target.replacen('*', matched, 1))
+        } else if self.matches_source(name) {
+            Some(target.clone())
+        } else {
+            None
+        }
+    }
+
+    /// The ref of the other side that the local ref `name` is stored from,
+    /// which is how stale remote-tracking branches are found.
+    pub fn map_target(&self, name: &str) -> Option<String> {
+        let target = self.target.as_ref()?;
+
+        if self.is_glob() {
+            let matched = match_glob(target, name)?;
+            Some(self.source.replacen('*', matched, 1))
+        } else if target == name {
+            Some(self.source.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// The part of `name` matched by the `*` in `pattern`.
+fn match_glob<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+
+    if name.len() < prefix.len() + suffix.len() {
+        return None;
+    }
+
+    name.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// Works out which of the advertised `refs` the `refspecs` fetch. Refs that
+/// a negative refspec matches are left out, and every ref is fetched once,
+/// by the first refspec it matches.
+pub fn map_refs(refspecs: &[Refspec], refs: &[(String, Id)]) -> Vec<RefMapping> {
+    let (negative, positive): (Vec<&Refspec>, Vec<&Refspec>) =
+        refspecs.iter().partition(|refspec| refspec.negative);
+
+    let mut mappings: Vec<RefMapping> = vec![];
+
+    for refspec in positive {
+        // a short name only stands for the first ref it expands to
+        let matches: Vec<&(String, Id)> = if refspec.is_glob() {
+            refs.iter()
+                .filter(|(name, _)| refspec.matches_source(name))
+                .collect()
+        } else {
+            SHORT_NAME_RULES
+                .iter()
+                .map(|rule| rule.replace("{}", &refspec.source))
+                .find_map(|expanded| refs.iter().find(|(name, _)| *name == expanded))
+                .into_iter()
+                .collect()
+        };
+
+        for (name, id) in matches {
+            if negative.iter().any(|refspec| refspec.matches_source(name))
+                || mappings.iter().any(|mapping| &mapping.source == name)
+            {
+                continue;
+            }
+
+            mappings.push(RefMapping {
+                source: name.clone(),
+                target: refspec.map_source(name),
+                id: id.clone(),
+                force: refspec.force,
+            });
+        }
+    }
+
+    mappings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Id {
+        Id::from_hex(&format!("{:040x}", n)).unwrap()
+    }
+
+    #[test]
+    fn it_parses_refspecs() {
+        let refspec = Refspec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+
+        assert!(refspec.force && refspec.is_glob() && !refspec.negative);
+        assert_eq!(
+            Some("refs/remotes/origin/topic".to_string()),
+            refspec.map_source("refs/heads/topic")
+        );
+        assert_eq!(
+            Some("refs/heads/topic".to_string()),
+            refspec.map_target("refs/remotes/origin/topic")
+        );
+
+        let refspec = Refspec::parse("master:backup").unwrap();
+
+        assert_eq!(Some("refs/heads/backup".to_string()), refspec.target);
+        assert!(refspec.matches_source("refs/heads/master"));
+
+        for invalid in ["refs/heads/*:refs/x", "^+a", "^a:b", "a*b*:c*", ""] {
+            assert!(Refspec::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn it_maps_refs_leaving_out_negative_matches() {
+        let refspecs = Refspec::parse_all(&[
+            "+refs/heads/*:refs/remotes/origin/*".to_string(),
+            "^refs/heads/wip/*".to_string(),
+            "v1".to_string(),
+        ])
+        .unwrap();
+        let refs = vec![
+            ("refs/heads/master".to_string(), id(1)),
+            ("refs/heads/wip/idea".to_string(), id(2)),
+            ("refs/tags/v1".to_string(), id(3)),
+        ];
+
+        let mappings = map_refs(&refspecs, &refs);
+
+        assert_eq!(2, mappings.len());
+        assert_eq!(
+            Some("refs/remotes/origin/master".to_string()),
+            mappings[0].target
+        );
+        assert_eq!("refs/tags/v1", mappings[1].source);
+        assert_eq!(None, mappings[1].target);
+    }
+}
@@ -0,0 +1,351 @@
+use super::{http::HttpClient, pkt_line, Remote};
+use std::{
+    cell::RefCell,
+    env,
+    io::{self, prelude::*, BufReader, Cursor},
+    net::TcpStream,
+    path::Path,
+    process::{Child, Command, Stdio},
+    rc::Rc,
+};
+
+pub const DAEMON_PORT: u16 = 9418;
+
+/// Where a repository lives, as written in a url.
+#[derive(Debug, PartialEq)]
+enum Location {
+    Local(String),
+    Http(String),
+    Git {
+        host: String,
+        port: u16,
+        path: String,
+    },
+    Ssh {
+        host: String,
+        port: Option<String>,
+        path: String,
+    },
+}
+
+impl Location {
+    /// Understands `file://`, `http://`, `git://` and `ssh://` urls, the
+    /// scp-like `host:path` and plain paths. As in git, a colon only means
+    /// ssh when no slash comes before it.
+    fn parse(url: &str) -> Self {
+        if let Some(path) = url.strip_prefix("file://") {
+            return Location::Local(path.to_string());
+        }
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Location::Http(url.to_string());
+        }
+
+        if let Some(rest) = url.strip_prefix("git://") {
+            let (host, port, path) = split_authority(rest);
+
+            return Location::Git {
+                host: host.to_string(),
+                port: port
+                    .and_then(|port| port.parse().ok())
+                    .unwrap_or(DAEMON_PORT),
+                path: path.to_string(),
+            };
+        }
+
+        if let Some(rest) = url.strip_prefix("ssh://") {
+            let (host, port, path) = split_authority(rest);
+            let port = port.map(String::from);
+
+            return Location::Ssh {
+                host: host.to_string(),
+                port,
+                path: path.to_string(),
+            };
+        }
+
+        match url.split_once(':') {
+            Some((host, path)) if !host.is_empty() && !host.contains('/') => Location::Ssh {
+                host: host.to_string(),
+                port: None,
+                path: path.to_string(),
+            },
+            _ => Location::Local(url.to_string()),
+        }
+    }
+}
+
+/// Splits the part of a url after its scheme into host, port and path.
+fn split_authority(rest: &str) -> (&str, Option<&str>, &str) {
+    let (authority, path) = match rest.find('/') {
+        Some(n) => (&rest[..n], &rest[n..]),
+        None => (rest, ""),
+    };
+
+    match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port), path),
+        None => (authority, None, path),
+    }
+}
+
+/// Whether `url` is a plain path rather than a url, which lets `clone` copy
+/// the repository directly.
+pub fn is_path(url: &str) -> bool {
+    !url.starts_with("file://") && matches!(Location::parse(url), Location::Local(_))
+}
+
+/// What carries the conversation of a `Transport`.
+enum Connection {
+    Process(Child),
+    Daemon,
+    Http {
+        client: Box<HttpClient>,
+        service: String,
+        request: Rc<RefCell<Vec<u8>>>,
+    },
+}
+
+/// Collects what is written to an HTTP transport until it is sent as the
+/// body of a request.
+struct RequestBody(Rc<RefCell<Vec<u8>>>);
+
+impl Write for RequestBody {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A connection to `upload-pack` or `receive-pack` serving another
+/// repository, run as a child process either directly or through ssh, the way
+/// git reaches `file://` and ssh remotes. Its standard input and output carry
+/// the conversation. For `git://` urls, the conversation goes over a TCP
+/// connection to a daemon instead, and for `http://` urls, each round of it
+/// is a request of its own.
+pub struct Transport {
+    pub input: Box<dyn Read>,
+    pub output: Box<dyn Write>,
+    connection: Connection,
+}
+
+impl Transport {
+    /// Starts `service`, `upload-pack` or `receive-pack`, for the repository
+    /// `remote` points at. The program configured for the remote replaces the
+    /// command that is run, which is this executable for local repositories
+    /// and git's `git-<service>` over ssh.
+    pub fn connect(remote: &Remote, service: &str, dir: &Path) -> io::Result<Self> {
+        let program = match service {
+            super::UPLOAD_PACK => remote.upload_pack.as_deref(),
+            _ => remote.receive_pack.as_deref(),
+        };
+        let ssh_command = remote.ssh_command.as_deref();
+
+        let mut command = match Location::parse(&remote.url) {
+            Location::Http(url) => {
+                return Self::connect_http(&url, service, remote.credential_helpers.clone())
+            }
+            Location::Git { host, port, path } => {
+                return Self::connect_daemon(&host, port, &path, service)
+            }
+            Location::Local(path) => {
+                let program = match program {
+                    Some(program) => program.to_string(),
+                    None => format!(
+                        "{} {}",
+                        quote(&env::current_exe()?.to_string_lossy()),
+                        service
+                    ),
+                };
+
+                shell_command(&program, &[path])
+            }
+            Location::Ssh { host, port, path } => {
+                let program = program
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("git-{}", service));
+                let ssh = ssh_command
+                    .map(String::from)
+                    .or_else(|| env::var("GIT_SSH_COMMAND").ok())
+                    .unwrap_or_else(|| "ssh".to_string());
+
+                let mut args = vec![];
+
+                if let Some(port) = port {
+                    args.push("-p".to_string());
+                    args.push(port);
+                }
+
+                args.push(host);
+                args.push(format!("{} {}", program, quote(&path)));
+
+                shell_command(&ssh, &args)
+            }
+        };
+
+        let mut child = command
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let input = BufReader::new(child.stdout.take().unwrap());
+        let output = child.stdin.take().unwrap();
+
+        Ok(Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            connection: Connection::Process(child),
+        })
+    }
+
+    /// Discovers the refs of the repository at `url` over HTTP. What is
+    /// written to the transport after that waits to be sent as one request.
+    fn connect_http(url: &str, service: &str, helpers: Vec<String>) -> io::Result<Self> {
+        let service = format!("git-{}", service);
+        let mut client = HttpClient::new(url, helpers)?;
+        let advertisement = client.discover(&service)?;
+        let request = Rc::new(RefCell::new(vec![]));
+
+        Ok(Self {
+            input: Box::new(Cursor::new(advertisement)),
+            output: Box::new(RequestBody(request.clone())),
+            connection: Connection::Http {
+                client: Box::new(client),
+                service,
+                request,
+            },
+        })
+    }
+
+    /// Opens a connection to the daemon at `host` and asks it to run
+    /// `service` for the repository at `path`, in a request that also names
+    /// the host the way it was written in the url.
+    fn connect_daemon(host: &str, port: u16, path: &str, service: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect((host, port))?;
+
+        let host = if port == DAEMON_PORT {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, port)
+        };
+        let request = format!("git-{} {}\0host={}\0", service, path, host);
+        pkt_line::write(&mut stream, request.as_bytes())?;
+
+        let input = BufReader::new(stream.try_clone()?);
+
+        Ok(Self {
+            input: Box::new(input),
+            output: Box::new(stream),
+            connection: Connection::Daemon,
+        })
+    }
+
+    /// Whether each round of the conversation stands on its own, rather than
+    /// being part of one connection, which is the case over HTTP.
+    pub fn is_stateless(&self) -> bool {
+        matches!(self.connection, Connection::Http { .. })
+    }
+
+    /// Sends what has been written so far. Over HTTP, this is when the
+    /// request goes out, and the response becomes what is read next.
+    pub fn send(&mut self) -> io::Result<()> {
+        match &mut self.connection {
+            Connection::Http {
+                client,
+                service,
+                request,
+            } => {
+                let body = request.take();
+                let response = client.post(service, &body)?;
+                self.input = Box::new(Cursor::new(response));
+
+                Ok(())
+            }
+            _ => self.output.flush(),
+        }
+    }
+
+    /// Closes the connection and waits for the other side to exit.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.output);
+        drop(self.input);
+
+        let mut child = match self.connection {
+            Connection::Process(child) => child,
+            _ => return Ok(()),
+        };
+
+        let status = child.wait()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other("the remote end hung up unexpectedly"))
+        }
+    }
+}
+
+/// Runs `program` through the shell, so that it may carry its own arguments,
+/// with `args` appended.
+pub fn shell_command(program: &str, args: &[String]) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{} \"$@\"", program))
+        .arg(program)
+        .args(args);
+
+    command
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_locations() {
+        assert_eq!(
+            Location::Local("/srv/repo.git".to_string()),
+            Location::parse("file:///srv/repo.git")
+        );
+        assert_eq!(
+            Location::Local("./a:b".to_string()),
+            Location::parse("./a:b")
+        );
+        assert_eq!(
+            Location::Ssh {
+                host: "git@example.com".to_string(),
+                port: Some("2222".to_string()),
+                path: "/repo.git".to_string(),
+            },
+            Location::parse("ssh://git@example.com:2222/repo.git")
+        );
+        assert_eq!(
+            Location::Http("http://example.com/repo.git".to_string()),
+            Location::parse("http://example.com/repo.git")
+        );
+        assert_eq!(
+            Location::Git {
+                host: "localhost".to_string(),
+                port: 9419,
+                path: "/repo.git".to_string(),
+            },
+            Location::parse("git://localhost:9419/repo.git")
+        );
+        assert_eq!(
+            Location::Ssh {
+                host: "example.com".to_string(),
+                port: None,
+                path: "repo.git".to_string(),
+            },
+            Location::parse("example.com:repo.git")
+        );
+    }
+}
@@ -1,22 +1,113 @@
-use crate::{database::Database, index::Index, refs::Refs, workspace::Workspace};
+use crate::{
+    config::Config, database::Database, errors::RitError, id::Id, index::Index, objects::TreeEntry,
+    refs::Refs, workspace::Workspace,
+};
 use std::path::PathBuf;
 
 pub struct Repository {
+    pub config: Config,
     pub database: Database,
     pub index: Index,
     pub refs: Refs,
     pub workspace: Workspace,
+    pub git_path: PathBuf,
 }
 
 impl Repository {
     pub fn new(project_path: PathBuf) -> Self {
         let git_path = project_path.join(".git");
 
+        Self::with_git_path(project_path, git_path)
+    }
+
+    /// Opens a repository whose git directory is not `.git` in the project,
+    /// e.g. a bare one.
+    pub fn with_git_path(project_path: PathBuf, git_path: PathBuf) -> Self {
+        let config = Config::new(&git_path);
+        let mut index = Index::new(git_path.clone().join("index"));
+
+        if let Some(version) = config.get_int("index.version") {
+            index.set_default_version(version as u32);
+        }
+
         Self {
+            config,
             database: Database::new(git_path.join("objects")),
-            index: Index::new(git_path.clone().join("index")),
-            refs: Refs::new(git_path),
+            index,
+            refs: Refs::new(git_path.clone()),
             workspace: Workspace::new(project_path),
+            git_path,
+        }
+    }
+
+    /// Makes the index and the workspace match the tree of commit `id`,
+    /// discarding any local changes to tracked files. The index has to be
+    /// loaded for update.
+    pub fn hard_reset(&mut self, id: &Id) -> Result<(), RitError> {
+        self.reset_matching(id, |_| true)
+    }
+
+    /// Like `hard_reset`, but leaves paths for which `matches` is false alone.
+    pub fn reset_matching<F>(&mut self, id: &Id, matches: F) -> Result<(), RitError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut target = self.database.flatten_tree(Some(id))?;
+        target.retain(|pathname, _| matches(pathname));
+
+        for entry in self.index.entries() {
+            if matches(&entry.pathname) && !target.contains_key(&entry.pathname) {
+                // the repository of a submodule may hold work of its own
+                if !entry.is_gitlink() {
+                    self.workspace.remove(&entry.pathname)?;
+                }
+
+                self.index.remove(&entry.pathname);
+            }
+        }
+
+        for (pathname, item) in &target {
+            if !self.is_checked_out(pathname, item) {
+                self.checkout_entry(pathname, item)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the blob behind `item` to the workspace and stages it. For a
+    /// gitlink, only the directory of the submodule is created, to be
+    /// filled by `submodule update`.
+    pub fn checkout_entry(&mut self, pathname: &str, item: &TreeEntry) -> Result<(), RitError> {
+        if item.is_gitlink() {
+            if !self.workspace.is_repository(pathname) {
+                self.workspace.make_dir(pathname)?;
+            }
+
+            self.index.add_from_db(pathname, item);
+
+            return Ok(());
+        }
+
+        let blob = self.database.load_blob(&item.id)?;
+        let stat = self.workspace.write_file(pathname, &blob.data, item.mode)?;
+
+        self.index
+            .add(self.workspace.entry(pathname), item.id.clone(), stat);
+
+        Ok(())
+    }
+
+    fn is_checked_out(&self, pathname: &str, item: &TreeEntry) -> bool {
+        let entry = match self.index.entry_for_path(pathname) {
+            Some(entry) if entry.id == item.id && entry.mode == item.mode => entry,
+            _ => return false,
+        };
+
+        match self.workspace.stat_path(pathname) {
+            Some(stat) if entry.is_gitlink() => stat.is_dir(),
+            Some(stat) => entry.matches_stat(&stat) && entry.matches_times(&stat),
+            None => false,
         }
     }
 }
@@ -0,0 +1,171 @@
+use crate::{
+    database::DatabaseError,
+    id::Id,
+    objects::{Commit, Parsed},
+    repository::Repository,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Whether `ancestor` can be reached from `id` by following parents, which
+/// makes moving a ref from one to the other a fast-forward.
+pub fn is_ancestor(repo: &Repository, ancestor: &Id, id: &Id) -> Result<bool, DatabaseError> {
+    let commits = RevList::new(repo, vec![ancestor.clone()], vec![id.clone()]).commits()?;
+
+    Ok(commits.is_empty())
+}
+
+/// Walks the commit graph from a set of starting points, leaving out everything
+/// reachable from the excluded commits, as in `git rev-list a b ^c`.
+pub struct RevList<'a> {
+    repo: &'a Repository,
+    include: Vec<Id>,
+    exclude: Vec<Id>,
+    objects: Vec<Id>,
+}
+
+impl<'a> RevList<'a> {
+    pub fn new(repo: &'a Repository, include: Vec<Id>, exclude: Vec<Id>) -> Self {
+        Self {
+            repo,
+            include,
+            exclude,
+            objects: vec![],
+        }
+    }
+
+    /// Adds trees and blobs that `objects` should list along with whatever
+    /// they contain, even though no selected commit refers to them.
+    pub fn add_objects(&mut self, ids: Vec<Id>) {
+        self.objects.extend(ids);
+    }
+
+    /// Returns the selected commits in topological order, children before
+    /// their parents and newer commits first among unrelated ones.
+    pub fn commits(&self) -> Result<Vec<(Id, Commit)>, DatabaseError> {
+        let uninteresting = self.reachable(&self.exclude, &HashSet::new())?;
+        let selected = self.reachable(&self.include, &uninteresting)?;
+
+        let mut commits = HashMap::new();
+        let mut children: HashMap<Id, usize> = HashMap::new();
+
+        for id in selected {
+            let commit = self.repo.database.load_commit(&id)?;
+
+            for parent in &commit.parents {
+                *children.entry(parent.clone()).or_insert(0) += 1;
+            }
+
+            commits.insert(id, commit);
+        }
+
+        let mut ready: Vec<Id> = commits
+            .keys()
+            .filter(|id| !children.contains_key(id))
+            .cloned()
+            .collect();
+        let mut sorted = vec![];
+
+        while !ready.is_empty() {
+            ready.sort_by_key(|id| commits[id].committer.time);
+
+            let id = ready.pop().unwrap();
+            let commit = commits[&id].clone();
+
+            for parent in &commit.parents {
+                if let Some(count) = children.get_mut(parent) {
+                    *count -= 1;
+
+                    if *count == 0 && commits.contains_key(parent) {
+                        ready.push(parent.clone());
+                    }
+                }
+            }
+
+            sorted.push((id, commit));
+        }
+
+        Ok(sorted)
+    }
+
+    /// Lists the selected commits followed by the trees and blobs they
+    /// contain, each with the path it was first found at, as in
+    /// `git rev-list --objects`. Whatever the excluded commits contain is left
+    /// out as well.
+    pub fn objects(&self) -> Result<Vec<(Id, String)>, DatabaseError> {
+        let mut seen = HashSet::new();
+        let mut ignored = vec![];
+
+        for id in self.reachable(&self.exclude, &HashSet::new())? {
+            let commit = self.repo.database.load_commit(&id)?;
+            self.walk_tree(&commit.tree_id, "", &mut seen, &mut ignored)?;
+        }
+
+        let mut objects = vec![];
+        let mut roots = vec![];
+
+        for (id, commit) in self.commits()? {
+            roots.push(commit.tree_id);
+            objects.push((id, String::new()));
+        }
+
+        roots.extend(self.objects.iter().cloned());
+
+        for id in &roots {
+            self.walk_tree(id, "", &mut seen, &mut objects)?;
+        }
+
+        Ok(objects)
+    }
+
+    fn walk_tree(
+        &self,
+        id: &Id,
+        path: &str,
+        seen: &mut HashSet<Id>,
+        objects: &mut Vec<(Id, String)>,
+    ) -> Result<(), DatabaseError> {
+        if !seen.insert(id.clone()) {
+            return Ok(());
+        }
+
+        objects.push((id.clone(), path.to_string()));
+
+        if let Parsed::Tree(tree) = self.repo.database.load(id)? {
+            for (name, entry) in tree.stored_entries() {
+                let child_path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", path, name)
+                };
+
+                if entry.is_tree() {
+                    self.walk_tree(&entry.id, &child_path, seen, objects)?;
+                } else if entry.is_gitlink() {
+                    // the commit lives in the submodule's repository
+                    continue;
+                } else if seen.insert(entry.id.clone()) {
+                    objects.push((entry.id.clone(), child_path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reachable(&self, start: &[Id], stop: &HashSet<Id>) -> Result<HashSet<Id>, DatabaseError> {
+        let mut seen = HashSet::new();
+        let mut queue: Vec<Id> = start.to_vec();
+
+        while let Some(id) = queue.pop() {
+            if stop.contains(&id) || !seen.insert(id.clone()) {
+                continue;
+            }
+
+            let commit = self.repo.database.load_commit(&id)?;
+
+            queue.extend(commit.parents);
+        }
+
+        Ok(seen)
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{date, id::Id, objects::Parsed, reflog, refs::HEAD, repository::Repository};
+use chrono::Local;
+use std::fmt;
+
+const MIN_PREFIX_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum RevisionError {
+    InvalidObject(String),
+    Ambiguous(String, Vec<Id>),
+    NotCommit(String),
+    LogTooShort(String, usize),
+}
+
+impl fmt::Display for RevisionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RevisionError::InvalidObject(expr) => write!(f, "Not a valid object name: '{}'.", expr),
+            RevisionError::Ambiguous(expr, _) => write!(f, "short SHA1 {} is ambiguous", expr),
+            RevisionError::NotCommit(id) => write!(f, "object {} is not a commit", id),
+            RevisionError::LogTooShort(name, len) => {
+                write!(f, "log for '{}' only has {} entries", name, len)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Rev {
+    Ref(String),
+    Parent(Box<Rev>, usize),
+    Ancestor(Box<Rev>, usize),
+    Reflog(String, String),
+}
+
+pub struct Revision<'a> {
+    repo: &'a Repository,
+    expr: String,
+}
+
+impl<'a> Revision<'a> {
+    pub fn new(repo: &'a Repository, expr: &str) -> Self {
+        Self {
+            repo,
+            expr: expr.to_string(),
+        }
+    }
+
+    /// Resolves the expression to the id of the commit it names.
+    pub fn resolve(&self) -> Result<Id, RevisionError> {
+        let id = self.resolve_object()?;
+
+        self.ensure_commit(id)
+    }
+
+    /// Resolves the expression to the object it names, without peeling tags.
+    pub fn resolve_object(&self) -> Result<Id, RevisionError> {
+        let invalid = || RevisionError::InvalidObject(self.expr.clone());
+
+        let query = Self::parse(&self.expr).ok_or_else(invalid)?;
+        let id = self.resolve_query(&query)?.ok_or_else(invalid)?;
+
+        if !self.repo.database.exists(&id) {
+            return Err(invalid());
+        }
+
+        Ok(id)
+    }
+
+    fn parse(expr: &str) -> Option<Rev> {
+        if let Some(pos) = expr.rfind(['^', '~']) {
+            let (head, tail) = expr.split_at(pos);
+            let digits = &tail[1..];
+
+            if !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+
+            let n = if digits.is_empty() {
+                1
+            } else {
+                digits.parse().ok()?
+            };
+            let rev = Box::new(Self::parse(head)?);
+
+            return if tail.starts_with('^') {
+                Some(Rev::Parent(rev, n))
+            } else {
+                Some(Rev::Ancestor(rev, n))
+            };
+        }
+
+        if let Some((name, selector)) = reflog::parse_selector(expr) {
+            return if name.is_empty() || Self::is_valid_ref(name) {
+                Some(Rev::Reflog(name.to_string(), selector.to_string()))
+            } else {
+                None
+            };
+        }
+
+        match expr {
+            "@" => Some(Rev::Ref(HEAD.to_string())),
+            _ if Self::is_valid_ref(expr) => Some(Rev::Ref(expr.to_string())),
+            _ => None,
+        }
+    }
+
+    fn is_valid_ref(expr: &str) -> bool {
+        !(expr.is_empty()
+            || expr.starts_with('.')
+            || expr.starts_with('/')
+            || expr.ends_with('/')
+            || expr.ends_with(".lock")
+            || expr.contains("..")
+            || expr.contains("/.")
+            || expr.contains("@{")
+            || expr
+                .chars()
+                .any(|c| c.is_control() || " ~^:?*[\\".contains(c)))
+    }
+
+    fn resolve_query(&self, query: &Rev) -> Result<Option<Id>, RevisionError> {
+        match query {
+            Rev::Ref(name) => self.read_ref(name),
+            Rev::Parent(rev, n) => match self.resolve_query(rev)? {
+                Some(id) if *n == 0 => Ok(Some(id)),
+                Some(id) => Ok(self.commit_parent(id, *n - 1)?),
+                None => Ok(None),
+            },
+            Rev::Ancestor(rev, n) => {
+                let mut id = self.resolve_query(rev)?;
+
+                for _ in 0..*n {
+                    id = match id {
+                        Some(id) => self.commit_parent(id, 0)?,
+                        None => return Ok(None),
+                    };
+                }
+
+                Ok(id)
+            }
+            Rev::Reflog(name, selector) => self.read_reflog(name, selector),
+        }
+    }
+
+    /// Looks a value up in the reflog of `name`, or of the current branch when
+    /// the name is empty: `<n>` selects the value `n` moves ago and a date the
+    /// value the ref had at that time.
+    fn read_reflog(&self, name: &str, selector: &str) -> Result<Option<Id>, RevisionError> {
+        let refs = &self.repo.refs;
+
+        let full_name = if name.is_empty() {
+            refs.current_ref().unwrap_or_else(|| HEAD.to_string())
+        } else {
+            match refs.expand_name(name) {
+                Some(full_name) => full_name,
+                None => return Ok(None),
+            }
+        };
+
+        let mut entries = refs.reflog().read(&full_name);
+        entries.reverse();
+
+        if entries.is_empty() {
+            return Ok(refs.read_ref(&full_name));
+        }
+
+        if let Ok(n) = selector.parse::<usize>() {
+            return match entries.get(n) {
+                Some(entry) => Ok(Some(entry.new_id.clone())),
+                None => {
+                    let name = refs.short_name(&full_name).to_string();
+
+                    Err(RevisionError::LogTooShort(name, entries.len()))
+                }
+            };
+        }
+
+        let time = date::parse(selector, Local::now())
+            .ok_or_else(|| RevisionError::InvalidObject(self.expr.clone()))?;
+
+        let entry = entries
+            .iter()
+            .find(|entry| entry.committer.time.timestamp() <= time);
+
+        Ok(match entry {
+            Some(entry) => Some(entry.new_id.clone()),
+            None => entries.last().map(|oldest| {
+                oldest
+                    .old_id
+                    .clone()
+                    .unwrap_or_else(|| oldest.new_id.clone())
+            }),
+        })
+    }
+
+    fn read_ref(&self, name: &str) -> Result<Option<Id>, RevisionError> {
+        if let Some(id) = self.repo.refs.read_ref(name) {
+            return Ok(Some(id));
+        }
+
+        if name.len() < MIN_PREFIX_LEN || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+
+        let mut candidates = self.repo.database.prefix_match(&name.to_lowercase());
+
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(candidates.pop()),
+            _ => Err(RevisionError::Ambiguous(name.to_string(), candidates)),
+        }
+    }
+
+    fn commit_parent(&self, id: Id, n: usize) -> Result<Option<Id>, RevisionError> {
+        let id = self.ensure_commit(id)?;
+
+        match self.repo.database.load_commit(&id) {
+            Ok(commit) => Ok(commit.parents.get(n).cloned()),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Checks that `id` names a commit, peeling tags down to the object they
+    /// point at.
+    fn ensure_commit(&self, id: Id) -> Result<Id, RevisionError> {
+        match self.repo.database.load(&id) {
+            Ok(Parsed::Commit(_)) => Ok(id),
+            Ok(Parsed::Tag(tag)) => self.ensure_commit(tag.object),
+            Ok(_) => Err(RevisionError::NotCommit(id.as_str)),
+            Err(_) => Err(RevisionError::InvalidObject(self.expr.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(name: &str) -> Rev {
+        Rev::Ref(name.to_string())
+    }
+
+    #[test]
+    fn it_parses_a_ref() {
+        assert_eq!(Some(reference("master")), Revision::parse("master"));
+    }
+
+    #[test]
+    fn it_parses_head_alias() {
+        assert_eq!(Some(reference("HEAD")), Revision::parse("@"));
+    }
+
+    #[test]
+    fn it_parses_parents_and_ancestors() {
+        let expected = Rev::Ancestor(Box::new(Rev::Parent(Box::new(reference("topic")), 2)), 3);
+
+        assert_eq!(Some(expected), Revision::parse("topic^2~3"));
+    }
+
+    #[test]
+    fn it_parses_reflog_selectors() {
+        let expected = Rev::Parent(
+            Box::new(Rev::Reflog("master".to_string(), "1".to_string())),
+            1,
+        );
+
+        assert_eq!(Some(expected), Revision::parse("master@{1}^"));
+        assert_eq!(
+            Some(Rev::Reflog(String::new(), "2.days.ago".to_string())),
+            Revision::parse("@{2.days.ago}")
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_names() {
+        assert_eq!(None, Revision::parse("a..b"));
+        assert_eq!(None, Revision::parse("topic^x"));
+    }
+}
@@ -0,0 +1,332 @@
+use crate::id::Id;
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+
+const DIR_NAME: &str = "rebase-merge";
+const TODO_FILE: &str = "git-rebase-todo";
+const DONE_FILE: &str = "done";
+const HEAD_NAME_FILE: &str = "head-name";
+const ONTO_FILE: &str = "onto";
+const ORIG_HEAD_FILE: &str = "orig-head";
+const AMEND_FILE: &str = "amend";
+
+pub const DETACHED_HEAD: &str = "detached HEAD";
+
+#[derive(Debug)]
+pub enum SequencerError {
+    Io,
+    InProgress,
+    NotInProgress,
+    UnresolvedConflicts,
+    UnstagedChanges,
+    UncommittedChanges,
+    Corrupt(String),
+    InvalidTodo(String),
+    NothingToDo,
+}
+
+impl fmt::Display for SequencerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SequencerError::Io => write!(f, "Cannot access the rebase state"),
+            SequencerError::InProgress => write!(
+                f,
+                "It seems that there is already a {} directory, and
+I wonder if you are in the middle of another rebase.",
+                DIR_NAME
+            ),
+            SequencerError::NotInProgress => write!(f, "No rebase in progress?"),
+            SequencerError::UnresolvedConflicts => write!(
+                f,
+                "You must edit all merge conflicts and then
+mark them as resolved using rit add"
+            ),
+            SequencerError::UnstagedChanges => {
+                write!(f, "cannot rebase: You have unstaged changes.")
+            }
+            SequencerError::UncommittedChanges => {
+                write!(f, "cannot rebase: Your index contains uncommitted changes.")
+            }
+            SequencerError::Corrupt(file) => write!(f, "could not parse '{}'", file),
+            SequencerError::InvalidTodo(message) => write!(f, "{}", message),
+            SequencerError::NothingToDo => write!(f, "nothing to do"),
+        }
+    }
+}
+
+impl From<io::Error> for SequencerError {
+    fn from(_err: io::Error) -> SequencerError {
+        SequencerError::Io
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Pick(Id, String),
+    Reword(Id, String),
+    Edit(Id, String),
+    Squash(Id, String),
+    Fixup(Id, String),
+    Drop(Id, String),
+    Exec(String),
+}
+
+impl Step {
+    pub fn parse(line: &str) -> Option<Self> {
+        Self::parse_with(line, Id::from_hex)
+    }
+
+    /// Parses a todo line, using `resolve` to turn the commit name the user
+    /// wrote into an id.
+    pub fn parse_with<F>(line: &str, resolve: F) -> Option<Self>
+    where
+        F: Fn(&str) -> Option<Id>,
+    {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        if let "exec" | "x" = command {
+            let command = rest.trim();
+
+            return if command.is_empty() {
+                None
+            } else {
+                Some(Step::Exec(command.to_string()))
+            };
+        }
+
+        let (name, subject) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+        let id = resolve(name)?;
+        let subject = subject.to_string();
+
+        match command {
+            "pick" | "p" => Some(Step::Pick(id, subject)),
+            "reword" | "r" => Some(Step::Reword(id, subject)),
+            "edit" | "e" => Some(Step::Edit(id, subject)),
+            "squash" | "s" => Some(Step::Squash(id, subject)),
+            "fixup" | "f" => Some(Step::Fixup(id, subject)),
+            "drop" | "d" => Some(Step::Drop(id, subject)),
+            _ => None,
+        }
+    }
+
+    pub fn commit(&self) -> Option<(&Id, &str)> {
+        match self {
+            Step::Pick(id, subject)
+            | Step::Reword(id, subject)
+            | Step::Edit(id, subject)
+            | Step::Squash(id, subject)
+            | Step::Fixup(id, subject)
+            | Step::Drop(id, subject) => Some((id, subject)),
+            Step::Exec(_) => None,
+        }
+    }
+
+    pub fn is_squash(&self) -> bool {
+        matches!(self, Step::Squash(..) | Step::Fixup(..))
+    }
+
+    pub fn command(&self) -> &str {
+        match self {
+            Step::Pick(..) => "pick",
+            Step::Reword(..) => "reword",
+            Step::Edit(..) => "edit",
+            Step::Squash(..) => "squash",
+            Step::Fixup(..) => "fixup",
+            Step::Drop(..) => "drop",
+            Step::Exec(_) => "exec",
+        }
+    }
+}
+
+impl Step {
+    /// Formats the step for a todo list shown to the user, with an abbreviated
+    /// commit id.
+    pub fn to_short_string(&self) -> String {
+        match self.commit() {
+            Some((id, subject)) => format!("{} {} {}", self.command(), id.short(), subject),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Exec(command) => write!(f, "exec {}", command),
+            step => {
+                let (id, subject) = step.commit().unwrap();
+
+                write!(f, "{} {} {}", step.command(), id, subject)
+            }
+        }
+    }
+}
+
+/// Persistent state of a rebase, stored in `.git/rebase-merge` so that it can
+/// stop on a conflict and be resumed by a later process.
+pub struct Sequencer {
+    path: PathBuf,
+    pub head_name: String,
+    pub onto: Option<Id>,
+    pub orig_head: Option<Id>,
+    todo: VecDeque<Step>,
+    done: Vec<Step>,
+}
+
+impl Sequencer {
+    pub fn new(git_path: &Path) -> Self {
+        Self {
+            path: git_path.join(DIR_NAME),
+            head_name: DETACHED_HEAD.to_string(),
+            onto: None,
+            orig_head: None,
+            todo: VecDeque::new(),
+            done: vec![],
+        }
+    }
+
+    pub fn is_in_progress(&self) -> bool {
+        self.path.is_dir()
+    }
+
+    pub fn start(
+        &mut self,
+        head_name: &str,
+        onto: &Id,
+        orig_head: &Id,
+        steps: Vec<Step>,
+    ) -> Result<(), SequencerError> {
+        if self.is_in_progress() {
+            return Err(SequencerError::InProgress);
+        }
+
+        fs::create_dir_all(&self.path)?;
+
+        self.head_name = head_name.to_string();
+        self.onto = Some(onto.clone());
+        self.orig_head = Some(orig_head.clone());
+        self.todo = steps.into_iter().collect();
+        self.done = vec![];
+
+        self.write_file(HEAD_NAME_FILE, head_name)?;
+        self.write_file(ONTO_FILE, &onto.as_str)?;
+        self.write_file(ORIG_HEAD_FILE, &orig_head.as_str)?;
+
+        self.save()
+    }
+
+    pub fn load(&mut self) -> Result<(), SequencerError> {
+        if !self.is_in_progress() {
+            return Err(SequencerError::NotInProgress);
+        }
+
+        self.head_name = self.read_file(HEAD_NAME_FILE)?.trim().to_string();
+        self.onto = Id::from_hex(&self.read_file(ONTO_FILE)?);
+        self.orig_head = Id::from_hex(&self.read_file(ORIG_HEAD_FILE)?);
+        self.todo = self.read_steps(TODO_FILE)?.into_iter().collect();
+        self.done = self.read_steps(DONE_FILE)?;
+
+        Ok(())
+    }
+
+    pub fn next_step(&self) -> Option<&Step> {
+        self.todo.front()
+    }
+
+    /// Moves the next step from the todo list to the done list.
+    pub fn mark_done(&mut self) -> Result<(), SequencerError> {
+        if let Some(step) = self.todo.pop_front() {
+            self.done.push(step);
+        }
+
+        let amend_path = self.path.join(AMEND_FILE);
+
+        if amend_path.exists() {
+            fs::remove_file(amend_path)?;
+        }
+
+        self.save()
+    }
+
+    pub fn done(&self) -> &[Step] {
+        &self.done
+    }
+
+    pub fn todo_path(&self) -> PathBuf {
+        self.path.join(TODO_FILE)
+    }
+
+    /// Replaces the remaining steps, e.g. with the list the user edited.
+    pub fn set_todo(&mut self, steps: Vec<Step>) -> Result<(), SequencerError> {
+        self.todo = steps.into_iter().collect();
+
+        self.save()
+    }
+
+    /// Records that the rebase stopped after committing `id`, so that changes
+    /// staged before continuing are amended into it.
+    pub fn set_amend(&self, id: &Id) -> Result<(), SequencerError> {
+        self.write_file(AMEND_FILE, &id.as_str)
+    }
+
+    pub fn amend(&self) -> Option<Id> {
+        Id::from_hex(self.read_file(AMEND_FILE).ok()?.trim())
+    }
+
+    /// The step that was being applied when the rebase stopped.
+    pub fn current_step(&self) -> Option<&Step> {
+        self.done.last()
+    }
+
+    pub fn quit(&self) -> Result<(), SequencerError> {
+        fs::remove_dir_all(&self.path)?;
+
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), SequencerError> {
+        self.write_steps(TODO_FILE, self.todo.iter())?;
+        self.write_steps(DONE_FILE, self.done.iter())
+    }
+
+    fn read_steps(&self, name: &str) -> Result<Vec<Step>, SequencerError> {
+        let content = self.read_file(name).unwrap_or_default();
+
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| Step::parse(line).ok_or_else(|| SequencerError::Corrupt(name.to_string())))
+            .collect()
+    }
+
+    fn write_steps<'a>(
+        &self,
+        name: &str,
+        steps: impl Iterator<Item = &'a Step>,
+    ) -> Result<(), SequencerError> {
+        let content: String = steps.map(|step| format!("{}\n", step)).collect();
+
+        self.write_file(name, &content)
+    }
+
+    fn read_file(&self, name: &str) -> Result<String, SequencerError> {
+        Ok(fs::read_to_string(self.path.join(name))?)
+    }
+
+    fn write_file(&self, name: &str, content: &str) -> Result<(), SequencerError> {
+        let mut file = fs::File::create(self.path.join(name))?;
+
+        file.write_all(content.as_bytes())?;
+
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    id::Id,
+    objects::Author,
+    reflog::{Reflog, ReflogEntry},
+    refs::{Refs, RefsError},
+};
+use std::{fmt, io, path::Path};
+
+const STASH_REF: &str = "refs/stash";
+
+#[derive(Debug)]
+pub enum StashError {
+    Io,
+    Refs(RefsError),
+    NoEntries,
+    InvalidStash(String),
+    NoInitialCommit,
+    LocalChanges(Vec<String>),
+    UntrackedFiles(Vec<String>),
+    IndexConflicts,
+}
+
+impl fmt::Display for StashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StashError::Io => write!(f, "Cannot access the stash"),
+            StashError::Refs(err) => write!(f, "{}", err),
+            StashError::NoEntries => write!(f, "No stash entries found."),
+            StashError::InvalidStash(name) => write!(f, "{} is not a valid reference", name),
+            StashError::NoInitialCommit => write!(f, "You do not have the initial commit yet"),
+            StashError::LocalChanges(paths) => write!(
+                f,
+                "Your local changes to the following files would be overwritten by merge:
+{}Please commit your changes or stash them before you merge.
+Aborting",
+                format_paths(paths)
+            ),
+            StashError::UntrackedFiles(paths) => write!(
+                f,
+                "The following untracked working tree files would be overwritten by merge:
+{}Please move or remove them before you merge.
+Aborting",
+                format_paths(paths)
+            ),
+            StashError::IndexConflicts => write!(f, "Conflicts in index. Try without --index."),
+        }
+    }
+}
+
+impl From<io::Error> for StashError {
+    fn from(_err: io::Error) -> StashError {
+        StashError::Io
+    }
+}
+
+impl From<RefsError> for StashError {
+    fn from(err: RefsError) -> StashError {
+        StashError::Refs(err)
+    }
+}
+
+fn format_paths(paths: &[String]) -> String {
+    paths.iter().map(|path| format!("\t{}\n", path)).collect()
+}
+
+/// The stack of stash entries: `refs/stash` points at the newest one and its
+/// reflog remembers all of them, with `stash@{0}` being the last line.
+pub struct Stashes {
+    refs: Refs,
+    reflog: Reflog,
+}
+
+impl Stashes {
+    pub fn new(git_path: &Path) -> Self {
+        Self {
+            refs: Refs::new(git_path.to_path_buf()),
+            reflog: Reflog::new(git_path),
+        }
+    }
+
+    /// Parses `stash@{<n>}` or a bare `<n>` into the position of the entry.
+    pub fn parse_name(name: Option<&str>) -> Result<usize, StashError> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(0),
+        };
+
+        let n = name
+            .strip_prefix("stash@{")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap_or(name);
+
+        n.parse()
+            .map_err(|_| StashError::InvalidStash(name.to_string()))
+    }
+
+    /// Lists the entries, newest first.
+    pub fn list(&self) -> Vec<ReflogEntry> {
+        let mut entries = self.reflog.read(STASH_REF);
+        entries.reverse();
+
+        entries
+    }
+
+    pub fn get(&self, n: usize) -> Result<Id, StashError> {
+        let entries = self.list();
+
+        if entries.is_empty() {
+            return Err(StashError::NoEntries);
+        }
+
+        entries
+            .get(n)
+            .map(|entry| entry.new_id.clone())
+            .ok_or_else(|| StashError::InvalidStash(format!("stash@{{{}}}", n)))
+    }
+
+    pub fn push(&self, id: &Id, committer: Author, message: &str) -> Result<(), StashError> {
+        let entry = ReflogEntry {
+            old_id: self.refs.read_ref(STASH_REF),
+            new_id: id.clone(),
+            committer,
+            message: message.to_string(),
+        };
+
+        self.refs.update_ref(STASH_REF, id)?;
+        self.reflog.append(STASH_REF, &entry)?;
+
+        Ok(())
+    }
+
+    /// Removes entry `n` and returns the commit it pointed to.
+    pub fn drop(&self, n: usize) -> Result<Id, StashError> {
+        let id = self.get(n)?;
+
+        let mut entries = self.list();
+        entries.remove(n);
+
+        match entries.first() {
+            Some(newest) => {
+                self.refs.update_ref(STASH_REF, &newest.new_id)?;
+
+                entries.reverse();
+                self.reflog.write(STASH_REF, &entries)?;
+            }
+            None => self.clear()?,
+        }
+
+        Ok(id)
+    }
+
+    pub fn clear(&self) -> Result<(), StashError> {
+        self.refs.delete_ref(STASH_REF)?;
+        self.reflog.delete(STASH_REF)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    config::{ConfigError, ConfigFile},
+    id::Id,
+    refs::Refs,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file at the top of the worktree that names the submodules and says
+/// where to clone them from.
+pub const GITMODULES: &str = ".gitmodules";
+
+const GITDIR_PREFIX: &str = "gitdir:";
+
+/// Finds the git directory of the repository nested at `path`: its `.git`
+/// directory, or the one a `.git` file points at, as git leaves when it
+/// keeps the repository of a submodule under the superproject's.
+pub fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    let metadata = fs::symlink_metadata(&dot_git).ok()?;
+
+    if metadata.is_dir() {
+        return Some(dot_git);
+    }
+
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let target = content.lines().next()?.strip_prefix(GITDIR_PREFIX)?;
+
+    Some(path.join(target.trim()))
+}
+
+/// The commit checked out in the repository nested at `path`, if there is
+/// one.
+pub fn head(path: &Path) -> Option<Id> {
+    Refs::new(git_dir(path)?).read_head()
+}
+
+/// The submodules described in `.gitmodules`, each in a `submodule` section
+/// named after it with its `path` and `url`.
+pub struct Gitmodules {
+    file: ConfigFile,
+}
+
+impl Gitmodules {
+    pub fn load(worktree: &Path) -> Self {
+        Self {
+            file: ConfigFile::new(worktree.join(GITMODULES)),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.file.subsections("submodule")
+    }
+
+    pub fn path(&self, name: &str) -> Option<String> {
+        self.get(name, "path")
+    }
+
+    pub fn url(&self, name: &str) -> Option<String> {
+        self.get(name, "url")
+    }
+
+    /// The name of the submodule at `path`, which is the path itself when
+    /// `.gitmodules` does not mention it.
+    pub fn name_for_path(&self, path: &str) -> String {
+        self.names()
+            .into_iter()
+            .find(|name| self.path(name).as_deref() == Some(path))
+            .unwrap_or_else(|| path.to_string())
+    }
+
+    pub fn add(&mut self, name: &str, path: &str, url: &str) -> Result<(), ConfigError> {
+        self.file.set(&format!("submodule.{}.path", name), path)?;
+        self.file.set(&format!("submodule.{}.url", name), url)?;
+
+        self.file.save()
+    }
+
+    fn get(&self, name: &str, variable: &str) -> Option<String> {
+        self.file
+            .get_all(&format!("submodule.{}.{}", name, variable))
+            .pop()
+            .flatten()
+    }
+}
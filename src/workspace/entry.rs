@@ -1,4 +1,4 @@
-use std::{fmt, path::PathBuf};
+use std::{fmt, fs, path::PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -14,7 +14,9 @@ impl Entry {
     pub fn new(absolute_path: PathBuf, relative_path: PathBuf) -> Self {
         let relative_path_name: String = relative_path.to_string_lossy().into();
         let len = relative_path_name.len();
-        let is_dir = absolute_path.is_dir();
+        let is_dir = fs::symlink_metadata(&absolute_path)
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false);
 
         Self {
             name: relative_path.file_name().unwrap().to_string_lossy().into(),
@@ -1,7 +1,9 @@
 mod entry;
+mod pathspec;
 mod stat;
 mod workspace;
 
 pub use entry::Entry;
-pub use stat::Stat;
+pub use pathspec::matches_pathspec;
+pub use stat::{Stat, SYMLINK_MODE};
 pub use workspace::Workspace;
@@ -0,0 +1,6 @@
+/// Tells whether `pathname` is `spec` itself or a file inside it.
+pub fn matches_pathspec(spec: &str, pathname: &str) -> bool {
+    let spec = spec.trim_end_matches('/');
+
+    spec == "." || pathname == spec || pathname.starts_with(&format!("{}/", spec))
+}
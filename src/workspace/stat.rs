@@ -1,4 +1,10 @@
-#[derive(Debug, Clone)]
+/// The mode git records for a symbolic link, whose blob holds its target.
+pub const SYMLINK_MODE: u32 = 0o120000;
+
+const FILE_TYPE_MASK: u32 = 0o170000;
+const DIRECTORY_TYPE: u32 = 0o040000;
+
+#[derive(Debug, Clone, Default)]
 pub struct Stat {
     pub ctime: i64,
     pub ctime_nsec: i64,
@@ -16,4 +22,12 @@ impl Stat {
     pub fn is_executable(&self) -> bool {
         self.mode & 0o111 != 0
     }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & FILE_TYPE_MASK == DIRECTORY_TYPE
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & FILE_TYPE_MASK == SYMLINK_MODE
+    }
 }
@@ -1,9 +1,14 @@
-use super::{Entry, Stat};
-use crate::errors::RitError;
+use super::{Entry, Stat, SYMLINK_MODE};
+use crate::{errors::RitError, submodule};
 use pathdiff::diff_paths;
 use std::{
+    ffi::OsStr,
     fs::{self, File, OpenOptions},
-    os::unix::fs::MetadataExt,
+    io::prelude::*,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{symlink, MetadataExt, PermissionsExt},
+    },
     path::{Path, PathBuf},
 };
 
@@ -16,17 +21,27 @@ impl Workspace {
         Self { path }
     }
 
+    /// Lists the files at or below `path`. A repository nested in the
+    /// workspace is listed itself, as a directory, rather than its files.
     pub fn list_files(&self, path: Option<&PathBuf>) -> Vec<Entry> {
         let path = path.unwrap_or(&self.path);
 
-        if path.is_dir() {
-            self.read_dir(path)
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() && path != &self.path && is_repository(path) => {
+                let relative_path = diff_paths(&path, &self.path).unwrap();
+
+                vec![Entry::new(path.clone(), relative_path)]
+            }
+            Ok(metadata) if metadata.is_dir() => self
+                .read_dir(path)
                 .flat_map(|entry| self.list_files(Some(&path.join(entry))))
-                .collect()
-        } else {
-            let relative_path = diff_paths(&path, &self.path).unwrap();
+                .collect(),
+            Ok(metadata) if is_trackable(&metadata) => {
+                let relative_path = diff_paths(&path, &self.path).unwrap();
 
-            vec![Entry::new(path.clone(), relative_path)]
+                vec![Entry::new(path.clone(), relative_path)]
+            }
+            _ => vec![],
         }
     }
 
@@ -34,54 +49,171 @@ impl Workspace {
         let path = path.unwrap_or(&self.path);
 
         self.read_dir(path)
-            .map(|path| {
-                let relative_path = diff_paths(&path, &self.path).unwrap();
+            .filter_map(|path| {
+                let metadata = fs::symlink_metadata(&path).ok()?;
 
-                let file = File::open(&path).unwrap();
-                let stat = self.stat_file(&file);
+                if !metadata.is_dir() && !is_trackable(&metadata) {
+                    return None;
+                }
+
+                let relative_path = diff_paths(&path, &self.path).unwrap();
 
-                (Entry::new(path, relative_path), stat)
+                Some((
+                    Entry::new(path, relative_path),
+                    stat_from_metadata(metadata),
+                ))
             })
             .collect()
     }
 
-    pub fn read_file(&self, entry: &Entry) -> Result<File, RitError> {
-        OpenOptions::new()
-            .read(true)
-            .append(true)
-            .open(&entry.absolute_path)
-            .map_err(|err| match err.kind() {
-                std::io::ErrorKind::PermissionDenied => {
-                    RitError::PermissionDenied(entry.relative_path_name.clone())
-                }
-                _ => RitError::Io(err),
-            })
+    /// Reads what gets stored as the blob of `entry` along with its stat:
+    /// the contents of a file, or the target of a symbolic link.
+    pub fn read_data(&self, entry: &Entry) -> Result<(Vec<u8>, Stat), RitError> {
+        let path = &entry.absolute_path;
+        let metadata = fs::symlink_metadata(path)?;
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+
+            return Ok((
+                target.into_os_string().into_vec(),
+                stat_from_metadata(metadata),
+            ));
+        }
+
+        let mut file =
+            OpenOptions::new()
+                .read(true)
+                .open(path)
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::PermissionDenied => {
+                        RitError::PermissionDenied(entry.relative_path_name.clone())
+                    }
+                    _ => RitError::Io(err),
+                })?;
+
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+
+        Ok((data, self.stat_file(&file)))
     }
 
-    pub fn stat_file(&self, file: &File) -> Stat {
-        let metadata = file.metadata().unwrap();
-
-        Stat {
-            ctime: metadata.ctime(),
-            ctime_nsec: metadata.ctime_nsec(),
-            mtime: metadata.mtime(),
-            mtime_nsec: metadata.mtime_nsec(),
-            dev: metadata.dev(),
-            ino: metadata.ino(),
-            mode: metadata.mode(),
-            uid: metadata.uid(),
-            gid: metadata.gid(),
-            size: metadata.size(),
+    pub fn entry(&self, pathname: &str) -> Entry {
+        Entry::new(self.path.join(pathname), PathBuf::from(pathname))
+    }
+
+    /// Writes `data` to `pathname`, replacing whatever file or directory is in
+    /// the way, and returns the stat of the written file. With the mode of
+    /// a symbolic link, `data` is the target of the link to create.
+    pub fn write_file(&self, pathname: &str, data: &[u8], mode: u32) -> Result<Stat, RitError> {
+        let path = self.path.join(pathname);
+
+        if let Some(parent) = path.parent() {
+            self.make_parent_dirs(parent)?;
+        }
+
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+            Ok(_) => fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+
+        if mode == SYMLINK_MODE {
+            symlink(OsStr::from_bytes(data), &path)?;
+
+            return Ok(stat_from_metadata(fs::symlink_metadata(&path)?));
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+
+        file.write_all(data)?;
+
+        let permissions = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+        fs::set_permissions(&path, fs::Permissions::from_mode(permissions))?;
+
+        Ok(self.stat_file(&file))
+    }
+
+    /// Removes the file or directory at `pathname` along with any parent
+    /// directories left empty by its removal.
+    pub fn remove(&self, pathname: &str) -> Result<(), RitError> {
+        let path = self.path.join(pathname);
+
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+            Ok(_) => fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+
+        let mut parent = path.parent();
+
+        while let Some(dir) = parent {
+            if dir == self.path || fs::remove_dir(dir).is_err() {
+                break;
+            }
+
+            parent = dir.parent();
         }
+
+        Ok(())
+    }
+
+    pub fn stat_file(&self, file: &File) -> Stat {
+        stat_from_metadata(file.metadata().unwrap())
+    }
+
+    /// The stat of the file, directory or symbolic link at `pathname`, if
+    /// there is one. Links are not followed.
+    pub fn stat_path(&self, pathname: &str) -> Option<Stat> {
+        fs::symlink_metadata(self.path.join(pathname))
+            .ok()
+            .map(stat_from_metadata)
+    }
+
+    /// Whether there is a repository nested at `pathname`, as in the
+    /// directory of a submodule.
+    pub fn is_repository(&self, pathname: &str) -> bool {
+        !pathname.is_empty() && is_repository(&self.path.join(pathname))
+    }
+
+    /// Creates the directory `pathname` if it is missing, replacing any file
+    /// in the way.
+    pub fn make_dir(&self, pathname: &str) -> Result<(), RitError> {
+        self.make_parent_dirs(&self.path.join(pathname))
     }
 
+    /// Resolves `pathname` to an absolute path, following symbolic links on
+    /// the way to it but not one it names itself.
     pub fn expand_path(&self, pathname: &str) -> Result<PathBuf, RitError> {
-        let path = fs::canonicalize(&self.path.join(pathname));
+        let path = self.path.join(pathname);
+        let missing = || RitError::MissingFile(String::from(pathname));
+
+        fs::symlink_metadata(&path).map_err(|_| missing())?;
+
+        let path = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => fs::canonicalize(parent).map(|parent| parent.join(name)),
+            _ => fs::canonicalize(&path),
+        };
+
+        path.map_err(|_| missing())
+    }
+
+    fn make_parent_dirs(&self, dir: &Path) -> Result<(), RitError> {
+        for ancestor in dir.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            let is_dir = fs::symlink_metadata(ancestor).map(|metadata| metadata.is_dir());
 
-        match path {
-            Ok(path) => Ok(path),
-            Err(_) => Err(RitError::MissingFile(String::from(pathname))),
+            if ancestor.starts_with(&self.path) && matches!(is_dir, Ok(false)) {
+                fs::remove_file(ancestor)?;
+            }
         }
+
+        fs::create_dir_all(dir)?;
+
+        Ok(())
     }
 
     fn read_dir(&self, path: &Path) -> impl Iterator<Item = PathBuf> {
@@ -97,3 +229,28 @@ impl Workspace {
             })
     }
 }
+
+/// Whether the file is one that can be tracked: a regular file or a
+/// symbolic link, rather than a FIFO, socket or device.
+fn is_trackable(metadata: &fs::Metadata) -> bool {
+    metadata.is_file() || metadata.file_type().is_symlink()
+}
+
+fn is_repository(path: &Path) -> bool {
+    submodule::git_dir(path).is_some()
+}
+
+fn stat_from_metadata(metadata: fs::Metadata) -> Stat {
+    Stat {
+        ctime: metadata.ctime(),
+        ctime_nsec: metadata.ctime_nsec(),
+        mtime: metadata.mtime(),
+        mtime_nsec: metadata.mtime_nsec(),
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        mode: metadata.mode(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        size: metadata.size(),
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::index::CacheTree;
+
+fn cache_tree(project: &Project) -> CacheTree {
+    let mut repo = project.repo();
+    repo.index.load().unwrap();
+
+    repo.index.cache_tree().cloned().unwrap()
+}
+
+fn head_tree(project: &Project) -> String {
+    let repo = project.repo();
+    let head = repo.refs.read_head().unwrap();
+
+    repo.database
+        .load_commit(&head)
+        .unwrap()
+        .tree_id
+        .to_string()
+}
+
+#[test]
+fn it_records_the_trees_written_by_a_commit() {
+    filled_project(|project| {
+        let mut cache = cache_tree(project);
+
+        assert_eq!(
+            Some(head_tree(project)),
+            cache.id().map(|id| id.to_string())
+        );
+        assert_eq!(3, cache.entry_count());
+        assert_eq!(2, cache.child_mut("a").entry_count());
+        assert_eq!(head_tree(project), project.git(&["write-tree"]));
+    });
+}
+
+#[test]
+fn it_invalidates_only_the_directories_of_changed_paths() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.add(vec!["."]).unwrap();
+
+        let mut cache = cache_tree(project);
+
+        assert_eq!(None, cache.id());
+        assert!(cache.child_mut("a").id().is_some());
+
+        project.write_file("a/b/3.txt", "changed");
+        project.add(vec!["."]).unwrap();
+
+        let mut cache = cache_tree(project);
+        let a = cache.child_mut("a");
+
+        assert_eq!(None, a.id());
+        assert_eq!(None, a.child_mut("b").id());
+
+        project.commit("second").unwrap();
+
+        assert_eq!(
+            Some(head_tree(project)),
+            cache_tree(project).id().map(|id| id.to_string())
+        );
+        assert_eq!(head_tree(project), project.git(&["write-tree"]));
+    });
+}
+
+#[test]
+fn it_drops_directories_that_are_removed() {
+    filled_project(|project| {
+        project.delete("a/b");
+        project.git(&["add", "-A"]);
+        project.write_file("a/b", "now a file");
+        project.add(vec!["a/b"]).unwrap();
+        project.commit("second").unwrap();
+
+        let mut cache = cache_tree(project);
+
+        assert_eq!(2, cache.child_mut("a").entry_count());
+        assert_eq!(head_tree(project), project.git(&["write-tree"]));
+        assert_eq!(
+            "a/2.txt\na/b",
+            project.git(&["ls-tree", "--name-only", "HEAD", "a/"])
+        );
+    });
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, CloneOptions, RepackOptions};
+use std::fs;
+
+fn clone_options(source: &Project) -> CloneOptions {
+    CloneOptions {
+        source: source.dir().to_string_lossy().to_string(),
+        directory: Some("copy".to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn it_clones_the_checked_out_branch_with_a_working_tree() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            project.clone_from(clone_options(source)).unwrap();
+
+            let copy = project.dir().join("copy");
+            let repo = rit::Repository::new(copy.clone());
+            let head = source.repo().refs.read_head().unwrap();
+
+            assert_eq!("three", fs::read_to_string(copy.join("a/b/3.txt")).unwrap());
+            assert_eq!(
+                Some("refs/heads/master".to_string()),
+                repo.refs.current_ref()
+            );
+            assert_eq!(Some(head.clone()), repo.refs.read_head());
+            assert_eq!(Some(head), repo.refs.read_ref("refs/remotes/origin/topic"));
+
+            let url = source.dir().to_string_lossy().to_string();
+            assert_eq!(Some(url), repo.config.get("remote.origin.url"));
+            assert_eq!(
+                Some("origin".to_string()),
+                repo.config.get("branch.master.remote")
+            );
+
+            let mut repo = repo;
+            repo.index.load().unwrap();
+            assert_eq!(3, repo.index.entries().len());
+        });
+    });
+}
+
+#[test]
+fn it_checks_out_the_branch_given_with_branch() {
+    filled_project(|source| {
+        source.branch("topic");
+        source.checkout("topic");
+        source.write_file("1.txt", "changed");
+        source.add(vec!["."]).unwrap();
+        source.commit("second").unwrap();
+        source.checkout("master");
+
+        Project::open_clean(|project| {
+            let options = CloneOptions {
+                branch: Some("topic".to_string()),
+                ..clone_options(source)
+            };
+            project.clone_from(options).unwrap();
+
+            let copy = project.dir().join("copy");
+            let repo = rit::Repository::new(copy.clone());
+
+            assert_eq!("changed", fs::read_to_string(copy.join("1.txt")).unwrap());
+            assert_eq!(
+                Some("refs/heads/topic".to_string()),
+                repo.refs.current_ref()
+            );
+            assert_eq!(
+                Some("refs/heads/topic".to_string()),
+                repo.config.get("branch.topic.merge")
+            );
+
+            let options = CloneOptions {
+                branch: Some("missing".to_string()),
+                directory: Some("other".to_string()),
+                ..clone_options(source)
+            };
+            assert!(matches!(
+                project.clone_from(options),
+                Err(RitError::CloneFailed(_))
+            ));
+        });
+    });
+}
+
+#[test]
+fn it_makes_bare_clones_of_packed_repositories() {
+    filled_project(|source| {
+        let options = RepackOptions {
+            all: true,
+            delete: true,
+        };
+        source.repack(options).unwrap();
+
+        let head = source.repo().refs.read_head().unwrap();
+        source.write_file(
+            ".git/packed-refs",
+            &format!("# pack-refs with: peeled\n{} refs/heads/master\n", head),
+        );
+        source.delete(".git/refs/heads/master");
+
+        Project::open_clean(|project| {
+            let options = CloneOptions {
+                directory: None,
+                bare: true,
+                ..clone_options(source)
+            };
+            project.clone_from(options).unwrap();
+
+            let name = source.dir().file_name().unwrap().to_string_lossy();
+            let git_path = project.dir().join(format!("{}.git", name));
+            let repo = rit::Repository::with_git_path(git_path.clone(), git_path.clone());
+
+            assert!(!git_path.join("1.txt").exists());
+            assert_eq!(Some(head.clone()), repo.refs.read_ref("refs/heads/master"));
+            assert!(repo.database.load_commit(&head).is_ok());
+            assert_eq!(Some(true), repo.config.get_bool("core.bare"));
+            assert_eq!(None, repo.config.get("remote.origin.fetch"));
+        });
+    });
+}
+
+#[test]
+fn it_refuses_to_clone_into_a_non_empty_directory() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            project.write_file("copy/file.txt", "content");
+
+            assert!(matches!(
+                project.clone_from(clone_options(source)),
+                Err(RitError::CloneFailed(_))
+            ));
+        });
+    });
+}
+
+#[test]
+fn it_resolves_the_cloned_tip_as_origin_master() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            project.clone_from(clone_options(source)).unwrap();
+
+            let repo = rit::Repository::new(project.dir().join("copy"));
+            let head = source.repo().refs.read_head();
+
+            assert_eq!(head, repo.refs.read_ref("origin/master"));
+            assert_eq!(head, repo.refs.read_ref("origin"));
+            assert_eq!(
+                Some("refs/remotes/origin/master".to_string()),
+                repo.refs.expand_name("origin/master")
+            );
+            assert_eq!(
+                format!("clone: from {}", source.dir().display()),
+                project.git(&["-C", "copy", "reflog", "-1", "--format=%gs"])
+            );
+        });
+    });
+}
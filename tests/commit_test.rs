@@ -0,0 +1,445 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, Cleanup, CommitOptions};
+
+fn amend(project: &Project, message: Option<&str>, reset_author: bool) {
+    let options = CommitOptions {
+        messages: message.into_iter().map(String::from).collect(),
+        amend: true,
+        reset_author,
+        no_edit: true,
+        ..Default::default()
+    };
+
+    project.commit_with(options).unwrap();
+}
+
+/// Replaces HEAD with a commit by another author, as if someone else had
+/// made it.
+fn commit_as_other_author(project: &Project) {
+    let tree = project.git(&["rev-parse", "HEAD^{tree}"]);
+    let parent = project.git(&["rev-parse", "HEAD~"]);
+
+    project.write_file(
+        ".git/other-commit",
+        &format!(
+            "tree {}\nparent {}\nauthor Other <other@example.com> 1600000000 +0200\n\
+             committer Other <other@example.com> 1600000000 +0200\n\nby other\n",
+            tree, parent
+        ),
+    );
+
+    let id = project.git(&["hash-object", "-t", "commit", "-w", ".git/other-commit"]);
+    project.git(&["update-ref", "HEAD", &id]);
+}
+
+#[test]
+fn it_replaces_the_head_commit_on_top_of_its_parents() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.add(vec!["1.txt"]).unwrap();
+        project.commit("second").unwrap();
+
+        project.write_file("new.txt", "new");
+        project.add(vec!["new.txt"]).unwrap();
+        amend(project, Some("amended"), false);
+
+        assert_eq!(vec!["amended", "message"], project.commit_messages("HEAD"));
+        assert_eq!("new", project.git(&["show", "HEAD:new.txt"]));
+        assert_eq!(
+            "commit (amend): amended",
+            project.git(&["reflog", "-1", "--format=%gs"])
+        );
+    });
+}
+
+#[test]
+fn it_amends_root_commits() {
+    filled_project(|project| {
+        amend(project, Some("root"), false);
+
+        assert_eq!(vec!["root"], project.commit_messages("HEAD"));
+        assert_eq!("", project.git(&["log", "-1", "--format=%P"]));
+    });
+}
+
+#[test]
+fn it_amends_merge_commits_with_their_parents() {
+    filled_project(|project| {
+        project.branch("topic");
+        project.write_file("1.txt", "master");
+        project.add(vec!["1.txt"]).unwrap();
+        project.commit("on master").unwrap();
+
+        project.checkout("topic");
+        project.write_file("new.txt", "topic");
+        project.add(vec!["new.txt"]).unwrap();
+        project.commit("on topic").unwrap();
+        project.checkout("master");
+
+        project.git(&[
+            "-c",
+            "user.name=name",
+            "-c",
+            "user.email=email",
+            "merge",
+            "--no-edit",
+            "-q",
+            "topic",
+        ]);
+        let parents = project.git(&["log", "-1", "--format=%P"]);
+
+        amend(project, Some("merged"), false);
+
+        assert_eq!(parents, project.git(&["log", "-1", "--format=%P"]));
+        assert_eq!("merged", project.git(&["log", "-1", "--format=%s"]));
+    });
+}
+
+#[test]
+fn it_keeps_the_message_and_author_unless_told_otherwise() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.add(vec!["1.txt"]).unwrap();
+        project.commit("second").unwrap();
+        commit_as_other_author(project);
+
+        amend(project, None, false);
+
+        assert_eq!("by other", project.git(&["log", "-1", "--format=%B"]));
+        assert_eq!(
+            "Other <other@example.com> 1600000000",
+            project.git(&["log", "-1", "--format=%an <%ae> %at"])
+        );
+        assert_eq!("name", project.git(&["log", "-1", "--format=%cn"]));
+
+        amend(project, None, true);
+
+        assert_eq!(
+            "name <email>",
+            project.git(&["log", "-1", "--format=%an <%ae>"])
+        );
+        assert_eq!("by other", project.git(&["log", "-1", "--format=%B"]));
+    });
+}
+
+#[test]
+fn it_fails_without_a_commit_to_amend() {
+    Project::open(|project| {
+        project.write_file("1.txt", "one");
+        project.add(vec!["1.txt"]).unwrap();
+
+        let options = CommitOptions {
+            amend: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            project.commit_with(options),
+            Err(RitError::NothingToAmend)
+        ));
+    });
+}
+
+fn commit_with(project: &Project, options: CommitOptions) -> Result<String, RitError> {
+    project.commit_with(options)?;
+
+    Ok(project.commit_messages("HEAD").remove(0))
+}
+
+/// The editors are configured per test repository, so editors set in the
+/// environment must not take precedence over them.
+fn clear_editor_env() {
+    for name in &["GIT_EDITOR", "VISUAL", "EDITOR"] {
+        std::env::remove_var(name);
+    }
+}
+
+/// Sets an editor that keeps a copy of what it was given in `.git/seen`
+/// and then runs `script` on the message file, passed as `$1`.
+fn set_editor(project: &Project, script: &str) {
+    clear_editor_env();
+
+    let seen = project.dir().join(".git/seen");
+    project.set_config(
+        "core.editor",
+        &format!("f() {{ cp \"$1\" '{}' && {}; }}; f", seen.display(), script),
+    );
+}
+
+fn stage_new_file(project: &Project) {
+    project.write_file("new.txt", "new");
+    project.add(vec!["new.txt"]).unwrap();
+}
+
+#[test]
+fn it_joins_messages_as_paragraphs() {
+    filled_project(|project| {
+        stage_new_file(project);
+
+        let options = CommitOptions {
+            messages: vec!["subject".to_string(), "body  \n\n\n".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!("subject\n\nbody", commit_with(project, options).unwrap());
+    });
+}
+
+#[test]
+fn it_reads_the_message_from_a_file() {
+    filled_project(|project| {
+        project.write_file("message.txt", "\n\nfrom a file\n\n\n\n# not a comment\n");
+        stage_new_file(project);
+
+        let options = CommitOptions {
+            file: Some("message.txt".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "from a file\n\n# not a comment",
+            commit_with(project, options).unwrap()
+        );
+    });
+}
+
+#[test]
+fn it_edits_the_template_with_the_staged_changes() {
+    filled_project(|project| {
+        project.write_file(".git/template", "# fill in\n");
+        project.set_config("commit.template", ".git/template");
+        set_editor(project, "echo edited >> \"$1\"");
+
+        project.write_file("1.txt", "changed");
+        project.add(vec!["1.txt"]).unwrap();
+        project.write_file("a/2.txt", "not staged");
+        stage_new_file(project);
+
+        assert_eq!(
+            "edited",
+            commit_with(project, CommitOptions::default()).unwrap()
+        );
+
+        let seen = project.read_file(".git/seen");
+        assert!(seen.starts_with("# fill in\n\n# Please enter the commit message"));
+        assert!(seen.contains("# On branch master\n"));
+        assert!(seen.contains(
+            "# Changes to be committed:\n\
+             #\tmodified:   1.txt\n\
+             #\tnew file:   new.txt\n#\n"
+        ));
+    });
+}
+
+#[test]
+fn it_starts_the_editor_on_the_amended_message() {
+    filled_project(|project| {
+        set_editor(project, "true");
+
+        let options = CommitOptions {
+            amend: true,
+            ..Default::default()
+        };
+
+        assert_eq!("message", commit_with(project, options).unwrap());
+        assert!(project.read_file(".git/seen").starts_with("message\n\n#"));
+    });
+}
+
+#[test]
+fn it_aborts_on_an_empty_or_unedited_message() {
+    filled_project(|project| {
+        set_editor(project, "true");
+        stage_new_file(project);
+
+        let aborted = |project: &Project, expected: &str| {
+            assert!(matches!(
+                project.commit_with(CommitOptions::default()),
+                Err(RitError::CommitAborted(message)) if message == expected
+            ));
+            assert_eq!(vec!["message"], project.commit_messages("HEAD"));
+        };
+
+        aborted(project, "Aborting commit due to empty commit message.");
+
+        project.write_file(".git/template", "subject\n");
+        project.set_config("commit.template", ".git/template");
+
+        aborted(project, "Aborting commit; you did not edit the message.");
+    });
+}
+
+#[test]
+fn it_cleans_up_messages_as_asked() {
+    filled_project(|project| {
+        let message = "  subject  \n\n\n# comment\n";
+
+        for (cleanup, expected) in [
+            (Cleanup::Verbatim, message),
+            (Cleanup::Whitespace, "  subject\n\n# comment"),
+            (Cleanup::Strip, "  subject"),
+        ] {
+            let options = CommitOptions {
+                messages: vec![message.to_string()],
+                cleanup: Some(cleanup),
+                amend: true,
+                ..Default::default()
+            };
+
+            assert_eq!(expected, commit_with(project, options).unwrap());
+        }
+    });
+}
+
+#[test]
+fn it_cuts_edited_messages_at_the_scissors_line() {
+    filled_project(|project| {
+        set_editor(
+            project,
+            "printf 'kept\\n# kept comment\\n' | cat - \"$1\" > \"$1.new\" && mv \"$1.new\" \"$1\"",
+        );
+        stage_new_file(project);
+
+        let options = CommitOptions {
+            cleanup: Some(Cleanup::Scissors),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "kept\n# kept comment",
+            commit_with(project, options).unwrap()
+        );
+    });
+}
+
+fn commit_paths(project: &Project, paths: Vec<&str>, include: bool) -> Result<(), RitError> {
+    let options = CommitOptions {
+        paths: paths.into_iter().map(String::from).collect(),
+        include,
+        messages: vec!["partial".to_string()],
+        ..Default::default()
+    };
+
+    project.commit_with(options).map(|_| ())
+}
+
+#[test]
+fn it_commits_all_changes_to_tracked_files() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.delete("a/2.txt");
+        project.write_file("untracked.txt", "untracked");
+
+        let options = CommitOptions {
+            all: true,
+            messages: vec!["all".to_string()],
+            ..Default::default()
+        };
+        project.commit_with(options).unwrap();
+
+        assert_eq!(
+            "1.txt\na/b/3.txt",
+            project.git(&["ls-tree", "-r", "--name-only", "HEAD"])
+        );
+        assert_eq!("changed", project.git(&["show", "HEAD:1.txt"]));
+        assert_eq!("?? untracked.txt", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_commits_only_the_given_paths() {
+    filled_project(|project| {
+        stage_new_file(project);
+        project.write_file("1.txt", "changed");
+        project.delete("a/b/3.txt");
+        project.write_file("a/2.txt", "not committed");
+
+        commit_paths(project, vec!["1.txt", "a/b"], false).unwrap();
+
+        assert_eq!(
+            "1.txt\na/2.txt",
+            project.git(&["ls-tree", "-r", "--name-only", "HEAD"])
+        );
+        assert_eq!("changed", project.git(&["show", "HEAD:1.txt"]));
+        assert_eq!("two", project.git(&["show", "HEAD:a/2.txt"]));
+        assert_eq!(
+            " M a/2.txt\nA  new.txt",
+            project.git(&["status", "--porcelain"])
+        );
+        assert!(!project
+            .dir()
+            .join(".git")
+            .read_dir()
+            .unwrap()
+            .any(|entry| entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("next-index")));
+    });
+}
+
+#[test]
+fn it_commits_the_given_paths_along_with_the_index() {
+    filled_project(|project| {
+        stage_new_file(project);
+        project.write_file("1.txt", "changed");
+        project.write_file("a/2.txt", "not committed");
+
+        commit_paths(project, vec!["1.txt"], true).unwrap();
+
+        assert_eq!(
+            "1.txt\na/2.txt\na/b/3.txt\nnew.txt",
+            project.git(&["ls-tree", "-r", "--name-only", "HEAD"])
+        );
+        assert_eq!("changed", project.git(&["show", "HEAD:1.txt"]));
+        assert_eq!(" M a/2.txt", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_amends_just_the_message_with_only_and_no_paths() {
+    filled_project(|project| {
+        let tree = project.git(&["rev-parse", "HEAD^{tree}"]);
+        stage_new_file(project);
+        project.write_file("1.txt", "changed");
+
+        let options = CommitOptions {
+            only: true,
+            amend: true,
+            messages: vec!["reworded".to_string()],
+            ..Default::default()
+        };
+        project.commit_with(options).unwrap();
+
+        assert_eq!(vec!["reworded"], project.commit_messages("HEAD"));
+        assert_eq!(tree, project.git(&["rev-parse", "HEAD^{tree}"]));
+        assert_eq!(
+            " M 1.txt\nA  new.txt",
+            project.git(&["status", "--porcelain"])
+        );
+    });
+}
+
+#[test]
+fn it_fails_for_paths_not_known_to_the_index() {
+    filled_project(|project| {
+        project.write_file("untracked.txt", "untracked");
+
+        for include in [false, true] {
+            assert!(matches!(
+                commit_paths(project, vec!["untracked.txt"], include),
+                Err(RitError::MissingFile(path)) if path == "untracked.txt"
+            ));
+        }
+
+        assert_eq!(vec!["message"], project.commit_messages("HEAD"));
+
+        project.write_file("1.txt", "changed");
+        commit_paths(project, vec!["1.txt"], false).unwrap();
+
+        assert_eq!(vec!["partial", "message"], project.commit_messages("HEAD"));
+    });
+}
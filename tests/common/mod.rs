@@ -9,6 +9,8 @@ use std::{
     os::unix::fs::PermissionsExt,
     panic,
     path::PathBuf,
+    process, thread,
+    time::Duration,
 };
 
 pub fn filled_project<T>(test: T)
@@ -98,10 +100,192 @@ impl Project {
         rit::Commit::new(self.session.clone(), message.to_string()).execute()
     }
 
+    pub fn commit_with(&self, options: rit::CommitOptions) -> Result<rit::Execution, RitError> {
+        rit::Commit::with_options(self.session.clone(), options).execute()
+    }
+
+    pub fn commit_fixup(&self, rev: &str) -> Result<rit::Execution, RitError> {
+        let options = rit::CommitOptions {
+            fixup: Some(rev.to_string()),
+            ..Default::default()
+        };
+
+        rit::Commit::with_options(self.session.clone(), options).execute()
+    }
+
     pub fn status(&self) -> Result<rit::Execution, RitError> {
         rit::Status::new(self.session.clone()).execute()
     }
 
+    pub fn rebase(&self, action: rit::RebaseAction) -> Result<rit::Execution, RitError> {
+        rit::Rebase::new(self.session.clone(), action).execute()
+    }
+
+    pub fn stash(&self, action: rit::StashAction) -> Result<rit::Execution, RitError> {
+        rit::Stash::new(self.session.clone(), action).execute()
+    }
+
+    pub fn reflog(&self, action: rit::ReflogAction) -> Result<rit::Execution, RitError> {
+        rit::Reflog::new(self.session.clone(), action).execute()
+    }
+
+    pub fn tag(&self, action: rit::TagAction) -> Result<rit::Execution, RitError> {
+        rit::Tag::new(self.session.clone(), action).execute()
+    }
+
+    pub fn index_pack(
+        &self,
+        data: Vec<u8>,
+        options: rit::IndexPackOptions,
+    ) -> Result<rit::Execution, RitError> {
+        rit::IndexPack::new(self.session.clone(), data, options).execute()
+    }
+
+    pub fn unpack_objects(&self, data: Vec<u8>) -> Result<rit::Execution, RitError> {
+        rit::UnpackObjects::new(self.session.clone(), data).execute()
+    }
+
+    pub fn repack(&self, options: rit::RepackOptions) -> Result<rit::Execution, RitError> {
+        rit::Repack::new(self.session.clone(), options).execute()
+    }
+
+    pub fn fsck(&self, options: rit::FsckOptions) -> Result<rit::Execution, RitError> {
+        rit::Fsck::new(self.session.clone(), options).execute()
+    }
+
+    pub fn fsmonitor_daemon(
+        &self,
+        action: rit::FsmonitorAction,
+    ) -> Result<rit::Execution, RitError> {
+        rit::FsmonitorDaemon::new(self.session.clone(), action).execute()
+    }
+
+    /// Runs the filesystem monitor daemon in a thread until it is stopped.
+    pub fn run_fsmonitor_daemon(&self) -> thread::JoinHandle<bool> {
+        let session = self.session.clone();
+
+        let handle = thread::spawn(move || {
+            rit::FsmonitorDaemon::new(session, rit::FsmonitorAction::Run)
+                .execute()
+                .is_ok()
+        });
+
+        while !matches!(
+            self.fsmonitor_daemon(rit::FsmonitorAction::Status),
+            Ok(rit::Execution::Fsmonitor(res)) if res.running
+        ) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        handle
+    }
+
+    pub fn clone_from(&self, options: rit::CloneOptions) -> Result<rit::Execution, RitError> {
+        rit::Clone::new(self.session.clone(), options).execute()
+    }
+
+    pub fn fetch(&self, options: rit::FetchOptions) -> Result<rit::Execution, RitError> {
+        rit::Fetch::new(self.session.clone(), options).execute()
+    }
+
+    pub fn push(&self, options: rit::PushOptions) -> Result<rit::Execution, RitError> {
+        rit::Push::new(self.session.clone(), options).execute()
+    }
+
+    pub fn remote(&self, action: rit::RemoteAction) -> Result<rit::Execution, RitError> {
+        rit::Remote::new(self.session.clone(), action).execute()
+    }
+
+    pub fn submodule(&self, action: rit::SubmoduleAction) -> Result<rit::Execution, RitError> {
+        rit::Submodule::new(self.session.clone(), action).execute()
+    }
+
+    /// A project for the directory `name` inside this one, as for a nested
+    /// repository.
+    pub fn nested(&self, name: &str) -> Project {
+        let project_dir = self.session.project_dir.join(name);
+        fs::create_dir_all(&project_dir).unwrap();
+
+        Project {
+            session: Session {
+                project_dir,
+                ..self.session.clone()
+            },
+        }
+    }
+
+    /// Runs git in the project, for checking that it reads what rit wrote
+    /// and the other way round.
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = process::Command::new("git")
+            .args(args)
+            .current_dir(self.dir())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "git {:?} failed", args);
+
+        String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string()
+    }
+
+    pub fn repo(&self) -> rit::Repository {
+        rit::Repository::new(self.session.project_dir.clone())
+    }
+
+    pub fn branch(&self, name: &str) {
+        let repo = self.repo();
+        let id = repo.refs.read_head().unwrap();
+
+        repo.refs.create_branch(name, &id).unwrap();
+    }
+
+    pub fn checkout(&self, name: &str) {
+        let mut repo = self.repo();
+        let id = repo.refs.read_ref(name).unwrap();
+
+        repo.index.load_for_update().unwrap();
+        repo.hard_reset(&id).unwrap();
+        repo.index.write_updates().unwrap();
+
+        repo.refs.set_head(name, &id).unwrap();
+    }
+
+    pub fn set_config(&self, key: &str, value: &str) {
+        self.repo().config.set(key, value).unwrap();
+    }
+
+    pub fn head_name(&self) -> Option<String> {
+        self.repo().refs.current_ref()
+    }
+
+    pub fn commit_messages(&self, name: &str) -> Vec<String> {
+        let repo = self.repo();
+        let mut id = repo.refs.read_ref(name);
+        let mut messages = vec![];
+
+        while let Some(commit_id) = id {
+            let commit = repo.database.load_commit(&commit_id).unwrap();
+
+            messages.push(commit.message.clone());
+            id = commit.parent().cloned();
+        }
+
+        messages
+    }
+
+    pub fn read_file(&self, name: &str) -> String {
+        fs::read_to_string(self.session.project_dir.join(name)).unwrap()
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.session.project_dir.join(name).exists()
+    }
+
+    /// Replaces the content of the file `name`. Truncating matters once a
+    /// file is rewritten with something shorter, as when resolving a
+    /// conflict: otherwise the tail of the conflict markers is left behind.
     pub fn write_file(&self, name: &str, content: &str) {
         let path = self.session.project_dir.join(name);
         let prefix = path.parent().unwrap();
@@ -110,6 +294,7 @@ impl Project {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(path)
             .unwrap();
 
@@ -131,6 +316,12 @@ impl Project {
         name.to_string()
     }
 
+    pub fn symlink(&self, target: &str, name: &str) {
+        let path = self.session.project_dir.join(name);
+
+        std::os::unix::fs::symlink(target, path).unwrap();
+    }
+
     pub fn make_executable(&self, name: &str) {
         self.set_file_mode(name, 0o755);
     }
@@ -157,7 +348,7 @@ impl Project {
     pub fn delete(&self, name: &str) {
         let path = self.session.project_dir.join(name);
 
-        if path.is_dir() {
+        if fs::symlink_metadata(&path).unwrap().is_dir() {
             fs::remove_dir_all(path).unwrap();
         } else {
             fs::remove_file(path).unwrap();
@@ -0,0 +1,135 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, CloneOptions, Command, DaemonOptions, Execution, FetchOptions};
+use std::{
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+/// Starts a daemon for the repositories next to `source` on a free port and
+/// waits until it accepts connections.
+fn start_daemon(source: &Project, export_all: bool) -> u16 {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let session = rit::Session {
+        author_name: String::new(),
+        author_email: String::new(),
+        project_dir: source.dir().clone(),
+    };
+    let options = DaemonOptions {
+        listen: Some("127.0.0.1".to_string()),
+        port: Some(port),
+        base_path: source.dir().parent().map(|dir| dir.to_path_buf()),
+        export_all,
+        timeout: Some(5),
+        ..Default::default()
+    };
+
+    thread::spawn(move || rit::Daemon::new(session, options).execute());
+
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return port;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("daemon did not start");
+}
+
+fn url(source: &Project, port: u16) -> String {
+    let name = source.dir().file_name().unwrap().to_string_lossy();
+
+    format!("git://127.0.0.1:{}/{}", port, name)
+}
+
+fn clone_options(source: &Project, port: u16) -> CloneOptions {
+    CloneOptions {
+        source: url(source, port),
+        directory: Some(".".to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn it_serves_exported_repositories() {
+    filled_project(|source| {
+        source.write_file(".git/git-daemon-export-ok", "");
+        let port = start_daemon(source, false);
+
+        Project::open_clean(|project| {
+            project.clone_from(clone_options(source, port)).unwrap();
+
+            assert_eq!("three", project.read_file("a/b/3.txt"));
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_head()
+            );
+
+            source.write_file("1.txt", "changed");
+            source.add(vec!["."]).unwrap();
+            source.commit("second").unwrap();
+
+            match project.fetch(FetchOptions::default()).unwrap() {
+                Execution::Fetch(result) => assert!(result.to_string().contains("master")),
+                _ => panic!("unexpected execution"),
+            }
+
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_ref("refs/remotes/origin/master")
+            );
+        });
+    });
+}
+
+#[test]
+fn it_refuses_repositories_that_are_not_exported() {
+    filled_project(|source| {
+        let port = start_daemon(source, false);
+
+        Project::open_clean(|project| {
+            let result = project.clone_from(clone_options(source, port));
+
+            assert!(matches!(
+                result,
+                Err(RitError::Remote(message)) if message.contains("not exported")
+            ));
+            assert!(!project.exists(".git"));
+        });
+    });
+}
+
+#[test]
+fn it_serves_every_repository_with_export_all() {
+    filled_project(|source| {
+        let port = start_daemon(source, true);
+
+        Project::open_clean(|project| {
+            project.clone_from(clone_options(source, port)).unwrap();
+
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_head()
+            );
+
+            let options = CloneOptions {
+                source: format!("git://127.0.0.1:{}/../etc", port),
+                directory: Some("other".to_string()),
+                ..Default::default()
+            };
+
+            assert!(matches!(
+                project.clone_from(options),
+                Err(RitError::Remote(_))
+            ));
+        });
+    });
+}
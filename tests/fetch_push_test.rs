@@ -0,0 +1,373 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{CloneOptions, Execution, FetchOptions, PushOptions};
+use sha1::Sha1;
+use std::{
+    io::prelude::*,
+    process::{self, Stdio},
+};
+
+fn program(service: &str) -> Option<String> {
+    Some(format!("'{}' {}", env!("CARGO_BIN_EXE_rit"), service))
+}
+
+fn url(source: &Project) -> String {
+    format!("file://{}", source.dir().to_string_lossy())
+}
+
+fn clone_into(project: &Project, source: &Project) {
+    let options = CloneOptions {
+        source: url(source),
+        directory: Some(".".to_string()),
+        upload_pack: program("upload-pack"),
+        ..Default::default()
+    };
+
+    project.clone_from(options).unwrap();
+}
+
+fn fetch(project: &Project) -> String {
+    let options = FetchOptions {
+        upload_pack: program("upload-pack"),
+        ..Default::default()
+    };
+
+    match project.fetch(options).unwrap() {
+        Execution::Fetch(result) => result.to_string(),
+        _ => panic!("unexpected execution"),
+    }
+}
+
+fn push(project: &Project, branch: &str, force: bool) -> (String, bool) {
+    let options = PushOptions {
+        branches: vec![branch.to_string()],
+        force,
+        receive_pack: program("receive-pack"),
+        ..Default::default()
+    };
+
+    match project.push(options).unwrap() {
+        Execution::Push(result) => (result.to_string(), result.is_rejected()),
+        _ => panic!("unexpected execution"),
+    }
+}
+
+/// Runs `service` on `source` with a request written by hand, as a client
+/// that does not play by the rules would send it.
+fn serve(source: &Project, service: &str, request: &[u8]) -> String {
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_rit"))
+        .args([service, "--stateless-rpc", "."])
+        .current_dir(source.dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(request).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn pkt_line(line: &str) -> String {
+    format!("{:04x}{}\n", line.len() + 5, line)
+}
+
+fn commit_file(project: &Project, name: &str, content: &str) {
+    project.write_file(name, content);
+    project.add(vec!["."]).unwrap();
+    project.commit(content).unwrap();
+}
+
+#[test]
+fn it_clones_through_upload_pack() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            let head = source.repo().refs.read_head();
+            let repo = project.repo();
+
+            assert_eq!("three", project.read_file("a/b/3.txt"));
+            assert_eq!(Some("refs/heads/master".to_string()), project.head_name());
+            assert_eq!(head, repo.refs.read_head());
+            assert_eq!(head, repo.refs.read_ref("refs/remotes/origin/topic"));
+        });
+    });
+}
+
+#[test]
+fn it_fetches_new_commits_into_remote_tracking_branches() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            let old_head = source.repo().refs.read_head().unwrap();
+            commit_file(source, "1.txt", "second");
+            let new_head = source.repo().refs.read_head().unwrap();
+
+            let output = fetch(project);
+            let repo = project.repo();
+
+            assert!(output.contains(&format!("{}..{}", old_head.short(), new_head.short())));
+            assert!(output.contains(" master -> origin/master"));
+            assert_eq!(
+                Some(new_head.clone()),
+                repo.refs.read_ref("refs/remotes/origin/master")
+            );
+            assert!(repo.database.load_commit(&new_head).is_ok());
+            assert_eq!(Some(old_head), repo.refs.read_head());
+
+            assert_eq!("", fetch(project));
+        });
+    });
+}
+
+#[test]
+fn it_pushes_new_branches_and_fast_forwards() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            project.branch("topic");
+            project.checkout("topic");
+            commit_file(project, "1.txt", "second");
+
+            let (output, rejected) = push(project, "topic", false);
+            let head = project.repo().refs.read_head();
+
+            assert!(!rejected);
+            assert!(output.contains("* [new branch]      topic -> topic"));
+            assert_eq!(head, source.repo().refs.read_ref("refs/heads/topic"));
+            assert_eq!(
+                head,
+                project.repo().refs.read_ref("refs/remotes/origin/topic")
+            );
+
+            commit_file(project, "1.txt", "third");
+
+            let (_, rejected) = push(project, "topic", false);
+            let head = project.repo().refs.read_head();
+
+            assert!(!rejected);
+            assert_eq!(head, source.repo().refs.read_ref("refs/heads/topic"));
+            assert!(source.repo().database.load_commit(&head.unwrap()).is_ok());
+
+            assert_eq!(
+                ("Everything up-to-date".to_string(), false),
+                push(project, "topic", false)
+            );
+        });
+    });
+}
+
+#[test]
+fn it_rejects_non_fast_forwards_unless_forced() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            source.checkout("topic");
+            commit_file(source, "1.txt", "theirs");
+            source.checkout("master");
+            fetch(project);
+
+            project.branch("topic");
+            project.checkout("topic");
+            commit_file(project, "1.txt", "ours");
+
+            let (output, rejected) = push(project, "topic", false);
+
+            assert!(rejected);
+            assert!(output.contains("! [rejected]        topic -> topic (non-fast-forward)"));
+
+            let (output, rejected) = push(project, "topic", true);
+
+            assert!(!rejected);
+            assert!(output.contains("(forced update)"));
+            assert_eq!(
+                project.repo().refs.read_head(),
+                source.repo().refs.read_ref("refs/heads/topic")
+            );
+        });
+    });
+}
+
+#[test]
+fn it_refuses_to_update_the_checked_out_branch() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            let head = source.repo().refs.read_head();
+            commit_file(project, "1.txt", "second");
+
+            let (output, rejected) = push(project, "master", false);
+
+            assert!(rejected);
+            assert!(output.contains("(branch is currently checked out)"));
+            assert_eq!(head, source.repo().refs.read_head());
+
+            source.set_config("receive.denyCurrentBranch", "ignore");
+
+            let (_, rejected) = push(project, "master", false);
+
+            assert!(!rejected);
+            assert_eq!(
+                project.repo().refs.read_head(),
+                source.repo().refs.read_head()
+            );
+        });
+    });
+}
+
+/// Whether git is there to test against; the tests talking to its services
+/// pass without doing anything otherwise.
+fn has_git() -> bool {
+    std::process::Command::new("git")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+#[test]
+fn it_clones_and_fetches_from_git_upload_pack() {
+    if !has_git() {
+        return;
+    }
+
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            let options = CloneOptions {
+                source: url(source),
+                directory: Some(".".to_string()),
+                upload_pack: Some("git-upload-pack".to_string()),
+                ..Default::default()
+            };
+            project.clone_from(options).unwrap();
+
+            commit_file(source, "1.txt", "second");
+
+            let options = FetchOptions {
+                upload_pack: Some("git-upload-pack".to_string()),
+                ..Default::default()
+            };
+            project.fetch(options).unwrap();
+
+            let head = source.repo().refs.read_head();
+
+            assert_eq!(
+                head,
+                project.repo().refs.read_ref("refs/remotes/origin/master")
+            );
+            assert_eq!("three", project.read_file("a/b/3.txt"));
+            project.git(&["fsck", "--full", "--strict"]);
+        });
+    });
+}
+
+#[test]
+fn it_pushes_to_git_receive_pack() {
+    if !has_git() {
+        return;
+    }
+
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            project.branch("topic");
+            project.checkout("topic");
+            commit_file(project, "1.txt", "second");
+            commit_file(project, "a/2.txt", "third");
+
+            let options = PushOptions {
+                branches: vec!["topic".to_string()],
+                receive_pack: Some("git-receive-pack".to_string()),
+                ..Default::default()
+            };
+
+            match project.push(options).unwrap() {
+                Execution::Push(result) => assert!(!result.is_rejected(), "{}", result),
+                _ => panic!("unexpected execution"),
+            }
+
+            let head = project.repo().refs.read_head().unwrap();
+
+            assert_eq!(head.as_str, source.git(&["rev-parse", "refs/heads/topic"]));
+            assert_eq!("third", source.git(&["show", "topic:a/2.txt"]));
+            source.git(&["fsck", "--full", "--strict"]);
+        });
+    });
+}
+
+#[test]
+fn it_refuses_pushes_to_funny_refnames() {
+    filled_project(|source| {
+        let head = source.repo().refs.read_head().unwrap();
+        let zero = "0".repeat(40);
+
+        let mut request = pkt_line(&format!(
+            "{} {} refs/../../hooks/x\0report-status",
+            zero, head
+        ));
+        for name in ["refs/heads//x", "refs/heads/x.lock/y", "refs/heads/x."] {
+            request.push_str(&pkt_line(&format!("{} {} {}", zero, head, name)));
+        }
+        request.push_str("0000");
+
+        let mut request = request.into_bytes();
+        let mut pack = b"PACK\0\0\0\x02\0\0\0\0".to_vec();
+        pack.extend_from_slice(&Sha1::from(&pack).digest().bytes());
+        request.extend(pack);
+
+        let output = serve(source, "receive-pack", &request);
+
+        assert!(output.contains("ng refs/../../hooks/x funny refname"));
+        assert!(output.contains("ng refs/heads//x funny refname"));
+        assert!(output.contains("ng refs/heads/x.lock/y funny refname"));
+        assert!(output.contains("ng refs/heads/x. funny refname"));
+        assert!(!source.exists("hooks/x"));
+        assert!(!source.exists(".git/hooks/x"));
+        assert_eq!(None, source.repo().refs.read_ref("refs/heads/x"));
+    });
+}
+
+#[test]
+fn it_only_uploads_what_it_advertised() {
+    filled_project(|source| {
+        let parent = source.repo().refs.read_head().unwrap();
+        commit_file(source, "1.txt", "second");
+
+        let repo = source.repo();
+        let head = repo.refs.read_head().unwrap();
+        let blob = repo.database.flatten_tree(Some(&head)).unwrap()["1.txt"]
+            .id
+            .clone();
+
+        for id in [&parent, &blob] {
+            let request = format!(
+                "{}0000{}",
+                pkt_line(&format!("want {}", id)),
+                pkt_line("done")
+            );
+            let output = serve(source, "upload-pack", request.as_bytes());
+
+            assert!(output.contains(&format!("ERR upload-pack: not our ref {}", id)));
+            assert!(!output.contains("PACK"));
+        }
+
+        let request = format!(
+            "{}0000{}",
+            pkt_line(&format!("want {}", head)),
+            pkt_line("done")
+        );
+        assert!(serve(source, "upload-pack", request.as_bytes()).contains("PACK"));
+    });
+}
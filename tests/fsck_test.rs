@@ -0,0 +1,102 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{Execution, FsckOptions};
+use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+fn fsck(project: &Project, lost_found: bool) -> (String, bool) {
+    match project.fsck(FsckOptions { lost_found }).unwrap() {
+        Execution::Fsck(result) => (result.to_string(), result.is_broken()),
+        _ => panic!("unexpected execution"),
+    }
+}
+
+fn blob_id(project: &Project, name: &str) -> String {
+    let mut repo = project.repo();
+    repo.index.load().unwrap();
+
+    repo.index.entry_for_path(name).unwrap().id.to_string()
+}
+
+fn object_path(project: &Project, id: &str) -> PathBuf {
+    project
+        .dir()
+        .join(format!(".git/objects/{}/{}", &id[..2], &id[2..]))
+}
+
+#[test]
+fn it_finds_nothing_wrong_with_a_clean_repository() {
+    filled_project(|project| {
+        assert_eq!((String::new(), false), fsck(project, false));
+    });
+}
+
+#[test]
+fn it_reports_missing_objects() {
+    filled_project(|project| {
+        let id = blob_id(project, "a/b/3.txt");
+        fs::remove_file(object_path(project, &id)).unwrap();
+
+        let (output, broken) = fsck(project, false);
+
+        assert!(broken);
+        assert!(output.contains(&format!("missing blob {}", id)));
+        assert!(output.contains(&format!(
+            "error: {}: invalid sha1 pointer in index (a/b/3.txt)",
+            id
+        )));
+    });
+}
+
+#[test]
+fn it_reports_objects_that_do_not_match_their_id() {
+    filled_project(|project| {
+        let id = blob_id(project, "1.txt");
+        let path = object_path(project, &id);
+        let other = fs::read(object_path(project, &blob_id(project, "a/2.txt"))).unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::write(&path, other).unwrap();
+
+        let (output, broken) = fsck(project, false);
+
+        assert!(broken);
+        assert!(output.contains(&format!("error: hash mismatch for {}", id)));
+    });
+}
+
+#[test]
+fn it_reports_and_recovers_dangling_objects() {
+    filled_project(|project| {
+        project.write_file("1.txt", "lost");
+        project.add(vec!["."]).unwrap();
+        let id = blob_id(project, "1.txt");
+
+        project.write_file("1.txt", "kept");
+        project.add(vec!["."]).unwrap();
+
+        let (output, broken) = fsck(project, true);
+
+        assert!(!broken);
+        assert_eq!(format!("dangling blob {}", id), output);
+        assert_eq!(
+            "lost",
+            project.read_file(&format!(".git/lost-found/other/{}", id))
+        );
+    });
+}
+
+#[test]
+fn it_reports_a_corrupt_index() {
+    filled_project(|project| {
+        let path = project.dir().join(".git/index");
+        let mut data = fs::read(&path).unwrap();
+        data[40] ^= 0xff;
+        fs::write(&path, data).unwrap();
+
+        let (output, broken) = fsck(project, false);
+
+        assert!(broken);
+        assert!(output.contains("error: index file corrupt"));
+    });
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, FsmonitorAction};
+
+fn fsmonitor_valid(project: &Project) -> Vec<String> {
+    let mut repo = project.repo();
+    repo.index.load().unwrap();
+
+    repo.index
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.is_fsmonitor_valid())
+        .map(|entry| entry.pathname)
+        .collect()
+}
+
+fn fsmonitor_token(project: &Project) -> Option<String> {
+    let mut repo = project.repo();
+    repo.index.load().unwrap();
+
+    repo.index.fsmonitor_token().map(String::from)
+}
+
+fn modified(project: &Project) -> Vec<String> {
+    match project.status().unwrap() {
+        rit::Execution::Status(res) => res.modified.iter().map(|e| e.to_string()).collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn it_only_checks_the_files_reported_as_changed() {
+    filled_project(|project| {
+        project.set_config("core.fsmonitor", "true");
+        let daemon = project.run_fsmonitor_daemon();
+
+        assert!(modified(project).is_empty());
+        assert_eq!(
+            vec!["1.txt", "a/2.txt", "a/b/3.txt"],
+            fsmonitor_valid(project)
+        );
+
+        project.write_file("a/b/3.txt", "changed");
+
+        assert_eq!(vec!["a/b/3.txt"], modified(project));
+        assert_eq!(vec!["1.txt", "a/2.txt"], fsmonitor_valid(project));
+
+        project.delete("a");
+
+        assert!(modified(project).is_empty());
+        assert_eq!(vec!["1.txt"], fsmonitor_valid(project));
+
+        project.fsmonitor_daemon(FsmonitorAction::Stop).unwrap();
+        assert!(daemon.join().unwrap());
+    });
+}
+
+#[test]
+fn it_checks_every_file_when_no_daemon_answers() {
+    filled_project(|project| {
+        project.set_config("core.fsmonitor", "true");
+        let daemon = project.run_fsmonitor_daemon();
+
+        project.status().unwrap();
+        assert!(fsmonitor_token(project).is_some());
+
+        project.fsmonitor_daemon(FsmonitorAction::Stop).unwrap();
+        daemon.join().unwrap();
+
+        project.write_file("1.txt", "changed");
+
+        assert_eq!(vec!["1.txt"], modified(project));
+        assert_eq!(None, fsmonitor_token(project));
+        assert!(fsmonitor_valid(project).is_empty());
+    });
+}
+
+#[test]
+fn it_forgets_the_token_when_disabled() {
+    filled_project(|project| {
+        project.set_config("core.fsmonitor", "true");
+        let daemon = project.run_fsmonitor_daemon();
+
+        project.status().unwrap();
+        assert!(fsmonitor_token(project).is_some());
+
+        project.set_config("core.fsmonitor", "false");
+        project.status().unwrap();
+        assert_eq!(None, fsmonitor_token(project));
+
+        project.fsmonitor_daemon(FsmonitorAction::Stop).unwrap();
+        daemon.join().unwrap();
+    });
+}
+
+#[test]
+fn it_reports_whether_the_daemon_is_running() {
+    Project::open(|project| {
+        let running = |project: &Project| match project.fsmonitor_daemon(FsmonitorAction::Status) {
+            Ok(rit::Execution::Fsmonitor(res)) => res.running,
+            _ => unreachable!(),
+        };
+
+        assert!(!running(project));
+        assert!(matches!(
+            project.fsmonitor_daemon(FsmonitorAction::Stop),
+            Err(RitError::Fsmonitor(_))
+        ));
+
+        let daemon = project.run_fsmonitor_daemon();
+
+        assert!(running(project));
+        assert!(matches!(
+            project.fsmonitor_daemon(FsmonitorAction::Run),
+            Err(RitError::Fsmonitor(_))
+        ));
+
+        project.fsmonitor_daemon(FsmonitorAction::Stop).unwrap();
+        daemon.join().unwrap();
+
+        assert!(!running(project));
+        assert!(!project.dir().join(".git/fsmonitor--daemon.ipc").exists());
+    });
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use common::{filled_project, Project};
+use libflate::gzip;
+use rit::{Command, HttpBackendOptions};
+use std::{
+    io::prelude::*,
+    net::{TcpListener, TcpStream},
+    process::{self, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// Runs `rit http-backend` as a web server would run a CGI script, with
+/// `source` under the project root.
+fn cgi(source: &Project, vars: &[(&str, &str)], body: &[u8]) -> String {
+    let root = source.dir().parent().unwrap();
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_rit"))
+        .arg("http-backend")
+        .current_dir(root)
+        .env("GIT_PROJECT_ROOT", root)
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .envs(vars.iter().cloned())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Refused requests are answered without reading the body, so the backend
+    // may already have exited by the time it is written.
+    let _ = child.stdin.take().unwrap().write_all(body);
+    let output = child.wait_with_output().unwrap();
+
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn path(source: &Project, endpoint: &str) -> String {
+    let name = source.dir().file_name().unwrap().to_string_lossy();
+
+    format!("/{}/{}", name, endpoint)
+}
+
+fn pkt_line(line: &str) -> String {
+    format!("{:04x}{}\n", line.len() + 5, line)
+}
+
+fn upload_request(source: &Project) -> String {
+    let head = source.repo().refs.read_head().unwrap();
+
+    format!(
+        "{}0000{}",
+        pkt_line(&format!("want {}", head)),
+        pkt_line("done")
+    )
+}
+
+#[test]
+fn it_advertises_refs_for_a_service() {
+    filled_project(|source| {
+        source.write_file(".git/git-daemon-export-ok", "");
+        let head = source.repo().refs.read_head().unwrap();
+
+        let response = cgi(
+            source,
+            &[
+                ("REQUEST_METHOD", "GET"),
+                ("PATH_INFO", &path(source, "info/refs")),
+                ("QUERY_STRING", "service=git-upload-pack"),
+            ],
+            b"",
+        );
+
+        assert!(response.starts_with("Status: 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/x-git-upload-pack-advertisement\r\n"));
+        assert!(response.contains("Cache-Control: no-cache"));
+        assert!(response.contains("\r\n\r\n001e# service=git-upload-pack\n0000"));
+        assert!(response.contains(&format!("{} HEAD\0", head)));
+        assert!(response.contains(&format!("{} refs/heads/master\n", head)));
+    });
+}
+
+#[test]
+fn it_answers_upload_pack_requests_without_advertising() {
+    filled_project(|source| {
+        source.write_file(".git/git-daemon-export-ok", "");
+        let vars = [
+            ("REQUEST_METHOD", "POST"),
+            ("PATH_INFO", &path(source, "git-upload-pack")[..]),
+            ("CONTENT_TYPE", "application/x-git-upload-pack-request"),
+        ];
+
+        let response = cgi(source, &vars, upload_request(source).as_bytes());
+
+        assert!(response.contains("Content-Type: application/x-git-upload-pack-result\r\n"));
+        assert!(response.contains("\r\n\r\n0008NAK\nPACK"));
+
+        let mut body = vec![];
+        let mut encoder = gzip::Encoder::new(&mut body).unwrap();
+        encoder
+            .write_all(upload_request(source).as_bytes())
+            .unwrap();
+        encoder.finish().into_result().unwrap();
+
+        let mut vars = vars.to_vec();
+        vars.push(("HTTP_CONTENT_ENCODING", "gzip"));
+
+        let response = cgi(source, &vars, &body);
+
+        assert!(response.contains("\r\n\r\n0008NAK\nPACK"));
+    });
+}
+
+#[test]
+fn it_refuses_requests_it_does_not_serve() {
+    filled_project(|source| {
+        let advertise = |service| {
+            let query = format!("service={}", service);
+
+            cgi(
+                source,
+                &[
+                    ("REQUEST_METHOD", "GET"),
+                    ("PATH_INFO", &path(source, "info/refs")),
+                    ("QUERY_STRING", &query),
+                ],
+                b"",
+            )
+        };
+
+        assert!(advertise("git-upload-pack").starts_with("Status: 404 Not Found"));
+
+        source.write_file(".git/git-daemon-export-ok", "");
+
+        assert!(advertise("git-receive-pack").starts_with("Status: 403 Forbidden"));
+
+        source.set_config("http.receivepack", "true");
+
+        assert!(advertise("git-receive-pack").starts_with("Status: 200 OK"));
+
+        let response = cgi(
+            source,
+            &[
+                ("REQUEST_METHOD", "POST"),
+                ("PATH_INFO", &path(source, "git-upload-pack")),
+                ("CONTENT_TYPE", "text/plain"),
+            ],
+            upload_request(source).as_bytes(),
+        );
+
+        assert!(response.starts_with("Status: 415 Unsupported Media Type"));
+
+        let response = cgi(
+            source,
+            &[
+                ("REQUEST_METHOD", "GET"),
+                ("PATH_INFO", &path(source, "git-upload-pack")),
+            ],
+            b"",
+        );
+
+        assert!(response.starts_with("Status: 405 Method Not Allowed"));
+    });
+}
+
+#[test]
+fn it_listens_for_http_requests_with_chunked_bodies() {
+    filled_project(|source| {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let session = rit::Session {
+            author_name: String::new(),
+            author_email: String::new(),
+            project_dir: source.dir().clone(),
+        };
+        let options = HttpBackendOptions {
+            project_root: source.dir().parent().map(|dir| dir.to_path_buf()),
+            export_all: true,
+            listen: Some("127.0.0.1".to_string()),
+            port: Some(port),
+            ..Default::default()
+        };
+
+        thread::spawn(move || {
+            rit::HttpBackend::new(
+                session,
+                options,
+                Box::new(std::io::empty()),
+                Box::new(std::io::sink()),
+            )
+            .execute()
+        });
+
+        let mut stream = (0..100)
+            .find_map(|_| {
+                thread::sleep(Duration::from_millis(10));
+                TcpStream::connect(("127.0.0.1", port)).ok()
+            })
+            .unwrap();
+
+        let body = upload_request(source);
+        let (first, second) = body.split_at(10);
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\n\
+             Content-Type: application/x-git-upload-pack-request\r\n\
+             Transfer-Encoding: chunked\r\n\r\n\
+             {:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            path(source, "git-upload-pack"),
+            first.len(),
+            first,
+            second.len(),
+            second
+        )
+        .unwrap();
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        let response = String::from_utf8_lossy(&response);
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\nConnection: close\r\n"));
+        assert!(response.contains("\r\n\r\n0008NAK\nPACK"));
+    });
+}
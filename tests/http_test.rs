@@ -0,0 +1,289 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, CloneOptions, Execution, FetchOptions, PushOptions};
+use std::{
+    fs,
+    io::{prelude::*, BufReader},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    thread,
+};
+
+const AUTHORIZATION: &str = "Basic YWxpY2U6c2VjcmV0";
+
+/// A stand-in for a web server in front of `rit http-backend`. Paths under
+/// `/old/` are redirected to `/new/`, paths under `/private/` need alice's
+/// password, and everything else is handed to the backend as a CGI script.
+fn start_server(root: PathBuf) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let root = root.clone();
+            thread::spawn(move || serve(stream.unwrap(), &root));
+        }
+    });
+
+    port
+}
+
+fn serve(mut stream: TcpStream, root: &Path) {
+    let mut input = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    input.read_line(&mut request_line).unwrap();
+
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap().to_string();
+    let target = parts.next().unwrap().to_string();
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+    let mut headers = vec![];
+
+    loop {
+        let mut line = String::new();
+        input.read_line(&mut line).unwrap();
+
+        match line.trim_end().split_once(": ") {
+            Some((name, value)) => headers.push((name.to_lowercase(), value.to_string())),
+            None => break,
+        }
+    }
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let mut body = vec![0; header("content-length").map_or(0, |n| n.parse().unwrap())];
+    input.read_exact(&mut body).unwrap();
+
+    if let Some(rest) = path.strip_prefix("/old/") {
+        let location = format!("/new/{}?{}", rest, query);
+        write!(
+            stream,
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+            location
+        )
+        .unwrap();
+        return;
+    }
+
+    let path = if let Some(rest) = path.strip_prefix("/private/") {
+        if header("authorization").as_deref() != Some(AUTHORIZATION) {
+            write!(
+                stream,
+                "HTTP/1.1 401 Unauthorized\r\n\
+                 WWW-Authenticate: Basic realm=\"git\"\r\nContent-Length: 0\r\n\r\n"
+            )
+            .unwrap();
+            return;
+        }
+
+        format!("/{}", rest)
+    } else {
+        path.strip_prefix("/new").unwrap_or(path).to_string()
+    };
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_rit"))
+        .arg("http-backend")
+        .current_dir(root)
+        .env("GIT_PROJECT_ROOT", root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", method)
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("CONTENT_TYPE", header("content-type").unwrap_or_default())
+        .env(
+            "HTTP_CONTENT_ENCODING",
+            header("content-encoding").unwrap_or_default(),
+        )
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let _ = child.stdin.take().unwrap().write_all(&body);
+    let output = child.wait_with_output().unwrap().stdout;
+
+    let status = String::from_utf8_lossy(&output[..output.len().min(64)])
+        .strip_prefix("Status: ")
+        .and_then(|rest| rest.split("\r\n").next().map(String::from))
+        .unwrap();
+
+    write!(stream, "HTTP/1.1 {}\r\nConnection: close\r\n", status).unwrap();
+    stream
+        .write_all(&output[output.iter().position(|&b| b == b'\n').unwrap() + 1..])
+        .unwrap();
+}
+
+fn url(source: &Project, port: u16, prefix: &str) -> String {
+    let name = source.dir().file_name().unwrap().to_string_lossy();
+
+    format!("http://127.0.0.1:{}/{}{}", port, prefix, name)
+}
+
+fn clone_from_url(project: &Project, url: String) -> Result<Execution, RitError> {
+    let options = CloneOptions {
+        source: url,
+        directory: Some(".".to_string()),
+        ..Default::default()
+    };
+
+    project.clone_from(options)
+}
+
+fn commit_file(project: &Project, name: &str, content: &str) {
+    project.write_file(name, content);
+    project.add(vec!["."]).unwrap();
+    project.commit(content).unwrap();
+}
+
+#[test]
+fn it_clones_and_fetches_over_http() {
+    filled_project(|source| {
+        let port = start_server(source.dir().parent().unwrap().to_path_buf());
+
+        Project::open_clean(|project| {
+            clone_from_url(project, url(source, port, "")).unwrap();
+
+            assert_eq!("three", project.read_file("a/b/3.txt"));
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_head()
+            );
+
+            for n in 0..40 {
+                commit_file(project, "local.txt", &format!("local {}", n));
+            }
+            commit_file(source, "1.txt", "second");
+
+            match project.fetch(FetchOptions::default()).unwrap() {
+                Execution::Fetch(result) => {
+                    assert!(result.to_string().contains(" master -> origin/master"))
+                }
+                _ => panic!("unexpected execution"),
+            }
+
+            let head = source.repo().refs.read_head().unwrap();
+            let repo = project.repo();
+
+            assert_eq!(
+                Some(head.clone()),
+                repo.refs.read_ref("refs/remotes/origin/master")
+            );
+            assert!(repo.database.load_commit(&head).is_ok());
+        });
+    });
+}
+
+#[test]
+fn it_pushes_over_http() {
+    filled_project(|source| {
+        let port = start_server(source.dir().parent().unwrap().to_path_buf());
+
+        Project::open_clean(|project| {
+            clone_from_url(project, url(source, port, "")).unwrap();
+
+            project.branch("topic");
+            project.checkout("topic");
+            commit_file(project, "1.txt", "pushed");
+
+            let options = PushOptions {
+                branches: vec!["topic".to_string()],
+                ..Default::default()
+            };
+
+            assert!(matches!(
+                project.push(options),
+                Err(RitError::Remote(message)) if message.contains("error: 403")
+            ));
+            assert_eq!(None, source.repo().refs.read_ref("refs/heads/topic"));
+
+            source.set_config("http.receivepack", "true");
+
+            let options = PushOptions {
+                branches: vec!["topic".to_string()],
+                ..Default::default()
+            };
+
+            match project.push(options) {
+                Ok(Execution::Push(result)) => assert!(!result.is_rejected()),
+                _ => panic!("unexpected execution"),
+            }
+            assert_eq!(
+                project.repo().refs.read_head(),
+                source.repo().refs.read_ref("refs/heads/topic")
+            );
+        });
+    });
+}
+
+#[test]
+fn it_follows_redirects() {
+    filled_project(|source| {
+        let port = start_server(source.dir().parent().unwrap().to_path_buf());
+
+        Project::open_clean(|project| {
+            clone_from_url(project, url(source, port, "old/")).unwrap();
+
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_head()
+            );
+        });
+    });
+}
+
+#[test]
+fn it_authenticates_with_credentials() {
+    filled_project(|source| {
+        let port = start_server(source.dir().parent().unwrap().to_path_buf());
+
+        Project::open_clean(|project| {
+            let result = clone_from_url(project, url(source, port, "private/"));
+
+            assert!(matches!(
+                result,
+                Err(RitError::Remote(message)) if message.contains("Authentication failed")
+            ));
+
+            let url_with_password =
+                url(source, port, "private/").replacen("http://", "http://alice:secret@", 1);
+
+            clone_from_url(project, url_with_password).unwrap();
+
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_head()
+            );
+        });
+
+        Project::open_clean(|project| {
+            clone_from_url(project, url(source, port, "")).unwrap();
+
+            let log = project.dir().join("helper.log");
+            let helper = format!(
+                "!f() {{ echo \"$1\" >> '{}'; test \"$1\" = get && \
+                 echo username=alice && echo password=secret; }}; f",
+                log.display()
+            );
+            project.set_config("remote.origin.url", &url(source, port, "private/"));
+            project.set_config("credential.helper", &helper);
+
+            commit_file(source, "1.txt", "second");
+            project.fetch(FetchOptions::default()).unwrap();
+
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_ref("refs/remotes/origin/master")
+            );
+            assert_eq!("get\nstore\n", fs::read_to_string(log).unwrap());
+        });
+    });
+}
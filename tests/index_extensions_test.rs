@@ -0,0 +1,56 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::errors::RitError;
+use sha1::Sha1;
+use std::fs;
+
+/// Adds an extension to the index as another program would have written it.
+fn append_extension(project: &Project, signature: &[u8], data: &[u8]) {
+    let path = project.dir().join(".git/index");
+    let mut index = fs::read(&path).unwrap();
+
+    index.truncate(index.len() - 20);
+    index.extend(signature);
+    index.extend(&(data.len() as u32).to_be_bytes());
+    index.extend(data);
+
+    let checksum = Sha1::from(&index[..]).digest().bytes();
+    index.extend(&checksum);
+
+    fs::write(path, index).unwrap();
+}
+
+fn index_contains(project: &Project, bytes: &[u8]) -> bool {
+    let index = fs::read(project.dir().join(".git/index")).unwrap();
+
+    index.windows(bytes.len()).any(|window| window == bytes)
+}
+
+#[test]
+fn it_keeps_optional_extensions_it_does_not_understand() {
+    filled_project(|project| {
+        append_extension(project, b"ZZZZ", b"opaque");
+
+        project.write_file("1.txt", "changed");
+        project.add(vec!["1.txt"]).unwrap();
+
+        assert!(index_contains(project, b"ZZZZ\0\0\0\x06opaque"));
+        assert_eq!("M  1.txt", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_refuses_indexes_requiring_extensions_it_does_not_understand() {
+    filled_project(|project| {
+        project.git(&["update-index", "--split-index"]);
+        let index = fs::read(project.dir().join(".git/index")).unwrap();
+
+        assert!(matches!(
+            project.status(),
+            Err(RitError::Index(err))
+                if err.to_string() == "Index uses link extension, which we do not understand"
+        ));
+        assert_eq!(index, fs::read(project.dir().join(".git/index")).unwrap());
+    });
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use common::{filled_project, Project};
+use libflate::zlib::Encoder;
+use rit::{errors::RitError, IndexPackOptions, RepackOptions};
+use sha1::Sha1;
+use std::{fs, io::prelude::*, path::PathBuf};
+
+fn pack_path(project: &Project, extension: &str) -> PathBuf {
+    let dir = project.dir().join(".git/objects/pack");
+
+    fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().unwrap() == extension)
+        .unwrap()
+}
+
+/// Packs the whole history of `project` and returns the pack and its index.
+fn packed_history(project: &Project) -> (Vec<u8>, Vec<u8>) {
+    let options = RepackOptions {
+        all: true,
+        delete: true,
+    };
+    project.repack(options).unwrap();
+
+    (
+        fs::read(pack_path(project, "pack")).unwrap(),
+        fs::read(pack_path(project, "idx")).unwrap(),
+    )
+}
+
+fn stdin_options(fix_thin: bool) -> IndexPackOptions {
+    IndexPackOptions {
+        path: None,
+        fix_thin,
+    }
+}
+
+fn assert_same_history(source: &Project, target: &Project) {
+    let head = source.repo().refs.read_head().unwrap();
+    let source_repo = source.repo();
+    let target_repo = target.repo();
+
+    let files = source_repo.database.flatten_tree(Some(&head)).unwrap();
+    assert_eq!(
+        files,
+        target_repo.database.flatten_tree(Some(&head)).unwrap()
+    );
+
+    for entry in files.values() {
+        assert_eq!(
+            source_repo.database.load_blob(&entry.id).unwrap().data,
+            target_repo.database.load_blob(&entry.id).unwrap().data
+        );
+    }
+
+    let commit = target_repo.database.load_commit(&head).unwrap();
+    assert_eq!("message", commit.message.trim());
+}
+
+/// A pack holding a single `REF_DELTA` against `base`, which it leaves out.
+fn thin_pack(base: &[u8], delta: &[u8], size: usize) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", base.len()).as_bytes());
+    hasher.update(base);
+
+    let mut pack = b"PACK".to_vec();
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&1u32.to_be_bytes());
+    pack.push((7 << 4) | size as u8);
+    pack.extend_from_slice(&hasher.digest().bytes());
+
+    let mut encoder = Encoder::new(vec![]).unwrap();
+    encoder.write_all(delta).unwrap();
+    pack.extend(encoder.finish().into_result().unwrap());
+
+    seal(pack)
+}
+
+/// Appends the trailing checksum, so that only the entries are at fault.
+fn seal(mut pack: Vec<u8>) -> Vec<u8> {
+    let checksum = Sha1::from(&pack).digest().bytes();
+    pack.extend_from_slice(&checksum);
+
+    pack
+}
+
+fn rejection(project: &Project, pack: Vec<u8>) -> String {
+    match project.index_pack(pack, stdin_options(false)) {
+        Err(RitError::InvalidPack(message)) => message,
+        _ => panic!("expected the pack to be rejected"),
+    }
+}
+
+#[test]
+fn it_indexes_a_pack_read_from_stdin() {
+    filled_project(|source| {
+        let (pack, index) = packed_history(source);
+
+        Project::open(|target| {
+            target.index_pack(pack, stdin_options(false)).unwrap();
+
+            assert_eq!(index, fs::read(pack_path(target, "idx")).unwrap());
+            assert_same_history(source, target);
+        });
+    });
+}
+
+#[test]
+fn it_writes_the_index_next_to_a_pack_file() {
+    filled_project(|project| {
+        let (pack, index) = packed_history(project);
+        let path = pack_path(project, "idx");
+        fs::remove_file(&path).unwrap();
+
+        let options = IndexPackOptions {
+            path: Some(path.with_extension("pack")),
+            fix_thin: false,
+        };
+        project.index_pack(pack, options).unwrap();
+
+        assert_eq!(index, fs::read(path).unwrap());
+    });
+}
+
+#[test]
+fn it_unpacks_objects_into_loose_files() {
+    filled_project(|source| {
+        let (pack, _) = packed_history(source);
+
+        Project::open(|target| {
+            target.unpack_objects(pack).unwrap();
+
+            assert_eq!(7, target.repo().database.loose_ids().len());
+            assert!(!target.dir().join(".git/objects/pack").exists());
+            assert_same_history(source, target);
+        });
+    });
+}
+
+#[test]
+fn it_completes_thin_packs_with_local_bases() {
+    Project::open(|project| {
+        project.write_file("file.txt", "the quick brown fox");
+        project.add(vec!["."]).unwrap();
+
+        // copy "the quick ", insert "red", copy "fox"
+        let delta = [19, 16, 0x90, 10, 3, b'r', b'e', b'd', 0x91, 16, 3];
+        let pack = thin_pack(b"the quick brown fox", &delta, delta.len());
+
+        match project.index_pack(pack.clone(), stdin_options(false)) {
+            Err(RitError::InvalidPack(message)) => {
+                assert_eq!("pack has 1 unresolved delta", message)
+            }
+            _ => panic!("expected the thin pack to be rejected"),
+        }
+
+        project.index_pack(pack, stdin_options(true)).unwrap();
+
+        let pack_data = fs::read(pack_path(project, "pack")).unwrap();
+        assert_eq!(
+            2,
+            u32::from_be_bytes([pack_data[8], pack_data[9], pack_data[10], pack_data[11]])
+        );
+
+        let repo = project.repo();
+
+        for id in repo.database.loose_ids() {
+            repo.database.remove_loose(&id).unwrap();
+        }
+
+        let mut hasher = Sha1::new();
+        hasher.update(b"blob 16\0the quick redfox");
+        let id = rit::Id::from_hex(&hasher.digest().to_string()).unwrap();
+
+        let (kind, data) = project.repo().database.read_object(&id).unwrap();
+        assert_eq!("blob", kind);
+        assert_eq!(b"the quick redfox".to_vec(), data.to_vec());
+    });
+}
+
+#[test]
+fn it_rejects_truncated_packs() {
+    filled_project(|source| {
+        let (pack, _) = packed_history(source);
+        let body = &pack[..pack.len() - 20];
+
+        Project::open(|target| {
+            let half = body[..body.len() / 2].to_vec();
+
+            assert_eq!(
+                "pack is corrupted (SHA1 mismatch)",
+                rejection(target, half.clone())
+            );
+            rejection(target, seal(half));
+            rejection(target, seal(body[..13].to_vec()));
+        });
+    });
+}
+
+#[test]
+fn it_rejects_entry_sizes_that_overflow() {
+    Project::open(|project| {
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&1u32.to_be_bytes());
+        pack.push((3 << 4) | 0x80);
+        pack.extend_from_slice(&[0xff; 12]);
+        pack.push(0x01);
+
+        assert_eq!("pack entry size overflows", rejection(project, seal(pack)));
+    });
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::{filled_project, Project};
+use std::fs;
+
+fn index_version(project: &Project) -> u32 {
+    let data = fs::read(project.dir().join(".git/index")).unwrap();
+
+    u32::from_be_bytes([data[4], data[5], data[6], data[7]])
+}
+
+#[test]
+fn it_reads_and_writes_a_version_4_index_from_git() {
+    filled_project(|project| {
+        project.git(&["update-index", "--index-version", "4"]);
+        assert_eq!(4, index_version(project));
+
+        project.write_file("a/b/4.txt", "four");
+        project.add(vec!["a/b/4.txt"]).unwrap();
+
+        assert_eq!(4, index_version(project));
+        assert_eq!(
+            "1.txt\na/2.txt\na/b/3.txt\na/b/4.txt",
+            project.git(&["ls-files"])
+        );
+        assert_eq!("A  a/b/4.txt", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_keeps_the_extended_flags_of_a_version_3_index_from_git() {
+    filled_project(|project| {
+        project.write_file("new.txt", "new");
+        project.git(&["add", "-N", "new.txt"]);
+        project.git(&["update-index", "--skip-worktree", "1.txt"]);
+        assert_eq!(3, index_version(project));
+
+        project.write_file("a/2.txt", "changed");
+        project.add(vec!["a/2.txt"]).unwrap();
+
+        assert_eq!(3, index_version(project));
+        assert!(project.git(&["ls-files", "-v"]).starts_with("S 1.txt\n"));
+
+        let mut repo = project.repo();
+        repo.index.load().unwrap();
+
+        assert!(repo
+            .index
+            .entry_for_path("new.txt")
+            .unwrap()
+            .is_intent_to_add());
+        assert!(repo
+            .index
+            .entry_for_path("1.txt")
+            .unwrap()
+            .is_skip_worktree());
+
+        project.add(vec!["new.txt"]).unwrap();
+
+        let mut repo = project.repo();
+        repo.index.load().unwrap();
+
+        assert!(!repo
+            .index
+            .entry_for_path("new.txt")
+            .unwrap()
+            .is_intent_to_add());
+    });
+}
+
+#[test]
+fn it_writes_a_new_index_in_the_configured_version() {
+    Project::open(|project| {
+        project.set_config("index.version", "4");
+        project.write_file("a/1.txt", "one");
+        project.write_file("a/2.txt", "two");
+
+        project.add(vec!["."]).unwrap();
+        project.commit("message").unwrap();
+
+        assert_eq!(4, index_version(project));
+        assert_eq!("a/1.txt\na/2.txt", project.git(&["ls-files"]));
+        assert_eq!("", project.git(&["status", "--porcelain"]));
+    });
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use common::Project;
+use libflate::zlib::Encoder;
+use sha1::Sha1;
+use std::{fs, io::prelude::*};
+
+enum Entry<'a> {
+    Blob(&'a [u8]),
+    OfsDelta(usize, Vec<u8>),
+    RefDelta([u8; 20], Vec<u8>),
+}
+
+fn object_id(kind: &str, data: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", kind, data.len()).as_bytes());
+    hasher.update(data);
+
+    hasher.digest().bytes()
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::new(vec![]).unwrap();
+    encoder.write_all(data).unwrap();
+
+    encoder.finish().into_result().unwrap()
+}
+
+fn entry_header(kind: u8, mut size: usize) -> Vec<u8> {
+    let mut bytes = vec![(kind << 4) | (size & 0xf) as u8];
+    size >>= 4;
+
+    while size > 0 {
+        *bytes.last_mut().unwrap() |= 0x80;
+        bytes.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+
+    bytes
+}
+
+/// Writes a pack holding `entries` along with its version 2 index, given the
+/// ids of the objects each entry expands to.
+fn write_pack(project: &Project, entries: &[Entry], ids: &[[u8; 20]]) {
+    let mut pack = b"PACK".to_vec();
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    let mut offsets = vec![];
+
+    for entry in entries {
+        let offset = pack.len();
+        offsets.push(offset);
+
+        match entry {
+            Entry::Blob(data) => {
+                pack.extend(entry_header(3, data.len()));
+                pack.extend(compress(data));
+            }
+            Entry::OfsDelta(base, delta) => {
+                // a single byte is enough for the small distances used here
+                pack.extend(entry_header(6, delta.len()));
+                pack.push((offset - offsets[*base]) as u8);
+                pack.extend(compress(delta));
+            }
+            Entry::RefDelta(base_id, delta) => {
+                pack.extend(entry_header(7, delta.len()));
+                pack.extend_from_slice(base_id);
+                pack.extend(compress(delta));
+            }
+        }
+    }
+
+    let checksum = Sha1::from(&pack).digest().bytes();
+    pack.extend_from_slice(&checksum);
+
+    let mut sorted: Vec<([u8; 20], usize)> = ids.iter().cloned().zip(offsets).collect();
+    sorted.sort();
+
+    let mut index = vec![0xff, b't', b'O', b'c', 0, 0, 0, 2];
+
+    for byte in 0..=255u8 {
+        let count = sorted.iter().filter(|(id, _)| id[0] <= byte).count() as u32;
+        index.extend_from_slice(&count.to_be_bytes());
+    }
+
+    for (id, _) in &sorted {
+        index.extend_from_slice(id);
+    }
+
+    index.extend(vec![0; sorted.len() * 4]);
+
+    for (_, offset) in &sorted {
+        index.extend_from_slice(&(*offset as u32).to_be_bytes());
+    }
+
+    index.extend_from_slice(&checksum);
+    let index_checksum = Sha1::from(&index).digest().bytes();
+    index.extend_from_slice(&index_checksum);
+
+    let dir = project.dir().join(".git/objects/pack");
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("pack-test.pack"), pack).unwrap();
+    fs::write(dir.join("pack-test.idx"), index).unwrap();
+}
+
+fn id(bytes: &[u8; 20]) -> rit::Id {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    rit::Id::from_hex(&hex).unwrap()
+}
+
+fn read_blob(project: &Project, bytes: &[u8; 20]) -> Vec<u8> {
+    let (kind, data) = project.repo().database.read_object(&id(bytes)).unwrap();
+
+    assert_eq!("blob", kind);
+
+    data.to_vec()
+}
+
+#[test]
+fn it_reads_objects_and_resolves_delta_chains() {
+    Project::open(|project| {
+        let base = b"the quick brown fox";
+        let second = b"the quick redfox";
+        let third = b"the quick red fox jumps";
+
+        // copy 10 bytes, insert "red", copy "fox"
+        let ofs_delta = vec![19, 16, 0x90, 10, 3, b'r', b'e', b'd', 0x91, 16, 3];
+        // copy "the quick red", insert " fox jumps"
+        let mut ref_delta = vec![16, 23, 0x90, 13, 10];
+        ref_delta.extend_from_slice(b" fox jumps");
+
+        let ids = [
+            object_id("blob", base),
+            object_id("blob", second),
+            object_id("blob", third),
+        ];
+
+        let entries = [
+            Entry::Blob(base),
+            Entry::OfsDelta(0, ofs_delta),
+            Entry::RefDelta(ids[1], ref_delta),
+        ];
+
+        write_pack(project, &entries, &ids);
+
+        assert_eq!(base.to_vec(), read_blob(project, &ids[0]));
+        assert_eq!(second.to_vec(), read_blob(project, &ids[1]));
+        assert_eq!(third.to_vec(), read_blob(project, &ids[2]));
+    });
+}
+
+#[test]
+fn it_finds_packed_objects_next_to_loose_ones() {
+    Project::open(|project| {
+        let data = b"packed";
+        let packed_id = object_id("blob", data);
+
+        write_pack(project, &[Entry::Blob(data)], &[packed_id]);
+
+        project.write_file("file.txt", "loose");
+        project.add(vec!["."]).unwrap();
+
+        let repo = project.repo();
+        let loose_id = object_id("blob", b"loose");
+
+        assert!(repo.database.exists(&id(&packed_id)));
+        assert!(repo.database.exists(&id(&loose_id)));
+        assert_eq!(data.to_vec(), read_blob(project, &packed_id));
+        assert_eq!(b"loose".to_vec(), read_blob(project, &loose_id));
+
+        let prefix = &id(&packed_id).as_str[0..7];
+        assert_eq!(vec![id(&packed_id)], repo.database.prefix_match(prefix));
+    });
+}
+
+/// Has git commit several versions of a file and pack them into delta
+/// chains with `git repack -adf`, as deltas of one another by offset
+/// unless `ref_deltas` asks for them to name their bases by id.
+fn repack_with_git(project: &Project, ref_deltas: bool) {
+    let mut lines: Vec<String> = (0..200).map(|n| format!("line {}\n", n)).collect();
+
+    // git only takes the directory for a repository once HEAD exists
+    project.write_file("file.txt", &lines.concat());
+    project.add(vec!["file.txt"]).unwrap();
+    project.commit("first").unwrap();
+
+    // each version changes a line of its own, so that it is closest to the
+    // versions right before and after it
+    for version in 0..8 {
+        lines[version * 20] = format!("changed in version {}\n", version);
+        project.write_file("file.txt", &lines.concat());
+
+        project.git(&["add", "file.txt"]);
+        project.git(&[
+            "-c",
+            "user.name=name",
+            "-c",
+            "user.email=email",
+            "commit",
+            "-q",
+            "-m",
+            &format!("version {}", version),
+        ]);
+    }
+
+    let use_offsets = format!("repack.useDeltaBaseOffset={}", !ref_deltas);
+    project.git(&["-c", &use_offsets, "repack", "-adfq"]);
+
+    let index = fs::read_dir(project.dir().join(".git/objects/pack"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "idx"))
+        .unwrap();
+
+    let verified = project.git(&["verify-pack", "-v", &index.to_string_lossy()]);
+    assert!(verified.contains("chain length = 2"), "{}", verified);
+}
+
+fn assert_reads_every_object_git_packed(project: &Project) {
+    let repo = project.repo();
+    let listed = project.git(&[
+        "cat-file",
+        "--batch-all-objects",
+        "--batch-check=%(objectname) %(objecttype)",
+    ]);
+
+    assert!(repo.database.loose_ids().is_empty());
+
+    for line in listed.lines() {
+        let (hex, kind) = line.split_once(' ').unwrap();
+        let (read_kind, data) = repo
+            .database
+            .read_object(&rit::Id::from_hex(hex).unwrap())
+            .unwrap();
+
+        assert_eq!(kind, read_kind);
+        assert_eq!(hex, id(&object_id(kind, &data)).as_str);
+    }
+}
+
+#[test]
+fn it_reads_offset_delta_chains_packed_by_git() {
+    Project::open(|project| {
+        repack_with_git(project, false);
+
+        assert_reads_every_object_git_packed(project);
+    });
+}
+
+#[test]
+fn it_reads_ref_delta_chains_packed_by_git() {
+    Project::open(|project| {
+        repack_with_git(project, true);
+
+        assert_reads_every_object_git_packed(project);
+    });
+}
@@ -0,0 +1,274 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, RebaseAction, SequencerError};
+
+fn interactive(upstream: &str, autosquash: bool) -> RebaseAction {
+    RebaseAction::Start {
+        upstream: upstream.to_string(),
+        onto: None,
+        interactive: true,
+        autosquash,
+    }
+}
+
+fn assert_stopped(execution: rit::Execution) {
+    match execution {
+        rit::Execution::Rebase(res) => assert!(res.is_stopped(), "{}", res),
+        _ => panic!("Rebase execution expected"),
+    }
+}
+
+fn assert_finished(execution: rit::Execution) {
+    match execution {
+        rit::Execution::Rebase(res) => assert!(!res.is_stopped(), "{}", res),
+        _ => panic!("Rebase execution expected"),
+    }
+}
+
+/// The editors are configured per test repository, so editors set in the
+/// environment must not take precedence over them.
+fn clear_editor_env() {
+    for name in &["GIT_EDITOR", "GIT_SEQUENCE_EDITOR", "VISUAL", "EDITOR"] {
+        std::env::remove_var(name);
+    }
+}
+
+fn diverge(project: &Project) {
+    clear_editor_env();
+
+    project.branch("topic");
+
+    project.write_file("1.txt", "master change");
+    project.add(vec!["."]).unwrap();
+    project.commit("master").unwrap();
+
+    project.checkout("topic");
+
+    project.write_file("a/2.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic one").unwrap();
+
+    project.write_file("a/b/3.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic two").unwrap();
+}
+
+/// Rewrites the command of the todo line for the commit with `subject`.
+fn set_command(project: &Project, subject: &str, command: &str) {
+    let script = format!(
+        "sed -i -e 's/^pick \\([0-9a-f]*\\) {}$/{} \\1 {}/'",
+        subject, command, subject
+    );
+
+    project.set_config("sequence.editor", &script);
+}
+
+#[test]
+fn it_runs_an_unchanged_todo_list() {
+    filled_project(|project| {
+        diverge(project);
+        project.set_config("sequence.editor", "true");
+
+        assert_finished(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!(Some("refs/heads/topic".to_string()), project.head_name());
+    });
+}
+
+#[test]
+fn it_drops_commits() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic one", "drop");
+
+        assert_finished(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!("two", project.read_file("a/2.txt"));
+    });
+}
+
+#[test]
+fn it_rewords_commits() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic one", "reword");
+        project.set_config("core.editor", "sed -i -e 's/topic one/reworded/'");
+
+        assert_finished(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "reworded", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+    });
+}
+
+#[test]
+fn it_squashes_commits_and_combines_their_messages() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic two", "squash");
+        project.set_config("core.editor", "true");
+
+        assert_finished(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic one\n\ntopic two", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!("topic change", project.read_file("a/b/3.txt"));
+    });
+}
+
+#[test]
+fn it_fixes_up_commits_keeping_the_first_message() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic two", "fixup");
+
+        assert_finished(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!("topic change", project.read_file("a/2.txt"));
+        assert_eq!("topic change", project.read_file("a/b/3.txt"));
+    });
+}
+
+#[test]
+fn it_stops_to_edit_a_commit() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic one", "edit");
+
+        assert_stopped(project.rebase(interactive("master", false)).unwrap());
+
+        assert_eq!(
+            vec!["topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+
+        project.write_file("a/2.txt", "edited");
+        project.add(vec!["."]).unwrap();
+
+        assert_finished(project.rebase(RebaseAction::Continue).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+
+        let repo = project.repo();
+        let id = repo.refs.read_ref("HEAD").unwrap();
+        let parent = repo.database.load_commit(&id).unwrap().parents[0].clone();
+        let tree = repo.database.flatten_tree(Some(&parent)).unwrap();
+        let blob = repo.database.load_blob(&tree["a/2.txt"].id).unwrap();
+
+        assert_eq!(&b"edited"[..], &blob.data[..]);
+    });
+}
+
+#[test]
+fn it_runs_exec_steps_and_stops_when_they_fail() {
+    filled_project(|project| {
+        diverge(project);
+        project.set_config(
+            "sequence.editor",
+            "sed -i -e '1i exec touch marker' -e '2i exec false'",
+        );
+
+        assert_stopped(project.rebase(interactive("master", false)).unwrap());
+
+        assert!(project.exists("marker"));
+        assert_eq!(
+            vec!["topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+
+        assert_finished(project.rebase(RebaseAction::Continue).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+    });
+}
+
+#[test]
+fn it_aborts_when_the_todo_list_is_emptied() {
+    filled_project(|project| {
+        diverge(project);
+        project.set_config("sequence.editor", "sed -i -e '/^pick/d'");
+
+        match project.rebase(interactive("master", false)) {
+            Err(RitError::Sequencer(SequencerError::NothingToDo)) => {}
+            _ => panic!("Nothing to do expected"),
+        }
+
+        assert_eq!(
+            vec!["topic two", "topic one", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert!(!project.exists(".git/rebase-merge"));
+    });
+}
+
+#[test]
+fn it_rejects_a_squash_without_a_previous_commit() {
+    filled_project(|project| {
+        diverge(project);
+        set_command(project, "topic one", "squash");
+
+        match project.rebase(interactive("master", false)) {
+            Err(RitError::Sequencer(SequencerError::InvalidTodo(_))) => {}
+            _ => panic!("Invalid todo expected"),
+        }
+
+        assert!(!project.exists(".git/rebase-merge"));
+    });
+}
+
+#[test]
+fn it_creates_fixup_commits() {
+    filled_project(|project| {
+        diverge(project);
+
+        project.write_file("1.txt", "fixed");
+        project.add(vec!["."]).unwrap();
+        project.commit_fixup("HEAD~1").unwrap();
+
+        assert_eq!("fixup! topic one", project.commit_messages("HEAD")[0]);
+    });
+}
+
+#[test]
+fn it_moves_fixups_after_their_target_with_autosquash() {
+    filled_project(|project| {
+        diverge(project);
+
+        project.write_file("a/2.txt", "fixed");
+        project.add(vec!["."]).unwrap();
+        project.commit_fixup("HEAD~1").unwrap();
+
+        project.set_config("sequence.editor", "true");
+
+        assert_finished(project.rebase(interactive("master", true)).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!("fixed", project.read_file("a/2.txt"));
+    });
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, RebaseAction};
+
+fn start(upstream: &str) -> RebaseAction {
+    RebaseAction::Start {
+        upstream: upstream.to_string(),
+        onto: None,
+        interactive: false,
+        autosquash: false,
+    }
+}
+
+fn assert_stopped(execution: rit::Execution) {
+    match execution {
+        rit::Execution::Rebase(res) => assert!(res.is_stopped(), "{}", res),
+        _ => panic!("Rebase execution expected"),
+    }
+}
+
+fn assert_finished(execution: rit::Execution) {
+    match execution {
+        rit::Execution::Rebase(res) => assert!(!res.is_stopped(), "{}", res),
+        _ => panic!("Rebase execution expected"),
+    }
+}
+
+fn diverge(project: &Project) {
+    project.branch("topic");
+
+    project.write_file("1.txt", "master change");
+    project.add(vec!["."]).unwrap();
+    project.commit("master").unwrap();
+
+    project.checkout("topic");
+
+    project.write_file("a/2.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic one").unwrap();
+
+    project.write_file("a/b/3.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic two").unwrap();
+}
+
+fn conflict(project: &Project) {
+    project.branch("topic");
+
+    project.write_file("1.txt", "master change");
+    project.add(vec!["."]).unwrap();
+    project.commit("master").unwrap();
+
+    project.checkout("topic");
+
+    project.write_file("1.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic one").unwrap();
+
+    project.write_file("a/2.txt", "topic change");
+    project.add(vec!["."]).unwrap();
+    project.commit("topic two").unwrap();
+}
+
+#[test]
+fn it_replays_commits_on_top_of_upstream() {
+    filled_project(|project| {
+        diverge(project);
+
+        assert_finished(project.rebase(start("master")).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!(Some("refs/heads/topic".to_string()), project.head_name());
+        assert_eq!("master change", project.read_file("1.txt"));
+        assert_eq!("topic change", project.read_file("a/2.txt"));
+        assert_eq!("topic change", project.read_file("a/b/3.txt"));
+    });
+}
+
+#[test]
+fn it_keeps_the_upstream_branch_in_place() {
+    filled_project(|project| {
+        diverge(project);
+
+        project.rebase(start("master")).unwrap();
+
+        assert_eq!(vec!["master", "message"], project.commit_messages("master"));
+    });
+}
+
+#[test]
+fn it_does_nothing_when_up_to_date() {
+    filled_project(|project| {
+        project.branch("topic");
+        project.checkout("topic");
+
+        project.write_file("1.txt", "topic change");
+        project.add(vec!["."]).unwrap();
+        project.commit("topic").unwrap();
+
+        match project.rebase(start("master")).unwrap() {
+            rit::Execution::Rebase(res) => {
+                assert_eq!("Current branch topic is up to date.", format!("{}", res))
+            }
+            _ => panic!("Rebase execution expected"),
+        }
+    });
+}
+
+#[test]
+fn it_replays_onto_a_new_base() {
+    filled_project(|project| {
+        diverge(project);
+
+        let action = RebaseAction::Start {
+            upstream: "topic~1".to_string(),
+            onto: Some("master".to_string()),
+            interactive: false,
+            autosquash: false,
+        };
+
+        assert_finished(project.rebase(action).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "master", "message"],
+            project.commit_messages("topic")
+        );
+        assert_eq!("master change", project.read_file("1.txt"));
+        assert_eq!("two", project.read_file("a/2.txt"));
+        assert_eq!("topic change", project.read_file("a/b/3.txt"));
+    });
+}
+
+#[test]
+fn it_stops_on_conflicts() {
+    filled_project(|project| {
+        conflict(project);
+
+        assert_stopped(project.rebase(start("master")).unwrap());
+
+        assert!(project
+            .read_file("1.txt")
+            .starts_with("<<<<<<< HEAD\nmaster change\n=======\ntopic change\n>>>>>>> "));
+        assert!(project.repo().refs.current_ref().is_none());
+        assert!(project.exists(".git/rebase-merge/git-rebase-todo"));
+    });
+}
+
+#[test]
+fn it_continues_after_conflicts_are_resolved() {
+    filled_project(|project| {
+        conflict(project);
+        project.rebase(start("master")).unwrap();
+
+        match project.rebase(RebaseAction::Continue) {
+            Err(RitError::Sequencer(_)) => {}
+            _ => panic!("Unresolved conflicts should be rejected"),
+        }
+
+        project.write_file("1.txt", "resolved");
+        project.add(vec!["1.txt"]).unwrap();
+
+        assert_finished(project.rebase(RebaseAction::Continue).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "master", "message"],
+            project.commit_messages("topic")
+        );
+        assert_eq!("resolved", project.read_file("1.txt"));
+        assert_eq!("topic change", project.read_file("a/2.txt"));
+        assert!(!project.exists(".git/rebase-merge"));
+    });
+}
+
+#[test]
+fn it_skips_the_conflicting_commit() {
+    filled_project(|project| {
+        conflict(project);
+        project.rebase(start("master")).unwrap();
+
+        assert_finished(project.rebase(RebaseAction::Skip).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "master", "message"],
+            project.commit_messages("topic")
+        );
+        assert_eq!("master change", project.read_file("1.txt"));
+    });
+}
+
+#[test]
+fn it_aborts_and_restores_the_original_branch() {
+    filled_project(|project| {
+        conflict(project);
+        project.rebase(start("master")).unwrap();
+
+        assert_finished(project.rebase(RebaseAction::Abort).unwrap());
+
+        assert_eq!(
+            vec!["topic two", "topic one", "message"],
+            project.commit_messages("HEAD")
+        );
+        assert_eq!(Some("refs/heads/topic".to_string()), project.head_name());
+        assert_eq!("topic change", project.read_file("1.txt"));
+        assert!(!project.exists(".git/rebase-merge"));
+    });
+}
+
+#[test]
+fn it_refuses_to_start_with_unstaged_changes() {
+    filled_project(|project| {
+        diverge(project);
+        project.write_file("1.txt", "uncommitted");
+
+        match project.rebase(start("master")) {
+            Err(RitError::Sequencer(_)) => {}
+            _ => panic!("Unstaged changes should be rejected"),
+        }
+    });
+}
+
+#[test]
+fn it_fails_without_a_rebase_in_progress() {
+    filled_project(|project| match project.rebase(RebaseAction::Continue) {
+        Err(RitError::Sequencer(_)) => {}
+        _ => panic!("Missing rebase should be rejected"),
+    });
+}
+
+#[test]
+fn it_releases_the_index_lock_when_starting_fails() {
+    filled_project(|project| {
+        diverge(project);
+        project.write_file(".git/ORIG_HEAD.lock", "");
+
+        assert!(project.rebase(start("master")).is_err());
+        assert!(!project.exists(".git/index.lock"));
+    });
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, RebaseAction, ReflogAction, TagAction};
+
+fn show(project: &Project, name: &str) -> Vec<String> {
+    let action = ReflogAction::Show {
+        name: Some(name.to_string()),
+    };
+
+    match project.reflog(action).unwrap() {
+        rit::Execution::Reflog(res) => res
+            .to_string()
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1.to_string())
+            .collect(),
+        _ => panic!("Reflog execution expected"),
+    }
+}
+
+fn resolve(project: &Project, rev: &str) -> Result<String, RitError> {
+    let action = TagAction::Create {
+        name: "resolved".to_string(),
+        target: Some(rev.to_string()),
+        message: None,
+        annotate: false,
+        force: true,
+    };
+
+    project.tag(action)?;
+
+    Ok(project
+        .repo()
+        .refs
+        .read_ref("refs/tags/resolved")
+        .unwrap()
+        .as_str)
+}
+
+fn commit_change(project: &Project, content: &str, message: &str) -> String {
+    project.write_file("1.txt", content);
+    project.add(vec!["."]).unwrap();
+    project.commit(message).unwrap();
+
+    project.repo().refs.read_head().unwrap().as_str
+}
+
+#[test]
+fn it_logs_commits_to_head_and_the_branch() {
+    filled_project(|project| {
+        commit_change(project, "changed", "second");
+
+        let expected = vec![
+            "HEAD@{0}: commit: second",
+            "HEAD@{1}: commit (initial): message",
+        ];
+        assert_eq!(expected, show(project, "HEAD"));
+
+        let expected = vec![
+            "master@{0}: commit: second",
+            "master@{1}: commit (initial): message",
+        ];
+        assert_eq!(expected, show(project, "master"));
+    });
+}
+
+#[test]
+fn it_resolves_previous_values() {
+    filled_project(|project| {
+        let first = project.repo().refs.read_head().unwrap().as_str;
+        let second = commit_change(project, "changed", "second");
+        commit_change(project, "changed again", "third");
+
+        assert_eq!(second, resolve(project, "master@{1}").unwrap());
+        assert_eq!(first, resolve(project, "HEAD@{2}").unwrap());
+        assert_eq!(first, resolve(project, "@{1}^").unwrap());
+        assert_eq!(first, resolve(project, "master@{2000-01-01}").unwrap());
+
+        match resolve(project, "master@{3}") {
+            Err(RitError::Revision(err)) => {
+                assert_eq!("log for 'master' only has 3 entries", err.to_string())
+            }
+            _ => panic!("Expected the log to be too short"),
+        }
+    });
+}
+
+#[test]
+fn it_logs_rebases() {
+    filled_project(|project| {
+        project.branch("topic");
+        commit_change(project, "master change", "master");
+        project.checkout("topic");
+
+        project.write_file("a/2.txt", "topic change");
+        project.add(vec!["."]).unwrap();
+        project.commit("topic").unwrap();
+
+        let action = RebaseAction::Start {
+            upstream: "master".to_string(),
+            onto: None,
+            interactive: false,
+            autosquash: false,
+        };
+        project.rebase(action).unwrap();
+
+        let head = show(project, "HEAD");
+
+        assert_eq!(
+            "HEAD@{0}: rebase (finish): returning to refs/heads/topic",
+            head[0]
+        );
+        assert_eq!("HEAD@{1}: rebase (pick): topic", head[1]);
+        assert_eq!("HEAD@{2}: rebase (start): checkout master", head[2]);
+        assert!(show(project, "topic")[0]
+            .starts_with("topic@{0}: rebase (finish): refs/heads/topic onto "));
+    });
+}
+
+#[test]
+fn it_deletes_entries() {
+    filled_project(|project| {
+        commit_change(project, "changed", "second");
+        commit_change(project, "changed again", "third");
+
+        let action = ReflogAction::Delete {
+            entries: vec!["master@{1}".to_string()],
+        };
+        project.reflog(action).unwrap();
+
+        let expected = vec![
+            "master@{0}: commit: third",
+            "master@{1}: commit (initial): message",
+        ];
+        assert_eq!(expected, show(project, "master"));
+    });
+}
+
+#[test]
+fn it_expires_old_entries() {
+    filled_project(|project| {
+        commit_change(project, "changed", "second");
+
+        let action = ReflogAction::Expire {
+            names: vec![],
+            all: true,
+            expire: Some("1.day.ago".to_string()),
+        };
+        project.reflog(action).unwrap();
+
+        assert_eq!(2, show(project, "HEAD").len());
+
+        let action = ReflogAction::Expire {
+            names: vec!["master".to_string()],
+            all: false,
+            expire: Some("now".to_string()),
+        };
+        project.reflog(action).unwrap();
+
+        assert!(show(project, "master").is_empty());
+        assert_eq!(2, show(project, "HEAD").len());
+    });
+}
@@ -0,0 +1,258 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, CloneOptions, Execution, FetchOptions, RemoteAction};
+
+fn program(service: &str) -> Option<String> {
+    Some(format!("'{}' {}", env!("CARGO_BIN_EXE_rit"), service))
+}
+
+fn url(source: &Project) -> String {
+    format!("file://{}", source.dir().to_string_lossy())
+}
+
+fn clone_into(project: &Project, source: &Project) {
+    let options = CloneOptions {
+        source: url(source),
+        directory: Some(".".to_string()),
+        upload_pack: program("upload-pack"),
+        ..Default::default()
+    };
+
+    project.clone_from(options).unwrap();
+}
+
+fn fetch(project: &Project, remote: &str, refspecs: &[&str], prune: bool) -> (String, bool) {
+    let options = FetchOptions {
+        remote: Some(remote.to_string()),
+        refspecs: refspecs.iter().map(|refspec| refspec.to_string()).collect(),
+        prune,
+        upload_pack: program("upload-pack"),
+    };
+
+    match project.fetch(options).unwrap() {
+        Execution::Fetch(result) => (result.to_string(), result.is_rejected()),
+        _ => panic!("unexpected execution"),
+    }
+}
+
+fn list(project: &Project, verbose: bool) -> String {
+    match project.remote(RemoteAction::List { verbose }).unwrap() {
+        Execution::Remote(result) => result.to_string(),
+        _ => panic!("unexpected execution"),
+    }
+}
+
+fn commit_file(project: &Project, name: &str, content: &str) {
+    project.write_file(name, content);
+    project.add(vec!["."]).unwrap();
+    project.commit(content).unwrap();
+}
+
+#[test]
+fn it_adds_renames_and_removes_remotes() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            project
+                .remote(RemoteAction::Add {
+                    name: "upstream".to_string(),
+                    url: url(source),
+                })
+                .unwrap();
+
+            assert_eq!("origin\nupstream", list(project, false));
+            assert!(list(project, true).contains(&format!("upstream\t{} (push)", url(source))));
+            assert!(matches!(
+                project.remote(RemoteAction::Add {
+                    name: "upstream".to_string(),
+                    url: url(source),
+                }),
+                Err(RitError::Remote(message)) if message.contains("already exists")
+            ));
+
+            fetch(project, "upstream", &[], false);
+
+            let head = source.repo().refs.read_head();
+            assert_eq!(
+                head,
+                project.repo().refs.read_ref("refs/remotes/upstream/master")
+            );
+
+            project
+                .remote(RemoteAction::Rename {
+                    old_name: "origin".to_string(),
+                    new_name: "primary".to_string(),
+                })
+                .unwrap();
+
+            let repo = project.repo();
+            assert_eq!(None, repo.refs.read_ref("refs/remotes/origin/master"));
+            assert_eq!(head, repo.refs.read_ref("refs/remotes/primary/master"));
+            assert_eq!(
+                Some("refs/remotes/primary/master".to_string()),
+                repo.refs.read_symref("refs/remotes/primary/HEAD")
+            );
+            assert_eq!(
+                Some("+refs/heads/*:refs/remotes/primary/*".to_string()),
+                repo.config.get("remote.primary.fetch")
+            );
+            assert_eq!(
+                Some("primary".to_string()),
+                repo.config.get("branch.master.remote")
+            );
+
+            project
+                .remote(RemoteAction::Remove {
+                    name: "primary".to_string(),
+                })
+                .unwrap();
+
+            let repo = project.repo();
+            assert_eq!("upstream", list(project, false));
+            assert!(repo.refs.list_refs("refs/remotes/primary").is_empty());
+            assert_eq!(None, repo.config.get("branch.master.remote"));
+            assert!(matches!(
+                project.remote(RemoteAction::Remove {
+                    name: "primary".to_string(),
+                }),
+                Err(RitError::Remote(message)) if message == "No such remote: 'primary'"
+            ));
+        });
+    });
+}
+
+#[test]
+fn it_prunes_branches_deleted_on_the_remote() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            source.repo().refs.delete_ref("refs/heads/topic").unwrap();
+
+            fetch(project, "origin", &[], false);
+            assert!(project
+                .repo()
+                .refs
+                .read_ref("refs/remotes/origin/topic")
+                .is_some());
+
+            let (output, _) = fetch(project, "origin", &[], true);
+
+            assert!(output.contains(" - [deleted]"));
+            assert!(output.contains("-> origin/topic"));
+
+            let repo = project.repo();
+            assert_eq!(None, repo.refs.read_ref("refs/remotes/origin/topic"));
+            assert!(repo.refs.read_ref("refs/remotes/origin/HEAD").is_some());
+        });
+    });
+}
+
+#[test]
+fn it_writes_fetch_head() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            clone_into(project, source);
+            commit_file(source, "1.txt", "second");
+
+            fetch(project, "origin", &[], false);
+
+            let head = source.repo().refs.read_head().unwrap();
+            let url = url(source);
+            let fetch_head = project.read_file(".git/FETCH_HEAD");
+            let mut lines = fetch_head.lines();
+
+            assert_eq!(
+                Some(&format!("{}\t\tbranch 'master' of {}", head, url)[..]),
+                lines.next()
+            );
+            assert!(lines
+                .next()
+                .unwrap()
+                .ends_with(&format!("\tnot-for-merge\tbranch 'topic' of {}", url)));
+
+            fetch(project, "origin", &["topic"], false);
+
+            let fetch_head = project.read_file(".git/FETCH_HEAD");
+            assert!(fetch_head.ends_with(&format!("\t\tbranch 'topic' of {}\n", url)));
+        });
+    });
+}
+
+#[test]
+fn it_fetches_with_refspecs_from_the_command_line() {
+    filled_project(|source| {
+        source.branch("topic");
+
+        Project::open_clean(|project| {
+            clone_into(project, source);
+
+            fetch(
+                project,
+                "origin",
+                &["+refs/heads/*:refs/remotes/mirror/*", "^refs/heads/topic"],
+                false,
+            );
+
+            let repo = project.repo();
+            assert!(repo.refs.read_ref("refs/remotes/mirror/master").is_some());
+            assert_eq!(None, repo.refs.read_ref("refs/remotes/mirror/topic"));
+
+            project.branch("copy");
+            project.checkout("copy");
+            commit_file(project, "1.txt", "local");
+            project.checkout("master");
+
+            let (output, rejected) = fetch(project, "origin", &["master:copy"], false);
+
+            assert!(rejected);
+            assert!(output.contains("! [rejected]"));
+            assert!(output.contains("(non-fast-forward)"));
+
+            let (_, rejected) = fetch(project, "origin", &["+master:copy"], false);
+
+            assert!(!rejected);
+            assert_eq!(
+                source.repo().refs.read_head(),
+                project.repo().refs.read_ref("refs/heads/copy")
+            );
+
+            let options = FetchOptions {
+                remote: Some("origin".to_string()),
+                refspecs: vec!["master:master".to_string()],
+                upload_pack: program("upload-pack"),
+                ..Default::default()
+            };
+
+            assert!(matches!(
+                project.fetch(options),
+                Err(RitError::Remote(message)) if message.contains("refusing to fetch")
+            ));
+        });
+    });
+}
+
+#[test]
+fn it_resolves_remote_tracking_branches_by_name() {
+    filled_project(|source| {
+        Project::open_clean(|project| {
+            clone_into(project, source);
+            commit_file(source, "1.txt", "second");
+
+            fetch(project, "origin", &[], false);
+
+            let head = source.repo().refs.read_head();
+            let refs = project.repo().refs;
+
+            assert_eq!(head, refs.read_ref("origin/master"));
+            assert_eq!(head, refs.read_ref("origin"));
+            assert_ne!(head, refs.read_ref("master"));
+        });
+    });
+}
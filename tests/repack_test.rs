@@ -0,0 +1,143 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{RepackOptions, TagAction};
+use std::fs;
+
+fn repack(project: &Project, all: bool, delete: bool) -> String {
+    match project.repack(RepackOptions { all, delete }).unwrap() {
+        rit::Execution::Repack(res) => res.to_string(),
+        _ => panic!("Repack execution expected"),
+    }
+}
+
+/// Reads the object and delta counts out of "Total <n> (delta <n>)"; how many
+/// commits turn into deltas depends on their timestamps.
+fn counts(output: &str) -> (usize, usize) {
+    let numbers: Vec<usize> = output
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|part| part.parse().ok())
+        .collect();
+
+    (numbers[0], numbers[1])
+}
+
+fn loose_objects(project: &Project) -> usize {
+    project.repo().database.loose_ids().len()
+}
+
+fn pack_files(project: &Project) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(project.dir().join(".git/objects/pack"))
+        .map(|dir| {
+            dir.map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    names.sort();
+
+    names
+}
+
+fn numbered_lines(count: usize) -> String {
+    (0..count).map(|n| format!("line {}\n", n)).collect()
+}
+
+#[test]
+fn it_packs_all_objects_and_removes_the_loose_ones() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.add(vec!["."]).unwrap();
+        project.commit("second").unwrap();
+
+        let action = TagAction::Create {
+            name: "v1".to_string(),
+            target: None,
+            message: Some("release".to_string()),
+            annotate: true,
+            force: false,
+        };
+        project.tag(action).unwrap();
+
+        assert_eq!(11, loose_objects(project));
+        assert_eq!(11, counts(&repack(project, true, true)).0);
+
+        assert_eq!(0, loose_objects(project));
+        assert_eq!(2, pack_files(project).len());
+        assert_eq!(vec!["second", "message"], project.commit_messages("HEAD"));
+        assert_eq!("three", project.read_file("a/b/3.txt"));
+
+        let repo = project.repo();
+        let head = repo.refs.read_head().unwrap();
+        let tag_id = repo.refs.read_ref("refs/tags/v1").unwrap();
+        let tree = repo.database.flatten_tree(Some(&head)).unwrap();
+
+        assert_eq!(head, repo.database.load_tag(&tag_id).unwrap().object);
+
+        assert_eq!(
+            b"three".to_vec(),
+            repo.database.load_blob(&tree["a/b/3.txt"].id).unwrap().data
+        );
+    });
+}
+
+#[test]
+fn it_stores_similar_objects_as_deltas() {
+    Project::open(|project| {
+        for count in &[200, 210, 220] {
+            project.write_file("file.txt", &numbered_lines(*count));
+            project.add(vec!["."]).unwrap();
+            project.commit(&format!("{} lines", count)).unwrap();
+        }
+
+        let (total, deltas) = counts(&repack(project, true, true));
+
+        assert_eq!(9, total);
+        assert!(deltas >= 2);
+
+        let repo = project.repo();
+        let head = repo.refs.read_head().unwrap();
+        let parent = repo.database.load_commit(&head).unwrap().parents[0].clone();
+
+        for (id, count) in &[(head, 220), (parent, 210)] {
+            let tree = repo.database.flatten_tree(Some(id)).unwrap();
+            let blob = repo.database.load_blob(&tree["file.txt"].id).unwrap();
+
+            assert_eq!(numbered_lines(*count).into_bytes(), blob.data.to_vec());
+        }
+
+        let pack_size: u64 = pack_files(project)
+            .iter()
+            .filter(|name| name.ends_with(".pack"))
+            .map(|name| {
+                let path = project.dir().join(".git/objects/pack").join(name);
+
+                fs::metadata(path).unwrap().len()
+            })
+            .sum();
+
+        assert!(pack_size < numbered_lines(220).len() as u64 * 2);
+    });
+}
+
+#[test]
+fn it_only_packs_loose_objects_without_all() {
+    filled_project(|project| {
+        assert_eq!("Total 7 (delta 0)", repack(project, false, true));
+
+        project.write_file("1.txt", "changed");
+        project.add(vec!["."]).unwrap();
+        project.commit("second").unwrap();
+
+        assert_eq!("Total 3 (delta 0)", repack(project, false, false));
+        assert_eq!(3, loose_objects(project));
+        assert_eq!(4, pack_files(project).len());
+
+        assert_eq!("Nothing new to pack.", repack(project, false, true));
+        assert_eq!(0, loose_objects(project));
+
+        assert_eq!(10, counts(&repack(project, true, true)).0);
+        assert_eq!(2, pack_files(project).len());
+        assert_eq!(vec!["second", "message"], project.commit_messages("HEAD"));
+    });
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, StashAction, StashError};
+
+fn push(include_untracked: bool, pathspecs: Vec<&str>) -> StashAction {
+    StashAction::Push {
+        message: None,
+        include_untracked,
+        pathspecs: pathspecs.iter().map(|path| path.to_string()).collect(),
+    }
+}
+
+fn apply(restore_index: bool) -> StashAction {
+    StashAction::Apply {
+        stash: None,
+        restore_index,
+    }
+}
+
+fn pop() -> StashAction {
+    StashAction::Pop {
+        stash: None,
+        restore_index: false,
+    }
+}
+
+fn output(execution: rit::Execution) -> String {
+    match execution {
+        rit::Execution::Stash(res) => res.to_string(),
+        _ => panic!("Stash execution expected"),
+    }
+}
+
+fn staged_paths(project: &Project) -> Vec<String> {
+    let mut repo = project.repo();
+    let head = repo.refs.read_head().unwrap();
+    let tree = repo.database.flatten_tree(Some(&head)).unwrap();
+
+    repo.index.load().unwrap();
+
+    repo.index
+        .entries()
+        .into_iter()
+        .filter(|entry| tree.get(&entry.pathname).map(|item| &item.id) != Some(&entry.id))
+        .map(|entry| entry.pathname)
+        .collect()
+}
+
+#[test]
+fn it_saves_and_restores_workspace_changes() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+
+        let saved = output(project.stash(push(false, vec![])).unwrap());
+
+        assert!(saved.starts_with("Saved working directory and index state WIP on master:"));
+        assert_eq!("one", project.read_file("1.txt"));
+
+        output(project.stash(pop()).unwrap());
+
+        assert_eq!("changed", project.read_file("1.txt"));
+        assert!(staged_paths(project).is_empty());
+        assert_eq!("", output(project.stash(StashAction::List).unwrap()));
+    });
+}
+
+#[test]
+fn it_does_nothing_without_local_changes() {
+    filled_project(|project| {
+        assert_eq!(
+            "No local changes to save",
+            output(project.stash(push(false, vec![])).unwrap())
+        );
+    });
+}
+
+#[test]
+fn it_lists_entries_newest_first() {
+    filled_project(|project| {
+        project.write_file("1.txt", "first");
+        project.stash(push(false, vec![])).unwrap();
+
+        project.write_file("1.txt", "second");
+        project
+            .stash(StashAction::Push {
+                message: Some("named".to_string()),
+                include_untracked: false,
+                pathspecs: vec![],
+            })
+            .unwrap();
+
+        let list = output(project.stash(StashAction::List).unwrap());
+        let lines: Vec<&str> = list.lines().collect();
+
+        assert_eq!(2, lines.len());
+        assert_eq!("stash@{0}: On master: named", lines[0]);
+        assert!(lines[1].starts_with("stash@{1}: WIP on master:"));
+    });
+}
+
+#[test]
+fn it_stashes_untracked_files() {
+    filled_project(|project| {
+        project.write_file("new/file.txt", "new");
+
+        project.stash(push(true, vec![])).unwrap();
+
+        assert!(!project.exists("new/file.txt"));
+
+        project.stash(pop()).unwrap();
+
+        assert_eq!("new", project.read_file("new/file.txt"));
+        assert!(project
+            .index_entries()
+            .iter()
+            .all(|(path, _)| path != "new/file.txt"));
+    });
+}
+
+#[test]
+fn it_only_stashes_matching_paths() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+        project.write_file("a/2.txt", "changed");
+
+        project.stash(push(false, vec!["a"])).unwrap();
+
+        assert_eq!("changed", project.read_file("1.txt"));
+        assert_eq!("two", project.read_file("a/2.txt"));
+
+        project.stash(apply(false)).unwrap();
+
+        assert_eq!("changed", project.read_file("a/2.txt"));
+    });
+}
+
+#[test]
+fn it_rejects_unknown_pathspecs() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed");
+
+        match project.stash(push(false, vec!["missing"])) {
+            Err(RitError::MissingFile(path)) => assert_eq!("missing", path),
+            _ => panic!("Missing file expected"),
+        }
+    });
+}
+
+#[test]
+fn it_restores_the_index_with_index_option() {
+    filled_project(|project| {
+        project.write_file("1.txt", "staged");
+        project.add(vec!["1.txt"]).unwrap();
+        project.write_file("a/2.txt", "unstaged");
+
+        project.stash(push(false, vec![])).unwrap();
+        project.stash(apply(true)).unwrap();
+
+        assert_eq!(vec!["1.txt".to_string()], staged_paths(project));
+        assert_eq!("unstaged", project.read_file("a/2.txt"));
+    });
+}
+
+#[test]
+fn it_shows_the_stashed_changes() {
+    filled_project(|project| {
+        project.write_file("1.txt", "changed\n");
+        project.stash(push(false, vec![])).unwrap();
+
+        let stat = output(
+            project
+                .stash(StashAction::Show {
+                    stash: None,
+                    patch: false,
+                })
+                .unwrap(),
+        );
+
+        assert_eq!(
+            " 1.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)",
+            stat
+        );
+
+        let patch = output(
+            project
+                .stash(StashAction::Show {
+                    stash: Some("stash@{0}".to_string()),
+                    patch: true,
+                })
+                .unwrap(),
+        );
+
+        assert!(patch.starts_with("diff --git a/1.txt b/1.txt\n"));
+        assert!(patch.ends_with(
+            "--- a/1.txt\n+++ b/1.txt\n@@ -1 +1 @@\n-one\n\\ No newline at end of file\n+changed"
+        ));
+    });
+}
+
+#[test]
+fn it_keeps_the_entry_when_pop_conflicts() {
+    filled_project(|project| {
+        project.write_file("1.txt", "stashed");
+        project.stash(push(false, vec![])).unwrap();
+
+        project.write_file("1.txt", "committed");
+        project.add(vec!["."]).unwrap();
+        project.commit("change").unwrap();
+
+        match project.stash(pop()).unwrap() {
+            rit::Execution::Stash(res) => assert!(res.is_conflicted()),
+            _ => panic!("Stash execution expected"),
+        }
+
+        let list = output(project.stash(StashAction::List).unwrap());
+
+        assert_eq!(1, list.lines().count());
+    });
+}
+
+#[test]
+fn it_refuses_to_overwrite_local_changes() {
+    filled_project(|project| {
+        project.write_file("1.txt", "stashed");
+        project.stash(push(false, vec![])).unwrap();
+
+        project.write_file("1.txt", "local");
+
+        match project.stash(apply(false)) {
+            Err(RitError::Stash(StashError::LocalChanges(paths))) => {
+                assert_eq!(vec!["1.txt".to_string()], paths)
+            }
+            _ => panic!("Local changes error expected"),
+        }
+
+        assert_eq!("local", project.read_file("1.txt"));
+    });
+}
+
+#[test]
+fn it_drops_and_clears_entries() {
+    filled_project(|project| {
+        project.write_file("1.txt", "first");
+        project.stash(push(false, vec![])).unwrap();
+        project.write_file("1.txt", "second");
+        project.stash(push(false, vec![])).unwrap();
+
+        let dropped = output(
+            project
+                .stash(StashAction::Drop {
+                    stash: Some("1".to_string()),
+                })
+                .unwrap(),
+        );
+
+        assert!(dropped.starts_with("Dropped refs/stash@{1} ("));
+        assert_eq!(
+            1,
+            output(project.stash(StashAction::List).unwrap())
+                .lines()
+                .count()
+        );
+
+        project.stash(StashAction::Clear).unwrap();
+
+        assert_eq!("", output(project.stash(StashAction::List).unwrap()));
+        assert!(!project.exists(".git/refs/stash"));
+
+        match project.stash(pop()) {
+            Err(RitError::Stash(StashError::NoEntries)) => {}
+            _ => panic!("No entries error expected"),
+        }
+    });
+}
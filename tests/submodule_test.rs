@@ -0,0 +1,177 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, CloneOptions, SubmoduleAction};
+use std::fs;
+
+fn status(project: &Project) -> (Vec<String>, Vec<String>) {
+    match project.status().unwrap() {
+        rit::Execution::Status(res) => (
+            res.untracked.iter().map(|e| e.to_string()).collect(),
+            res.modified.iter().map(|e| e.to_string()).collect(),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+fn submodule_status(project: &Project) -> String {
+    match project.submodule(SubmoduleAction::Status).unwrap() {
+        rit::Execution::Submodule(res) => res.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+fn nested_repository(project: &Project, name: &str) -> Project {
+    let nested = project.nested(name);
+    nested.init(None).unwrap();
+    nested.write_file("x.txt", "x");
+    nested.add(vec!["."]).unwrap();
+    nested.commit("nested").unwrap();
+
+    nested
+}
+
+fn head(project: &Project) -> String {
+    project.repo().refs.read_head().unwrap().as_str
+}
+
+#[test]
+fn it_stages_nested_repositories_as_gitlinks() {
+    filled_project(|project| {
+        let nested = nested_repository(project, "sub");
+
+        assert_eq!(vec!["sub/"], status(project).0);
+
+        project.add(vec!["."]).unwrap();
+        project.commit("add sub").unwrap();
+
+        assert!(project
+            .index_entries()
+            .contains(&("sub".to_string(), 0o160000)));
+        assert!(!project
+            .index_entries()
+            .iter()
+            .any(|(pathname, _)| pathname.starts_with("sub/")));
+        assert_eq!(
+            format!("160000 commit {}\tsub", head(&nested)),
+            project.git(&["ls-tree", "HEAD", "sub"])
+        );
+        assert_eq!((vec![], vec![]), status(project));
+        assert_eq!("", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_reports_submodules_with_another_commit_checked_out() {
+    filled_project(|project| {
+        let nested = nested_repository(project, "sub");
+        project.add(vec!["sub"]).unwrap();
+
+        nested.write_file("y.txt", "y");
+        nested.add(vec!["y.txt"]).unwrap();
+        nested.commit("second").unwrap();
+
+        assert_eq!((vec![], vec!["sub".to_string()]), status(project));
+        assert_eq!("AM sub", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_lists_empty_nested_repositories_as_untracked() {
+    filled_project(|project| {
+        project.nested("empty").init(None).unwrap();
+
+        assert_eq!(vec!["empty/"], status(project).0);
+        assert!(matches!(
+            project.add(vec!["empty"]),
+            Err(RitError::Submodule(message))
+                if message == "'empty/' does not have a commit checked out"
+        ));
+    });
+}
+
+#[test]
+fn it_adds_submodules_and_checks_them_out_in_clones() {
+    filled_project(|source| {
+        Project::open(|project| {
+            let url = source.dir().to_string_lossy().to_string();
+            let id = head(source);
+
+            project
+                .submodule(SubmoduleAction::Add {
+                    url: url.clone(),
+                    path: Some("lib".to_string()),
+                })
+                .unwrap();
+            project.commit("add lib").unwrap();
+
+            assert_eq!("one", project.read_file("lib/1.txt"));
+            assert_eq!(
+                format!("[submodule \"lib\"]\n\tpath = lib\n\turl = {}\n", url),
+                project.read_file(".gitmodules")
+            );
+            assert_eq!(format!(" {} lib", id), submodule_status(project));
+            assert_eq!(url, project.git(&["config", "submodule.lib.url"]));
+
+            let clone = Project::new();
+            clone
+                .clone_from(CloneOptions {
+                    source: project.dir().to_string_lossy().to_string(),
+                    directory: Some(".".to_string()),
+                    ..Default::default()
+                })
+                .unwrap();
+
+            assert!(clone.dir().join("lib").is_dir());
+            assert!(!clone.exists("lib/1.txt"));
+            assert_eq!(format!("-{} lib", id), submodule_status(&clone));
+
+            clone
+                .submodule(SubmoduleAction::Update { init: true })
+                .unwrap();
+
+            let lib = clone.nested("lib");
+
+            assert_eq!("one", clone.read_file("lib/1.txt"));
+            assert_eq!(None, lib.repo().refs.current_ref());
+            assert_eq!(format!(" {} lib", id), submodule_status(&clone));
+            assert_eq!((vec![], vec![]), status(&clone));
+        });
+    });
+}
+
+#[test]
+fn it_updates_submodules_back_to_the_recorded_commit() {
+    filled_project(|project| {
+        let nested = nested_repository(project, "sub");
+        let id = head(&nested);
+
+        fs::write(
+            project.dir().join(".gitmodules"),
+            "[submodule \"sub\"]\n\tpath = sub\n\turl = ./sub\n",
+        )
+        .unwrap();
+        project.add(vec![".gitmodules", "sub"]).unwrap();
+
+        nested.write_file("y.txt", "y");
+        nested.add(vec!["y.txt"]).unwrap();
+        nested.commit("second").unwrap();
+        let second = head(&nested);
+
+        assert_eq!(format!("+{} sub", second), submodule_status(project));
+
+        project.submodule(SubmoduleAction::Init).unwrap();
+        project
+            .submodule(SubmoduleAction::Update { init: false })
+            .unwrap();
+
+        assert_eq!(id, head(&nested));
+        assert_eq!(second, nested.git(&["rev-parse", "HEAD@{1}"]));
+        assert_eq!(
+            format!("submodule update: checkout {}", id),
+            nested.git(&["reflog", "-1", "--format=%gs"])
+        );
+        assert!(!nested.exists("y.txt"));
+        assert_eq!(format!(" {} sub", id), submodule_status(project));
+    });
+}
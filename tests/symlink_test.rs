@@ -0,0 +1,110 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::StashAction;
+use std::{fs, process};
+
+fn status(project: &Project) -> (Vec<String>, Vec<String>) {
+    match project.status().unwrap() {
+        rit::Execution::Status(res) => (
+            res.untracked.iter().map(|e| e.to_string()).collect(),
+            res.modified.iter().map(|e| e.to_string()).collect(),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+fn link_target(project: &Project, name: &str) -> String {
+    fs::read_link(project.dir().join(name))
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn it_stores_symlinks_with_their_target_as_content() {
+    filled_project(|project| {
+        project.symlink("a/2.txt", "file-link");
+        project.symlink("a", "dir-link");
+        project.symlink("missing", "dangling");
+
+        assert_eq!(vec!["dangling", "dir-link", "file-link"], status(project).0);
+
+        project.add(vec!["."]).unwrap();
+        project.commit("links").unwrap();
+
+        assert_eq!(
+            "120000 blob dangling\n120000 blob dir-link\n120000 blob file-link",
+            project.git(&[
+                "ls-tree",
+                "--format=%(objectmode) %(objecttype) %(path)",
+                "HEAD",
+                "dangling",
+                "dir-link",
+                "file-link"
+            ])
+        );
+        assert_eq!("a", project.git(&["cat-file", "-p", "HEAD:dir-link"]));
+        assert_eq!("", project.git(&["status", "--porcelain"]));
+        assert_eq!((vec![], vec![]), status(project));
+    });
+}
+
+#[test]
+fn it_detects_changed_symlink_targets() {
+    filled_project(|project| {
+        project.symlink("1.txt", "link");
+        project.add(vec!["link"]).unwrap();
+        project.commit("link").unwrap();
+
+        project.delete("link");
+        project.symlink("a/2.txt", "link");
+
+        assert_eq!(vec!["link"], status(project).1);
+        assert_eq!(" M link", project.git(&["status", "--porcelain"]));
+    });
+}
+
+#[test]
+fn it_recreates_symlinks_on_checkout() {
+    filled_project(|project| {
+        project.symlink("1.txt", "link");
+        project.add(vec!["link"]).unwrap();
+        project.commit("link").unwrap();
+
+        project.delete("link");
+        project.write_file("link", "now a file");
+
+        project
+            .stash(StashAction::Push {
+                message: None,
+                include_untracked: false,
+                pathspecs: vec![],
+            })
+            .unwrap();
+
+        assert_eq!("1.txt", link_target(project, "link"));
+        assert_eq!("one", project.read_file("1.txt"));
+        assert_eq!((vec![], vec![]), status(project));
+    });
+}
+
+#[test]
+fn it_skips_files_that_cannot_be_tracked() {
+    filled_project(|project| {
+        let mkfifo = process::Command::new("mkfifo")
+            .arg(project.dir().join("fifo"))
+            .status()
+            .unwrap();
+        assert!(mkfifo.success());
+
+        assert_eq!((vec![], vec![]), status(project));
+
+        project.add(vec!["."]).unwrap();
+
+        assert!(project
+            .index_entries()
+            .iter()
+            .all(|(name, _)| name != "fifo"));
+    });
+}
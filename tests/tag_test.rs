@@ -0,0 +1,159 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::{errors::RitError, TagAction, TagSort};
+
+fn create(name: &str, message: Option<&str>) -> TagAction {
+    TagAction::Create {
+        name: name.to_string(),
+        target: None,
+        message: message.map(str::to_string),
+        annotate: message.is_some(),
+        force: false,
+    }
+}
+
+fn list(project: &Project, patterns: Vec<&str>, sort: &str) -> Vec<String> {
+    let action = TagAction::List {
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        sort: TagSort::parse(sort).unwrap(),
+    };
+
+    match project.tag(action).unwrap() {
+        rit::Execution::Tag(res) => res.to_string().lines().map(str::to_string).collect(),
+        _ => panic!("Tag execution expected"),
+    }
+}
+
+#[test]
+fn it_creates_lightweight_tags() {
+    filled_project(|project| {
+        project.tag(create("v1.0", None)).unwrap();
+
+        let repo = project.repo();
+
+        assert_eq!(repo.refs.read_head(), repo.refs.read_ref("refs/tags/v1.0"));
+    });
+}
+
+#[test]
+fn it_creates_annotated_tags() {
+    filled_project(|project| {
+        project.tag(create("v1.0", Some("Release 1.0"))).unwrap();
+
+        let repo = project.repo();
+        let tag_id = repo.refs.read_ref("v1.0").unwrap();
+        let tag = repo.database.load_tag(&tag_id).unwrap();
+
+        assert_eq!(repo.refs.read_head(), Some(tag.object));
+        assert_eq!("commit", tag.kind);
+        assert_eq!("v1.0", tag.name);
+        assert_eq!("Release 1.0\n", tag.message);
+    });
+}
+
+#[test]
+fn it_peels_annotated_tags_to_commits() {
+    filled_project(|project| {
+        project.tag(create("v1.0", Some("Release 1.0"))).unwrap();
+
+        project.write_file("1.txt", "changed");
+        project.add(vec!["."]).unwrap();
+        project.commit_fixup("v1.0").unwrap();
+
+        assert_eq!(
+            vec!["fixup! message", "message"],
+            project.commit_messages("HEAD")
+        );
+    });
+}
+
+#[test]
+fn it_lists_tags_matching_a_pattern() {
+    filled_project(|project| {
+        for name in &["v1.0", "v2.0", "release"] {
+            project.tag(create(name, None)).unwrap();
+        }
+
+        assert_eq!(
+            vec!["release", "v1.0", "v2.0"],
+            list(project, vec![], "refname")
+        );
+        assert_eq!(vec!["v1.0", "v2.0"], list(project, vec!["v*"], "refname"));
+    });
+}
+
+#[test]
+fn it_sorts_tags_by_version() {
+    filled_project(|project| {
+        for name in &["v1.10", "v1.9", "v1.2"] {
+            project.tag(create(name, None)).unwrap();
+        }
+
+        assert_eq!(
+            vec!["v1.10", "v1.2", "v1.9"],
+            list(project, vec![], "refname")
+        );
+        assert_eq!(
+            vec!["v1.2", "v1.9", "v1.10"],
+            list(project, vec![], "version:refname")
+        );
+        assert_eq!(
+            vec!["v1.10", "v1.9", "v1.2"],
+            list(project, vec![], "-version:refname")
+        );
+    });
+}
+
+#[test]
+fn it_deletes_tags() {
+    filled_project(|project| {
+        project.tag(create("v1.0", None)).unwrap();
+
+        let id = project.repo().refs.read_head().unwrap();
+        let action = TagAction::Delete {
+            names: vec!["v1.0".to_string()],
+        };
+
+        match project.tag(action).unwrap() {
+            rit::Execution::Tag(res) => assert_eq!(
+                format!("Deleted tag 'v1.0' (was {})", id.short()),
+                res.to_string()
+            ),
+            _ => panic!("Tag execution expected"),
+        }
+
+        assert_eq!(None, project.repo().refs.read_ref("refs/tags/v1.0"));
+    });
+}
+
+#[test]
+fn it_refuses_to_replace_an_existing_tag() {
+    filled_project(|project| {
+        project.tag(create("v1.0", None)).unwrap();
+
+        match project.tag(create("v1.0", None)) {
+            Err(RitError::Refs(err)) => assert_eq!("tag 'v1.0' already exists", err.to_string()),
+            _ => panic!("Expected the tag to exist"),
+        }
+    });
+}
+
+#[test]
+fn it_does_not_delete_refs_outside_the_tags() {
+    filled_project(|project| {
+        let head = project.repo().refs.read_head();
+        let action = TagAction::Delete {
+            names: vec!["../heads/master".to_string()],
+        };
+
+        match project.tag(action) {
+            Err(RitError::Refs(err)) => {
+                assert_eq!("tag '../heads/master' not found.", err.to_string())
+            }
+            _ => panic!("Expected the tag not to be found"),
+        }
+
+        assert_eq!(head, project.repo().refs.read_ref("refs/heads/master"));
+    });
+}
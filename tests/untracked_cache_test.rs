@@ -0,0 +1,116 @@
+mod common;
+
+use common::{filled_project, Project};
+use rit::index::UntrackedCache;
+
+fn untracked_cache(project: &Project) -> Option<UntrackedCache> {
+    let mut repo = project.repo();
+    repo.index.load().unwrap();
+
+    repo.index.untracked_cache().cloned()
+}
+
+fn untracked(project: &Project) -> Vec<String> {
+    match project.status().unwrap() {
+        rit::Execution::Status(res) => res.untracked.iter().map(|e| e.to_string()).collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn it_records_the_untracked_files_of_each_directory() {
+    filled_project(|project| {
+        project.set_config("core.untrackedCache", "true");
+        project.write_file("new.txt", "");
+        project.write_file("a/b/new.txt", "");
+        project.write_file("u/v/new.txt", "");
+
+        assert_eq!(vec!["a/b/new.txt", "new.txt", "u/"], untracked(project));
+
+        let cache = untracked_cache(project).unwrap();
+        let root = cache.root().unwrap();
+
+        assert_eq!(vec!["new.txt", "u/"], root.untracked);
+        assert_eq!(vec!["new.txt"], root.children["a"].children["b"].untracked);
+        assert!(root.children["u"].check_only);
+        assert_eq!(
+            "?? a/b/new.txt\n?? new.txt\n?? u/",
+            project.git(&["status", "--porcelain"])
+        );
+    });
+}
+
+#[test]
+fn it_reuses_the_lists_of_unchanged_directories() {
+    filled_project(|project| {
+        project.set_config("core.untrackedCache", "true");
+        project.write_file("a/new.txt", "");
+        project.status().unwrap();
+
+        let mut repo = project.repo();
+        repo.index.load_for_update().unwrap();
+
+        let mut cache = repo.index.untracked_cache().cloned().unwrap();
+        let mut root = cache.root().cloned().unwrap();
+        root.children
+            .get_mut("a")
+            .unwrap()
+            .untracked
+            .push("cached.txt".to_string());
+        cache.set_root(root);
+
+        repo.index.set_untracked_cache(Some(cache));
+        repo.index.write_updates().unwrap();
+
+        assert_eq!(vec!["a/cached.txt", "a/new.txt"], untracked(project));
+    });
+}
+
+#[test]
+fn it_lists_again_the_directories_that_changed() {
+    filled_project(|project| {
+        project.set_config("core.untrackedCache", "true");
+        project.write_file("a/new.txt", "");
+        project.write_file("u/v/new.txt", "");
+        project.status().unwrap();
+
+        project.delete("a/new.txt");
+        project.write_file("a/b/new.txt", "");
+        project.delete("u/v/new.txt");
+
+        assert_eq!(vec!["a/b/new.txt"], untracked(project));
+        assert_eq!("?? a/b/new.txt", project.git(&["status", "--porcelain"]));
+
+        project.add(vec!["a/b/new.txt"]).unwrap();
+
+        assert!(untracked(project).is_empty());
+    });
+}
+
+#[test]
+fn it_reads_a_cache_written_by_git() {
+    filled_project(|project| {
+        project.set_config("core.untrackedCache", "true");
+        project.write_file("u/v/new.txt", "");
+        project.git(&["status"]);
+
+        assert!(untracked_cache(project).is_some());
+        assert_eq!(vec!["u/"], untracked(project));
+    });
+}
+
+#[test]
+fn it_drops_the_cache_when_disabled() {
+    filled_project(|project| {
+        project.status().unwrap();
+        assert!(untracked_cache(project).is_none());
+
+        project.set_config("core.untrackedCache", "true");
+        project.status().unwrap();
+        assert!(untracked_cache(project).is_some());
+
+        project.set_config("core.untrackedCache", "false");
+        project.status().unwrap();
+        assert!(untracked_cache(project).is_none());
+    });
+}